@@ -9,18 +9,28 @@ use serde::{Deserialize, Serialize};
 pub struct LoginAttempt {
     pub username: String,
     pub password: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp_code: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Props)]
 pub struct LoginFormProps {
     pub error: Option<String>,
     pub username: Option<String>,
+
+    /// Show the two-factor code field
+    #[props(default)]
+    pub totp_required: bool,
+
     pub onlogin: EventHandler<LoginAttempt>,
 }
 
 pub fn LoginForm(props: LoginFormProps) -> Element {
     let mut username = use_signal(move || props.username.unwrap_or_default().to_owned());
     let mut password = use_signal(|| "".to_owned());
+    let mut totp_code = use_signal(|| "".to_owned());
+    let totp_required = props.totp_required;
 
     rsx! {
         form {
@@ -46,6 +56,19 @@ pub fn LoginForm(props: LoginFormProps) -> Element {
                     oninput: move |event| password.set(event.value()),
                 }
             }
+            if totp_required {
+                label {
+                    class: "login-form-password",
+                    "Authentication code",
+                    input {
+                        r#type: "text",
+                        placeholder: "6-digit code or recovery code",
+                        autocomplete: "one-time-code",
+                        value: "{totp_code}",
+                        oninput: move |event| totp_code.set(event.value()),
+                    }
+                }
+            }
             div {
                 class: "login-form-buttons",
                 button {
@@ -54,8 +77,9 @@ pub fn LoginForm(props: LoginFormProps) -> Element {
                     onclick: move |_| {
                         let username = username();
                         let password = password();
+                        let totp_code = if totp_required { Some(totp_code().trim().to_owned()) } else { None };
                         tracing::info!("Login attempt: {username}");
-                        props.onlogin.call(LoginAttempt { username, password });
+                        props.onlogin.call(LoginAttempt { username, password, totp_code });
                     },
                     "Login",
                 }
@@ -77,10 +101,110 @@ pub fn LoginForm(props: LoginFormProps) -> Element {
     }
 }
 
+/// Format a Unix timestamp (seconds) in the browser's locale
+pub fn format_unix_time(time: Option<i64>) -> String {
+    match time {
+        Some(time) => {
+            let date = js_sys::Date::new(&wasm_bindgen::JsValue::from_f64(time as f64 * 1000.0));
+            date.to_locale_string("default", &wasm_bindgen::JsValue::UNDEFINED).into()
+        }
+        None => "Never".to_string(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PasswordChangeRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Props)]
+pub struct PasswordChangeFormProps {
+    pub error: Option<String>,
+    pub message: Option<String>,
+    pub onsubmit: EventHandler<PasswordChangeRequest>,
+}
+
+pub fn PasswordChangeForm(props: PasswordChangeFormProps) -> Element {
+    let mut old_password = use_signal(|| "".to_owned());
+    let mut new_password = use_signal(|| "".to_owned());
+    let mut new_password_confirm = use_signal(|| "".to_owned());
+    let mut mismatch = use_signal(|| false);
+
+    rsx! {
+        form {
+            class: "login-form",
+            h1 { "Change Password" }
+            label {
+                class: "login-form-password",
+                "Current password",
+                input {
+                    r#type: "password",
+                    value: "{old_password}",
+                    oninput: move |event| old_password.set(event.value()),
+                }
+            }
+            label {
+                class: "login-form-password",
+                "New password",
+                input {
+                    r#type: "password",
+                    value: "{new_password}",
+                    oninput: move |event| new_password.set(event.value()),
+                }
+            }
+            label {
+                class: "login-form-password",
+                "Confirm new password",
+                input {
+                    r#type: "password",
+                    value: "{new_password_confirm}",
+                    oninput: move |event| new_password_confirm.set(event.value()),
+                }
+            }
+            div {
+                class: "login-form-buttons",
+                button {
+                    class: "login-form-login-button",
+                    r#type: "button",
+                    onclick: move |_| {
+                        if new_password() != new_password_confirm() {
+                            mismatch.set(true);
+                            return;
+                        }
+                        mismatch.set(false);
+                        props.onsubmit.call(PasswordChangeRequest {
+                            old_password: old_password(),
+                            new_password: new_password(),
+                        });
+                    },
+                    "Change Password",
+                }
+            }
+            if mismatch() {
+                div {
+                    class: "login-form-error",
+                    "The new passwords do not match"
+                }
+            } else if let Some(error) = &props.error {
+                div {
+                    class: "login-form-error",
+                    "{error}"
+                }
+            } else if let Some(message) = &props.message {
+                div {
+                    "{message}"
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct User {
     pub username: String,
     pub api_token: String,
+    pub is_admin: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Props)]
@@ -106,11 +230,42 @@ pub fn AccountBar(props: AccountBarProps) -> Element {
                                 }
                                 "{&user.username}"
                             }
+                            Link {
+                                class: "link-button",
+                                to: crate::Route::AccountTokens {},
+                                onclick: move |_| { crate::close_drawer(); },
+                                "API Tokens",
+                            }
+                            Link {
+                                class: "link-button",
+                                to: crate::Route::AccountPassword {},
+                                onclick: move |_| { crate::close_drawer(); },
+                                "Change Password",
+                            }
+                            Link {
+                                class: "link-button",
+                                to: crate::Route::AccountTotp {},
+                                onclick: move |_| { crate::close_drawer(); },
+                                "Two-Factor Authentication",
+                            }
+                            if user.is_admin {
+                                Link {
+                                    class: "link-button",
+                                    to: crate::Route::AdminUsers {},
+                                    onclick: move |_| { crate::close_drawer(); },
+                                    "Users",
+                                }
+                            }
                             button {
                                 class: "link-button",
                                 onclick: move |_| {
                                     crate::close_drawer();
-                                    props.onlogout.call(user.clone());
+                                    let user = user.clone();
+                                    spawn(async move {
+                                        // Revoke the session token; log out locally even if this fails.
+                                        let _ = crate::fetch::post::<crate::inet::ApiResponse, _>("/api/v1/logout", serde_json::json!({}), Some(&user.api_token)).await;
+                                        props.onlogout.call(user);
+                                    });
                                 },
                                 "Logout",
                             }
@@ -0,0 +1,132 @@
+//! Address-level views of IPv4/IPv6 assignments
+
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+
+
+/// What an address of an assignment is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Free,
+    Reserved,
+    Gateway,
+    Host,
+}
+
+impl CellKind {
+    fn style(&self) -> &'static str {
+        match self {
+            CellKind::Free => "background-color: #e8f5e9; cursor: pointer;",
+            CellKind::Reserved => "background-color: #e0e0e0; color: #757575;",
+            CellKind::Gateway => "background-color: #fff3e0; font-weight: bold;",
+            CellKind::Host => "background-color: #e3f2fd;",
+        }
+    }
+}
+
+/// Cell of the address grid of an assignment
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridCell {
+    /// Stringified address
+    pub address: String,
+
+    /// Last part of the address, shown in the cell
+    pub short: String,
+
+    /// Host name, or what the address is reserved for
+    pub label: String,
+
+    pub kind: CellKind,
+}
+
+/// Host table row for IPv4/IPv6 hosts
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostRow {
+    pub id: i32,
+
+    /// Stringified address
+    pub address: String,
+
+    pub hostname: String,
+    pub mac_address: String,
+    pub description: String,
+    pub gateway: bool,
+}
+
+/// Every address of a small assignment; clicking a free address selects it
+#[component]
+pub fn HostGrid(cells: Vec<GridCell>, onselect: EventHandler<String>) -> Element {
+    rsx! {
+        div {
+            style: "display: grid; grid-template-columns: repeat(16, minmax(0, 1fr)); gap: 2px; margin: 1em 0;",
+            {cells.into_iter().map(|cell| {
+                let title = if cell.label.is_empty() { cell.address.clone() } else { format!("{} ({})", cell.address, cell.label) };
+                let free = cell.kind == CellKind::Free;
+                let address = cell.address;
+                rsx! {
+                    div {
+                        style: "padding: 0.25em; text-align: center; font-size: 0.8em; overflow: hidden; {cell.kind.style()}",
+                        title: "{title}",
+                        onclick: move |_| {
+                            if free {
+                                onselect.call(address.clone());
+                            }
+                        },
+                        "{cell.short}"
+                    }
+                }
+            })}
+        }
+    }
+}
+
+#[component]
+pub fn HostTable(rows: Vec<HostRow>, ondelete: EventHandler<i32>) -> Element {
+    rsx! {
+        table {
+            class: "assignment-table",
+            thead {
+                tr {
+                    th { "Address" }
+                    th { "Host name" }
+                    th { "MAC address" }
+                    th { "Description" }
+                    th { "" }
+                }
+            }
+            tbody {
+                for row in rows {
+                    tr {
+                        td {
+                            "{row.address}"
+                            if row.gateway {
+                                " (gateway)"
+                            }
+                        }
+                        td {
+                            div {
+                                class: "scrollable",
+                                "{row.hostname}"
+                            }
+                        }
+                        td { "{row.mac_address}" }
+                        td {
+                            div {
+                                class: "scrollable",
+                                "{row.description}"
+                            }
+                        }
+                        td {
+                            button {
+                                class: "delete-button",
+                                onclick: move |_| ondelete.call(row.id),
+                                "Delete"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
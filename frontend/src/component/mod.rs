@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 pub mod account;
+pub mod hosts;
 pub mod table;
 
 use crate::Route;
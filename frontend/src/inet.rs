@@ -64,6 +64,15 @@ pub fn ipv4_broadcast_address(ip: RawIpv4Addr, prefix_len: u8) -> RawIpv4Addr {
     addr
 }
 
+/// Addresses of a network that cannot be given to hosts: the network and broadcast addresses,
+/// except in /31 and /32 networks, which have none (RFC 3021)
+pub fn ipv4_reserved_addresses(ip: RawIpv4Addr, prefix_len: u8) -> Vec<RawIpv4Addr> {
+    if prefix_len >= 31 {
+        return Vec::new();
+    }
+    vec![ipv4_network_address(ip, prefix_len), ipv4_broadcast_address(ip, prefix_len)]
+}
+
 pub fn ipv6_subnet_mask(prefix_len: u8) -> RawIpv6Addr {
     let mut mask = 0xffffffff_ffffffff_ffffffff_ffffffffu128;
     mask = mask.checked_shr(prefix_len as u32).unwrap_or(0);
@@ -99,6 +108,15 @@ pub fn ipv6_broadcast_address(ip: RawIpv6Addr, prefix_len: u8) -> RawIpv6Addr {
     addr
 }
 
+/// Addresses of a network that cannot be given to hosts: the Subnet-Router anycast address
+/// (the first address), except in /127 and /128 networks, which have none (RFC 6164)
+pub fn ipv6_reserved_addresses(ip: RawIpv6Addr, prefix_len: u8) -> Vec<RawIpv6Addr> {
+    if prefix_len >= 127 {
+        return Vec::new();
+    }
+    vec![ipv6_network_address(ip, prefix_len)]
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Ipv4Prefix {
     prefix: RawIpv4Addr,
@@ -224,6 +242,19 @@ pub struct ApiResponse {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")] 
 pub enum ApiResponseVariant {
+    User(CurrentUser),
+    UserInfo(UserInfo),
+    UserInfos(Vec<UserInfo>),
+    LoginLockouts(Vec<LoginLockout>),
+    ApiToken(String),
+    ApiTokens(Vec<ApiToken>),
+    NewApiToken(NewApiToken),
+    TotpRequired(bool),
+    TotpStatus(TotpStatus),
+    TotpEnrolment(TotpEnrolment),
+    RecoveryCodes(Vec<String>),
+    LoginMethods(LoginMethods),
+
     AsnAssignmentSpace(AssignmentSpaceAsn),
     AsnAssignmentPool(AssignmentPoolAsn),
     AsnAssignment(AssignmentAsn),
@@ -243,6 +274,146 @@ pub enum ApiResponseVariant {
     Ipv6AssignmentSpaces(Vec<AssignmentSpaceIpv6>),
     Ipv6AssignmentPools(Vec<AssignmentPoolIpv6>),
     Ipv6Assignments(Vec<AssignmentIpv6>),
+
+    Ipv4Host(HostIpv4),
+    Ipv4Hosts(Vec<HostIpv4>),
+    Ipv4Address(Option<RawIpv4Addr>),
+    Ipv6Host(HostIpv6),
+    Ipv6Hosts(Vec<HostIpv6>),
+    Ipv6Address(Option<RawIpv6Addr>),
+}
+
+/// Role of a user
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Display for UserRole {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UserRole::User => write!(f, "User"),
+            UserRole::Admin => write!(f, "Admin"),
+        }
+    }
+}
+
+/// The currently logged-in user
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CurrentUser {
+    pub username: String,
+
+    #[serde(default)]
+    pub role: UserRole,
+}
+
+/// A user with metadata, as shown to administrators
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserInfo {
+    pub username: String,
+
+    pub role: UserRole,
+
+    /// Creation time (Unix seconds). 0 if unknown.
+    pub created_at: i64,
+
+    /// Number of API tokens (including login sessions)
+    pub api_token_count: i64,
+
+    /// Last time any API token of the user was used (Unix seconds)
+    pub last_active_at: Option<i64>,
+}
+
+/// Two-factor authentication state of the current user
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub pending: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+/// A started TOTP enrolment
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TotpEnrolment {
+    /// Base32-encoded secret, for manual entry
+    pub secret: String,
+
+    pub otpauth_uri: String,
+
+    /// QR code of `otpauth_uri`, as an SVG document
+    pub qr_code_svg: Option<String>,
+}
+
+/// What login failures are counted against
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum LoginThrottleKey {
+    Username(String),
+    Source(String),
+}
+
+/// A username or source address that is currently throttled or locked out
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoginLockout {
+    pub key: LoginThrottleKey,
+    pub failures: i64,
+
+    /// Time until which logins are refused (Unix seconds)
+    pub blocked_until: i64,
+
+    pub locked_out: bool,
+}
+
+/// What an API token is allowed to do
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenScope {
+    /// Only allow requests that do not modify anything
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Restrict the token to these resource families (`asn`, `ipv4`, `ipv6`). `None` means all families.
+    #[serde(default)]
+    pub families: Option<Vec<String>>,
+}
+
+impl Display for TokenScope {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let access = if self.read_only { "Read-only" } else { "Read-write" };
+        match &self.families {
+            Some(families) => write!(f, "{} ({})", access, families.join(", ")),
+            None => write!(f, "{}", access),
+        }
+    }
+}
+
+/// Metadata of an API token
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiToken {
+    pub id: i32,
+
+    /// Human-readable token name
+    pub name: String,
+
+    /// Creation time (Unix seconds)
+    pub created_at: i64,
+
+    /// Expiry time (Unix seconds)
+    pub expires_at: Option<i64>,
+
+    /// Last time the token was used (Unix seconds)
+    pub last_used_at: Option<i64>,
+
+    pub scope: TokenScope,
+}
+
+/// A newly created API token, including its secret
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewApiToken {
+    pub token: ApiToken,
+    pub secret: String,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -430,6 +601,10 @@ pub struct AssignmentIpv4 {
 
     /// Length of the IPv4 prefix
     pub ipv4_prefix_len: i32,
+
+    /// ASN assignment originating the prefix, for route objects
+    #[serde(default)]
+    pub origin_asn_id: Option<i32>,
 }
 
 /// IPv6 assignment space. Can contain multiple pools.
@@ -502,4 +677,72 @@ pub struct AssignmentIpv6 {
 
     /// Length of the IPv6 prefix
     pub ipv6_prefix_len: i32,
+
+    /// ASN assignment originating the prefix, for route objects
+    #[serde(default)]
+    pub origin_asn_id: Option<i32>,
+}
+
+/// An address within an IPv4 assignment, such as a server in a server subnet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HostIpv4 {
+    #[serde(default)]
+    pub id: i32,
+
+    /// Parent assignment ID
+    pub assignment_id: i32,
+
+    /// IPv4 address of the host, in big-endian bit and byte order
+    pub ipv4_address: [u8; 4],
+
+    /// Host name, such as `web1.example.net`; may be empty
+    #[serde(default)]
+    pub hostname: String,
+
+    /// MAC address, such as `00:00:5e:00:53:01`
+    #[serde(default)]
+    pub mac_address: Option<String>,
+
+    /// Document the purpose of the host, its owner, etc.
+    #[serde(default)]
+    pub description: String,
+
+    /// The host is the default gateway of the assignment
+    #[serde(default)]
+    pub gateway: bool,
+}
+
+/// An address within an IPv6 assignment, such as a server in a server subnet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HostIpv6 {
+    #[serde(default)]
+    pub id: i32,
+
+    /// Parent assignment ID
+    pub assignment_id: i32,
+
+    /// IPv6 address of the host, in big-endian bit and byte order
+    pub ipv6_address: [u8; 16],
+
+    /// Host name, such as `web1.example.net`; may be empty
+    #[serde(default)]
+    pub hostname: String,
+
+    /// MAC address, such as `00:00:5e:00:53:01`
+    #[serde(default)]
+    pub mac_address: Option<String>,
+
+    /// Document the purpose of the host, its owner, etc.
+    #[serde(default)]
+    pub description: String,
+
+    /// The host is the default gateway of the assignment
+    #[serde(default)]
+    pub gateway: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoginMethods {
+    pub password: bool,
+    pub oidc: bool,
 }
@@ -28,6 +28,18 @@ pub enum Route {
     #[route("/login/")]
     Login {},
 
+    #[route("/account/tokens/")]
+    AccountTokens {},
+
+    #[route("/account/password/")]
+    AccountPassword {},
+
+    #[route("/account/2fa/")]
+    AccountTotp {},
+
+    #[route("/admin/users/")]
+    AdminUsers {},
+
 
     // ASN assignments
 
@@ -258,46 +270,811 @@ pub struct LoginResult {
 #[component]
 fn Login() -> Element {
     let mut error = use_signal(|| None);
-    let mut user = use_context::<Signal<Option<component::account::User>>>();
+    let mut totp_required = use_signal(|| false);
+    let user = use_context::<Signal<Option<component::account::User>>>();
 
     let log_in = move |attempt: component::account::LoginAttempt| {
         spawn(async move {
             let username = attempt.username.clone();
 
 
-            let res: Result<LoginResult, _> = fetch::post("/api/v1/login", &attempt, None).await;
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/login", &attempt, None).await;
             let res = match res {
-                Ok(res) => {
-                res
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::ApiToken(api_token)) }) => {
+                    LoginResult { api_token }
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: Some(inet::ApiResponseVariant::TotpRequired(true)) }) => {
+                    totp_required.set(true);
+                    error.set(Some(err));
+                    return;
+                }
+                // Throttled or locked out
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) if err != "Unauthorized" => {
+                    error.set(Some(err));
+                    return;
                 }
-                Err(_e) => {
+                _ => {
                     error.set(Some(format!("Login failed")));
                     return;
                 }
             };
 
-            user.set(Some(component::account::User {
-                username,
-                api_token: res.api_token,
-            }));
-
-            let nav = use_context::<Navigator>();
-            nav.replace(Route::Home {});
+            finish_login(user, username, res.api_token).await;
         });
     };
+
+    // Single sign-on redirects back here with the result in the URL fragment.
+    use_effect(move || {
+        let location = web_sys::window().unwrap().location();
+        let hash = location.hash().unwrap_or_default();
+        let params: std::collections::HashMap<String, String> = url::form_urlencoded::parse(hash.trim_start_matches('#').as_bytes()).into_owned().collect();
+        if params.is_empty() {
+            return;
+        }
+        let _ = location.set_hash("");
+        if let Some(err) = params.get("error") {
+            error.set(Some(err.clone()));
+        } else if let (Some(api_token), Some(username)) = (params.get("api_token"), params.get("username")) {
+            let (api_token, username) = (api_token.clone(), username.clone());
+            spawn(async move {
+                finish_login(user, username, api_token).await;
+            });
+        }
+    });
+
+    let methods = use_resource(|| async {
+        match fetch::get::<inet::ApiResponse>("/api/v1/login/methods", None).await {
+            Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::LoginMethods(methods)) }) => Some(methods),
+            _ => None,
+        }
+    });
+    let oidc = methods.read().as_ref().and_then(|methods| methods.as_ref()).map(|methods| methods.oidc).unwrap_or(false);
+
     rsx! {
         component::account::LoginForm {
             error: error(),
             username: None,
+            totp_required: totp_required(),
             onlogin: log_in
         }
+        if oidc {
+            p {
+                a { href: "/api/v1/oidc/login", "Log in with single sign-on" }
+            }
+        }
     }
 }
 
+/// Store the logged-in user and go to the home page
+async fn finish_login(mut user: Signal<Option<component::account::User>>, username: String, api_token: String) {
+    // The role only decides which pages are offered; the API enforces it.
+    let is_admin = match fetch::get::<inet::ApiResponse>("/api/v1/user/self", Some(&api_token)).await {
+        Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::User(current)) }) => current.role == inet::UserRole::Admin,
+        _ => false,
+    };
+
+    user.set(Some(component::account::User {
+        username,
+        api_token,
+        is_admin,
+    }));
+
+    let nav = use_context::<Navigator>();
+    nav.replace(Route::Home {});
+}
+
 pub fn use_token() -> Option<String> {
     use_context::<Signal<Option<component::account::User>>>().as_ref().map(|u| u.api_token.clone())
 }
 
+#[component]
+fn AccountPassword() -> Element {
+    let mut error = use_signal(|| None);
+    let mut message = use_signal(|| None);
+
+    let change_password = move |req: component::account::PasswordChangeRequest| {
+        let token = use_token();
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::put("/api/v1/user/self/password", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: _ }) => {
+                    error.set(None);
+                    message.set(Some("Password changed. All other sessions have been logged out.".to_string()));
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => {
+                    message.set(None);
+                    error.set(Some(err));
+                }
+                Err(_) => {
+                    message.set(None);
+                    error.set(Some("Failed to change password".to_string()));
+                }
+            }
+        });
+    };
+
+    rsx! {
+        component::account::PasswordChangeForm {
+            error: error(),
+            message: message(),
+            onsubmit: change_password,
+        }
+    }
+}
+
+#[component]
+fn AccountTotp() -> Element {
+    let token = use_token();
+    let mut error = use_signal(|| None);
+    let mut enrolment = use_signal(|| Option::<inet::TotpEnrolment>::None);
+    let mut recovery_codes = use_signal(|| Option::<Vec<String>>::None);
+    let mut code = use_signal(|| String::new());
+    let mut password = use_signal(|| String::new());
+
+    let token_copy = token.clone();
+    let mut status = use_resource(move || {
+        let token = token_copy.clone();
+        async move {
+            match fetch::get::<inet::ApiResponse>("/api/v1/user/self/totp", token.as_deref()).await {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::TotpStatus(status)) }) => Some(status),
+                _ => None,
+            }
+        }
+    });
+
+    let token_copy = token.clone();
+    let begin = move |_| {
+        let token = token_copy.clone();
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/user/self/totp", serde_json::json!({}), token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::TotpEnrolment(started)) }) => {
+                    error.set(None);
+                    recovery_codes.set(None);
+                    enrolment.set(Some(started));
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                _ => error.set(Some("Failed to start enrolment".to_string())),
+            }
+        });
+    };
+
+    let token_copy = token.clone();
+    let confirm = move |_| {
+        let token = token_copy.clone();
+        let req = serde_json::json!({ "code": code().trim() });
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/user/self/totp/confirm", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::RecoveryCodes(codes)) }) => {
+                    error.set(None);
+                    enrolment.set(None);
+                    code.set(String::new());
+                    recovery_codes.set(Some(codes));
+                    status.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                _ => error.set(Some("Failed to confirm enrolment".to_string())),
+            }
+        });
+    };
+
+    let token_copy = token.clone();
+    let regenerate = move |_| {
+        let token = token_copy.clone();
+        let req = serde_json::json!({ "password": password() });
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/user/self/totp/recovery_codes", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::RecoveryCodes(codes)) }) => {
+                    error.set(None);
+                    password.set(String::new());
+                    recovery_codes.set(Some(codes));
+                    status.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                _ => error.set(Some("Failed to generate recovery codes".to_string())),
+            }
+        });
+    };
+
+    let token_copy = token.clone();
+    let disable = move |_| {
+        let token = token_copy.clone();
+        let req = serde_json::json!({ "password": password() });
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/user/self/totp/disable", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: _ }) => {
+                    error.set(None);
+                    password.set(String::new());
+                    recovery_codes.set(None);
+                    status.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                _ => error.set(Some("Failed to disable two-factor authentication".to_string())),
+            }
+        });
+    };
+
+    let crumbs = vec![component::BreadCrumb {
+        name: "Home".to_string(),
+        route: Route::Home {},
+    }];
+
+    let current = match &*status.read_unchecked() {
+        Some(Some(current)) => Some(current.clone()),
+        _ => None,
+    };
+
+    rsx! {
+        component::BreadCrumbs { crumbs, title: "Two-Factor Authentication" }
+        h1 { "Two-Factor Authentication" }
+        if let Some(err) = error() {
+            p { style: "color: red;", "{err}" }
+        }
+        if let Some(codes) = recovery_codes() {
+            div {
+                class: "new-token",
+                p { "Recovery codes. Store them somewhere safe, they will not be shown again. Each code can be used once instead of an authentication code:" }
+                pre { {codes.join("\n")} }
+            }
+        }
+        match current {
+            Some(current) if current.enabled => {
+                rsx! {
+                    p { "Two-factor authentication is enabled. {current.recovery_codes_remaining} recovery codes remaining." }
+                    input {
+                        r#type: "password",
+                        placeholder: "Current password",
+                        value: "{password}",
+                        oninput: move |e: Event<FormData>| password.set(e.value().clone()),
+                    }
+                    button {
+                        onclick: regenerate,
+                        "Generate New Recovery Codes"
+                    }
+                    button {
+                        class: "delete-button",
+                        onclick: disable,
+                        "Disable"
+                    }
+                }
+            }
+            Some(_) => {
+                rsx! {
+                    p { "Two-factor authentication is disabled. When enabled, logging in requires a code from an authenticator app." }
+                    if let Some(started) = enrolment() {
+                        p { "Scan this QR code with your authenticator app, or enter the secret manually:" }
+                        if let Some(svg) = started.qr_code_svg.clone() {
+                            div {
+                                class: "totp-qr-code",
+                                dangerous_inner_html: "{svg}",
+                            }
+                        }
+                        pre { "{started.secret}" }
+                        component::TextInput {
+                            placeholder: "6-digit code",
+                            value: "{code}",
+                            oninput: move |e: Event<FormData>| code.set(e.value().clone()),
+                        }
+                        button {
+                            onclick: confirm,
+                            "Enable"
+                        }
+                    } else {
+                        button {
+                            onclick: begin,
+                            "Set Up Two-Factor Authentication"
+                        }
+                    }
+                }
+            }
+            None => {
+                rsx! {
+                    p { "Loading..." }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn AccountTokens() -> Element {
+    let token = use_token();
+    let mut name = use_signal(|| String::new());
+    let mut expires_in_days = use_signal(|| String::new());
+    let mut read_only = use_signal(|| false);
+    let mut families = use_signal(|| String::new());
+    let mut error = use_signal(|| None);
+    let mut new_token = use_signal(|| Option::<inet::NewApiToken>::None);
+
+    let token_copy = token.clone();
+    let mut future = use_resource(move || {
+        let token = token_copy.clone();
+        async move {
+            let api_res = fetch::get::<inet::ApiResponse>("/api/v1/user/tokens", token.as_deref()).await;
+            match api_res {
+                Ok(api_res) => {
+                    match api_res.result {
+                        Some(inet::ApiResponseVariant::ApiTokens(tokens)) => {
+                            Some(tokens)
+                        }
+                        _ => {
+                            None
+                        }
+                    }
+                }
+                Err(_) => {
+                    None
+                }
+            }
+        }
+    });
+
+    let token_copy = token.clone();
+    let create_token = move |_| {
+        let token = token_copy.clone();
+        let name = name().trim().to_owned();
+        if name.is_empty() {
+            error.set(Some("Token name is required".to_string()));
+            return;
+        }
+
+        let expires_in = match expires_in_days().trim() {
+            "" => None,
+            days => match days.parse::<i64>() {
+                Ok(days) if days > 0 => Some(days * 86400),
+                _ => {
+                    error.set(Some("Invalid expiry value".to_string()));
+                    return;
+                }
+            },
+        };
+
+        let families = families().split(',').map(|family| family.trim().to_owned()).filter(|family| !family.is_empty()).collect::<Vec<_>>();
+        let scope = inet::TokenScope {
+            read_only: read_only(),
+            families: if families.is_empty() { None } else { Some(families) },
+        };
+
+        let req = serde_json::json!({
+            "name": name,
+            "expires_in": expires_in,
+            "scope": scope,
+        });
+
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/user/tokens", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::NewApiToken(created)) }) => {
+                    error.set(None);
+                    new_token.set(Some(created));
+                    future.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => {
+                    error.set(Some(err));
+                }
+                _ => {
+                    error.set(Some("Failed to create API token".to_string()));
+                }
+            }
+        });
+    };
+
+    let crumbs = vec![component::BreadCrumb {
+        name: "Home".to_string(),
+        route: Route::Home {},
+    }];
+
+    let tokens = match &*future.read_unchecked() {
+        Some(Some(tokens)) => Some(tokens.clone()),
+        _ => None,
+    };
+
+    rsx! {
+        component::BreadCrumbs { crumbs, title: "API Tokens" }
+        h1 { "API Tokens" }
+        if let Some(err) = error() {
+            p { style: "color: red;", "{err}" }
+        }
+        if let Some(created) = new_token() {
+            div {
+                class: "new-token",
+                p { "New token \"{created.token.name}\" created. Copy it now, it will not be shown again:" }
+                pre { "{created.secret}" }
+            }
+        }
+        match tokens {
+            Some(tokens) => {
+                rsx! {
+                    table {
+                        class: "assignment-table",
+                        thead {
+                            tr {
+                                th { "Name" }
+                                th { "Scope" }
+                                th { "Created" }
+                                th { "Expires" }
+                                th { "Last used" }
+                                th { "" }
+                            }
+                        }
+                        tbody {
+                            for api_token in tokens {
+                                tr {
+                                    td { "{api_token.name}" }
+                                    td { "{api_token.scope}" }
+                                    td { {component::account::format_unix_time(Some(api_token.created_at))} }
+                                    td { {component::account::format_unix_time(api_token.expires_at)} }
+                                    td { {component::account::format_unix_time(api_token.last_used_at)} }
+                                    td {
+                                        button {
+                                            class: "delete-button",
+                                            onclick: move |_| {
+                                                let token = use_token();
+                                                let token_id = api_token.id;
+                                                spawn(async move {
+                                                    let _ = fetch::delete::<inet::ApiResponse>(&format!("/api/v1/user/tokens/{token_id}"), token.as_deref()).await;
+                                                    future.restart();
+                                                });
+                                            },
+                                            "Revoke"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            None => {
+                rsx! {
+                    p { "Loading..." }
+                }
+            }
+        }
+        h2 { "Create API Token" }
+        component::TextInput {
+            placeholder: "Name",
+            value: "{name}",
+            oninput: move |e: Event<FormData>| name.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Expires in (days, empty for never)",
+            value: "{expires_in_days}",
+            oninput: move |e: Event<FormData>| expires_in_days.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Resource families (comma-separated, empty for all)",
+            value: "{families}",
+            oninput: move |e: Event<FormData>| families.set(e.value().clone()),
+        }
+        label {
+            class: "select-label",
+            "Access"
+            select {
+                value: if read_only() { "Read-only" } else { "Read-write" },
+                oninput: move |e| read_only.set(e.value() == "Read-only"),
+                option { "Read-write" }
+                option { "Read-only" }
+            }
+        }
+        button {
+            onclick: create_token,
+            "Create API Token"
+        }
+    }
+}
+
+#[component]
+fn AdminUsers() -> Element {
+    let token = use_token();
+    let mut username = use_signal(|| String::new());
+    let mut password = use_signal(|| String::new());
+    let mut role = use_signal(|| inet::UserRole::User);
+    let mut error = use_signal(|| None);
+    let mut message = use_signal(|| None);
+
+    let token_copy = token.clone();
+    let mut future = use_resource(move || {
+        let token = token_copy.clone();
+        async move {
+            let api_res = fetch::get::<inet::ApiResponse>("/api/v1/users", token.as_deref()).await;
+            match api_res {
+                Ok(api_res) => {
+                    match api_res.result {
+                        Some(inet::ApiResponseVariant::UserInfos(users)) => {
+                            Ok(users)
+                        }
+                        _ => {
+                            Err(api_res.error.unwrap_or("Failed to load users".to_string()))
+                        }
+                    }
+                }
+                Err(_) => {
+                    Err("Failed to load users".to_string())
+                }
+            }
+        }
+    });
+
+    let token_copy = token.clone();
+    let create_user = move |_| {
+        let token = token_copy.clone();
+        let req = serde_json::json!({
+            "username": username().trim(),
+            "password": password(),
+            "role": role(),
+        });
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post("/api/v1/users", &req, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: _ }) => {
+                    error.set(None);
+                    message.set(Some("User created".to_string()));
+                    username.set(String::new());
+                    password.set(String::new());
+                    future.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => {
+                    error.set(Some(err));
+                }
+                Err(_) => {
+                    error.set(Some("Failed to create user".to_string()));
+                }
+            }
+        });
+    };
+
+    let crumbs = vec![component::BreadCrumb {
+        name: "Home".to_string(),
+        route: Route::Home {},
+    }];
+
+    let token_copy = token.clone();
+    let mut lockouts = use_resource(move || {
+        let token = token_copy.clone();
+        async move {
+            let api_res = fetch::get::<inet::ApiResponse>("/api/v1/login_attempts/lockouts", token.as_deref()).await;
+            match api_res {
+                Ok(inet::ApiResponse { error: None, result: Some(inet::ApiResponseVariant::LoginLockouts(lockouts)) }) => lockouts,
+                _ => vec![],
+            }
+        }
+    });
+
+    let users = match &*future.read_unchecked() {
+        Some(users) => Some(users.clone()),
+        None => None,
+    };
+
+    let lockout_list = match &*lockouts.read_unchecked() {
+        Some(lockouts) => lockouts.clone(),
+        None => vec![],
+    };
+
+    rsx! {
+        component::BreadCrumbs { crumbs, title: "Users" }
+        h1 { "Users" }
+        if let Some(err) = error() {
+            p { style: "color: red;", "{err}" }
+        } else if let Some(msg) = message() {
+            p { "{msg}" }
+        }
+        match users {
+            Some(Ok(users)) => {
+                rsx! {
+                    table {
+                        class: "assignment-table",
+                        thead {
+                            tr {
+                                th { "Username" }
+                                th { "Role" }
+                                th { "Created" }
+                                th { "Tokens" }
+                                th { "Last active" }
+                                th { "" }
+                            }
+                        }
+                        tbody {
+                            for user in users {
+                                tr {
+                                    td { "{user.username}" }
+                                    td {
+                                        select {
+                                            value: "{user.role}",
+                                            oninput: {
+                                                let username = user.username.clone();
+                                                move |e: Event<FormData>| {
+                                                    let token = use_token();
+                                                    let username = username.clone();
+                                                    let role = if e.value() == "Admin" { inet::UserRole::Admin } else { inet::UserRole::User };
+                                                    spawn(async move {
+                                                        let req = serde_json::json!({ "role": role });
+                                                        let res: Result<inet::ApiResponse, _> = fetch::put(&format!("/api/v1/users/{username}/role"), &req, token.as_deref()).await;
+                                                        match res {
+                                                            Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                                                            Err(_) => error.set(Some("Failed to change role".to_string())),
+                                                            _ => error.set(None),
+                                                        }
+                                                        future.restart();
+                                                    });
+                                                }
+                                            },
+                                            option { "User" }
+                                            option { "Admin" }
+                                        }
+                                    }
+                                    td {
+                                        if user.created_at > 0 {
+                                            {component::account::format_unix_time(Some(user.created_at))}
+                                        } else {
+                                            "Unknown"
+                                        }
+                                    }
+                                    td { "{user.api_token_count}" }
+                                    td { {component::account::format_unix_time(user.last_active_at)} }
+                                    td {
+                                        button {
+                                            onclick: {
+                                                let username = user.username.clone();
+                                                move |_| {
+                                                    let token = use_token();
+                                                    let username = username.clone();
+                                                    let new_password = web_sys::window()
+                                                        .and_then(|window| window.prompt_with_message(&format!("New password for {username}:")).ok().flatten())
+                                                        .unwrap_or_default();
+                                                    if new_password.is_empty() {
+                                                        return;
+                                                    }
+                                                    spawn(async move {
+                                                        let req = serde_json::json!({ "password": new_password });
+                                                        let res: Result<inet::ApiResponse, _> = fetch::put(&format!("/api/v1/users/{username}/password"), &req, token.as_deref()).await;
+                                                        match res {
+                                                            Ok(inet::ApiResponse { error: None, result: _ }) => {
+                                                                error.set(None);
+                                                                message.set(Some(format!("Password of {username} reset. Their sessions have been logged out.")));
+                                                            }
+                                                            Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                                                            Err(_) => error.set(Some("Failed to reset password".to_string())),
+                                                        }
+                                                        future.restart();
+                                                    });
+                                                }
+                                            },
+                                            "Reset Password"
+                                        }
+                                        button {
+                                            class: "delete-button",
+                                            onclick: {
+                                                let username = user.username.clone();
+                                                move |_| {
+                                                    let token = use_token();
+                                                    let username = username.clone();
+                                                    let confirmed = web_sys::window()
+                                                        .and_then(|window| window.confirm_with_message(&format!("Delete user {username}?")).ok())
+                                                        .unwrap_or(false);
+                                                    if !confirmed {
+                                                        return;
+                                                    }
+                                                    spawn(async move {
+                                                        let res = fetch::delete::<inet::ApiResponse>(&format!("/api/v1/users/{username}"), token.as_deref()).await;
+                                                        match res {
+                                                            Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                                                            Err(_) => error.set(Some("Failed to delete user".to_string())),
+                                                            _ => error.set(None),
+                                                        }
+                                                        future.restart();
+                                                    });
+                                                }
+                                            },
+                                            "Delete"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Some(Err(err)) => {
+                rsx! {
+                    p { style: "color: red;", "{err}" }
+                }
+            }
+            None => {
+                rsx! {
+                    p { "Loading..." }
+                }
+            }
+        }
+        h2 { "Login Lockouts" }
+        if lockout_list.is_empty() {
+            p { "No usernames or addresses are currently throttled." }
+        } else {
+            table {
+                class: "assignment-table",
+                thead {
+                    tr {
+                        th { "Username / Address" }
+                        th { "Failures" }
+                        th { "Blocked until" }
+                        th { "" }
+                    }
+                }
+                tbody {
+                    for lockout in lockout_list {
+                        tr {
+                            td {
+                                match &lockout.key {
+                                    inet::LoginThrottleKey::Username(username) => rsx! { "User: {username}" },
+                                    inet::LoginThrottleKey::Source(source) => rsx! { "Address: {source}" },
+                                }
+                            }
+                            td { "{lockout.failures}" }
+                            td {
+                                {component::account::format_unix_time(Some(lockout.blocked_until))}
+                                if lockout.locked_out { " (locked out)" }
+                            }
+                            td {
+                                button {
+                                    onclick: {
+                                        let key = lockout.key.clone();
+                                        move |_| {
+                                            let token = use_token();
+                                            let query = match &key {
+                                                inet::LoginThrottleKey::Username(username) => format!("username={}", js_sys::encode_uri_component(username)),
+                                                inet::LoginThrottleKey::Source(source) => format!("source={}", js_sys::encode_uri_component(source)),
+                                            };
+                                            spawn(async move {
+                                                let res = fetch::delete::<inet::ApiResponse>(&format!("/api/v1/login_attempts/lockouts?{query}"), token.as_deref()).await;
+                                                match res {
+                                                    Ok(inet::ApiResponse { error: Some(err), result: _ }) => error.set(Some(err)),
+                                                    Err(_) => error.set(Some("Failed to clear lockout".to_string())),
+                                                    _ => error.set(None),
+                                                }
+                                                lockouts.restart();
+                                            });
+                                        }
+                                    },
+                                    "Clear"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        h2 { "Create User" }
+        component::TextInput {
+            placeholder: "Username",
+            value: "{username}",
+            oninput: move |e: Event<FormData>| username.set(e.value().clone()),
+        }
+        input {
+            r#type: "password",
+            placeholder: "Password",
+            value: "{password}",
+            oninput: move |e: Event<FormData>| password.set(e.value().clone()),
+        }
+        label {
+            class: "select-label",
+            "Role"
+            select {
+                value: "{role}",
+                oninput: move |e| role.set(if e.value() == "Admin" { inet::UserRole::Admin } else { inet::UserRole::User }),
+                option { "User" }
+                option { "Admin" }
+            }
+        }
+        button {
+            onclick: create_user,
+            "Create User"
+        }
+    }
+}
+
 #[component]
 fn AsnSpaceList() -> Element {
     let token = use_token();
@@ -1155,9 +1932,198 @@ fn Ipv4Pool(space_id: i32, pool_id: i32) -> Element {
     }
 }
 
+/// Grid cells for every address of an IPv4 assignment
+fn ipv4_grid_cells(prefix: [u8; 4], prefix_len: i32, hosts: &[inet::HostIpv4]) -> Vec<component::hosts::GridCell> {
+    use component::hosts::CellKind;
+
+    let prefix_len = prefix_len as u8;
+    let reserved = inet::ipv4_reserved_addresses(prefix, prefix_len);
+    let first = u32::from_be_bytes(inet::ipv4_network_address(prefix, prefix_len));
+    let last = u32::from_be_bytes(inet::ipv4_broadcast_address(prefix, prefix_len));
+    (first..=last).map(|address| {
+        let octets = address.to_be_bytes();
+        let (label, kind) = match hosts.iter().find(|host| host.ipv4_address == octets) {
+            Some(host) if host.gateway => (host.hostname.clone(), CellKind::Gateway),
+            Some(host) => (host.hostname.clone(), CellKind::Host),
+            None if reserved.first() == Some(&octets) => ("network".to_string(), CellKind::Reserved),
+            None if reserved.contains(&octets) => ("broadcast".to_string(), CellKind::Reserved),
+            None => (String::new(), CellKind::Free),
+        };
+        component::hosts::GridCell {
+            address: std::net::Ipv4Addr::from(octets).to_string(),
+            short: octets[3].to_string(),
+            label,
+            kind,
+        }
+    }).collect()
+}
+
+/// Hosts of an IPv4 assignment, with a grid of its addresses if it is small enough
+#[component]
+fn Ipv4Hosts(space_id: i32, pool_id: i32, assignment_id: i32, prefix: [u8; 4], prefix_len: i32) -> Element {
+    let token = use_token();
+    let mut address = use_signal(|| String::new());
+    let mut hostname = use_signal(|| String::new());
+    let mut mac_address = use_signal(|| String::new());
+    let mut description = use_signal(|| String::new());
+    let mut gateway = use_signal(|| false);
+    let mut error = use_signal(|| None);
+    let hosts_url = format!("/api/v1/ipv4/assignment_space/{space_id}/pool/{pool_id}/assignment/{assignment_id}/host");
+
+    let token_copy = token.clone();
+    let url = hosts_url.clone();
+    let mut future = use_resource(move || {
+        let token = token_copy.clone();
+        let url = url.clone();
+        async move {
+            let hosts = match fetch::get::<inet::ApiResponse>(&url, token.as_deref()).await {
+                Ok(inet::ApiResponse { error: _, result: Some(inet::ApiResponseVariant::Ipv4Hosts(hosts)) }) => hosts,
+                _ => return None,
+            };
+            // Suggest the next free address for a new host
+            let next_free = fetch::get::<inet::ApiResponse>(&format!("{url}/next_free"), token.as_deref()).await;
+            if let Ok(inet::ApiResponse { error: _, result: Some(inet::ApiResponseVariant::Ipv4Address(Some(next_free))) }) = next_free {
+                address.set(std::net::Ipv4Addr::from(next_free).to_string());
+            }
+            Some(hosts)
+        }
+    });
+
+    let token_copy = token.clone();
+    let url = hosts_url.clone();
+    let add_host = move |_| {
+        let token = token_copy.clone();
+        let url = url.clone();
+        let ipv4_address = match address().trim().parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip.octets(),
+            Err(_) => {
+                error.set(Some("Invalid IPv4 address".to_string()));
+                return;
+            }
+        };
+        let mac = mac_address().trim().to_owned();
+        let new_host = inet::HostIpv4 {
+            id: 0, // This will be set by the server
+            assignment_id,
+            ipv4_address,
+            hostname: hostname().trim().to_owned(),
+            mac_address: if mac.is_empty() { None } else { Some(mac) },
+            description: description().trim().to_owned(),
+            gateway: gateway(),
+        };
+
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post(&url, &new_host, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: _ }) => {
+                    error.set(None);
+                    hostname.set(String::new());
+                    mac_address.set(String::new());
+                    description.set(String::new());
+                    gateway.set(false);
+                    future.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => {
+                    error.set(Some(err));
+                }
+                Err(_) => {
+                    error.set(Some("Failed to add host".to_string()));
+                }
+            }
+        });
+    };
+
+    let hosts = match &*future.read_unchecked() {
+        Some(Some(hosts)) => Some(hosts.clone()),
+        _ => None,
+    };
+
+    rsx! {
+        h2 { "Hosts" }
+        match hosts {
+            Some(hosts) => {
+                // 1024 addresses at most
+                let cells = if prefix_len >= 22 { Some(ipv4_grid_cells(prefix, prefix_len, &hosts)) } else { None };
+                let rows = hosts.into_iter().map(|host| component::hosts::HostRow {
+                    id: host.id,
+                    address: std::net::Ipv4Addr::from(host.ipv4_address).to_string(),
+                    hostname: host.hostname,
+                    mac_address: host.mac_address.unwrap_or_default(),
+                    description: host.description,
+                    gateway: host.gateway,
+                }).collect::<Vec<_>>();
+                let url = hosts_url.clone();
+                rsx! {
+                    if let Some(cells) = cells {
+                        component::hosts::HostGrid {
+                            cells,
+                            onselect: move |selected| address.set(selected),
+                        }
+                    }
+                    component::hosts::HostTable {
+                        rows,
+                        ondelete: move |host_id| {
+                            let token = use_token();
+                            let url = url.clone();
+                            spawn(async move {
+                                let _ = fetch::delete::<inet::ApiResponse>(&format!("{url}/{host_id}"), token.as_deref()).await;
+                                future.restart();
+                            });
+                        },
+                    }
+                }
+            }
+            None => {
+                rsx! {
+                    p { "Loading..." }
+                }
+            }
+        }
+        h3 { "Add Host" }
+        if let Some(err) = error() {
+            p { style: "color: red;", "{err}" }
+        }
+        component::TextInput {
+            placeholder: "IPv4 Address",
+            value: "{address}",
+            oninput: move |e: Event<FormData>| address.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Host name",
+            value: "{hostname}",
+            oninput: move |e: Event<FormData>| hostname.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "MAC address",
+            value: "{mac_address}",
+            oninput: move |e: Event<FormData>| mac_address.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Description",
+            value: "{description}",
+            oninput: move |e: Event<FormData>| description.set(e.value().clone()),
+        }
+        label {
+            class: "select-label",
+            "Role"
+            select {
+                value: if gateway() { "Gateway" } else { "Host" },
+                oninput: move |e| gateway.set(e.value() == "Gateway"),
+                option { "Host" }
+                option { "Gateway" }
+            }
+        }
+        button {
+            onclick: add_host,
+            "Add Host"
+        }
+    }
+}
+
 #[component]
 fn Ipv4Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
     let token = use_token();
+    let logged_in = token.is_some();
     let mut delete_popup_shown = use_signal(|| false);
     let future = use_resource(move || {
         let token = token.clone();
@@ -1226,6 +2192,7 @@ fn Ipv4Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
             let description = assignment.description.clone();
             let space = inet::format_ipv4_prefix(space.ipv4_prefix, space.ipv4_prefix_len);
             let pool = inet::format_ipv4_prefix(pool.ipv4_prefix, pool.ipv4_prefix_len);
+            let (prefix, prefix_len) = (assignment.ipv4_prefix, assignment.ipv4_prefix_len);
             let assignment = inet::format_ipv4_prefix(assignment.ipv4_prefix, assignment.ipv4_prefix_len);
             let crumbs = vec![component::BreadCrumb {
                 name: "Home".to_string(),
@@ -1255,6 +2222,9 @@ fn Ipv4Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
                         });
                     }
                 }
+                if logged_in {
+                    Ipv4Hosts { space_id, pool_id, assignment_id, prefix, prefix_len }
+                }
                 div {
                     class: "delete-toolbar",
                     if delete_popup_shown() {
@@ -1657,9 +2627,197 @@ fn Ipv6Pool(space_id: i32, pool_id: i32) -> Element {
     }
 }
 
+/// Grid cells for every address of an IPv6 assignment
+fn ipv6_grid_cells(prefix: [u8; 16], prefix_len: i32, hosts: &[inet::HostIpv6]) -> Vec<component::hosts::GridCell> {
+    use component::hosts::CellKind;
+
+    let prefix_len = prefix_len as u8;
+    let reserved = inet::ipv6_reserved_addresses(prefix, prefix_len);
+    let first = u128::from_be_bytes(inet::ipv6_network_address(prefix, prefix_len));
+    let last = u128::from_be_bytes(inet::ipv6_broadcast_address(prefix, prefix_len));
+    (first..=last).map(|address| {
+        let octets = address.to_be_bytes();
+        let (label, kind) = match hosts.iter().find(|host| host.ipv6_address == octets) {
+            Some(host) if host.gateway => (host.hostname.clone(), CellKind::Gateway),
+            Some(host) => (host.hostname.clone(), CellKind::Host),
+            None if reserved.contains(&octets) => ("Subnet-Router anycast".to_string(), CellKind::Reserved),
+            None => (String::new(), CellKind::Free),
+        };
+        component::hosts::GridCell {
+            address: std::net::Ipv6Addr::from(octets).to_string(),
+            short: format!("{:x}", u16::from_be_bytes([octets[14], octets[15]])),
+            label,
+            kind,
+        }
+    }).collect()
+}
+
+/// Hosts of an IPv6 assignment, with a grid of its addresses if it is small enough
+#[component]
+fn Ipv6Hosts(space_id: i32, pool_id: i32, assignment_id: i32, prefix: [u8; 16], prefix_len: i32) -> Element {
+    let token = use_token();
+    let mut address = use_signal(|| String::new());
+    let mut hostname = use_signal(|| String::new());
+    let mut mac_address = use_signal(|| String::new());
+    let mut description = use_signal(|| String::new());
+    let mut gateway = use_signal(|| false);
+    let mut error = use_signal(|| None);
+    let hosts_url = format!("/api/v1/ipv6/assignment_space/{space_id}/pool/{pool_id}/assignment/{assignment_id}/host");
+
+    let token_copy = token.clone();
+    let url = hosts_url.clone();
+    let mut future = use_resource(move || {
+        let token = token_copy.clone();
+        let url = url.clone();
+        async move {
+            let hosts = match fetch::get::<inet::ApiResponse>(&url, token.as_deref()).await {
+                Ok(inet::ApiResponse { error: _, result: Some(inet::ApiResponseVariant::Ipv6Hosts(hosts)) }) => hosts,
+                _ => return None,
+            };
+            // Suggest the next free address for a new host
+            let next_free = fetch::get::<inet::ApiResponse>(&format!("{url}/next_free"), token.as_deref()).await;
+            if let Ok(inet::ApiResponse { error: _, result: Some(inet::ApiResponseVariant::Ipv6Address(Some(next_free))) }) = next_free {
+                address.set(std::net::Ipv6Addr::from(next_free).to_string());
+            }
+            Some(hosts)
+        }
+    });
+
+    let token_copy = token.clone();
+    let url = hosts_url.clone();
+    let add_host = move |_| {
+        let token = token_copy.clone();
+        let url = url.clone();
+        let ipv6_address = match address().trim().parse::<std::net::Ipv6Addr>() {
+            Ok(ip) => ip.octets(),
+            Err(_) => {
+                error.set(Some("Invalid IPv6 address".to_string()));
+                return;
+            }
+        };
+        let mac = mac_address().trim().to_owned();
+        let new_host = inet::HostIpv6 {
+            id: 0, // This will be set by the server
+            assignment_id,
+            ipv6_address,
+            hostname: hostname().trim().to_owned(),
+            mac_address: if mac.is_empty() { None } else { Some(mac) },
+            description: description().trim().to_owned(),
+            gateway: gateway(),
+        };
+
+        spawn(async move {
+            let res: Result<inet::ApiResponse, _> = fetch::post(&url, &new_host, token.as_deref()).await;
+            match res {
+                Ok(inet::ApiResponse { error: None, result: _ }) => {
+                    error.set(None);
+                    hostname.set(String::new());
+                    mac_address.set(String::new());
+                    description.set(String::new());
+                    gateway.set(false);
+                    future.restart();
+                }
+                Ok(inet::ApiResponse { error: Some(err), result: _ }) => {
+                    error.set(Some(err));
+                }
+                Err(_) => {
+                    error.set(Some("Failed to add host".to_string()));
+                }
+            }
+        });
+    };
+
+    let hosts = match &*future.read_unchecked() {
+        Some(Some(hosts)) => Some(hosts.clone()),
+        _ => None,
+    };
+
+    rsx! {
+        h2 { "Hosts" }
+        match hosts {
+            Some(hosts) => {
+                // 256 addresses at most
+                let cells = if prefix_len >= 120 { Some(ipv6_grid_cells(prefix, prefix_len, &hosts)) } else { None };
+                let rows = hosts.into_iter().map(|host| component::hosts::HostRow {
+                    id: host.id,
+                    address: std::net::Ipv6Addr::from(host.ipv6_address).to_string(),
+                    hostname: host.hostname,
+                    mac_address: host.mac_address.unwrap_or_default(),
+                    description: host.description,
+                    gateway: host.gateway,
+                }).collect::<Vec<_>>();
+                let url = hosts_url.clone();
+                rsx! {
+                    if let Some(cells) = cells {
+                        component::hosts::HostGrid {
+                            cells,
+                            onselect: move |selected| address.set(selected),
+                        }
+                    }
+                    component::hosts::HostTable {
+                        rows,
+                        ondelete: move |host_id| {
+                            let token = use_token();
+                            let url = url.clone();
+                            spawn(async move {
+                                let _ = fetch::delete::<inet::ApiResponse>(&format!("{url}/{host_id}"), token.as_deref()).await;
+                                future.restart();
+                            });
+                        },
+                    }
+                }
+            }
+            None => {
+                rsx! {
+                    p { "Loading..." }
+                }
+            }
+        }
+        h3 { "Add Host" }
+        if let Some(err) = error() {
+            p { style: "color: red;", "{err}" }
+        }
+        component::TextInput {
+            placeholder: "IPv6 Address",
+            value: "{address}",
+            oninput: move |e: Event<FormData>| address.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Host name",
+            value: "{hostname}",
+            oninput: move |e: Event<FormData>| hostname.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "MAC address",
+            value: "{mac_address}",
+            oninput: move |e: Event<FormData>| mac_address.set(e.value().clone()),
+        }
+        component::TextInput {
+            placeholder: "Description",
+            value: "{description}",
+            oninput: move |e: Event<FormData>| description.set(e.value().clone()),
+        }
+        label {
+            class: "select-label",
+            "Role"
+            select {
+                value: if gateway() { "Gateway" } else { "Host" },
+                oninput: move |e| gateway.set(e.value() == "Gateway"),
+                option { "Host" }
+                option { "Gateway" }
+            }
+        }
+        button {
+            onclick: add_host,
+            "Add Host"
+        }
+    }
+}
+
 #[component]
 fn Ipv6Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
     let token = use_token();
+    let logged_in = token.is_some();
     let mut delete_popup_shown = use_signal(|| false);
     let future = use_resource(move || {
         let token = token.clone();
@@ -1728,6 +2886,7 @@ fn Ipv6Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
             let description = assignment.description.clone();
             let space = inet::format_ipv6_prefix(space.ipv6_prefix, space.ipv6_prefix_len);
             let pool = inet::format_ipv6_prefix(pool.ipv6_prefix, pool.ipv6_prefix_len);
+            let (prefix, prefix_len) = (assignment.ipv6_prefix, assignment.ipv6_prefix_len);
             let assignment = inet::format_ipv6_prefix(assignment.ipv6_prefix, assignment.ipv6_prefix_len);
             let crumbs = vec![component::BreadCrumb {
                 name: "Home".to_string(),
@@ -1757,6 +2916,9 @@ fn Ipv6Assignment(space_id: i32, pool_id: i32, assignment_id: i32) -> Element {
                         });
                     }
                 }
+                if logged_in {
+                    Ipv6Hosts { space_id, pool_id, assignment_id, prefix, prefix_len }
+                }
                 div {
                     class: "delete-toolbar",
                     if delete_popup_shown() {
@@ -2470,6 +3632,7 @@ fn Ipv4AssignmentAdd(space_id: i32, pool_id: i32) -> Element {
             ipv4_prefix_len,
             assignment_visibility: inet::ObjectVisibility::from_str(&visibility).unwrap(),
             assignment_pool_id: pool_id,
+            origin_asn_id: None,
         };
 
         spawn(async move {
@@ -2829,6 +3992,7 @@ fn Ipv6AssignmentAdd(space_id: i32, pool_id: i32) -> Element {
             ipv6_prefix_len,
             assignment_visibility: inet::ObjectVisibility::from_str(&visibility).unwrap(),
             assignment_pool_id: pool_id,
+            origin_asn_id: None,
         };
 
         spawn(async move {
@@ -1,6 +1,6 @@
 
 use crate::types::Error;
-use crate::types::ObjectVisibility;
+use crate::types::{HasVisibility, ObjectVisibility};
 
 use serde::{Serialize, Deserialize};
 
@@ -27,6 +27,12 @@ pub struct AssignmentSpaceAsn {
     pub asn_to: u32,
 }
 
+impl HasVisibility for AssignmentSpaceAsn {
+    fn visibility(&self) -> ObjectVisibility {
+        self.space_visibility
+    }
+}
+
 /// ASN assignment pool. Can contain multiple assignments.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentPoolAsn {
@@ -52,6 +58,12 @@ pub struct AssignmentPoolAsn {
     pub asn_to: u32,
 }
 
+impl HasVisibility for AssignmentPoolAsn {
+    fn visibility(&self) -> ObjectVisibility {
+        self.pool_visibility
+    }
+}
+
 /// ASN assignment to a specific entity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentAsn {
@@ -74,6 +86,12 @@ pub struct AssignmentAsn {
     pub asn: u32,
 }
 
+impl HasVisibility for AssignmentAsn {
+    fn visibility(&self) -> ObjectVisibility {
+        self.assignment_visibility
+    }
+}
+
 
 pub trait AsnAssignmentStore {
     /// Get an assignment space by ID
@@ -82,6 +100,9 @@ pub trait AsnAssignmentStore {
     /// Get all assignment spaces
     fn get_spaces(&self) -> Result<Vec<AssignmentSpaceAsn>, Error>;
 
+    /// Get the assignment spaces containing an ASN
+    fn get_spaces_containing(&self, asn: u32) -> Result<Vec<AssignmentSpaceAsn>, Error>;
+
     /// Create a new assignment space
     /// Returns the ID of the new assignment space
     /// ID in input is ignored
@@ -100,6 +121,9 @@ pub trait AsnAssignmentStore {
     /// Get all assignment pools in a space
     fn get_pools(&self, space_id: i32) -> Result<Vec<AssignmentPoolAsn>, Error>;
 
+    /// Get the assignment pools in a space containing an ASN
+    fn get_pools_containing(&self, space_id: i32, asn: u32) -> Result<Vec<AssignmentPoolAsn>, Error>;
+
     /// Create a new assignment pool
     /// Returns the ID of the new assignment pool
     /// ID in input is ignored
@@ -118,6 +142,9 @@ pub trait AsnAssignmentStore {
     /// Get all assignments in a pool
     fn get_assignments(&self, pool_id: i32) -> Result<Vec<AssignmentAsn>, Error>;
 
+    /// Get the assignments of an ASN in a pool
+    fn get_assignments_containing(&self, pool_id: i32, asn: u32) -> Result<Vec<AssignmentAsn>, Error>;
+
     /// Create a new assignment
     /// Returns the ID of the new assignment
     /// ID in input is ignored
@@ -1,6 +1,9 @@
 
 use std::path::PathBuf;
 use clap::{Parser, Subcommand};
+use mirams::user::{ResourceFamily, UserRole};
+use mirams::server::auth::AddressPrefix;
+use mirams::server::listener::ListenAddr;
 
 /// MIRAMS: Menhera.org Internet Resources Assignment Management System
 #[derive(Debug, Parser, Clone)] // requires `derive` feature
@@ -15,6 +18,10 @@ pub(crate) struct Cli {
     #[arg(short, long)]
     pub db_path: Option<PathBuf>,
 
+    /// Path to the TOML configuration file
+    #[arg(short, long, env = "MIRAMS_CONFIG")]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -25,16 +32,39 @@ pub(crate) enum Commands {
     /// Start the MIRAMS server
     #[command(name = "server")]
     Server {
-        /// Address to listen on
+        /// Address to listen on: host:port or unix:/path/to/socket (repeatable)
         #[arg(short, long)]
-        listen_addr: Option<String>,
+        listen_addr: Vec<ListenAddr>,
+
+        /// Permissions of Unix domain sockets, in octal (e.g. 660)
+        #[arg(long, value_parser = parse_mode)]
+        unix_socket_mode: Option<u32>,
 
         /// Enable example dataset
         #[arg(long)]
         with_example_data: bool,
+
+        #[command(flatten)]
+        tls: Box<TlsArgs>,
+
+        #[command(flatten)]
+        whois: WhoisArgs,
+
+        #[command(flatten)]
+        rtr: RtrArgs,
+
+        #[command(flatten)]
+        oidc: Box<OidcArgs>,
+
+        #[command(flatten)]
+        ldap: Box<LdapArgs>,
+
+        #[command(flatten)]
+        external_auth: ExternalAuthArgs,
     },
 
-    /// Set a user's password. If the user does not exist, it will be created.
+    /// Set a user's password. If the user does not exist, it will be created
+    /// (as an admin if there is no admin yet).
     #[command(name = "user-set-password")]
     UserSetPassword {
         /// Username
@@ -46,7 +76,19 @@ pub(crate) enum Commands {
         password: String,
     },
 
-    /// Delete a user
+    /// Set a user's role (user or admin). The last admin cannot be demoted.
+    #[command(name = "user-set-role")]
+    UserSetRole {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+
+        /// New role
+        #[arg(short, long)]
+        role: UserRole,
+    },
+
+    /// Delete a user. The last admin cannot be deleted.
     #[command(name = "user-delete")]
     UserDelete {
         /// Username
@@ -54,7 +96,331 @@ pub(crate) enum Commands {
         username: String,
     },
 
-    /// List all users
+    /// Clear failed login attempts of a user, lifting any lockout
+    #[command(name = "user-unlock")]
+    UserUnlock {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+    },
+
+    /// Remove a user's two-factor authentication enrolment and recovery codes
+    #[command(name = "user-totp-reset")]
+    UserTotpReset {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+    },
+
+    /// Link a single sign-on identity to an existing user, so that it logs in as that user
+    #[command(name = "user-oidc-link")]
+    UserOidcLink {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+
+        /// Issuer (`iss` claim) of the identity
+        #[arg(long)]
+        issuer: String,
+
+        /// Subject (`sub` claim) of the identity
+        #[arg(long)]
+        subject: String,
+    },
+
+    /// Unlink a single sign-on identity from a user
+    #[command(name = "user-oidc-unlink")]
+    UserOidcUnlink {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+
+        /// Issuer (`iss` claim) of the identity
+        #[arg(long)]
+        issuer: String,
+
+        /// Subject (`sub` claim) of the identity
+        #[arg(long)]
+        subject: String,
+    },
+
+    /// List all users with their roles
     #[command(name = "user-list")]
     UserList,
+
+    /// Create a named API token for a user. The token secret is printed once.
+    #[command(name = "user-token-create")]
+    UserTokenCreate {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+
+        /// Token name
+        #[arg(short, long)]
+        name: String,
+
+        /// Lifetime of the token in days (no expiry if omitted)
+        #[arg(long)]
+        expires_in_days: Option<u32>,
+
+        /// Only allow read requests with this token
+        #[arg(long)]
+        read_only: bool,
+
+        /// Restrict the token to these resource families (asn, ipv4, ipv6)
+        #[arg(long, value_delimiter = ',')]
+        families: Option<Vec<ResourceFamily>>,
+    },
+
+    /// List API tokens of a user
+    #[command(name = "user-token-list")]
+    UserTokenList {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+    },
+
+    /// Revoke an API token of a user
+    #[command(name = "user-token-revoke")]
+    UserTokenRevoke {
+        /// Username
+        #[arg(short, long)]
+        username: String,
+
+        /// Token ID (see `user-token-list`)
+        #[arg(short, long)]
+        id: i32,
+    },
+
+    /// Configuration file commands
+    #[command(name = "config")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Export the assignment data to standard output
+    #[command(name = "export")]
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub(crate) enum ConfigCommands {
+    /// Validate the configuration file given with --config
+    #[command(name = "check")]
+    Check,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub(crate) enum ExportCommands {
+    /// RPSL objects for public assignments, for submission to an IRR
+    #[command(name = "rpsl")]
+    Rpsl {
+        /// mnt-by: of the objects (default: rpsl.maintainer in the configuration file)
+        #[arg(short, long)]
+        maintainer: Option<String>,
+
+        /// source: of the objects (default: rpsl.source in the configuration file)
+        #[arg(short, long)]
+        source: Option<String>,
+    },
+
+    /// ROAs as locally added assertions in an RFC 8416 SLURM file
+    #[command(name = "slurm")]
+    Slurm,
+
+    /// IPv4 and IPv6 assignments without a ROA, one per line
+    #[command(name = "roa-coverage")]
+    RoaCoverage,
+
+    /// Reverse zones of all IPv4 and IPv6 spaces as BIND zone files
+    #[command(name = "dns-reverse")]
+    DnsReverse {
+        /// Nameserver of the zones, the first being the primary (repeatable; default: dns.nameservers in the configuration file)
+        #[arg(short, long)]
+        nameserver: Vec<String>,
+
+        /// Write one file per zone into this directory instead of printing the zones
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Forward A/AAAA records of hosts as BIND zone file fragments, one per domain
+    #[command(name = "dns-forward")]
+    DnsForward {
+        /// Domain of the records (repeatable; default: dns.forward_domains in the configuration file)
+        #[arg(short, long)]
+        domain: Vec<String>,
+
+        /// Write one file per domain into this directory instead of printing the fragments
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+
+        /// Fail if any host is outside the domains or disagrees with the reverse DNS
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// subnet4 and subnet6 lists of DHCP-enabled assignments for ISC Kea, as JSON
+    #[command(name = "kea")]
+    Kea,
+}
+
+/// OpenID Connect login. Enabled when an issuer is given.
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct OidcArgs {
+    /// OpenID Connect issuer URL
+    #[arg(long, env = "MIRAMS_OIDC_ISSUER")]
+    pub oidc_issuer: Option<String>,
+
+    /// OpenID Connect client ID
+    #[arg(long, env = "MIRAMS_OIDC_CLIENT_ID", requires = "oidc_issuer")]
+    pub oidc_client_id: Option<String>,
+
+    /// OpenID Connect client secret (omit for public clients)
+    #[arg(long, env = "MIRAMS_OIDC_CLIENT_SECRET", hide_env_values = true)]
+    pub oidc_client_secret: Option<String>,
+
+    /// Public URL of /api/v1/oidc/callback on this server
+    #[arg(long, env = "MIRAMS_OIDC_REDIRECT_URL")]
+    pub oidc_redirect_url: Option<String>,
+
+    /// ID token claim used as the username of users created on their first login
+    #[arg(long, default_value = "preferred_username")]
+    pub oidc_username_claim: String,
+
+    /// ID token claim that decides the role (e.g. groups)
+    #[arg(long)]
+    pub oidc_role_claim: Option<String>,
+
+    /// Value of the role claim that grants the admin role (repeatable)
+    #[arg(long)]
+    pub oidc_admin_value: Vec<String>,
+
+    /// Create local users on their first OpenID Connect login
+    #[arg(long)]
+    pub oidc_create_users: bool,
+}
+
+/// LDAP password authentication. Enabled when a URL is given.
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct LdapArgs {
+    /// LDAP server URL (ldap:// or ldaps://)
+    #[arg(long, env = "MIRAMS_LDAP_URL")]
+    pub ldap_url: Option<String>,
+
+    /// Upgrade ldap:// connections with StartTLS
+    #[arg(long)]
+    pub ldap_starttls: bool,
+
+    /// Bind directly to this DN; {username} is replaced (e.g. uid={username},ou=people,dc=example,dc=org)
+    #[arg(long)]
+    pub ldap_user_dn: Option<String>,
+
+    /// Search for users below this DN, then bind to the DN found
+    #[arg(long)]
+    pub ldap_search_base: Option<String>,
+
+    /// User search filter; {username} is replaced
+    #[arg(long, default_value = "(uid={username})")]
+    pub ldap_search_filter: String,
+
+    /// DN of the service account used for searches
+    #[arg(long)]
+    pub ldap_bind_dn: Option<String>,
+
+    /// Password of the service account
+    #[arg(long, env = "MIRAMS_LDAP_BIND_PASSWORD", hide_env_values = true)]
+    pub ldap_bind_password: Option<String>,
+
+    /// Search for groups below this DN
+    #[arg(long)]
+    pub ldap_group_base: Option<String>,
+
+    /// Group search filter; {dn} and {username} are replaced
+    #[arg(long, default_value = "(|(member={dn})(uniqueMember={dn})(memberUid={username}))")]
+    pub ldap_group_filter: String,
+
+    /// Group (DN or cn) whose members are admins (repeatable)
+    #[arg(long)]
+    pub ldap_admin_group: Vec<String>,
+
+    /// Group (DN or cn) whose members may log in (repeatable; default: everyone)
+    #[arg(long)]
+    pub ldap_user_group: Vec<String>,
+
+    /// Create local users on their first LDAP login
+    #[arg(long)]
+    pub ldap_create_users: bool,
+
+    /// User that logs in with the local password instead of LDAP (repeatable)
+    #[arg(long)]
+    pub ldap_local_user: Vec<String>,
+}
+
+/// Authentication by a reverse proxy or a TLS client certificate
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct ExternalAuthArgs {
+    /// Address or prefix of a reverse proxy whose username header is trusted (repeatable)
+    #[arg(long)]
+    pub trusted_proxy: Vec<AddressPrefix>,
+
+    /// Header in which trusted proxies pass the username
+    #[arg(long, default_value = "X-Remote-User")]
+    pub proxy_auth_header: String,
+
+    /// Trust the username header on connections to the Unix domain socket
+    #[arg(long)]
+    pub proxy_auth_unix_socket: bool,
+
+    /// Map a client certificate subject to a user, as SUBJECT=USERNAME (repeatable)
+    #[arg(long)]
+    pub client_cert_user: Vec<String>,
+
+    /// Use the common name of other client certificates as the username
+    #[arg(long)]
+    pub client_cert_cn_as_username: bool,
+}
+
+/// HTTPS. Enabled when a certificate is given; send SIGHUP to reload it.
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct TlsArgs {
+    /// PEM file with the TLS certificate chain
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file with the TLS private key
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// PEM file with CAs that client certificates are verified against
+    #[arg(long, requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Also listen for plain HTTP on this address and redirect to HTTPS
+    #[arg(long, requires = "tls_cert")]
+    pub http_redirect_addr: Option<String>,
+}
+
+/// WHOIS (port 43) for public resources
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct WhoisArgs {
+    /// Answer WHOIS queries for public resources on this address, such as [::]:43 (repeatable)
+    #[arg(long)]
+    pub whois_listen: Vec<ListenAddr>,
+}
+
+/// RPKI to Router protocol for the ROAs
+#[derive(Debug, clap::Args, Clone)]
+pub(crate) struct RtrArgs {
+    /// Serve the ROAs to routers over RTR on this address, such as [::]:323 (repeatable)
+    #[arg(long)]
+    pub rtr_listen: Vec<ListenAddr>,
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8).ok().filter(|mode| *mode <= 0o777).ok_or_else(|| format!("invalid mode: {}", s))
 }
@@ -3,31 +3,62 @@ mod cli;
 
 use cli::Cli;
 use cli::Commands;
+use cli::OidcArgs;
+use cli::LdapArgs;
+use cli::ExternalAuthArgs;
+use cli::TlsArgs;
+use cli::WhoisArgs;
+use cli::RtrArgs;
+use cli::ConfigCommands;
+use cli::ExportCommands;
 
 use mirams::Store;
+use mirams::config::Config;
+use mirams::store::DbConnection;
+use mirams::ldap::{LdapBindMode, LdapConfig, LdapConnection};
 use mirams::db_sqlite::SqliteConnection;
 use mirams::server::Server;
+use mirams::server::listener::{ListenAddr, Listener};
+use mirams::server::oidc::OidcConfig;
+use mirams::server::auth::{ClientCertAuthConfig, ProxyAuthConfig};
+use mirams::server::tls::TlsConfig;
+use mirams::server::whois::WhoisConfig;
+use mirams::server::rtr::RtrConfig;
+use mirams::rpsl::RpslConfig;
+use mirams::dns::DnsConfig;
+use mirams::user::{LoginThrottleKey, TokenScope};
 
 use clap::Parser;
 use syslog::{Facility, Formatter3164, BasicLogger};
 use rand::prelude::*;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 
 #[derive(Debug, Clone)]
 pub(crate) struct GlobalConfig {
     pub db_path: Option<PathBuf>,
+    pub config_path: Option<PathBuf>,
+    pub config: Config,
     pub command: Commands,
 }
 
 impl GlobalConfig {
     pub fn open_sqlite_connection(&self) -> SqliteConnection {
-        if let Some(path) = &self.db_path {
+        let db = if let Some(path) = &self.db_path {
             SqliteConnection::open_file(path.to_str().unwrap())
         } else {
             SqliteConnection::open_memory()
-        }.unwrap()
+        }.unwrap();
+        match &self.config.database.api_key_secret_file {
+            Some(path) => db.with_api_key_secret(mirams::user::load_api_key_secret(path).unwrap_or_else(|e| {
+                log::error!("Cannot load the API key secret: {}", e);
+                std::process::exit(1);
+            })),
+            None => db,
+        }
     }
 
     pub fn store(&self) -> Store<SqliteConnection> {
@@ -36,10 +67,25 @@ impl GlobalConfig {
 
     pub fn run(&self) {
         match &self.command {
-            Commands::Server { listen_addr: _, with_example_data: _ } => server(self.clone()),
+            Commands::Server { .. } => server(self.clone()),
             Commands::UserSetPassword { username: _, password: _ } => user_set_password(self.clone()),
+            Commands::UserSetRole { username: _, role: _ } => user_set_role(self.clone()),
             Commands::UserDelete { username: _ } => user_delete(self.clone()),
+            Commands::UserUnlock { username: _ } => user_unlock(self.clone()),
+            Commands::UserTotpReset { username: _ } => user_totp_reset(self.clone()),
+            Commands::UserOidcLink { .. } => user_oidc_link(self.clone()),
+            Commands::UserOidcUnlink { .. } => user_oidc_unlink(self.clone()),
             Commands::UserList => user_list(self.clone()),
+            Commands::UserTokenCreate { .. } => user_token_create(self.clone()),
+            Commands::UserTokenList { username: _ } => user_token_list(self.clone()),
+            Commands::UserTokenRevoke { username: _, id: _ } => user_token_revoke(self.clone()),
+            Commands::Config { command: ConfigCommands::Check } => config_check(self.clone()),
+            Commands::Export { command: ExportCommands::Rpsl { .. } } => export_rpsl(self.clone()),
+            Commands::Export { command: ExportCommands::Slurm } => export_slurm(self.clone()),
+            Commands::Export { command: ExportCommands::RoaCoverage } => export_roa_coverage(self.clone()),
+            Commands::Export { command: ExportCommands::DnsReverse { .. } } => export_dns_reverse(self.clone()),
+            Commands::Export { command: ExportCommands::DnsForward { .. } } => export_dns_forward(self.clone()),
+            Commands::Export { command: ExportCommands::Kea } => export_kea(self.clone()),
 
             #[allow(unreachable_patterns)]
             _ => unimplemented!(),
@@ -60,10 +106,23 @@ impl GlobalConfig {
 
 fn main() {
     let args = Cli::parse();
+
+    // Logging is not set up yet, since the file configures it.
+    let config = match &args.config {
+        Some(path) => Config::load(path).unwrap_or_else(|e| {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+
     let log_level = if args.verbose {
         log::Level::Debug
     } else {
-        log::Level::Info
+        config.logging.level().unwrap_or_else(|e| {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        })
     };
 
     let formatter = Formatter3164 {
@@ -74,16 +133,21 @@ fn main() {
     };
 
     let mut loggers: Vec<Box<dyn log::Log>> = Vec::new();
-    loggers.push(Box::new(env_logger::Builder::new().filter_level(log::LevelFilter::Debug).build()));
+    if config.logging.stderr {
+        loggers.push(Box::new(env_logger::Builder::new().filter_level(log::LevelFilter::Debug).build()));
+    }
 
-    let logger = syslog::unix(formatter).ok();
+    let logger = if config.logging.syslog { syslog::unix(formatter).ok() } else { None };
     if let Some(logger) = logger {
         loggers.push(Box::new(BasicLogger::new(logger)));
     }
 
     multi_log::MultiLogger::init(loggers, log_level).unwrap();
 
-    let mut db_path = std::env::var("MIRAMS_DB_PATH").ok().map(PathBuf::from);
+    let mut db_path = config.database.path.clone();
+    if let Ok(path) = std::env::var("MIRAMS_DB_PATH") {
+        db_path = Some(PathBuf::from(path));
+    }
     if let Some(path) = &args.db_path {
         db_path = Some(path.clone());
     }
@@ -96,6 +160,8 @@ fn main() {
 
     let global_config = GlobalConfig {
         db_path,
+        config_path: args.config,
+        config,
         command: args.command,
     };
 
@@ -103,21 +169,58 @@ fn main() {
 }
 
 fn server(global_config: GlobalConfig) {
-    let mut listen_addr = std::env::var("MIRAMS_LISTEN_ADDR").unwrap_or("127.0.0.1:3001".to_string());
-
-    let mut add_example_data = false;
-
-    if let Some(addr) = match &global_config.command {
-        Commands::Server { listen_addr, with_example_data } => {
-            add_example_data = *with_example_data;
-            listen_addr.clone()
-        },
-        _ => None,
-    } {
-        listen_addr = addr;
+    let config = &global_config.config;
+    // The same checks as `config check`, so that bad values fail here rather than at run time
+    if let Err(e) = config.validate() {
+        log::error!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    }
+    let mut listen = config.server.listen.clone();
+    if let Ok(addrs) = std::env::var("MIRAMS_LISTEN_ADDR") {
+        listen = addrs.split(',').map(|addr| addr.trim().parse()).collect::<Result<_, _>>().unwrap_or_else(|e| {
+            log::error!("Invalid MIRAMS_LISTEN_ADDR: {}", e);
+            std::process::exit(1);
+        });
+    }
+    let Commands::Server { listen_addr, unix_socket_mode, with_example_data, tls, whois, rtr, oidc, ldap, external_auth } = &global_config.command else {
+        unreachable!()
+    };
+    if !listen_addr.is_empty() {
+        listen = listen_addr.clone();
+    }
+    let unix_socket_mode = unix_socket_mode.or(config.server.unix_socket_mode);
+    let add_example_data = *with_example_data;
+
+    // Flags replace whole sections of the configuration file.
+    let mut options = ServerOptions::from_config(config);
+    options.tls = tls_config_from_args(tls).or(options.tls);
+    options.whois = whois_config_from_args(whois).or(options.whois);
+    options.rtr = rtr_config_from_args(rtr).or(options.rtr);
+    options.oidc = oidc_config_from_args(oidc).or(options.oidc);
+    options.proxy_auth = proxy_auth_config_from_args(external_auth).or(options.proxy_auth);
+    options.client_cert_auth = client_cert_auth_config_from_args(external_auth).or(options.client_cert_auth);
+    let ldap_config = ldap_config_from_args(ldap).or(config.auth.ldap.clone());
+
+    // Bind before anything else so that a busy port fails early
+    let mut listeners = Listener::from_systemd().unwrap_or_else(|e| {
+        log::error!("{}", e);
+        std::process::exit(1);
+    });
+    if listen.is_empty() && listeners.is_empty() {
+        listen.push(ListenAddr::Tcp("127.0.0.1:3001".to_string()));
+    }
+    for addr in &listen {
+        listeners.extend(Listener::bind(addr, unix_socket_mode).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }));
     }
 
-    let store = global_config.store();
+    let db = global_config.open_sqlite_connection();
+    let store = Store::new(db.clone());
+    if !global_config.is_in_memory_db() && config.database.api_key_secret_file.is_none() {
+        log::warn!("database.api_key_secret_file is not set; API key hashes are keyed with a secret stored in the database itself");
+    }
 
     if global_config.is_in_memory_db() {
         let mut rng = rand::thread_rng();
@@ -135,13 +238,358 @@ fn server(global_config: GlobalConfig) {
         mirams::example_data::add_example_data(store.clone());
     }
 
-    let server = Server::new(store);
+    if let Some(config) = ldap_config {
+        log::info!("Checking passwords against LDAP server {}", config.url);
+        start_server(Store::new(LdapConnection::new(db, config)), options, listeners);
+    } else {
+        start_server(store, options, listeners);
+    }
+    log::info!("Server stopped");
+}
+
+/// Server settings from the configuration file and flags
+#[derive(Debug)]
+struct ServerOptions {
+    request_timeout: Duration,
+    shutdown_timeout: Duration,
+    cors_origins: Vec<String>,
+    oidc: Option<OidcConfig>,
+    proxy_auth: Option<ProxyAuthConfig>,
+    client_cert_auth: Option<ClientCertAuthConfig>,
+    tls: Option<TlsConfig>,
+    whois: Option<WhoisConfig>,
+    rtr: Option<RtrConfig>,
+    rpsl: RpslConfig,
+    dns: DnsConfig,
+}
+
+impl ServerOptions {
+    fn from_config(config: &Config) -> Self {
+        ServerOptions {
+            request_timeout: Duration::from_secs(config.server.request_timeout),
+            shutdown_timeout: Duration::from_secs(config.server.shutdown_timeout),
+            cors_origins: config.server.cors_origins.clone(),
+            oidc: config.auth.oidc.clone(),
+            proxy_auth: config.auth.proxy.clone(),
+            client_cert_auth: config.auth.client_cert.clone(),
+            tls: config.tls.clone(),
+            whois: config.whois.clone(),
+            rtr: config.rtr.clone(),
+            rpsl: config.rpsl.clone(),
+            dns: config.dns.clone(),
+        }
+    }
+}
+
+fn start_server<T>(store: Store<T>, options: ServerOptions, listeners: Vec<Listener>)
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut server = Server::new(store)
+        .with_request_timeout(options.request_timeout)
+        .with_shutdown_timeout(options.shutdown_timeout)
+        .with_cors_origins(options.cors_origins)
+        .with_rpsl(options.rpsl)
+        .with_dns(options.dns);
+    if let Some(config) = options.oidc {
+        log::info!("OpenID Connect login enabled with issuer {}", config.issuer_url);
+        server = server.with_oidc(config);
+    }
+    if let Some(config) = options.proxy_auth {
+        log::info!("Trusting the {} header from {:?}", config.header, config.trusted_proxies.iter().map(|prefix| prefix.to_string()).collect::<Vec<_>>());
+        server = server.with_proxy_auth(config);
+    }
+    if let Some(config) = options.client_cert_auth {
+        server = match server.with_client_cert_auth(config) {
+            Ok(server) => server,
+            Err(e) => {
+                log::error!("Failed to set up client certificate authentication: {}", e);
+                std::process::exit(1);
+            },
+        };
+    }
+    if let Some(config) = options.tls {
+        server = match server.with_tls(config) {
+            Ok(server) => server,
+            Err(e) => {
+                log::error!("Failed to set up TLS: {}", e);
+                std::process::exit(1);
+            },
+        };
+    }
+
+    if let Some(config) = options.whois {
+        server = server.with_whois(config);
+    }
+    if let Some(config) = options.rtr {
+        server = server.with_rtr(config);
+    }
+
+    if let Err(e) = server.run(listeners) {
+        log::error!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn oidc_config_from_args(args: &OidcArgs) -> Option<OidcConfig> {
+    let issuer_url = args.oidc_issuer.clone()?;
+    let (client_id, redirect_url) = match (&args.oidc_client_id, &args.oidc_redirect_url) {
+        (Some(client_id), Some(redirect_url)) => (client_id.clone(), redirect_url.clone()),
+        _ => {
+            log::error!("--oidc-client-id and --oidc-redirect-url are required with --oidc-issuer");
+            std::process::exit(1);
+        },
+    };
+    Some(OidcConfig {
+        issuer_url,
+        client_id,
+        client_secret: args.oidc_client_secret.clone(),
+        redirect_url,
+        scopes: vec!["profile".to_string(), "email".to_string()],
+        username_claim: args.oidc_username_claim.clone(),
+        role_claim: args.oidc_role_claim.clone(),
+        admin_values: args.oidc_admin_value.clone(),
+        create_users: args.oidc_create_users,
+    })
+}
+
+fn whois_config_from_args(args: &WhoisArgs) -> Option<WhoisConfig> {
+    if args.whois_listen.is_empty() {
+        return None;
+    }
+    Some(WhoisConfig::new(args.whois_listen.clone()))
+}
+
+fn rtr_config_from_args(args: &RtrArgs) -> Option<RtrConfig> {
+    if args.rtr_listen.is_empty() {
+        return None;
+    }
+    Some(RtrConfig::new(args.rtr_listen.clone()))
+}
+
+fn tls_config_from_args(args: &TlsArgs) -> Option<TlsConfig> {
+    Some(TlsConfig {
+        cert_path: args.tls_cert.clone()?,
+        key_path: args.tls_key.clone()?,
+        client_ca_path: args.tls_client_ca.clone(),
+        redirect_http_addr: args.http_redirect_addr.clone(),
+    })
+}
+
+fn proxy_auth_config_from_args(args: &ExternalAuthArgs) -> Option<ProxyAuthConfig> {
+    if args.trusted_proxy.is_empty() && !args.proxy_auth_unix_socket {
+        return None;
+    }
+    Some(ProxyAuthConfig {
+        header: args.proxy_auth_header.clone(),
+        trusted_proxies: args.trusted_proxy.clone(),
+        trust_unix_socket: args.proxy_auth_unix_socket,
+    })
+}
+
+fn client_cert_auth_config_from_args(args: &ExternalAuthArgs) -> Option<ClientCertAuthConfig> {
+    if args.client_cert_user.is_empty() && !args.client_cert_cn_as_username {
+        return None;
+    }
+    let mut users = HashMap::new();
+    for mapping in &args.client_cert_user {
+        // The subject contains '=' itself, so split at the last one.
+        match mapping.rsplit_once('=') {
+            Some((subject, username)) if !subject.is_empty() && !username.is_empty() => {
+                users.insert(subject.to_string(), username.to_string());
+            },
+            _ => {
+                log::error!("Invalid --client-cert-user {}; expected SUBJECT=USERNAME", mapping);
+                std::process::exit(1);
+            },
+        }
+    }
+    Some(ClientCertAuthConfig {
+        users,
+        common_name_as_username: args.client_cert_cn_as_username,
+    })
+}
+
+fn ldap_config_from_args(args: &LdapArgs) -> Option<LdapConfig> {
+    let url = args.ldap_url.clone()?;
+    let bind = match (&args.ldap_user_dn, &args.ldap_search_base) {
+        (Some(user_dn_template), None) => LdapBindMode::Direct {
+            user_dn_template: user_dn_template.clone(),
+        },
+        (None, Some(base_dn)) => LdapBindMode::Search {
+            base_dn: base_dn.clone(),
+            filter: args.ldap_search_filter.clone(),
+            bind_dn: args.ldap_bind_dn.clone(),
+            bind_password: args.ldap_bind_password.clone(),
+        },
+        _ => {
+            log::error!("Exactly one of --ldap-user-dn and --ldap-search-base is required with --ldap-url");
+            std::process::exit(1);
+        },
+    };
+    Some(LdapConfig {
+        url,
+        starttls: args.ldap_starttls,
+        timeout: 10,
+        bind,
+        group_base_dn: args.ldap_group_base.clone(),
+        group_filter: args.ldap_group_filter.clone(),
+        admin_groups: args.ldap_admin_group.clone(),
+        user_groups: args.ldap_user_group.clone(),
+        create_users: args.ldap_create_users,
+        local_users: args.ldap_local_user.clone(),
+    })
+}
+
+fn config_check(global_config: GlobalConfig) {
+    let path = match &global_config.config_path {
+        Some(path) => path,
+        None => {
+            log::error!("No configuration file given; use --config or MIRAMS_CONFIG");
+            std::process::exit(1);
+        },
+    };
+    // The file has already been parsed; check the values.
+    if let Err(e) = global_config.config.validate() {
+        log::error!("Invalid configuration in {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+    println!("{}: OK", path.display());
+}
+
+fn export_rpsl(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::Export { command: ExportCommands::Rpsl { maintainer, source } } => {
+            let mut config = global_config.config.rpsl.clone();
+            if let Some(maintainer) = maintainer {
+                config.maintainer = Some(maintainer.clone());
+            }
+            if let Some(source) = source {
+                config.source = source.clone();
+            }
+            let store = global_config.store();
+            match mirams::rpsl::export(&store, &config) {
+                Ok(text) => print!("{}", text),
+                Err(e) => {
+                    log::error!("Failed to export RPSL objects: {}", e);
+                    std::process::exit(1);
+                },
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn export_slurm(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    let store = global_config.store();
+    match store.roas().get_roas() {
+        Ok(roas) => println!("{}", serde_json::to_string_pretty(&mirams::roa::slurm(&roas)).unwrap()),
+        Err(e) => {
+            log::error!("Failed to export SLURM file: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn export_kea(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    let store = global_config.store();
+    match mirams::dhcp::kea_config(&store) {
+        Ok(config) => println!("{}", serde_json::to_string_pretty(&config).unwrap()),
+        Err(e) => {
+            log::error!("Failed to export Kea subnets: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn export_roa_coverage(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    let store = global_config.store();
+    match mirams::roa::coverage(&store) {
+        Ok(uncovered) => {
+            for assignment in uncovered {
+                let origin = assignment.origin_asn.map(|asn| format!("AS{}", asn)).unwrap_or_else(|| "-".to_string());
+                println!("{}\t{}\t{}", assignment.prefix, origin, assignment.name);
+            }
+        },
+        Err(e) => {
+            log::error!("Failed to check ROA coverage: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn export_dns_reverse(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
 
-    log::info!("Starting server on {}", listen_addr);
-    server.serve(listen_addr);
+    match &global_config.command {
+        Commands::Export { command: ExportCommands::DnsReverse { nameserver, output_dir } } => {
+            let mut config = global_config.config.dns.clone();
+            if !nameserver.is_empty() {
+                config.nameservers = nameserver.clone();
+            }
+            let store = global_config.store();
+            let zones = store.reverse_dns().get_serial().and_then(|serial| mirams::dns::reverse_zones(&store, &config, serial)).unwrap_or_else(|e| {
+                log::error!("Failed to generate reverse zones: {}", e);
+                std::process::exit(1);
+            });
+            for zone in zones {
+                if let Some(dir) = output_dir {
+                    let path = dir.join(zone.file_name());
+                    if let Err(e) = std::fs::write(&path, &zone.content) {
+                        log::error!("Failed to write {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                } else {
+                    println!("{}", zone.content);
+                }
+            }
+        },
+        _ => unreachable!(),
+    }
+}
 
-    loop {
-        std::thread::park();
+fn export_dns_forward(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::Export { command: ExportCommands::DnsForward { domain, output_dir, strict } } => {
+            let mut config = global_config.config.dns.clone();
+            if !domain.is_empty() {
+                config.forward_domains = domain.clone();
+            }
+            let store = global_config.store();
+            let forward = mirams::dns::forward_dns(&store, &config).unwrap_or_else(|e| {
+                log::error!("Failed to generate forward records: {}", e);
+                std::process::exit(1);
+            });
+            for issue in &forward.issues {
+                log::warn!("{}", issue);
+            }
+            if *strict && !forward.issues.is_empty() {
+                log::error!("{} issues found in the forward records", forward.issues.len());
+                std::process::exit(1);
+            }
+            for fragment in forward.fragments {
+                if let Some(dir) = output_dir {
+                    let path = dir.join(fragment.file_name());
+                    if let Err(e) = std::fs::write(&path, &fragment.content) {
+                        log::error!("Failed to write {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                } else {
+                    println!("{}", fragment.content);
+                }
+            }
+        },
+        _ => unreachable!(),
     }
 }
 
@@ -157,13 +605,88 @@ fn user_set_password(global_config: GlobalConfig) {
     }
 }
 
+fn user_set_role(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserSetRole { username, role } => {
+            let store = global_config.store();
+            if let Err(e) = store.users().set_role(username, *role) {
+                log::error!("Failed to set role: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
 fn user_delete(global_config: GlobalConfig) {
     global_config.check_for_actual_db();
 
     match &global_config.command {
         Commands::UserDelete { username } => {
             let store = global_config.store();
-            store.users().delete_user(username).unwrap();
+            if let Err(e) = store.users().delete_user(username) {
+                log::error!("Failed to delete user: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_unlock(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserUnlock { username } => {
+            let store = global_config.store();
+            store.users().clear_login_failures(&LoginThrottleKey::Username(username.clone())).unwrap();
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_totp_reset(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserTotpReset { username } => {
+            let store = global_config.store();
+            if let Err(e) = store.users().disable_totp(username) {
+                log::error!("Failed to reset two-factor authentication: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_oidc_link(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserOidcLink { username, issuer, subject } => {
+            let store = global_config.store();
+            if let Err(e) = store.users().link_oidc_user(username, issuer, subject) {
+                log::error!("Failed to link identity: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_oidc_unlink(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserOidcUnlink { username, issuer, subject } => {
+            let store = global_config.store();
+            if let Err(e) = store.users().unlink_oidc_user(username, issuer, subject) {
+                log::error!("Failed to unlink identity: {}", e);
+                std::process::exit(1);
+            }
         },
         _ => unreachable!(),
     }
@@ -175,11 +698,62 @@ fn user_list(global_config: GlobalConfig) {
     match &global_config.command {
         Commands::UserList => {
             let store = global_config.store();
-            let users = store.users().list_users().unwrap();
+            let users = store.users().list_users_with_metadata().unwrap();
             for user in users {
-                println!("{}", user);
+                println!("{}\t{}", user.username, user.role);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_token_create(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserTokenCreate { username, name, expires_in_days, read_only, families } => {
+            let store = global_config.store();
+            let scope = TokenScope {
+                read_only: *read_only,
+                families: families.clone(),
+            };
+            let expires_at = expires_in_days.map(|days| mirams::user::unix_time_now() + days as i64 * 86400);
+            let token = store.users().create_api_token(username, name, expires_at, &scope).unwrap();
+            println!("{}", token.secret);
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn user_token_list(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserTokenList { username } => {
+            let store = global_config.store();
+            let tokens = store.users().list_api_tokens(username).unwrap();
+            for token in tokens {
+                let expires_at = token.expires_at.map(|t| t.to_string()).unwrap_or("never".to_string());
+                let last_used_at = token.last_used_at.map(|t| t.to_string()).unwrap_or("never".to_string());
+                let families = token.scope.families.map(|families| {
+                    families.iter().map(|family| family.as_str()).collect::<Vec<_>>().join(",")
+                }).unwrap_or("all".to_string());
+                let access = if token.scope.read_only { "read-only" } else { "read-write" };
+                println!("{}\t{}\tcreated={}\texpires={}\tlast_used={}\t{}\tfamilies={}", token.id, token.name, token.created_at, expires_at, last_used_at, access, families);
             }
         },
         _ => unreachable!(),
     }
 }
+
+fn user_token_revoke(global_config: GlobalConfig) {
+    global_config.check_for_actual_db();
+
+    match &global_config.command {
+        Commands::UserTokenRevoke { username, id } => {
+            let store = global_config.store();
+            store.users().revoke_api_token(username, *id).unwrap();
+        },
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,373 @@
+//! Configuration file (TOML)
+//!
+//! ```toml
+//! [database]
+//! path = "/var/lib/mirams/mirams.db"
+//! api_key_secret_file = "/etc/mirams/api_key.secret"
+//!
+//! [server]
+//! listen = ["127.0.0.1:3001", "[::1]:3001", "unix:/run/mirams/mirams.sock"]
+//! unix_socket_mode = 0o660
+//! request_timeout = 30
+//! cors_origins = ["https://noc.example.com"]
+//!
+//! [tls]
+//! cert_path = "/etc/mirams/fullchain.pem"
+//! key_path = "/etc/mirams/privkey.pem"
+//!
+//! [whois]
+//! listen = ["0.0.0.0:43", "[::]:43"]
+//! max_query_length = 256
+//! timeout = 10
+//!
+//! [rtr]
+//! listen = "[::]:323"
+//! refresh_interval = 3600
+//!
+//! [rpsl]
+//! source = "MIRAMS"
+//! maintainer = "MAINT-EXAMPLE"
+//!
+//! [dns]
+//! nameservers = ["ns1.example.net.", "ns2.example.net."]
+//! hostmaster = "hostmaster.example.net."
+//! ttl = 3600
+//! forward_domains = ["example.net", "servers.example.net"]
+//! default_domain = "servers.example.net"
+//!
+//! [dns.keys."mirams-update"]
+//! algorithm = "hmac-sha256"
+//! secret = "c2VjcmV0LXNoYXJlZC13aXRoLXRoZS1wcmltYXJ5LXNlcnZlcg=="
+//!
+//! [dns.update]
+//! retries = 5
+//! retry_interval = 10
+//! timeout = 10
+//!
+//! [auth.oidc]
+//! issuer_url = "https://idp.example.com/realms/example"
+//! client_id = "mirams"
+//! redirect_url = "https://mirams.example.com/api/v1/oidc/callback"
+//!
+//! [logging]
+//! level = "info"
+//! ```
+//!
+//! Every section is optional. Environment variables and command-line flags take precedence.
+
+use crate::types::{Error, ErrorKind};
+use crate::ldap::LdapConfig;
+use crate::server::auth::{ClientCertAuthConfig, DistinguishedName, ProxyAuthConfig};
+use crate::server::listener::ListenAddr;
+use crate::server::oidc::OidcConfig;
+use crate::server::tls::TlsConfig;
+use crate::server::whois::WhoisConfig;
+use crate::server::rtr::RtrConfig;
+use crate::rpsl::RpslConfig;
+use crate::dns::DnsConfig;
+
+use serde::{Serialize, Deserialize, Deserializer};
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+
+/// Origin that allows requests from anywhere
+pub const ANY_ORIGIN: &str = "*";
+
+fn default_request_timeout() -> u64 {
+    30
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_cors_origins() -> Vec<String> {
+    vec![ANY_ORIGIN.to_string()]
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Accept a single address as well as a list
+pub(crate) fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<ListenAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ListenAddr),
+        Many(Vec<ListenAddr>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub database: DatabaseConfig,
+
+    #[serde(default)]
+    pub server: ServerConfig,
+
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    #[serde(default)]
+    pub whois: Option<WhoisConfig>,
+
+    /// Serve the ROAs to routers
+    #[serde(default)]
+    pub rtr: Option<RtrConfig>,
+
+    /// Attributes of exported RPSL objects
+    #[serde(default)]
+    pub rpsl: RpslConfig,
+
+    /// Apex records of generated zone files
+    #[serde(default)]
+    pub dns: DnsConfig,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// SQLite database file. An in-memory database is used if unset.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Hex-encoded secret keying API key hashes, generated if the file does not exist. Without
+    /// it the secret is kept in the database, so a copy of the database suffices to check
+    /// guessed keys.
+    #[serde(default)]
+    pub api_key_secret_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Addresses to listen on: `host:port` or `unix:/path/to/socket`.
+    /// Sockets passed in by systemd are used as well.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub listen: Vec<ListenAddr>,
+
+    /// Permissions of Unix domain sockets, such as `0o660`
+    #[serde(default)]
+    pub unix_socket_mode: Option<u32>,
+
+    /// Seconds before an API request is aborted
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: u64,
+
+    /// Seconds to wait for requests in progress on shutdown
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+
+    /// Origins allowed to call the API from browsers; `*` allows any
+    #[serde(default = "default_cors_origins")]
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: Vec::new(),
+            unix_socket_mode: None,
+            request_timeout: default_request_timeout(),
+            shutdown_timeout: default_shutdown_timeout(),
+            cors_origins: default_cors_origins(),
+        }
+    }
+}
+
+/// Authentication methods besides local passwords and API tokens
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+
+    #[serde(default)]
+    pub ldap: Option<LdapConfig>,
+
+    #[serde(default)]
+    pub proxy: Option<ProxyAuthConfig>,
+
+    #[serde(default)]
+    pub client_cert: Option<ClientCertAuthConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    #[serde(default = "default_log_level")]
+    pub level: String,
+
+    /// Log to syslog if it is available
+    #[serde(default = "default_true")]
+    pub syslog: bool,
+
+    /// Log to standard error
+    #[serde(default = "default_true")]
+    pub stderr: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: default_log_level(),
+            syslog: true,
+            stderr: true,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn level(&self) -> Result<log::Level, Error> {
+        log::Level::from_str(&self.level)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Invalid log level: {}", self.level)))
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Cannot read {}: {}", path.display(), e)))?;
+        toml::from_str(&content)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", path.display(), e)))
+    }
+
+    /// Check what parsing cannot: values, addresses, URLs and that the TLS files load
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidInput, message));
+
+        for addr in &self.server.listen {
+            if let ListenAddr::Tcp(addr) = addr {
+                if std::net::ToSocketAddrs::to_socket_addrs(addr).is_err() {
+                    return invalid(format!("server.listen: invalid address {}", addr));
+                }
+            }
+        }
+        if let Some(mode) = self.server.unix_socket_mode {
+            if mode > 0o777 {
+                return invalid(format!("server.unix_socket_mode: invalid mode {:o}", mode));
+            }
+        }
+        if self.server.request_timeout == 0 {
+            return invalid("server.request_timeout must be positive".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if origin != ANY_ORIGIN && http::HeaderValue::from_str(origin).is_err() {
+                return invalid(format!("server.cors_origins: invalid origin {}", origin));
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            tls.load().map_err(|e| Error::new(ErrorKind::InvalidInput, format!("tls: {}", e)))?;
+        }
+
+        if let Some(whois) = &self.whois {
+            if whois.listen.is_empty() {
+                return invalid("whois.listen must not be empty".to_string());
+            }
+            for addr in &whois.listen {
+                if let ListenAddr::Tcp(addr) = addr {
+                    if std::net::ToSocketAddrs::to_socket_addrs(addr).is_err() {
+                        return invalid(format!("whois.listen: invalid address {}", addr));
+                    }
+                }
+            }
+            if whois.max_query_length == 0 || whois.timeout == 0 {
+                return invalid("whois.max_query_length and whois.timeout must be positive".to_string());
+            }
+        }
+
+        if let Some(rtr) = &self.rtr {
+            if rtr.listen.is_empty() {
+                return invalid("rtr.listen must not be empty".to_string());
+            }
+            for addr in &rtr.listen {
+                if let ListenAddr::Tcp(addr) = addr {
+                    if std::net::ToSocketAddrs::to_socket_addrs(addr).is_err() {
+                        return invalid(format!("rtr.listen: invalid address {}", addr));
+                    }
+                }
+            }
+            // Ranges from RFC 8210, section 6
+            if !(1..=86400).contains(&rtr.refresh_interval) || !(1..=7200).contains(&rtr.retry_interval) || !(600..=172800).contains(&rtr.expire_interval) {
+                return invalid("rtr: refresh_interval, retry_interval or expire_interval out of range".to_string());
+            }
+            if rtr.expire_interval <= rtr.refresh_interval.max(rtr.retry_interval) {
+                return invalid("rtr.expire_interval must be longer than refresh_interval and retry_interval".to_string());
+            }
+            if rtr.poll_interval == 0 {
+                return invalid("rtr.poll_interval must be positive".to_string());
+            }
+        }
+
+        if self.rpsl.source.trim().is_empty() {
+            return invalid("rpsl.source must not be empty".to_string());
+        }
+
+        self.dns.validate().map_err(|e| Error::new(ErrorKind::InvalidInput, format!("dns: {}", e.message())))?;
+
+        if let Some(oidc) = &self.auth.oidc {
+            for (name, url) in [("issuer_url", &oidc.issuer_url), ("redirect_url", &oidc.redirect_url)] {
+                if openidconnect::url::Url::parse(url).is_err() {
+                    return invalid(format!("auth.oidc.{}: invalid URL {}", name, url));
+                }
+            }
+        }
+        if let Some(ldap) = &self.auth.ldap {
+            if !ldap.url.starts_with("ldap://") && !ldap.url.starts_with("ldaps://") && !ldap.url.starts_with("ldapi://") {
+                return invalid(format!("auth.ldap.url: invalid URL {}", ldap.url));
+            }
+        }
+        if let Some(proxy) = &self.auth.proxy {
+            if http::HeaderName::from_bytes(proxy.header.as_bytes()).is_err() {
+                return invalid(format!("auth.proxy.header: invalid header {}", proxy.header));
+            }
+            if proxy.trusted_proxies.is_empty() && !proxy.trust_unix_socket {
+                return invalid("auth.proxy.trusted_proxies must not be empty unless trust_unix_socket is set".to_string());
+            }
+        }
+
+        if let Some(client_cert) = &self.auth.client_cert {
+            for dn in client_cert.users.keys() {
+                if dn.parse::<DistinguishedName>().is_err() {
+                    return invalid(format!("auth.client_cert.users: invalid DN {}", dn));
+                }
+            }
+        }
+
+        self.logging.level()?;
+        Ok(())
+    }
+}
@@ -14,18 +14,22 @@ use crate::types::Error;
 use crate::store::DbConnection;
 
 pub use crate::types::ObjectVisibility;
+pub use crate::user::UserRole;
+pub use crate::user::LoginOutcome;
 
 
 // Schema versioning
-const SCHEMA_VERSION: i32 = 1;
+const SCHEMA_VERSION: i32 = 14;
 
 
-// Structs for tables
+// Error conversions
 
 impl ErrorWithKind for rusqlite::Error {
     fn kind(&self) -> ErrorKind {
         match self {
             rusqlite::Error::QueryReturnedNoRows => ErrorKind::NotFound,
+            // Such as a UNIQUE constraint (a user name that is taken)
+            rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::ConstraintViolation => ErrorKind::Conflict,
             _ => ErrorKind::DatabaseError,
         }
     }
@@ -37,6 +41,21 @@ impl ErrorWithKind for r2d2::Error {
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::new(err.kind(), err)
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(err: r2d2::Error) -> Self {
+        Error::new(err.kind(), err)
+    }
+}
+
+
+// Structs for tables
+
 impl FromSql for ObjectVisibility {
     fn column_result(value: ValueRef) -> Result<Self, FromSqlError> {
         let value: i32 = value.as_i64()?.try_into().map_err(|_| FromSqlError::InvalidType)?;
@@ -54,6 +73,33 @@ impl ToSql for ObjectVisibility {
     }
 }
 
+impl FromSql for UserRole {
+    fn column_result(value: ValueRef) -> Result<Self, FromSqlError> {
+        let value: i32 = value.as_i64()?.try_into().map_err(|_| FromSqlError::InvalidType)?;
+        UserRole::try_from(value).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for UserRole {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, rusqlite::Error> {
+        Ok((*self as i64).into())
+    }
+}
+
+impl FromSql for LoginOutcome {
+    fn column_result(value: ValueRef) -> Result<Self, FromSqlError> {
+        let value: i32 = value.as_i64()?.try_into().map_err(|_| FromSqlError::InvalidType)?;
+        LoginOutcome::try_from(value).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for LoginOutcome {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, rusqlite::Error> {
+        Ok((*self as i64).into())
+    }
+}
+
+
 
 // Users and API keys
 
@@ -62,13 +108,22 @@ pub struct User {
     pub id: i32,
     pub name: String,
     pub hashed_password: String,
+    pub role: UserRole,
+    pub created_at: i64,
 }
 
 #[derive(Debug)]
 pub struct ApiKey {
     pub id: i32,
-    pub key: String,
+    pub key_prefix: String, // first characters of the key, for lookups
+    pub key_hash: String, // HMAC-SHA256 of the key, keyed with the `api_key_hmac` server secret
     pub user_id: i32,
+    pub name: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub read_only: bool,
+    pub families: Option<String>, // comma-separated, NULL for all families
 }
 
 // IPv4 and IPv6 prefixes
@@ -316,19 +371,312 @@ CREATE TABLE assignment_asn (
 CREATE INDEX assignment_asn_asn ON assignment_asn (asn);
 "#;
 
+// Named, expiring and scoped API tokens
+const MIGRATION_2: &str = r#"
+ALTER TABLE api_key ADD COLUMN name BLOB NOT NULL DEFAULT 'default';
+ALTER TABLE api_key ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE api_key ADD COLUMN expires_at INTEGER;
+ALTER TABLE api_key ADD COLUMN last_used_at INTEGER;
+ALTER TABLE api_key ADD COLUMN read_only INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE api_key ADD COLUMN families TEXT;
+
+CREATE INDEX api_key_key ON api_key (key);
+CREATE INDEX api_key_user_id ON api_key (user_id);
+"#;
+
+// Hashed API keys. Existing plaintext keys are converted by `hash_existing_api_keys`
+// before the `key` column is dropped.
+const MIGRATION_3: &str = r#"
+CREATE TABLE server_secret (
+    name TEXT PRIMARY KEY,
+    value BLOB NOT NULL
+);
+
+ALTER TABLE api_key ADD COLUMN key_prefix TEXT NOT NULL DEFAULT '';
+ALTER TABLE api_key ADD COLUMN key_hash TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX api_key_key_prefix ON api_key (key_prefix);
+"#;
+
+const MIGRATION_3_CLEANUP: &str = r#"
+DROP INDEX api_key_key;
+ALTER TABLE api_key DROP COLUMN key;
+"#;
+
+// User roles. The oldest existing user becomes the admin, as the first user created
+// would. Duplicate user rows (from an old bug in `set_password`) are refused by
+// `check_duplicate_users` before this runs.
+const MIGRATION_4: &str = r#"
+ALTER TABLE user ADD COLUMN role INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE user ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+
+UPDATE user SET role = 1 WHERE id = (SELECT MIN(id) FROM user);
+
+DROP INDEX user_name;
+CREATE UNIQUE INDEX user_name ON user (name);
+"#;
+
+// Login attempts, for throttling and auditing. `cleared` failures were reset by an admin.
+const MIGRATION_5: &str = r#"
+CREATE TABLE login_attempt (
+    id INTEGER PRIMARY KEY,
+    username BLOB NOT NULL,
+    source BLOB,
+    outcome INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    cleared INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX login_attempt_username ON login_attempt (username, time);
+CREATE INDEX login_attempt_source ON login_attempt (source, time);
+CREATE INDEX login_attempt_time ON login_attempt (time);
+"#;
+
+// TOTP two-factor authentication. Recovery codes are stored as keyed hashes like API keys.
+const MIGRATION_6: &str = r#"
+CREATE TABLE user_totp (
+    user_id INTEGER PRIMARY KEY,
+    secret BLOB NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    last_used_step INTEGER,
+    FOREIGN KEY (user_id) REFERENCES user (id) ON DELETE CASCADE
+);
+
+CREATE TABLE user_recovery_code (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at INTEGER,
+    FOREIGN KEY (user_id) REFERENCES user (id) ON DELETE CASCADE
+);
+
+CREATE INDEX user_recovery_code_user_id ON user_recovery_code (user_id);
+"#;
+
+// Origin AS of IPv4 and IPv6 assignments, for route objects
+const MIGRATION_7: &str = r#"
+ALTER TABLE assignment_ipv4 ADD COLUMN origin_asn_id INTEGER REFERENCES assignment_asn (id) ON DELETE SET NULL;
+ALTER TABLE assignment_ipv6 ADD COLUMN origin_asn_id INTEGER REFERENCES assignment_asn (id) ON DELETE SET NULL;
+"#;
+
+// Route Origin Authorisations. `prefix` holds 4 or 16 bytes depending on `ip_version`.
+const MIGRATION_8: &str = r#"
+CREATE TABLE roa (
+    id INTEGER PRIMARY KEY,
+    ip_version INTEGER NOT NULL,
+    prefix BLOB NOT NULL,
+    prefix_len INTEGER NOT NULL,
+    max_length INTEGER NOT NULL,
+    asn INTEGER NOT NULL,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE INDEX roa_prefix ON roa (ip_version, prefix);
+"#;
+
+// Serial of the ROA set, for RTR
+const MIGRATION_9: &str = r#"
+CREATE TABLE roa_serial (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    serial INTEGER NOT NULL
+);
+
+INSERT INTO roa_serial (id, serial) VALUES (0, 0);
+"#;
+
+// Reverse DNS of IPv4 and IPv6 assignments, deleted along with them, and the SOA serial of
+// the reverse zones. `nameservers` is separated by spaces.
+const MIGRATION_10: &str = r#"
+CREATE TABLE reverse_dns_ipv4 (
+    assignment_id INTEGER PRIMARY KEY,
+    nameservers TEXT NOT NULL,
+    ptr_template TEXT,
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv4 (id) ON DELETE CASCADE
+);
+
+CREATE TABLE reverse_dns_ipv6 (
+    assignment_id INTEGER PRIMARY KEY,
+    nameservers TEXT NOT NULL,
+    ptr_template TEXT,
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv6 (id) ON DELETE CASCADE
+);
+
+CREATE TABLE reverse_dns_serial (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    serial INTEGER NOT NULL
+);
+
+INSERT INTO reverse_dns_serial (id, serial) VALUES (0, 1);
+"#;
+
+// Dynamic DNS updates of the reverse zones of IPv4 and IPv6 assignment spaces, deleted along
+// with them
+const MIGRATION_11: &str = r#"
+CREATE TABLE dns_update_ipv4 (
+    space_id INTEGER PRIMARY KEY,
+    zone TEXT,
+    server TEXT NOT NULL,
+    key_name TEXT NOT NULL,
+    FOREIGN KEY (space_id) REFERENCES assignment_space_ipv4 (id) ON DELETE CASCADE
+);
+
+CREATE TABLE dns_update_ipv6 (
+    space_id INTEGER PRIMARY KEY,
+    zone TEXT,
+    server TEXT NOT NULL,
+    key_name TEXT NOT NULL,
+    FOREIGN KEY (space_id) REFERENCES assignment_space_ipv6 (id) ON DELETE CASCADE
+);
+"#;
+
+// Hosts within IPv4 and IPv6 assignments, deleted along with them. `mac_address` is normalized,
+// and `gateway` is 0 or 1.
+const MIGRATION_12: &str = r#"
+CREATE TABLE host_ipv4 (
+    id INTEGER PRIMARY KEY,
+    assignment_id INTEGER NOT NULL,
+    ipv4_address BLOB NOT NULL,
+    hostname TEXT NOT NULL,
+    mac_address TEXT,
+    description TEXT NOT NULL,
+    gateway INTEGER NOT NULL,
+    UNIQUE (assignment_id, ipv4_address),
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv4 (id) ON DELETE CASCADE
+);
+
+CREATE TABLE host_ipv6 (
+    id INTEGER PRIMARY KEY,
+    assignment_id INTEGER NOT NULL,
+    ipv6_address BLOB NOT NULL,
+    hostname TEXT NOT NULL,
+    mac_address TEXT,
+    description TEXT NOT NULL,
+    gateway INTEGER NOT NULL,
+    UNIQUE (assignment_id, ipv6_address),
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv6 (id) ON DELETE CASCADE
+);
+"#;
+
+// DHCP service of IPv4 and IPv6 assignments, deleted along with them. Addresses are stored
+// as text, and `dns_servers` is separated by spaces.
+const MIGRATION_13: &str = r#"
+CREATE TABLE dhcp_ipv4 (
+    assignment_id INTEGER PRIMARY KEY,
+    range_start TEXT,
+    range_end TEXT,
+    gateway TEXT,
+    dns_servers TEXT NOT NULL,
+    valid_lifetime INTEGER,
+    renew_timer INTEGER,
+    rebind_timer INTEGER,
+    preferred_lifetime INTEGER,
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv4 (id) ON DELETE CASCADE
+);
+
+CREATE TABLE dhcp_ipv6 (
+    assignment_id INTEGER PRIMARY KEY,
+    range_start TEXT,
+    range_end TEXT,
+    gateway TEXT,
+    dns_servers TEXT NOT NULL,
+    valid_lifetime INTEGER,
+    renew_timer INTEGER,
+    rebind_timer INTEGER,
+    preferred_lifetime INTEGER,
+    FOREIGN KEY (assignment_id) REFERENCES assignment_ipv6 (id) ON DELETE CASCADE
+);
+"#;
+
+// Single sign-on identities (`sub` claims of an issuer) linked to local users
+const MIGRATION_14: &str = r#"
+CREATE TABLE oidc_link (
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
+
+CREATE INDEX oidc_link_user_id ON oidc_link (user_id);
+"#;
+
+/// Migrations in order; `MIGRATIONS[n - 1]` upgrades the schema to version `n`.
+pub(crate) const MIGRATIONS: &[&str] = &[
+    MIGRATION_1,
+    MIGRATION_2,
+    MIGRATION_3,
+    MIGRATION_4,
+    MIGRATION_5,
+    MIGRATION_6,
+    MIGRATION_7,
+    MIGRATION_8,
+    MIGRATION_9,
+    MIGRATION_10,
+    MIGRATION_11,
+    MIGRATION_12,
+    MIGRATION_13,
+    MIGRATION_14,
+];
+
+/// Name of the server secret used to key API key hashes, unless one is configured
+pub(crate) const API_KEY_HMAC_SECRET: &str = "api_key_hmac";
+
+fn hash_existing_api_keys(tx: &rusqlite::Transaction) -> Result<(), Error> {
+    use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    tx.execute("INSERT INTO server_secret (name, value) VALUES (?, ?)", rusqlite::params![API_KEY_HMAC_SECRET, &secret[..]])?;
+
+    let keys = {
+        let mut stmt = tx.prepare("SELECT id, key FROM api_key")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+    for (id, key) in keys {
+        let prefix = crate::user::api_key_prefix(&key);
+        let hash = crate::user::hash_api_key(&secret, &key);
+        tx.execute("UPDATE api_key SET key_prefix = ?, key_hash = ? WHERE id = ?", rusqlite::params![prefix, hash, id])?;
+    }
+
+    tx.execute_batch(MIGRATION_3_CLEANUP)?;
+    Ok(())
+}
+
+/// Refuse to add roles while a username has several rows, which an old bug in `set_password`
+/// could create. Which row should be kept, with its API keys, is for the operator to decide.
+fn check_duplicate_users(tx: &rusqlite::Transaction) -> Result<(), Error> {
+    let names = {
+        let mut stmt = tx.prepare("SELECT CAST(name AS TEXT) FROM user GROUP BY name HAVING COUNT(*) > 1 ORDER BY name")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+    if names.is_empty() {
+        return Ok(());
+    }
+    Err(Error::new(ErrorKind::DatabaseError, format!(
+        "Cannot upgrade the database: the users {} have more than one row in the user table; delete the extra rows before upgrading",
+        names.join(", "),
+    )))
+}
+
 
 // Actual code below
 
 #[derive(Debug, Clone)]
 pub struct SqliteConnection {
     pool: r2d2::Pool<SqliteConnectionManager>,
+
+    /// Secret keying API key hashes, if it is kept outside the database
+    api_key_secret: Option<std::sync::Arc<Vec<u8>>>,
 }
 
 impl SqliteConnection {
     pub fn open_file(path: &str) -> Result<SqliteConnection, Error> {
         let manager = SqliteConnectionManager::file(path);
         let pool = r2d2::Pool::new(manager)?;
-        let db = SqliteConnection { pool };
+        let db = SqliteConnection { pool, api_key_secret: None };
         db.initialize()?;
         Ok(db)
     }
@@ -336,7 +684,7 @@ impl SqliteConnection {
     pub fn open_memory() -> Result<SqliteConnection, Error> {
         let manager = SqliteConnectionManager::memory();
         let pool = r2d2::Pool::new(manager)?;
-        let db = SqliteConnection { pool };
+        let db = SqliteConnection { pool, api_key_secret: None };
         db.initialize()?;
         Ok(db)
     }
@@ -349,22 +697,55 @@ impl SqliteConnection {
             let version: i32 = row.get(0)?;
             Ok(version)
         });
-        match version {
+        let current_version = match version {
             Ok(version) => {
-                if version != SCHEMA_VERSION {
+                if version > SCHEMA_VERSION {
                     return Err(Error::new(ErrorKind::InternalError, format!("Database schema version mismatch: expected {}, got {}", SCHEMA_VERSION, version)));
                 }
+                version
             },
             Err(rusqlite::Error::QueryReturnedNoRows) => {
-                tx.execute("INSERT INTO schema_version (id, version) VALUES (1, ?)", [SCHEMA_VERSION])?;
-                tx.execute_batch(MIGRATION_1)?;
+                tx.execute("INSERT INTO schema_version (id, version) VALUES (1, 0)", [])?;
+                0
             },
             Err(error) => return Err(error.into()),
+        };
+        for version in (current_version + 1)..=SCHEMA_VERSION {
+            log::info!("Migrating database schema to version {}", version);
+            if version == 4 {
+                check_duplicate_users(&tx)?;
+            }
+            tx.execute_batch(MIGRATIONS[(version - 1) as usize])?;
+            if version == 3 {
+                hash_existing_api_keys(&tx)?;
+            }
         }
+        tx.execute("UPDATE schema_version SET version = ? WHERE id = 1", [SCHEMA_VERSION])?;
         tx.commit()?;
         Ok(())
     }
 
+    /// Key API key hashes with `secret` instead of the secret stored in the database.
+    /// Keys hashed with the stored secret keep working, and are rehashed when next used.
+    pub fn with_api_key_secret(mut self, secret: Vec<u8>) -> Self {
+        self.api_key_secret = Some(std::sync::Arc::new(secret));
+        self
+    }
+
+    /// Secrets to check API key hashes with, the one to hash new keys with first
+    pub(crate) fn api_key_secrets(&self, conn: &rusqlite::Connection) -> Result<Vec<Vec<u8>>, Error> {
+        let stored = match conn.query_row("SELECT value FROM server_secret WHERE name = ?", rusqlite::params![API_KEY_HMAC_SECRET], |row| row.get(0)) {
+            Ok(secret) => Some(secret),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let secrets: Vec<Vec<u8>> = self.api_key_secret.as_deref().cloned().into_iter().chain(stored).collect();
+        if secrets.is_empty() {
+            return Err(Error::new(ErrorKind::InternalError, "No API key secret".to_string()));
+        }
+        Ok(secrets)
+    }
+
     pub(crate) fn get_conn(&self) -> Result<r2d2::PooledConnection<SqliteConnectionManager>, Error> {
         Ok(self.pool.get()?)
     }
@@ -386,4 +767,16 @@ impl DbConnection for SqliteConnection {
     fn asn_assignment_store(&self) -> Box<dyn crate::asn::AsnAssignmentStore> {
         Box::new(model::SqliteAsnAssignmentStore::new(self.clone()))
     }
+
+    fn roa_store(&self) -> Box<dyn crate::roa::RoaStore> {
+        Box::new(model::SqliteRoaStore::new(self.clone()))
+    }
+
+    fn reverse_dns_store(&self) -> Box<dyn crate::dns::ReverseDnsStore> {
+        Box::new(model::SqliteReverseDnsStore::new(self.clone()))
+    }
+
+    fn dhcp_store(&self) -> Box<dyn crate::dhcp::DhcpStore> {
+        Box::new(model::SqliteDhcpStore::new(self.clone()))
+    }
 }
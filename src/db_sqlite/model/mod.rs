@@ -3,8 +3,14 @@ mod sqlite_user;
 mod sqlite_ipv4;
 mod sqlite_ipv6;
 mod sqlite_asn;
+mod sqlite_roa;
+mod sqlite_dns;
+mod sqlite_dhcp;
 
 pub use sqlite_user::SqliteUserStore;
 pub use sqlite_ipv4::SqliteIpv4AssignmentStore;
 pub use sqlite_ipv6::SqliteIpv6AssignmentStore;
 pub use sqlite_asn::SqliteAsnAssignmentStore;
+pub use sqlite_roa::SqliteRoaStore;
+pub use sqlite_dns::SqliteReverseDnsStore;
+pub use sqlite_dhcp::SqliteDhcpStore;
@@ -65,6 +65,26 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
         Ok(spaces)
     }
 
+    fn get_spaces_containing(&self, asn: u32) -> Result<Vec<crate::asn::AssignmentSpaceAsn>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, description, space_visibility, asn_from, asn_to FROM assignment_space_asn WHERE asn_from <= ?1 AND asn_to >= ?1 ORDER BY asn_from ASC")?;
+        let rows = stmt.query_map(rusqlite::params![asn], |row| {
+            Ok(crate::asn::AssignmentSpaceAsn {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                space_visibility: row.get(3)?,
+                asn_from: row.get(4)?,
+                asn_to: row.get(5)?,
+            })
+        })?;
+        let mut spaces = Vec::new();
+        for space in rows {
+            spaces.push(space?);
+        }
+        Ok(spaces)
+    }
+
     fn create_space(&self, space: &crate::asn::AssignmentSpaceAsn) -> Result<i32, Error> {
         let conn = self.db.get_conn()?;
         let mut stmt = conn.prepare("INSERT INTO assignment_space_asn (name, description, space_visibility, asn_from, asn_to) VALUES (?, ?, ?, ?, ?)")?;
@@ -135,6 +155,27 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
         Ok(pools)
     }
 
+    fn get_pools_containing(&self, space_id: i32, asn: u32) -> Result<Vec<crate::asn::AssignmentPoolAsn>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, description, pool_visibility, assignment_space_id, asn_from, asn_to FROM assignment_pool_asn WHERE assignment_space_id = ?1 AND asn_from <= ?2 AND asn_to >= ?2 ORDER BY asn_from ASC")?;
+        let rows = stmt.query_map(rusqlite::params![space_id, asn], |row| {
+            Ok(crate::asn::AssignmentPoolAsn {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                pool_visibility: row.get(3)?,
+                assignment_space_id: row.get(4)?,
+                asn_from: row.get(5)?,
+                asn_to: row.get(6)?,
+            })
+        })?;
+        let mut pools = Vec::new();
+        for pool in rows {
+            pools.push(pool?);
+        }
+        Ok(pools)
+    }
+
     fn create_pool(&self, pool: &crate::asn::AssignmentPoolAsn) -> Result<i32, Error> {
         let mut conn = self.db.get_conn()?;
         let tx = conn.transaction()?;
@@ -147,7 +188,7 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
             })?;
 
             if pool.asn_from < space.0 || pool.asn_to > space.1 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Pool ASN range is out of space range".to_string()));
+                return Err(Error::new(ErrorKind::Validation, "Pool ASN range is out of space range".to_string()));
             }
         }
 
@@ -157,7 +198,7 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
             let count: i32 = check_stmt.query_row(rusqlite::params![pool.assignment_space_id, pool.asn_from, pool.asn_from, pool.asn_to, pool.asn_to], |row| row.get(0))?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping assignment pool exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping assignment pool exists".to_string()));
             }
         }
 
@@ -233,6 +274,26 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
         Ok(assignments)
     }
 
+    fn get_assignments_containing(&self, pool_id: i32, asn: u32) -> Result<Vec<crate::asn::AssignmentAsn>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, description, assignment_pool_id, asn, assignment_visibility FROM assignment_asn WHERE assignment_pool_id = ? AND asn = ?")?;
+        let rows = stmt.query_map(rusqlite::params![pool_id, asn], |row| {
+            Ok(crate::asn::AssignmentAsn {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                assignment_pool_id: row.get(3)?,
+                asn: row.get(4)?,
+                assignment_visibility: row.get(5)?,
+            })
+        })?;
+        let mut assignments = Vec::new();
+        for assignment in rows {
+            assignments.push(assignment?);
+        }
+        Ok(assignments)
+    }
+
     fn create_assignment(&self, assignment: &crate::asn::AssignmentAsn) -> Result<i32, Error> {
         let mut conn = self.db.get_conn()?;
         let tx = conn.transaction()?;
@@ -243,7 +304,7 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
             let count: i32 = check_stmt.query_row(rusqlite::params![assignment.assignment_pool_id, assignment.asn], |row| row.get(0))?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping assignment exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping assignment exists".to_string()));
             }
         }
 
@@ -255,7 +316,7 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
             })?;
 
             if assignment.asn < pool.0 || assignment.asn > pool.1 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Assignment ASN is out of pool range".to_string()));
+                return Err(Error::new(ErrorKind::Validation, "Assignment ASN is out of pool range".to_string()));
             }
         }
 
@@ -285,3 +346,12 @@ impl AsnAssignmentStore for SqliteAsnAssignmentStore {
     }
 }
 
+/// Check that `origin_asn_id` names an existing ASN assignment
+pub(super) fn check_origin_asn(tx: &rusqlite::Transaction, origin_asn_id: i32) -> Result<(), Error> {
+    let count: i32 = tx.query_row("SELECT COUNT(*) FROM assignment_asn WHERE id = ?", rusqlite::params![origin_asn_id], |row| row.get(0))?;
+    if count == 0 {
+        return Err(Error::new(ErrorKind::Validation, "Origin ASN assignment not found".to_string()));
+    }
+    Ok(())
+}
+
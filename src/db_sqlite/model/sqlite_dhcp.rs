@@ -0,0 +1,133 @@
+use crate::db_sqlite::SqliteConnection;
+use crate::types::{Error, ErrorKind};
+use crate::user::ResourceFamily;
+
+use crate::dhcp::{DhcpSettings, DhcpStore};
+
+use r2d2_sqlite::rusqlite;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone)]
+pub struct SqliteDhcpStore {
+    db: SqliteConnection,
+}
+
+impl SqliteDhcpStore {
+    pub fn new(db: SqliteConnection) -> Self {
+        SqliteDhcpStore { db }
+    }
+}
+
+/// Table of the DHCP service of the assignments of `family`
+fn dhcp_table(family: ResourceFamily) -> Result<&'static str, Error> {
+    match family {
+        ResourceFamily::Ipv4 => Ok("dhcp_ipv4"),
+        ResourceFamily::Ipv6 => Ok("dhcp_ipv6"),
+        ResourceFamily::Asn => Err(Error::new(ErrorKind::Validation, "DHCP only applies to IPv4 and IPv6 assignments".to_string())),
+    }
+}
+
+fn parse_address(addr: &str) -> Result<IpAddr, Error> {
+    addr.parse().map_err(|_| Error::new(ErrorKind::DatabaseError, format!("Invalid address in DHCP settings: {}", addr)))
+}
+
+/// Settings from a row of `range_start, range_end, gateway, dns_servers, valid_lifetime,
+/// renew_timer, rebind_timer, preferred_lifetime`
+fn dhcp_from_row(row: &rusqlite::Row) -> Result<DhcpSettings, Error> {
+    let range_start: Option<String> = row.get(0)?;
+    let range_end: Option<String> = row.get(1)?;
+    let gateway: Option<String> = row.get(2)?;
+    let dns_servers: String = row.get(3)?;
+    Ok(DhcpSettings {
+        range_start: range_start.as_deref().map(parse_address).transpose()?,
+        range_end: range_end.as_deref().map(parse_address).transpose()?,
+        gateway: gateway.as_deref().map(parse_address).transpose()?,
+        dns_servers: dns_servers.split_whitespace().map(parse_address).collect::<Result<_, _>>()?,
+        valid_lifetime: row.get(4)?,
+        renew_timer: row.get(5)?,
+        rebind_timer: row.get(6)?,
+        preferred_lifetime: row.get(7)?,
+    })
+}
+
+/// Prefix, prefix length and reserved addresses of an assignment
+fn assignment_network(tx: &rusqlite::Transaction, family: ResourceFamily, assignment_id: i32) -> Result<(IpAddr, u8, Vec<IpAddr>), Error> {
+    let result = match family {
+        ResourceFamily::Ipv4 => tx.query_row("SELECT ipv4_prefix, ipv4_prefix_len FROM assignment_ipv4 WHERE id = ?", rusqlite::params![assignment_id], |row| {
+            let prefix: crate::ipv4::RawIpv4Addr = row.get(0)?;
+            let prefix_len: u8 = row.get(1)?;
+            let reserved = crate::ipv4::ipv4_reserved_addresses(prefix, prefix_len).into_iter().map(|addr| IpAddr::V4(Ipv4Addr::from(addr))).collect();
+            Ok((IpAddr::V4(Ipv4Addr::from(prefix)), prefix_len, reserved))
+        }),
+        _ => tx.query_row("SELECT ipv6_prefix, ipv6_prefix_len FROM assignment_ipv6 WHERE id = ?", rusqlite::params![assignment_id], |row| {
+            let prefix: crate::ipv6::RawIpv6Addr = row.get(0)?;
+            let prefix_len: u8 = row.get(1)?;
+            let reserved = crate::ipv6::ipv6_reserved_addresses(prefix, prefix_len).into_iter().map(|addr| IpAddr::V6(Ipv6Addr::from(addr))).collect();
+            Ok((IpAddr::V6(Ipv6Addr::from(prefix)), prefix_len, reserved))
+        }),
+    };
+    match result {
+        Ok(network) => Ok(network),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::new(ErrorKind::NotFound, "Assignment not found".to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl DhcpStore for SqliteDhcpStore {
+    fn get_dhcp(&self, family: ResourceFamily, assignment_id: i32) -> Result<Option<DhcpSettings>, Error> {
+        let table = dhcp_table(family)?;
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT range_start, range_end, gateway, dns_servers, valid_lifetime, renew_timer, rebind_timer, preferred_lifetime FROM {} WHERE assignment_id = ?", table))?;
+        let mut rows = stmt.query(rusqlite::params![assignment_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(dhcp_from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_all_dhcp(&self, family: ResourceFamily) -> Result<HashMap<i32, DhcpSettings>, Error> {
+        let table = dhcp_table(family)?;
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT range_start, range_end, gateway, dns_servers, valid_lifetime, renew_timer, rebind_timer, preferred_lifetime, assignment_id FROM {}", table))?;
+        let mut rows = stmt.query([])?;
+        let mut all = HashMap::new();
+        while let Some(row) = rows.next()? {
+            all.insert(row.get(8)?, dhcp_from_row(row)?);
+        }
+        Ok(all)
+    }
+
+    fn set_dhcp(&self, family: ResourceFamily, assignment_id: i32, dhcp: Option<&DhcpSettings>) -> Result<(), Error> {
+        let table = dhcp_table(family)?;
+        if let Some(dhcp) = dhcp {
+            dhcp.validate(family)?;
+        }
+
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let (prefix, prefix_len, reserved) = assignment_network(&tx, family, assignment_id)?;
+        tx.execute(&format!("DELETE FROM {} WHERE assignment_id = ?", table), rusqlite::params![assignment_id])?;
+        if let Some(dhcp) = dhcp {
+            dhcp.check_within(family, prefix, prefix_len, &reserved)?;
+            let dns_servers = dhcp.dns_servers.iter().map(|addr| addr.to_string()).collect::<Vec<_>>().join(" ");
+            tx.execute(
+                &format!("INSERT INTO {} (assignment_id, range_start, range_end, gateway, dns_servers, valid_lifetime, renew_timer, rebind_timer, preferred_lifetime) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)", table),
+                rusqlite::params![
+                    assignment_id,
+                    dhcp.range_start.map(|addr| addr.to_string()),
+                    dhcp.range_end.map(|addr| addr.to_string()),
+                    dhcp.gateway.map(|addr| addr.to_string()),
+                    dns_servers,
+                    dhcp.valid_lifetime,
+                    dhcp.renew_timer,
+                    dhcp.rebind_timer,
+                    dhcp.preferred_lifetime,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::db_sqlite::SqliteConnection;
+use crate::types::{Error, ErrorKind};
+use crate::user::ResourceFamily;
+
+use crate::dns::{DnsUpdate, ReverseDns, ReverseDnsStore, MAX_PTR_TEMPLATE_BITS};
+
+use r2d2_sqlite::rusqlite;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct SqliteReverseDnsStore {
+    db: SqliteConnection,
+}
+
+impl SqliteReverseDnsStore {
+    pub fn new(db: SqliteConnection) -> Self {
+        SqliteReverseDnsStore { db }
+    }
+}
+
+/// Table of the reverse DNS of the assignments of `family`
+fn reverse_dns_table(family: ResourceFamily) -> Result<&'static str, Error> {
+    match family {
+        ResourceFamily::Ipv4 => Ok("reverse_dns_ipv4"),
+        ResourceFamily::Ipv6 => Ok("reverse_dns_ipv6"),
+        ResourceFamily::Asn => Err(Error::new(ErrorKind::Validation, "Reverse DNS only applies to IPv4 and IPv6 assignments".to_string())),
+    }
+}
+
+/// Table of the dynamic DNS updates of the assignment spaces of `family`
+fn dns_update_table(family: ResourceFamily) -> Result<&'static str, Error> {
+    match family {
+        ResourceFamily::Ipv4 => Ok("dns_update_ipv4"),
+        ResourceFamily::Ipv6 => Ok("dns_update_ipv6"),
+        ResourceFamily::Asn => Err(Error::new(ErrorKind::Validation, "Reverse DNS only applies to IPv4 and IPv6 assignments".to_string())),
+    }
+}
+
+fn reverse_dns_from_row(row: &rusqlite::Row) -> Result<ReverseDns, rusqlite::Error> {
+    let nameservers: String = row.get(0)?;
+    Ok(ReverseDns {
+        nameservers: nameservers.split_whitespace().map(str::to_string).collect(),
+        ptr_template: row.get(1)?,
+    })
+}
+
+/// Record a change of the reverse zones
+pub(super) fn increment_serial(tx: &rusqlite::Transaction) -> Result<(), Error> {
+    tx.execute("UPDATE reverse_dns_serial SET serial = (serial + 1) % 4294967296 WHERE id = 0", [])?;
+    Ok(())
+}
+
+/// Set the reverse DNS of the assignment `assignment_id` of `family` within `tx`
+pub(super) fn write_reverse_dns(tx: &rusqlite::Transaction, family: ResourceFamily, assignment_id: i32, reverse_dns: &ReverseDns) -> Result<(), Error> {
+    let table = reverse_dns_table(family)?;
+    let reverse_dns = reverse_dns.normalize()?;
+
+    let (query, bits) = match family {
+        ResourceFamily::Ipv4 => ("SELECT ipv4_prefix_len FROM assignment_ipv4 WHERE id = ?", 32),
+        _ => ("SELECT ipv6_prefix_len FROM assignment_ipv6 WHERE id = ?", 128),
+    };
+    let prefix_len: i32 = match tx.query_row(query, rusqlite::params![assignment_id], |row| row.get(0)) {
+        Ok(prefix_len) => prefix_len,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(Error::new(ErrorKind::NotFound, "Assignment not found".to_string())),
+        Err(e) => return Err(e.into()),
+    };
+    if reverse_dns.ptr_template.is_some() && bits - prefix_len > MAX_PTR_TEMPLATE_BITS as i32 {
+        return Err(Error::new(ErrorKind::Validation, format!("PTR templates are limited to assignments of at most 2^{} addresses; delegate larger ones", MAX_PTR_TEMPLATE_BITS)));
+    }
+
+    tx.execute(&format!("DELETE FROM {} WHERE assignment_id = ?", table), rusqlite::params![assignment_id])?;
+    if !reverse_dns.is_empty() {
+        tx.execute(
+            &format!("INSERT INTO {} (assignment_id, nameservers, ptr_template) VALUES (?, ?, ?)", table),
+            rusqlite::params![assignment_id, reverse_dns.nameservers.join(" "), reverse_dns.ptr_template],
+        )?;
+    }
+    increment_serial(tx)?;
+    Ok(())
+}
+
+impl ReverseDnsStore for SqliteReverseDnsStore {
+    fn get_reverse_dns(&self, family: ResourceFamily, assignment_id: i32) -> Result<ReverseDns, Error> {
+        let table = reverse_dns_table(family)?;
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT nameservers, ptr_template FROM {} WHERE assignment_id = ?", table))?;
+        let mut rows = stmt.query(rusqlite::params![assignment_id])?;
+        match rows.next()? {
+            Some(row) => Ok(reverse_dns_from_row(row)?),
+            None => Ok(ReverseDns::default()),
+        }
+    }
+
+    fn get_all_reverse_dns(&self, family: ResourceFamily) -> Result<HashMap<i32, ReverseDns>, Error> {
+        let table = reverse_dns_table(family)?;
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT nameservers, ptr_template, assignment_id FROM {}", table))?;
+        let mut rows = stmt.query([])?;
+        let mut all = HashMap::new();
+        while let Some(row) = rows.next()? {
+            all.insert(row.get(2)?, reverse_dns_from_row(row)?);
+        }
+        Ok(all)
+    }
+
+    fn set_reverse_dns(&self, family: ResourceFamily, assignment_id: i32, reverse_dns: &ReverseDns) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        write_reverse_dns(&tx, family, assignment_id, reverse_dns)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_serial(&self) -> Result<u32, Error> {
+        let conn = self.db.get_conn()?;
+        let serial: u32 = conn.query_row("SELECT serial FROM reverse_dns_serial WHERE id = 0", [], |row| row.get(0))?;
+        Ok(serial)
+    }
+
+    fn get_dns_update(&self, family: ResourceFamily, space_id: i32) -> Result<Option<DnsUpdate>, Error> {
+        let table = dns_update_table(family)?;
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT zone, server, key_name FROM {} WHERE space_id = ?", table))?;
+        let mut rows = stmt.query(rusqlite::params![space_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(DnsUpdate {
+                zone: row.get(0)?,
+                server: row.get(1)?,
+                key: row.get(2)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn set_dns_update(&self, family: ResourceFamily, space_id: i32, dns_update: Option<&DnsUpdate>) -> Result<(), Error> {
+        let table = dns_update_table(family)?;
+        let dns_update = dns_update.map(DnsUpdate::normalize).transpose()?;
+
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let query = match family {
+            ResourceFamily::Ipv4 => "SELECT COUNT(*) FROM assignment_space_ipv4 WHERE id = ?",
+            _ => "SELECT COUNT(*) FROM assignment_space_ipv6 WHERE id = ?",
+        };
+        let count: i32 = tx.query_row(query, rusqlite::params![space_id], |row| row.get(0))?;
+        if count == 0 {
+            return Err(Error::new(ErrorKind::NotFound, "Assignment space not found".to_string()));
+        }
+
+        tx.execute(&format!("DELETE FROM {} WHERE space_id = ?", table), rusqlite::params![space_id])?;
+        if let Some(dns_update) = dns_update {
+            tx.execute(
+                &format!("INSERT INTO {} (space_id, zone, server, key_name) VALUES (?, ?, ?, ?)", table),
+                rusqlite::params![space_id, dns_update.zone, dns_update.server, dns_update.key],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -3,9 +3,14 @@ use crate::db_sqlite::SqliteConnection;
 use crate::types::{Error, ErrorKind};
 
 use crate::ipv6::Ipv6AssignmentStore;
+use crate::dns::ReverseDns;
+use crate::user::ResourceFamily;
 
 use r2d2_sqlite::rusqlite;
 
+use super::sqlite_asn::check_origin_asn;
+use super::sqlite_dns::{increment_serial, write_reverse_dns};
+
 #[derive(Debug, Clone)]
 pub struct SqliteIpv6AssignmentStore {
     db: SqliteConnection,
@@ -17,6 +22,23 @@ impl SqliteIpv6AssignmentStore {
     }
 }
 
+/// Parameters selecting the objects that contain `prefix/prefix_len`, each after `leading`: the
+/// network of `prefix` at every prefix length up to `prefix_len`, as rows of
+/// `(ipv6_prefix, ipv6_prefix_len) IN (VALUES ...)`
+fn containing_params(leading: Option<i32>, prefix: crate::ipv6::RawIpv6Addr, prefix_len: u8) -> (String, Vec<rusqlite::types::Value>) {
+    use crate::ipv6::ipv6_network_address;
+    use rusqlite::types::Value;
+
+    let mut params: Vec<Value> = leading.into_iter().map(|id| Value::Integer(id as i64)).collect();
+    let mut rows = Vec::new();
+    for len in 0..=prefix_len.min(128) {
+        params.push(Value::Blob(ipv6_network_address(prefix, len).to_vec()));
+        params.push(Value::Integer(len as i64));
+        rows.push("(?, ?)");
+    }
+    (rows.join(", "), params)
+}
+
 impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
     fn get_space(&self, space_id: i32) -> Result<crate::ipv6::AssignmentSpaceIpv6, Error> {
         let conn = self.db.get_conn()?;
@@ -64,6 +86,26 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
         Ok(spaces)
     }
 
+    fn get_spaces_containing(&self, prefix: crate::ipv6::RawIpv6Addr, prefix_len: u8) -> Result<Vec<crate::ipv6::AssignmentSpaceIpv6>, Error> {
+        let (rows, params) = containing_params(None, prefix, prefix_len);
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT id, name, description, space_visibility, ipv6_prefix, ipv6_prefix_len FROM assignment_space_ipv6 WHERE (ipv6_prefix, ipv6_prefix_len) IN (VALUES {})", rows))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        let mut spaces = Vec::new();
+        while let Some(row) = rows.next()? {
+            let space = crate::ipv6::AssignmentSpaceIpv6 {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                space_visibility: row.get(3)?,
+                ipv6_prefix: row.get(4)?,
+                ipv6_prefix_len: row.get(5)?,
+            };
+            spaces.push(space);
+        }
+        Ok(spaces)
+    }
+
     fn create_space(&self, space: &crate::ipv6::AssignmentSpaceIpv6) -> Result<i32, Error> {
         use crate::ipv6::ipv6_network_address;
         use crate::ipv6::ipv6_broadcast_address;
@@ -86,7 +128,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping space exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping space exists".to_string()));
             }
         }
 
@@ -104,7 +146,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping space exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping space exists".to_string()));
             }
         }
 
@@ -118,6 +160,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             ])?;
         }
         
+        increment_serial(&tx)?;
         tx.commit()?;
         
         let id = conn.last_insert_rowid();
@@ -125,17 +168,21 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
     }
 
     fn update_space(&self, id: i32, name: &str, description: &str) -> Result<(), Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("UPDATE assignment_space_ipv6 SET name = ?, description = ? WHERE id = ?")?;
-        stmt.execute(rusqlite::params![name, description, id])?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE assignment_space_ipv6 SET name = ?, description = ? WHERE id = ?", rusqlite::params![name, description, id])?;
+        increment_serial(&tx)?;
+        tx.commit()?;
         Ok(())
     }
 
     fn delete_space(&self, space_id: i32) -> Result<(), Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("DELETE FROM assignment_space_ipv6 WHERE id = ?")?;
-        stmt.execute(rusqlite::params![space_id])?;
-        Ok(())    
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM assignment_space_ipv6 WHERE id = ?", rusqlite::params![space_id])?;
+        increment_serial(&tx)?;
+        tx.commit()?;
+        Ok(())
     }
     fn get_pool(&self, pool_id: i32) -> Result<crate::ipv6::AssignmentPoolIpv6, Error> {
         let conn = self.db.get_conn()?;
@@ -185,6 +232,27 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
         Ok(pools)
     }
 
+    fn get_pools_containing(&self, space_id: i32, prefix: crate::ipv6::RawIpv6Addr, prefix_len: u8) -> Result<Vec<crate::ipv6::AssignmentPoolIpv6>, Error> {
+        let (rows, params) = containing_params(Some(space_id), prefix, prefix_len);
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT id, name, description, pool_visibility, ipv6_prefix, ipv6_prefix_len, assignment_space_id FROM assignment_pool_ipv6 WHERE assignment_space_id = ? AND (ipv6_prefix, ipv6_prefix_len) IN (VALUES {})", rows))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        let mut pools = Vec::new();
+        while let Some(row) = rows.next()? {
+            let pool = crate::ipv6::AssignmentPoolIpv6 {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                pool_visibility: row.get(3)?,
+                ipv6_prefix: row.get(4)?,
+                ipv6_prefix_len: row.get(5)?,
+                assignment_space_id: row.get(6)?,
+            };
+            pools.push(pool);
+        }
+        Ok(pools)
+    }
+
     fn create_pool(&self, pool: &crate::ipv6::AssignmentPoolIpv6) -> Result<i32, Error> {
         use crate::ipv6::ipv6_network_address;
         use crate::ipv6::ipv6_broadcast_address;
@@ -212,7 +280,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             };
 
             if pool_network < space_network || pool_broadcast > space_broadcast {
-                return Err(Error::new(ErrorKind::InvalidInput, "Pool is not contained within the parent space".to_string()));
+                return Err(Error::new(ErrorKind::Validation, "Pool is not contained within the parent space".to_string()));
             }
         }
 
@@ -231,7 +299,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping pool exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping pool exists".to_string()));
             }
         }
 
@@ -249,7 +317,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping pool exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping pool exists".to_string()));
             }
         }
 
@@ -277,14 +345,16 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
     }
 
     fn delete_pool(&self, pool_id: i32) -> Result<(), Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("DELETE FROM assignment_pool_ipv6 WHERE id = ?")?;
-        stmt.execute(rusqlite::params![pool_id])?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM assignment_pool_ipv6 WHERE id = ?", rusqlite::params![pool_id])?;
+        increment_serial(&tx)?;
+        tx.commit()?;
         Ok(())
     }
     fn get_assignment(&self, assignment_id: i32) -> Result<crate::ipv6::AssignmentIpv6, Error> {
         let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("SELECT id, name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility FROM assignment_ipv6 WHERE id = ?")?;
+        let mut stmt = conn.prepare("SELECT id, name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility, origin_asn_id FROM assignment_ipv6 WHERE id = ?")?;
         let mut rows = stmt.query(rusqlite::params![assignment_id])?;
         let row = rows.next()?;
         let assignment = match row {
@@ -297,6 +367,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
                     ipv6_prefix_len: row.get(4)?,
                     assignment_pool_id: row.get(5)?,
                     assignment_visibility: row.get(6)?,
+                    origin_asn_id: row.get(7)?,
                 };
                 Some(assignment)
             },
@@ -312,7 +383,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
 
     fn get_assignments(&self, pool_id: i32) -> Result<Vec<crate::ipv6::AssignmentIpv6>, Error> {
         let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("SELECT id, name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility FROM assignment_ipv6 WHERE assignment_pool_id = ? ORDER BY ipv6_prefix ASC")?;
+        let mut stmt = conn.prepare("SELECT id, name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility, origin_asn_id FROM assignment_ipv6 WHERE assignment_pool_id = ? ORDER BY ipv6_prefix ASC")?;
         let mut rows = stmt.query(rusqlite::params![pool_id])?;
         let mut assignments = Vec::new();
         while let Some(row) = rows.next()? {
@@ -324,6 +395,29 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
                 ipv6_prefix_len: row.get(4)?,
                 assignment_pool_id: row.get(5)?,
                 assignment_visibility: row.get(6)?,
+                origin_asn_id: row.get(7)?,
+            };
+            assignments.push(assignment);
+        }
+        Ok(assignments)
+    }
+
+    fn get_assignments_containing(&self, pool_id: i32, prefix: crate::ipv6::RawIpv6Addr, prefix_len: u8) -> Result<Vec<crate::ipv6::AssignmentIpv6>, Error> {
+        let (rows, params) = containing_params(Some(pool_id), prefix, prefix_len);
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare(&format!("SELECT id, name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility, origin_asn_id FROM assignment_ipv6 WHERE assignment_pool_id = ? AND (ipv6_prefix, ipv6_prefix_len) IN (VALUES {})", rows))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        let mut assignments = Vec::new();
+        while let Some(row) = rows.next()? {
+            let assignment = crate::ipv6::AssignmentIpv6 {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                ipv6_prefix: row.get(3)?,
+                ipv6_prefix_len: row.get(4)?,
+                assignment_pool_id: row.get(5)?,
+                assignment_visibility: row.get(6)?,
+                origin_asn_id: row.get(7)?,
             };
             assignments.push(assignment);
         }
@@ -331,6 +425,10 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
     }
 
     fn create_assignment(&self, assignment: &crate::ipv6::AssignmentIpv6) -> Result<i32, Error> {
+        self.create_assignment_with_reverse_dns(assignment, &ReverseDns::default())
+    }
+
+    fn create_assignment_with_reverse_dns(&self, assignment: &crate::ipv6::AssignmentIpv6, reverse_dns: &ReverseDns) -> Result<i32, Error> {
         use crate::ipv6::ipv6_network_address;
         use crate::ipv6::ipv6_broadcast_address;
 
@@ -357,7 +455,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             };
 
             if assignment_network < pool_network || assignment_broadcast > pool_broadcast {
-            return Err(Error::new(ErrorKind::InvalidInput, "Assignment is not contained within the parent pool".to_string()));
+            return Err(Error::new(ErrorKind::Validation, "Assignment is not contained within the parent pool".to_string()));
             }
         }
 
@@ -376,7 +474,7 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping assignment exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping assignment exists".to_string()));
             }
         }
 
@@ -394,38 +492,209 @@ impl Ipv6AssignmentStore for SqliteIpv6AssignmentStore {
             )?;
 
             if count > 0 {
-                return Err(Error::new(ErrorKind::InvalidInput, "Overlapping assignment exists".to_string()));
+                return Err(Error::new(ErrorKind::Conflict, "Overlapping assignment exists".to_string()));
             }
         }
 
+        if let Some(origin_asn_id) = assignment.origin_asn_id {
+            check_origin_asn(&tx, origin_asn_id)?;
+        }
+
         {
             let mut stmt = tx.prepare(
-                "INSERT INTO assignment_ipv6 (name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility) 
-                VALUES (?, ?, ?, ?, ?, ?)"
+                "INSERT INTO assignment_ipv6 (name, description, ipv6_prefix, ipv6_prefix_len, assignment_pool_id, assignment_visibility, origin_asn_id) 
+                VALUES (?, ?, ?, ?, ?, ?, ?)"
             )?;
             stmt.execute(rusqlite::params![
-                assignment.name, assignment.description, assignment.ipv6_prefix, assignment.ipv6_prefix_len, assignment.assignment_pool_id, assignment.assignment_visibility
+                assignment.name, assignment.description, assignment.ipv6_prefix, assignment.ipv6_prefix_len, assignment.assignment_pool_id, assignment.assignment_visibility, assignment.origin_asn_id
             ])?;
         }
 
-        tx.commit()?;
+        let id = tx.last_insert_rowid() as i32;
+        if !reverse_dns.is_empty() {
+            write_reverse_dns(&tx, ResourceFamily::Ipv6, id, reverse_dns)?;
+        }
 
-        let id = conn.last_insert_rowid();
-        Ok(id as i32)
+        tx.commit()?;
+        Ok(id)
     }
 
     fn update_assignment(&self, id: i32, name: &str, description: &str) -> Result<(), Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("UPDATE assignment_ipv6 SET name = ?, description = ? WHERE id = ?")?;
-        stmt.execute(rusqlite::params![name, description, id])?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE assignment_ipv6 SET name = ?, description = ? WHERE id = ?", rusqlite::params![name, description, id])?;
+        increment_serial(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update_assignment_origin_asn(&self, id: i32, origin_asn_id: Option<i32>) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        if let Some(origin_asn_id) = origin_asn_id {
+            check_origin_asn(&tx, origin_asn_id)?;
+        }
+        tx.execute("UPDATE assignment_ipv6 SET origin_asn_id = ? WHERE id = ?", rusqlite::params![origin_asn_id, id])?;
+        tx.commit()?;
         Ok(())
     }
 
     fn delete_assignment(&self, assignment_id: i32) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM assignment_ipv6 WHERE id = ?", rusqlite::params![assignment_id])?;
+        increment_serial(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_host(&self, host_id: i32) -> Result<crate::ipv6::HostIpv6, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, assignment_id, ipv6_address, hostname, mac_address, description, gateway FROM host_ipv6 WHERE id = ?")?;
+        let mut rows = stmt.query(rusqlite::params![host_id])?;
+        match rows.next()? {
+            Some(row) => Ok(host_from_row(row)?),
+            None => Err(Error::new(ErrorKind::NotFound, "Host not found".to_string())),
+        }
+    }
+
+    fn get_hosts(&self, assignment_id: i32) -> Result<Vec<crate::ipv6::HostIpv6>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, assignment_id, ipv6_address, hostname, mac_address, description, gateway FROM host_ipv6 WHERE assignment_id = ? ORDER BY ipv6_address ASC")?;
+        let mut rows = stmt.query(rusqlite::params![assignment_id])?;
+        let mut hosts = Vec::new();
+        while let Some(row) = rows.next()? {
+            hosts.push(host_from_row(row)?);
+        }
+        Ok(hosts)
+    }
+
+    fn create_host(&self, host: &crate::ipv6::HostIpv6) -> Result<i32, Error> {
+        let host = host.normalize()?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        check_host(&tx, &host, None)?;
+        tx.execute(
+            "INSERT INTO host_ipv6 (assignment_id, ipv6_address, hostname, mac_address, description, gateway) VALUES (?, ?, ?, ?, ?, ?)",
+            rusqlite::params![host.assignment_id, host.ipv6_address, host.hostname, host.mac_address, host.description, host.gateway],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.commit()?;
+        Ok(id as i32)
+    }
+
+    fn update_host(&self, id: i32, host: &crate::ipv6::HostIpv6) -> Result<(), Error> {
+        let mut host = host.normalize()?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        host.assignment_id = match tx.query_row("SELECT assignment_id FROM host_ipv6 WHERE id = ?", rusqlite::params![id], |row| row.get(0)) {
+            Ok(assignment_id) => assignment_id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(Error::new(ErrorKind::NotFound, "Host not found".to_string())),
+            Err(e) => return Err(e.into()),
+        };
+        check_host(&tx, &host, Some(id))?;
+        tx.execute(
+            "UPDATE host_ipv6 SET ipv6_address = ?, hostname = ?, mac_address = ?, description = ?, gateway = ? WHERE id = ?",
+            rusqlite::params![host.ipv6_address, host.hostname, host.mac_address, host.description, host.gateway, id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_host(&self, host_id: i32) -> Result<(), Error> {
         let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("DELETE FROM assignment_ipv6 WHERE id = ?")?;
-        stmt.execute(rusqlite::params![assignment_id])?;
+        conn.execute("DELETE FROM host_ipv6 WHERE id = ?", rusqlite::params![host_id])?;
         Ok(())
     }
+
+    fn next_free_host(&self, assignment_id: i32) -> Result<Option<crate::ipv6::RawIpv6Addr>, Error> {
+        use crate::ipv6::{ipv6_network_address, ipv6_broadcast_address, ipv6_reserved_addresses};
+
+        let conn = self.db.get_conn()?;
+        let (prefix, prefix_len) = assignment_prefix(&conn, assignment_id)?;
+        let mut stmt = conn.prepare("SELECT ipv6_address, gateway FROM host_ipv6 WHERE assignment_id = ?")?;
+        let mut rows = stmt.query(rusqlite::params![assignment_id])?;
+        let mut taken = std::collections::HashSet::new();
+        let mut has_gateway = false;
+        while let Some(row) = rows.next()? {
+            let address: crate::ipv6::RawIpv6Addr = row.get(0)?;
+            taken.insert(address);
+            has_gateway |= row.get::<_, bool>(1)?;
+        }
+
+        let reserved = ipv6_reserved_addresses(prefix, prefix_len);
+        // Point-to-point networks have no gateway to keep an address for
+        let mut keep_gateway = !has_gateway && !reserved.is_empty();
+        let first = u128::from_be_bytes(ipv6_network_address(prefix, prefix_len));
+        let last = u128::from_be_bytes(ipv6_broadcast_address(prefix, prefix_len));
+        for address in first..=last {
+            let address = address.to_be_bytes();
+            if reserved.contains(&address) {
+                continue;
+            }
+            if keep_gateway {
+                keep_gateway = false;
+                continue;
+            }
+            if !taken.contains(&address) {
+                return Ok(Some(address));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn host_from_row(row: &rusqlite::Row) -> Result<crate::ipv6::HostIpv6, rusqlite::Error> {
+    Ok(crate::ipv6::HostIpv6 {
+        id: row.get(0)?,
+        assignment_id: row.get(1)?,
+        ipv6_address: row.get(2)?,
+        hostname: row.get(3)?,
+        mac_address: row.get(4)?,
+        description: row.get(5)?,
+        gateway: row.get(6)?,
+    })
+}
+
+/// Prefix and prefix length of an assignment
+fn assignment_prefix(conn: &rusqlite::Connection, assignment_id: i32) -> Result<(crate::ipv6::RawIpv6Addr, u8), Error> {
+    match conn.query_row("SELECT ipv6_prefix, ipv6_prefix_len FROM assignment_ipv6 WHERE id = ?", rusqlite::params![assignment_id], |row| Ok((row.get(0)?, row.get::<_, u8>(1)?))) {
+        Ok(prefix) => Ok(prefix),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::new(ErrorKind::NotFound, "Assignment not found".to_string())),
+        Err(e) => Err(e.into()),
+    }
 }
 
+/// Check that `host` fits in its assignment next to the other hosts there, except `host_id` itself
+fn check_host(tx: &rusqlite::Transaction, host: &crate::ipv6::HostIpv6, host_id: Option<i32>) -> Result<(), Error> {
+    use crate::ipv6::{ipv6_network_address, ipv6_reserved_addresses};
+
+    let (prefix, prefix_len) = assignment_prefix(tx, host.assignment_id)?;
+    if ipv6_network_address(host.ipv6_address, prefix_len) != prefix {
+        return Err(Error::new(ErrorKind::Validation, "Host address is not within the assignment".to_string()));
+    }
+    if ipv6_reserved_addresses(prefix, prefix_len).contains(&host.ipv6_address) {
+        return Err(Error::new(ErrorKind::Validation, "Reserved addresses of the network cannot be given to hosts".to_string()));
+    }
+
+    let host_id = host_id.unwrap_or(0);
+    let taken: i32 = tx.query_row(
+        "SELECT COUNT(*) FROM host_ipv6 WHERE assignment_id = ? AND ipv6_address = ? AND id != ?",
+        rusqlite::params![host.assignment_id, host.ipv6_address, host_id],
+        |row| row.get(0),
+    )?;
+    if taken > 0 {
+        return Err(Error::new(ErrorKind::Conflict, "Address is taken by another host".to_string()));
+    }
+    if host.gateway {
+        let gateways: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM host_ipv6 WHERE assignment_id = ? AND gateway = 1 AND id != ?",
+            rusqlite::params![host.assignment_id, host_id],
+            |row| row.get(0),
+        )?;
+        if gateways > 0 {
+            return Err(Error::new(ErrorKind::Conflict, "Assignment already has a gateway".to_string()));
+        }
+    }
+    Ok(())
+}
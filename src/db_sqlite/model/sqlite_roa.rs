@@ -0,0 +1,175 @@
+use crate::db_sqlite::SqliteConnection;
+use crate::types::{Error, ErrorKind};
+
+use crate::roa::{Roa, RoaStore};
+
+use r2d2_sqlite::rusqlite;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone)]
+pub struct SqliteRoaStore {
+    db: SqliteConnection,
+}
+
+impl SqliteRoaStore {
+    pub fn new(db: SqliteConnection) -> Self {
+        SqliteRoaStore { db }
+    }
+}
+
+/// IP version and prefix bytes of an address
+fn prefix_to_sql(prefix: IpAddr) -> (i32, Vec<u8>) {
+    match prefix {
+        IpAddr::V4(addr) => (4, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (6, addr.octets().to_vec()),
+    }
+}
+
+fn roa_from_row(row: &rusqlite::Row) -> Result<Roa, rusqlite::Error> {
+    let prefix: Vec<u8> = row.get(1)?;
+    let prefix = if let Ok(octets) = <[u8; 4]>::try_from(prefix.as_slice()) {
+        IpAddr::V4(Ipv4Addr::from(octets))
+    } else if let Ok(octets) = <[u8; 16]>::try_from(prefix.as_slice()) {
+        IpAddr::V6(Ipv6Addr::from(octets))
+    } else {
+        return Err(rusqlite::Error::InvalidColumnType(1, "prefix".to_string(), rusqlite::types::Type::Blob));
+    };
+    Ok(Roa {
+        id: row.get(0)?,
+        prefix,
+        prefix_len: row.get(2)?,
+        max_length: row.get(3)?,
+        asn: row.get(4)?,
+        description: row.get(5)?,
+    })
+}
+
+/// Check that `roa` is within one of our assignment spaces
+fn check_in_space(tx: &rusqlite::Transaction, roa: &Roa) -> Result<(), Error> {
+    let contained = match roa.prefix {
+        IpAddr::V4(addr) => {
+            use crate::ipv4::ipv4_network_address;
+
+            let mut stmt = tx.prepare("SELECT ipv4_prefix, ipv4_prefix_len FROM assignment_space_ipv4")?;
+            let mut rows = stmt.query([])?;
+            let mut contained = false;
+            while let Some(row) = rows.next()? {
+                let space_prefix: [u8; 4] = row.get(0)?;
+                let space_prefix_len: u8 = row.get(1)?;
+                if space_prefix_len <= roa.prefix_len && ipv4_network_address(addr.octets(), space_prefix_len) == ipv4_network_address(space_prefix, space_prefix_len) {
+                    contained = true;
+                    break;
+                }
+            }
+            contained
+        },
+        IpAddr::V6(addr) => {
+            use crate::ipv6::ipv6_network_address;
+
+            let mut stmt = tx.prepare("SELECT ipv6_prefix, ipv6_prefix_len FROM assignment_space_ipv6")?;
+            let mut rows = stmt.query([])?;
+            let mut contained = false;
+            while let Some(row) = rows.next()? {
+                let space_prefix: [u8; 16] = row.get(0)?;
+                let space_prefix_len: u8 = row.get(1)?;
+                if space_prefix_len <= roa.prefix_len && ipv6_network_address(addr.octets(), space_prefix_len) == ipv6_network_address(space_prefix, space_prefix_len) {
+                    contained = true;
+                    break;
+                }
+            }
+            contained
+        },
+    };
+    if !contained {
+        return Err(Error::new(ErrorKind::Validation, "ROA prefix is not contained within any assignment space".to_string()));
+    }
+    Ok(())
+}
+
+/// Record a change of the ROA set
+fn increment_serial(tx: &rusqlite::Transaction) -> Result<(), Error> {
+    tx.execute("UPDATE roa_serial SET serial = (serial + 1) % 4294967296 WHERE id = 0", [])?;
+    Ok(())
+}
+
+impl RoaStore for SqliteRoaStore {
+    fn get_roa(&self, roa_id: i32) -> Result<Roa, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, prefix, prefix_len, max_length, asn, description FROM roa WHERE id = ?")?;
+        let mut rows = stmt.query(rusqlite::params![roa_id])?;
+        match rows.next()? {
+            Some(row) => Ok(roa_from_row(row)?),
+            None => Err(Error::new(ErrorKind::NotFound, "ROA not found".to_string())),
+        }
+    }
+
+    fn get_roas(&self) -> Result<Vec<Roa>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, prefix, prefix_len, max_length, asn, description FROM roa ORDER BY ip_version ASC, prefix ASC, prefix_len ASC, max_length ASC, asn ASC")?;
+        let mut rows = stmt.query([])?;
+        let mut roas = Vec::new();
+        while let Some(row) = rows.next()? {
+            roas.push(roa_from_row(row)?);
+        }
+        Ok(roas)
+    }
+
+    fn create_roa(&self, roa: &Roa) -> Result<i32, Error> {
+        let roa = roa.normalize()?;
+
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        check_in_space(&tx, &roa)?;
+        {
+            let (ip_version, prefix) = prefix_to_sql(roa.prefix);
+            let mut stmt = tx.prepare(
+                "INSERT INTO roa (ip_version, prefix, prefix_len, max_length, asn, description)
+                VALUES (?, ?, ?, ?, ?, ?)"
+            )?;
+            stmt.execute(rusqlite::params![
+                ip_version, prefix, roa.prefix_len, roa.max_length, roa.asn, roa.description
+            ])?;
+        }
+        let id = tx.last_insert_rowid();
+        increment_serial(&tx)?;
+        tx.commit()?;
+
+        Ok(id as i32)
+    }
+
+    fn update_roa(&self, id: i32, roa: &Roa) -> Result<(), Error> {
+        let roa = roa.normalize()?;
+
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        check_in_space(&tx, &roa)?;
+        let (ip_version, prefix) = prefix_to_sql(roa.prefix);
+        let updated = tx.execute(
+            "UPDATE roa SET ip_version = ?, prefix = ?, prefix_len = ?, max_length = ?, asn = ?, description = ? WHERE id = ?",
+            rusqlite::params![ip_version, prefix, roa.prefix_len, roa.max_length, roa.asn, roa.description, id],
+        )?;
+        if updated == 0 {
+            return Err(Error::new(ErrorKind::NotFound, "ROA not found".to_string()));
+        }
+        increment_serial(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_roa(&self, roa_id: i32) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        if tx.execute("DELETE FROM roa WHERE id = ?", rusqlite::params![roa_id])? > 0 {
+            increment_serial(&tx)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_serial(&self) -> Result<u32, Error> {
+        let conn = self.db.get_conn()?;
+        let serial: u32 = conn.query_row("SELECT serial FROM roa_serial WHERE id = 0", [], |row| row.get(0))?;
+        Ok(serial)
+    }
+}
@@ -3,12 +3,35 @@ use crate::db_sqlite::SqliteConnection;
 use crate::types::{Error, ErrorKind};
 
 use crate::user::UserStore;
+use crate::user::{
+    ApiToken,
+    LoginAttempt,
+    LoginAttemptStart,
+    LoginFailures,
+    LoginOutcome,
+    LoginThrottleKey,
+    LoginThrottlePolicy,
+    NewApiToken,
+    ResourceFamily,
+    TokenScope,
+    TotpEnrolment,
+    TotpStatus,
+    UserInfo,
+    UserRole,
+    longest_login_lockout,
+    unix_time_now,
+};
+
+use crate::totp;
 
 use r2d2_sqlite::rusqlite;
 
 use crate::user::{
     hash_password,
     verify_password,
+    hash_api_key,
+    verify_api_key,
+    api_key_prefix,
 };
 
 
@@ -20,6 +43,7 @@ use argon2::password_hash::{
 
 // Structs for tables
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct User {
     pub id: i32,
@@ -27,6 +51,50 @@ pub struct User {
     pub hashed_password: String,
 }
 
+/// Login attempts older than this (in seconds) are pruned
+const LOGIN_ATTEMPT_RETENTION: i64 = 60 * 60 * 24 * 30;
+
+// Counted failures: not cleared, within the window, and (for usernames) after the last success.
+const LOGIN_FAILURES_BY_USERNAME: &str = "SELECT a.username, COUNT(*), MAX(a.time) FROM login_attempt a WHERE a.outcome = 1 AND a.cleared = 0 AND a.time > ?1 AND (?2 IS NULL OR a.username = ?2) AND a.id > COALESCE((SELECT MAX(s.id) FROM login_attempt s WHERE s.username = a.username AND s.outcome = 0), 0) GROUP BY a.username";
+const LOGIN_FAILURES_BY_SOURCE: &str = "SELECT a.source, COUNT(*), MAX(a.time) FROM login_attempt a WHERE a.outcome = 1 AND a.cleared = 0 AND a.time > ?1 AND a.source IS NOT NULL AND (?2 IS NULL OR a.source = ?2) GROUP BY a.source";
+
+const API_TOKEN_COLUMNS: &str = "api_key.id, api_key.name, api_key.created_at, api_key.expires_at, api_key.last_used_at, api_key.read_only, api_key.families";
+
+fn api_token_from_row(row: &rusqlite::Row, offset: usize) -> Result<ApiToken, rusqlite::Error> {
+    let families: Option<String> = row.get(offset + 6)?;
+    let families = families.map(|families| {
+        families.split(',').filter_map(|family| family.parse::<ResourceFamily>().ok()).collect()
+    });
+    Ok(ApiToken {
+        id: row.get(offset)?,
+        name: row.get(offset + 1)?,
+        created_at: row.get(offset + 2)?,
+        expires_at: row.get(offset + 3)?,
+        last_used_at: row.get(offset + 4)?,
+        scope: TokenScope {
+            read_only: row.get(offset + 5)?,
+            families,
+        },
+    })
+}
+
+fn families_to_sql(scope: &TokenScope) -> Option<String> {
+    scope.families.as_ref().map(|families| {
+        families.iter().map(|family| family.as_str()).collect::<Vec<_>>().join(",")
+    })
+}
+
+
+/// TOTP enrolment of a user
+struct TotpRow {
+    secret: Vec<u8>,
+
+    /// Whether the enrolment is confirmed
+    enabled: bool,
+
+    /// Last step whose code was accepted, so that codes cannot be replayed
+    last_used_step: Option<i64>,
+}
 
 #[derive(Debug, Clone)]
 pub struct SqliteUserStore {
@@ -63,104 +131,552 @@ impl SqliteUserStore {
         Ok(result)
     }
 
-    fn update_user(&self, name: &str, hashed_password: &str) -> Result<i32, Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("INSERT OR REPLACE INTO user (name, hashed_password) VALUES (?, ?)")?;
-        let id = stmt.insert(rusqlite::params![name, hashed_password])?;
+    /// Update (or create) a user, revoking all of its API tokens except `keep_token_id`.
+    fn update_user(&self, name: &str, hashed_password: &str, keep_token_id: Option<i32>) -> Result<i32, Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let id = match Self::get_user_id(&tx, name) {
+            Ok(id) => {
+                tx.execute("UPDATE user SET hashed_password = ? WHERE id = ?", rusqlite::params![hashed_password, id])?;
+                tx.execute("DELETE FROM api_key WHERE user_id = ? AND id IS NOT ?", rusqlite::params![id, keep_token_id])?;
+                id
+            },
+            Err(_) => {
+                // The first admin is bootstrapped by creating a user while there is none.
+                let role = if Self::count_admins(&tx)? == 0 { UserRole::Admin } else { UserRole::User };
+                Self::insert_user(&tx, name, hashed_password, role)?
+            },
+        };
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn insert_user(conn: &rusqlite::Connection, name: &str, hashed_password: &str, role: UserRole) -> Result<i32, Error> {
+        let mut stmt = conn.prepare("INSERT INTO user (name, hashed_password, role, created_at) VALUES (?, ?, ?, ?)")?;
+        let id = stmt.insert(rusqlite::params![name, hashed_password, role, unix_time_now()])?;
         Ok((id & 0x7FFFFFFF) as i32)
     }
 
-    /// Update the password for a user. If the user does not exist, it will be created.
+    fn count_admins(conn: &rusqlite::Connection) -> Result<i64, Error> {
+        let count = conn.query_row("SELECT COUNT(*) FROM user WHERE role = ?", rusqlite::params![UserRole::Admin], |row| row.get(0))?;
+        Ok(count)
+    }
+
+    fn get_role(conn: &rusqlite::Connection, username: &str) -> Result<UserRole, Error> {
+        let mut stmt = conn.prepare("SELECT role FROM user WHERE name = ?")?;
+        let mut rows = stmt.query(rusqlite::params![username])?;
+        match rows.next()? {
+            Some(row) => Ok(row.get(0)?),
+            None => Err(Error::new(ErrorKind::NotFound,"User not found".to_string())),
+        }
+    }
+
+    /// Fails if `username` is the only admin left
+    fn ensure_not_last_admin(conn: &rusqlite::Connection, username: &str) -> Result<(), Error> {
+        if Self::get_role(conn, username)? == UserRole::Admin && Self::count_admins(conn)? <= 1 {
+            return Err(Error::new(ErrorKind::Conflict,"Cannot remove the last admin".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn create_user(&self, username: &str, password: &str, role: UserRole) -> Result<(), Error> {
+        let hashed_password = hash_password(password).map_err(|_| Error::new(ErrorKind::InternalError,"Password hashing failed".to_string()))?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        if Self::get_user_id(&tx, username).is_ok() {
+            return Err(Error::new(ErrorKind::Conflict,"User already exists".to_string()));
+        }
+        Self::insert_user(&tx, username, &hashed_password, role)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn create_oidc_user(&self, username: &str, password: &str, role: UserRole, issuer: &str, subject: &str) -> Result<(), Error> {
+        let hashed_password = hash_password(password).map_err(|_| Error::new(ErrorKind::InternalError,"Password hashing failed".to_string()))?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        if Self::get_user_id(&tx, username).is_ok() {
+            return Err(Error::new(ErrorKind::Conflict,"User already exists".to_string()));
+        }
+        let user_id = Self::insert_user(&tx, username, &hashed_password, role)?;
+        tx.execute("INSERT INTO oidc_link (issuer, subject, user_id, created_at) VALUES (?, ?, ?, ?)", rusqlite::params![issuer, subject, user_id, unix_time_now()])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_oidc_user(&self, issuer: &str, subject: &str) -> Result<Option<String>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT user.name FROM oidc_link JOIN user ON user.id = oidc_link.user_id WHERE oidc_link.issuer = ? AND oidc_link.subject = ?")?;
+        let mut rows = stmt.query(rusqlite::params![issuer, subject])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn link_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        let linked: Option<i32> = {
+            let mut stmt = tx.prepare("SELECT user_id FROM oidc_link WHERE issuer = ? AND subject = ?")?;
+            let mut rows = stmt.query(rusqlite::params![issuer, subject])?;
+            match rows.next()? {
+                Some(row) => Some(row.get(0)?),
+                None => None,
+            }
+        };
+        match linked {
+            Some(linked) if linked == user_id => return Ok(()),
+            Some(_) => return Err(Error::new(ErrorKind::Conflict,"Identity is linked to another user".to_string())),
+            None => {},
+        }
+        tx.execute("INSERT INTO oidc_link (issuer, subject, user_id, created_at) VALUES (?, ?, ?, ?)", rusqlite::params![issuer, subject, user_id, unix_time_now()])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn unlink_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        let count = tx.execute("DELETE FROM oidc_link WHERE issuer = ? AND subject = ? AND user_id = ?", rusqlite::params![issuer, subject, user_id])?;
+        if count == 0 {
+            return Err(Error::new(ErrorKind::NotFound,"Identity is not linked to the user".to_string()));
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_user_role(&self, username: &str) -> Result<UserRole, Error> {
+        let conn = self.db.get_conn()?;
+        Self::get_role(&conn, username)
+    }
+
+    pub fn set_role(&self, username: &str, role: UserRole) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        if role != UserRole::Admin {
+            Self::ensure_not_last_admin(&tx, username)?;
+        }
+        let count = tx.execute("UPDATE user SET role = ? WHERE name = ?", rusqlite::params![role, username])?;
+        if count == 0 {
+            return Err(Error::new(ErrorKind::NotFound,"User not found".to_string()));
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn query_user_info(conn: &rusqlite::Connection, username: Option<&str>) -> Result<Vec<UserInfo>, Error> {
+        let mut stmt = conn.prepare("SELECT user.name, user.role, user.created_at, COUNT(CASE WHEN api_key.expires_at IS NULL OR api_key.expires_at > ?2 THEN api_key.id END), MAX(api_key.last_used_at) FROM user LEFT JOIN api_key ON user.id = api_key.user_id WHERE ?1 IS NULL OR user.name = ?1 GROUP BY user.id ORDER BY user.name ASC")?;
+        let mut rows = stmt.query(rusqlite::params![username, unix_time_now()])?;
+        let mut users = Vec::new();
+        while let Some(row) = rows.next()? {
+            users.push(UserInfo {
+                username: row.get(0)?,
+                role: row.get(1)?,
+                created_at: row.get(2)?,
+                api_token_count: row.get(3)?,
+                last_active_at: row.get(4)?,
+            });
+        }
+        Ok(users)
+    }
+
+    pub fn get_user(&self, username: &str) -> Result<UserInfo, Error> {
+        let conn = self.db.get_conn()?;
+        Self::query_user_info(&conn, Some(username))?.pop().ok_or_else(|| Error::new(ErrorKind::NotFound,"User not found".to_string()))
+    }
+
+    pub fn list_users_with_metadata(&self) -> Result<Vec<UserInfo>, Error> {
+        let conn = self.db.get_conn()?;
+        Self::query_user_info(&conn, None)
+    }
+
+    /// Update the password for a user. If the user does not exist, it will be created
+    /// (as an admin if there is no admin yet).
+    /// All existing API tokens of the user are revoked.
     pub fn set_password(&self, username: &str, password: &str) -> Result<(), Error> {
+        self.change_password(username, password, None)
+    }
+
+    /// Update the password for an existing user, revoking all API tokens except `keep_token_id`.
+    pub fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error> {
         let hashed_password = hash_password(password).map_err(|_| Error::new(ErrorKind::InternalError,"Password hashing failed".to_string()))?;
-        self.update_user(username, &hashed_password)?;
+        self.update_user(username, &hashed_password, keep_token_id)?;
         Ok(())
     }
 
     pub fn delete_user(&self, username: &str) -> Result<(), Error> {
-        let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("DELETE FROM user WHERE name = ?")?;
-        stmt.execute(rusqlite::params![username])?;
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        Self::ensure_not_last_admin(&tx, username)?;
+        tx.execute("DELETE FROM api_key WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.execute("DELETE FROM user_totp WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.execute("DELETE FROM user_recovery_code WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.execute("DELETE FROM oidc_link WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.execute("DELETE FROM user WHERE id = ?", rusqlite::params![user_id])?;
+        tx.commit()?;
         Ok(())
     }
 
+    fn get_user_id(conn: &rusqlite::Connection, username: &str) -> Result<i32, Error> {
+        let mut stmt = conn.prepare("SELECT id FROM user WHERE name = ?")?;
+        let mut rows = stmt.query(rusqlite::params![username])?;
+        match rows.next()? {
+            Some(row) => Ok(row.get(0)?),
+            None => Err(Error::new(ErrorKind::NotFound,"User not found".to_string())),
+        }
+    }
+
+    fn get_api_key_secret(&self, conn: &rusqlite::Connection) -> Result<Vec<u8>, Error> {
+        Ok(self.db.api_key_secrets(conn)?.swap_remove(0))
+    }
+
     pub fn generate_api_key(&self, username: &str) -> Result<String, Error> {
+        let token = self.create_api_token(username, "default", None, &TokenScope::default())?;
+        Ok(token.secret)
+    }
+
+    pub fn create_api_token(&self, username: &str, name: &str, expires_at: Option<i64>, scope: &TokenScope) -> Result<NewApiToken, Error> {
         let mut conn = self.db.get_conn()?;
         let tx = conn.transaction()?;
-        let user = {
-            let mut stmt = tx.prepare("SELECT id, name, hashed_password FROM user WHERE name = ?")?;
-            let mut rows = stmt.query(rusqlite::params![username])?;
-            let row = rows.next()?;
-            let user = match row {
-                Some(row) => {
-                    let user = User {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        hashed_password: row.get(2)?,
-                    };
-                    Some(user)
-                },
-                None => None,
-            };
-            user
+        let user_id = Self::get_user_id(&tx, username)?;
+        let created_at = unix_time_now();
+        // Every login creates a token, so expired ones are cleaned up here.
+        tx.execute("DELETE FROM api_key WHERE expires_at <= ?", rusqlite::params![created_at])?;
+
+        let mut api_key = [0u8; 32];
+        OsRng.fill_bytes(&mut api_key);
+        let api_key = hex::encode(api_key);
+        let secret = self.get_api_key_secret(&tx)?;
+        let key_hash = hash_api_key(&secret, &api_key);
+        let id = {
+            let mut stmt = tx.prepare("INSERT INTO api_key (key_prefix, key_hash, user_id, name, created_at, expires_at, read_only, families) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?;
+            stmt.insert(rusqlite::params![api_key_prefix(&api_key), key_hash, user_id, name, created_at, expires_at, scope.read_only, families_to_sql(scope)])?
         };
-        let user = if let Some(user) = user {
-            user
+        tx.commit()?;
+
+        let token = ApiToken {
+            id: id as i32,
+            name: name.to_string(),
+            created_at,
+            expires_at,
+            last_used_at: None,
+            scope: scope.clone(),
+        };
+        Ok(NewApiToken {
+            token,
+            secret: api_key,
+        })
+    }
+
+    pub fn list_api_tokens(&self, username: &str) -> Result<Vec<ApiToken>, Error> {
+        let conn = self.db.get_conn()?;
+        let user_id = Self::get_user_id(&conn, username)?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM api_key WHERE user_id = ? AND (expires_at IS NULL OR expires_at > ?) ORDER BY id ASC", API_TOKEN_COLUMNS))?;
+        let mut rows = stmt.query(rusqlite::params![user_id, unix_time_now()])?;
+        let mut tokens = Vec::new();
+        while let Some(row) = rows.next()? {
+            tokens.push(api_token_from_row(row, 0)?);
+        }
+        Ok(tokens)
+    }
+
+    pub fn revoke_api_token(&self, username: &str, token_id: i32) -> Result<(), Error> {
+        let conn = self.db.get_conn()?;
+        let user_id = Self::get_user_id(&conn, username)?;
+        let mut stmt = conn.prepare("DELETE FROM api_key WHERE id = ? AND user_id = ?")?;
+        let count = stmt.execute(rusqlite::params![token_id, user_id])?;
+        if count == 0 {
+            return Err(Error::new(ErrorKind::NotFound,"API token not found".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn get_api_token(&self, api_key: &str) -> Result<Option<(String, ApiToken)>, Error> {
+        let conn = self.db.get_conn()?;
+        let secrets = self.db.api_key_secrets(&conn)?;
+        let token = {
+            let mut stmt = conn.prepare(&format!("SELECT api_key.key_hash, user.name, {} FROM user JOIN api_key ON user.id = api_key.user_id WHERE api_key.key_prefix = ?", API_TOKEN_COLUMNS))?;
+            let mut rows = stmt.query(rusqlite::params![api_key_prefix(api_key)])?;
+            let mut token = None;
+            while let Some(row) = rows.next()? {
+                let key_hash: String = row.get(0)?;
+                if let Some(index) = secrets.iter().position(|secret| verify_api_key(secret, api_key, &key_hash)) {
+                    let username: String = row.get(1)?;
+                    token = Some((username, api_token_from_row(row, 2)?, index));
+                    break;
+                }
+            }
+            token
+        };
+        let (username, mut token) = if let Some((username, token, index)) = token {
+            if index > 0 {
+                // Hashed with the secret in the database before one was configured
+                conn.execute("UPDATE api_key SET key_hash = ? WHERE id = ?", rusqlite::params![hash_api_key(&secrets[0], api_key), token.id])?;
+            }
+            (username, token)
         } else {
-            return Err(Error::new(ErrorKind::NotFound,"User not found".to_string()));
+            return Ok(None);
         };
 
-        let api_key = {
-            let mut stmt = tx.prepare("SELECT key FROM api_key WHERE user_id = ?")?;
-            let mut rows = stmt.query(rusqlite::params![user.id])?;
-            let row = rows.next()?;
-            let api_key = match row {
-                Some(row) => {
-                    let api_key: String = row.get(0)?;
-                    Some(api_key)
-                },
-                None => None,
-            };
-            api_key
+        let now = unix_time_now();
+        if token.is_expired(now) {
+            return Ok(None);
+        }
+
+        let mut stmt = conn.prepare("UPDATE api_key SET last_used_at = ? WHERE id = ?")?;
+        stmt.execute(rusqlite::params![now, token.id])?;
+        token.last_used_at = Some(now);
+        Ok(Some((username, token)))
+    }
+
+    fn get_totp(conn: &rusqlite::Connection, user_id: i32) -> Result<Option<TotpRow>, Error> {
+        let mut stmt = conn.prepare("SELECT secret, enabled, last_used_step FROM user_totp WHERE user_id = ?")?;
+        let mut rows = stmt.query(rusqlite::params![user_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(TotpRow {
+                secret: row.get(0)?,
+                enabled: row.get(1)?,
+                last_used_step: row.get(2)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn replace_recovery_codes(&self, conn: &rusqlite::Connection, user_id: i32) -> Result<Vec<String>, Error> {
+        let secret = self.get_api_key_secret(conn)?;
+        let codes = totp::generate_recovery_codes();
+        conn.execute("DELETE FROM user_recovery_code WHERE user_id = ?", rusqlite::params![user_id])?;
+        let mut stmt = conn.prepare("INSERT INTO user_recovery_code (user_id, code_hash) VALUES (?, ?)")?;
+        for code in &codes {
+            stmt.execute(rusqlite::params![user_id, hash_api_key(&secret, code)])?;
+        }
+        Ok(codes)
+    }
+
+    pub fn get_totp_status(&self, username: &str) -> Result<TotpStatus, Error> {
+        let conn = self.db.get_conn()?;
+        let user_id = Self::get_user_id(&conn, username)?;
+        let (enabled, pending) = match Self::get_totp(&conn, user_id)? {
+            Some(totp) => (totp.enabled, !totp.enabled),
+            None => (false, false),
         };
+        let recovery_codes_remaining = conn.query_row("SELECT COUNT(*) FROM user_recovery_code WHERE user_id = ? AND used_at IS NULL", rusqlite::params![user_id], |row| row.get(0))?;
+        Ok(TotpStatus {
+            enabled,
+            pending,
+            recovery_codes_remaining,
+        })
+    }
 
-        // TODO: Reusing API keys is not a good idea, but it's of a low priority to fix
-        if let Some(api_key) = api_key {
-            return Ok(api_key);
+    pub fn begin_totp_enrolment(&self, username: &str) -> Result<TotpEnrolment, Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        if let Some(TotpRow { enabled: true, .. }) = Self::get_totp(&tx, user_id)? {
+            return Err(Error::new(ErrorKind::Conflict,"Two-factor authentication is already enabled".to_string()));
         }
+        let secret = totp::generate_secret();
+        tx.execute("INSERT OR REPLACE INTO user_totp (user_id, secret, enabled, created_at) VALUES (?, ?, 0, ?)", rusqlite::params![user_id, secret, unix_time_now()])?;
+        tx.commit()?;
 
-        let mut api_key = [0u8; 32];
-        OsRng.fill_bytes(&mut api_key);
-        let api_key = hex::encode(api_key);
-        {
-            let mut stmt = tx.prepare("INSERT OR REPLACE INTO api_key (key, user_id) VALUES (?, ?)")?;
-            stmt.execute(rusqlite::params![&api_key, user.id])?;
+        let otpauth_uri = totp::otpauth_uri(username, &secret);
+        Ok(TotpEnrolment {
+            secret: totp::encode_secret(&secret),
+            qr_code_svg: totp::qr_code_svg(&otpauth_uri),
+            otpauth_uri,
+        })
+    }
+
+    pub fn confirm_totp_enrolment(&self, username: &str, code: &str) -> Result<Vec<String>, Error> {
+        self.confirm_totp_enrolment_at(username, code, unix_time_now())
+    }
+
+    /// Confirm a pending enrolment with a code valid at `now` (Unix seconds)
+    pub fn confirm_totp_enrolment_at(&self, username: &str, code: &str, now: i64) -> Result<Vec<String>, Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        let secret = match Self::get_totp(&tx, user_id)? {
+            Some(TotpRow { secret, enabled: false, .. }) => secret,
+            Some(TotpRow { enabled: true, .. }) => return Err(Error::new(ErrorKind::Conflict,"Two-factor authentication is already enabled".to_string())),
+            None => return Err(Error::new(ErrorKind::Conflict,"No pending two-factor enrolment".to_string())),
+        };
+        let step = match totp::verify_code(&secret, code, now, None) {
+            Some(step) => step,
+            None => return Err(Error::new(ErrorKind::Validation,"Invalid code".to_string())),
+        };
+        tx.execute("UPDATE user_totp SET enabled = 1, last_used_step = ? WHERE user_id = ?", rusqlite::params![step, user_id])?;
+        let codes = self.replace_recovery_codes(&tx, user_id)?;
+        tx.commit()?;
+        Ok(codes)
+    }
+
+    pub fn verify_second_factor(&self, username: &str, code: &str) -> Result<bool, Error> {
+        self.verify_second_factor_at(username, code, unix_time_now())
+    }
+
+    /// Check a TOTP code valid at `now` (Unix seconds) or a recovery code
+    pub fn verify_second_factor_at(&self, username: &str, code: &str, now: i64) -> Result<bool, Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        let (secret, last_used_step) = match Self::get_totp(&tx, user_id)? {
+            Some(TotpRow { secret, enabled: true, last_used_step }) => (secret, last_used_step),
+            _ => return Ok(false),
+        };
+
+        if let Some(step) = totp::verify_code(&secret, code, now, last_used_step) {
+            tx.execute("UPDATE user_totp SET last_used_step = ? WHERE user_id = ?", rusqlite::params![step, user_id])?;
+            tx.commit()?;
+            return Ok(true);
         }
+
+        let key_secrets = self.db.api_key_secrets(&tx)?;
+        let code = totp::normalize_recovery_code(code);
+        let recovery_codes = {
+            let mut stmt = tx.prepare("SELECT id, code_hash FROM user_recovery_code WHERE user_id = ? AND used_at IS NULL")?;
+            let rows = stmt.query_map(rusqlite::params![user_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        for (id, code_hash) in recovery_codes {
+            if key_secrets.iter().any(|secret| verify_api_key(secret, &code, &code_hash)) {
+                tx.execute("UPDATE user_recovery_code SET used_at = ? WHERE id = ?", rusqlite::params![now, id])?;
+                tx.commit()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn regenerate_recovery_codes(&self, username: &str) -> Result<Vec<String>, Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        if !matches!(Self::get_totp(&tx, user_id)?, Some(TotpRow { enabled: true, .. })) {
+            return Err(Error::new(ErrorKind::Conflict,"Two-factor authentication is not enabled".to_string()));
+        }
+        let codes = self.replace_recovery_codes(&tx, user_id)?;
         tx.commit()?;
-        Ok(api_key)
+        Ok(codes)
     }
 
-    pub fn get_user_from_api_key(&self, api_key: &str) -> Result<Option<String>, Error> {
+    pub fn disable_totp(&self, username: &str) -> Result<(), Error> {
+        let mut conn = self.db.get_conn()?;
+        let tx = conn.transaction()?;
+        let user_id = Self::get_user_id(&tx, username)?;
+        tx.execute("DELETE FROM user_totp WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.execute("DELETE FROM user_recovery_code WHERE user_id = ?", rusqlite::params![user_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn record_login_attempt(&self, username: &str, source: Option<&str>, outcome: LoginOutcome) -> Result<(), Error> {
         let conn = self.db.get_conn()?;
-        let mut stmt = conn.prepare("SELECT user.id, user.name, user.hashed_password FROM user JOIN api_key ON user.id = api_key.user_id WHERE api_key.key = ?")?;
-        let mut rows = stmt.query(rusqlite::params![api_key])?;
-        let row = rows.next()?;
-        let user = match row {
-            Some(row) => {
-                let user = User {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    hashed_password: row.get(2)?,
-                };
-                Some(user)
-            },
-            None => None,
+        let now = unix_time_now();
+        conn.execute("INSERT INTO login_attempt (username, source, outcome, time) VALUES (?, ?, ?, ?)", rusqlite::params![username, source, outcome, now])?;
+        conn.execute("DELETE FROM login_attempt WHERE time < ?", rusqlite::params![now - LOGIN_ATTEMPT_RETENTION])?;
+        Ok(())
+    }
+
+    pub fn begin_login_attempt(&self, username: &str, source: Option<&str>) -> Result<LoginAttemptStart, Error> {
+        let mut conn = self.db.get_conn()?;
+        // Take the write lock before reading, so that parallel attempts see each other
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let now = unix_time_now();
+        let mut failures = Vec::new();
+        let window_start = LoginThrottlePolicy::USERNAME.window_start(now);
+        failures.extend(Self::query_login_failures(&tx, LOGIN_FAILURES_BY_USERNAME, window_start, Some(username), LoginThrottleKey::Username)?);
+        if let Some(source) = source {
+            let window_start = LoginThrottlePolicy::SOURCE.window_start(now);
+            failures.extend(Self::query_login_failures(&tx, LOGIN_FAILURES_BY_SOURCE, window_start, Some(source), LoginThrottleKey::Source)?);
+        }
+        let lockout = longest_login_lockout(&failures, now);
+        let outcome = if lockout.is_some() { LoginOutcome::Throttled } else { LoginOutcome::Failure };
+        tx.execute("INSERT INTO login_attempt (username, source, outcome, time) VALUES (?, ?, ?, ?)", rusqlite::params![username, source, outcome, now])?;
+        let attempt_id = tx.last_insert_rowid();
+        tx.execute("DELETE FROM login_attempt WHERE time < ?", rusqlite::params![now - LOGIN_ATTEMPT_RETENTION])?;
+        tx.commit()?;
+        Ok(match lockout {
+            Some(lockout) => LoginAttemptStart::Throttled(lockout),
+            None => LoginAttemptStart::Allowed(attempt_id),
+        })
+    }
+
+    pub fn finish_login_attempt(&self, attempt_id: i64, outcome: Option<LoginOutcome>) -> Result<(), Error> {
+        let conn = self.db.get_conn()?;
+        match outcome {
+            Some(outcome) => conn.execute("UPDATE login_attempt SET outcome = ? WHERE id = ?", rusqlite::params![outcome, attempt_id])?,
+            None => conn.execute("DELETE FROM login_attempt WHERE id = ?", rusqlite::params![attempt_id])?,
         };
-        let username = if let Some(user) = user {
-            Some(user.name)
-        } else {
-            None
+        Ok(())
+    }
+
+    fn query_login_failures(conn: &rusqlite::Connection, sql: &str, since: i64, key: Option<&str>, make_key: fn(String) -> LoginThrottleKey) -> Result<Vec<LoginFailures>, Error> {
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query(rusqlite::params![since, key])?;
+        let mut failures = Vec::new();
+        while let Some(row) = rows.next()? {
+            failures.push(LoginFailures {
+                key: make_key(row.get(0)?),
+                count: row.get(1)?,
+                last_failure_at: row.get(2)?,
+            });
+        }
+        Ok(failures)
+    }
+
+    pub fn get_login_failures(&self, key: &LoginThrottleKey, since: i64) -> Result<LoginFailures, Error> {
+        let conn = self.db.get_conn()?;
+        let failures = match key {
+            LoginThrottleKey::Username(username) => Self::query_login_failures(&conn, LOGIN_FAILURES_BY_USERNAME, since, Some(username), LoginThrottleKey::Username)?,
+            LoginThrottleKey::Source(source) => Self::query_login_failures(&conn, LOGIN_FAILURES_BY_SOURCE, since, Some(source), LoginThrottleKey::Source)?,
         };
+        Ok(failures.into_iter().next().unwrap_or(LoginFailures {
+            key: key.clone(),
+            count: 0,
+            last_failure_at: 0,
+        }))
+    }
+
+    pub fn list_login_failures(&self, since: i64) -> Result<Vec<LoginFailures>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut failures = Self::query_login_failures(&conn, LOGIN_FAILURES_BY_USERNAME, since, None, LoginThrottleKey::Username)?;
+        failures.extend(Self::query_login_failures(&conn, LOGIN_FAILURES_BY_SOURCE, since, None, LoginThrottleKey::Source)?);
+        Ok(failures)
+    }
+
+    pub fn list_login_attempts(&self, username: Option<&str>, source: Option<&str>, limit: u32) -> Result<Vec<LoginAttempt>, Error> {
+        let conn = self.db.get_conn()?;
+        let mut stmt = conn.prepare("SELECT id, username, source, outcome, time FROM login_attempt WHERE (?1 IS NULL OR username = ?1) AND (?2 IS NULL OR source = ?2) ORDER BY id DESC LIMIT ?3")?;
+        let mut rows = stmt.query(rusqlite::params![username, source, limit])?;
+        let mut attempts = Vec::new();
+        while let Some(row) = rows.next()? {
+            attempts.push(LoginAttempt {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                source: row.get(2)?,
+                outcome: row.get(3)?,
+                time: row.get(4)?,
+            });
+        }
+        Ok(attempts)
+    }
+
+    pub fn clear_login_failures(&self, key: &LoginThrottleKey) -> Result<(), Error> {
+        let conn = self.db.get_conn()?;
+        match key {
+            LoginThrottleKey::Username(username) => conn.execute("UPDATE login_attempt SET cleared = 1 WHERE username = ? AND outcome = ?", rusqlite::params![username, LoginOutcome::Failure])?,
+            LoginThrottleKey::Source(source) => conn.execute("UPDATE login_attempt SET cleared = 1 WHERE source = ? AND outcome = ?", rusqlite::params![source, LoginOutcome::Failure])?,
+        };
+        Ok(())
+    }
+
+    pub fn get_user_from_api_key(&self, api_key: &str) -> Result<Option<String>, Error> {
+        let username = self.get_api_token(api_key)?.map(|(username, _)| username);
         Ok(username)
     }
 
@@ -186,10 +702,50 @@ impl UserStore for SqliteUserStore {
         SqliteUserStore::set_password(self, username, password)
     }
 
+    fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error> {
+        SqliteUserStore::change_password(self, username, password, keep_token_id)
+    }
+
     fn delete_user(&self, username: &str) -> Result<(), Error> {
         SqliteUserStore::delete_user(self, username)
     }
 
+    fn create_user(&self, username: &str, password: &str, role: UserRole) -> Result<(), Error> {
+        SqliteUserStore::create_user(self, username, password, role)
+    }
+
+    fn create_oidc_user(&self, username: &str, password: &str, role: UserRole, issuer: &str, subject: &str) -> Result<(), Error> {
+        SqliteUserStore::create_oidc_user(self, username, password, role, issuer, subject)
+    }
+
+    fn get_oidc_user(&self, issuer: &str, subject: &str) -> Result<Option<String>, Error> {
+        SqliteUserStore::get_oidc_user(self, issuer, subject)
+    }
+
+    fn link_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        SqliteUserStore::link_oidc_user(self, username, issuer, subject)
+    }
+
+    fn unlink_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        SqliteUserStore::unlink_oidc_user(self, username, issuer, subject)
+    }
+
+    fn get_user(&self, username: &str) -> Result<UserInfo, Error> {
+        SqliteUserStore::get_user(self, username)
+    }
+
+    fn get_user_role(&self, username: &str) -> Result<UserRole, Error> {
+        SqliteUserStore::get_user_role(self, username)
+    }
+
+    fn list_users_with_metadata(&self) -> Result<Vec<UserInfo>, Error> {
+        SqliteUserStore::list_users_with_metadata(self)
+    }
+
+    fn set_role(&self, username: &str, role: UserRole) -> Result<(), Error> {
+        SqliteUserStore::set_role(self, username, role)
+    }
+
     fn generate_api_key(&self, username: &str) -> Result<String, Error> {
         SqliteUserStore::generate_api_key(self, username)
     }
@@ -201,4 +757,72 @@ impl UserStore for SqliteUserStore {
     fn list_users(&self) -> Result<Vec<String>, Error> {
         SqliteUserStore::list_users(self)
     }
+
+    fn create_api_token(&self, username: &str, name: &str, expires_at: Option<i64>, scope: &TokenScope) -> Result<NewApiToken, Error> {
+        SqliteUserStore::create_api_token(self, username, name, expires_at, scope)
+    }
+
+    fn list_api_tokens(&self, username: &str) -> Result<Vec<ApiToken>, Error> {
+        SqliteUserStore::list_api_tokens(self, username)
+    }
+
+    fn revoke_api_token(&self, username: &str, token_id: i32) -> Result<(), Error> {
+        SqliteUserStore::revoke_api_token(self, username, token_id)
+    }
+
+    fn get_api_token(&self, api_key: &str) -> Result<Option<(String, ApiToken)>, Error> {
+        SqliteUserStore::get_api_token(self, api_key)
+    }
+
+    fn get_totp_status(&self, username: &str) -> Result<TotpStatus, Error> {
+        SqliteUserStore::get_totp_status(self, username)
+    }
+
+    fn begin_totp_enrolment(&self, username: &str) -> Result<TotpEnrolment, Error> {
+        SqliteUserStore::begin_totp_enrolment(self, username)
+    }
+
+    fn confirm_totp_enrolment(&self, username: &str, code: &str) -> Result<Vec<String>, Error> {
+        SqliteUserStore::confirm_totp_enrolment(self, username, code)
+    }
+
+    fn verify_second_factor(&self, username: &str, code: &str) -> Result<bool, Error> {
+        SqliteUserStore::verify_second_factor(self, username, code)
+    }
+
+    fn regenerate_recovery_codes(&self, username: &str) -> Result<Vec<String>, Error> {
+        SqliteUserStore::regenerate_recovery_codes(self, username)
+    }
+
+    fn disable_totp(&self, username: &str) -> Result<(), Error> {
+        SqliteUserStore::disable_totp(self, username)
+    }
+
+    fn record_login_attempt(&self, username: &str, source: Option<&str>, outcome: LoginOutcome) -> Result<(), Error> {
+        SqliteUserStore::record_login_attempt(self, username, source, outcome)
+    }
+
+    fn begin_login_attempt(&self, username: &str, source: Option<&str>) -> Result<LoginAttemptStart, Error> {
+        SqliteUserStore::begin_login_attempt(self, username, source)
+    }
+
+    fn finish_login_attempt(&self, attempt_id: i64, outcome: Option<LoginOutcome>) -> Result<(), Error> {
+        SqliteUserStore::finish_login_attempt(self, attempt_id, outcome)
+    }
+
+    fn get_login_failures(&self, key: &LoginThrottleKey, since: i64) -> Result<LoginFailures, Error> {
+        SqliteUserStore::get_login_failures(self, key, since)
+    }
+
+    fn list_login_failures(&self, since: i64) -> Result<Vec<LoginFailures>, Error> {
+        SqliteUserStore::list_login_failures(self, since)
+    }
+
+    fn list_login_attempts(&self, username: Option<&str>, source: Option<&str>, limit: u32) -> Result<Vec<LoginAttempt>, Error> {
+        SqliteUserStore::list_login_attempts(self, username, source, limit)
+    }
+
+    fn clear_login_failures(&self, key: &LoginThrottleKey) -> Result<(), Error> {
+        SqliteUserStore::clear_login_failures(self, key)
+    }
 }
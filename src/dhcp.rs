@@ -0,0 +1,321 @@
+//! DHCP service of IPv4 and IPv6 assignments, exported as ISC Kea subnet configuration
+//!
+//! A DHCP-enabled assignment becomes a Kea `subnet4` or `subnet6` entry with its dynamic range,
+//! options and lifetimes, and a reservation for each of its hosts with a MAC address. Subnet IDs
+//! are the assignment IDs, so leases stay with their subnet when the configuration is rebuilt.
+
+use crate::types::{Error, ErrorKind};
+use crate::store::{DbConnection, Store};
+use crate::user::ResourceFamily;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+
+/// DHCP service of an IPv4 or IPv6 assignment
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DhcpSettings {
+    /// First address of the dynamic range; without a range, only reservations are served
+    #[serde(default)]
+    pub range_start: Option<IpAddr>,
+
+    /// Last address of the dynamic range
+    #[serde(default)]
+    pub range_end: Option<IpAddr>,
+
+    /// Default gateway of an IPv4 assignment; its gateway host if not set
+    #[serde(default)]
+    pub gateway: Option<IpAddr>,
+
+    /// DNS servers given to clients
+    #[serde(default)]
+    pub dns_servers: Vec<IpAddr>,
+
+    /// Lease time in seconds; Kea's default if not set
+    #[serde(default)]
+    pub valid_lifetime: Option<u32>,
+
+    /// Seconds until clients renew their lease (T1)
+    #[serde(default)]
+    pub renew_timer: Option<u32>,
+
+    /// Seconds until clients rebind their lease with any server (T2)
+    #[serde(default)]
+    pub rebind_timer: Option<u32>,
+
+    /// Preferred lifetime of IPv6 leases in seconds
+    #[serde(default)]
+    pub preferred_lifetime: Option<u32>,
+}
+
+/// Address as an integer, if it is of `family`
+fn address_bits(addr: IpAddr, family: ResourceFamily) -> Option<u128> {
+    match (addr, family) {
+        (IpAddr::V4(addr), ResourceFamily::Ipv4) => Some(u32::from(addr) as u128),
+        (IpAddr::V6(addr), ResourceFamily::Ipv6) => Some(u128::from(addr)),
+        _ => None,
+    }
+}
+
+impl DhcpSettings {
+    /// Check the settings for an assignment of `family`
+    pub fn validate(&self, family: ResourceFamily) -> Result<(), Error> {
+        if family == ResourceFamily::Asn {
+            return Err(Error::new(ErrorKind::Validation, "DHCP only applies to IPv4 and IPv6 assignments".to_string()));
+        }
+        let family_name = if family == ResourceFamily::Ipv4 { "IPv4" } else { "IPv6" };
+        for addr in self.range_start.iter().chain(&self.range_end).chain(&self.gateway).chain(&self.dns_servers) {
+            if address_bits(*addr, family).is_none() {
+                return Err(Error::new(ErrorKind::Validation, format!("{} is not an {} address", addr, family_name)));
+            }
+        }
+        match (self.range_start, self.range_end) {
+            (Some(start), Some(end)) if address_bits(start, family) > address_bits(end, family) => {
+                return Err(Error::new(ErrorKind::Validation, "The dynamic range must start before it ends".to_string()));
+            },
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(Error::new(ErrorKind::Validation, "Set both ends of the dynamic range, or neither".to_string()));
+            },
+            _ => (),
+        }
+        if family == ResourceFamily::Ipv6 && self.gateway.is_some() {
+            return Err(Error::new(ErrorKind::Validation, "IPv6 gateways are announced by router advertisements, not DHCPv6".to_string()));
+        }
+        if family == ResourceFamily::Ipv4 && self.preferred_lifetime.is_some() {
+            return Err(Error::new(ErrorKind::Validation, "Preferred lifetimes only apply to IPv6".to_string()));
+        }
+        if self.valid_lifetime == Some(0) {
+            return Err(Error::new(ErrorKind::Validation, "The lease time must be positive".to_string()));
+        }
+        let valid_lifetime = self.valid_lifetime.unwrap_or(u32::MAX);
+        let renew_timer = self.renew_timer.unwrap_or(0);
+        let rebind_timer = self.rebind_timer.unwrap_or(valid_lifetime);
+        if renew_timer > rebind_timer || rebind_timer > valid_lifetime {
+            return Err(Error::new(ErrorKind::Validation, "Timers must satisfy renew <= rebind <= lease time".to_string()));
+        }
+        if self.preferred_lifetime.unwrap_or(0) > valid_lifetime {
+            return Err(Error::new(ErrorKind::Validation, "The preferred lifetime must be at most the lease time".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check that the range and gateway are usable addresses of `prefix/prefix_len`, and that
+    /// the gateway is outside the range. `reserved` are the addresses hosts cannot have.
+    pub fn check_within(&self, family: ResourceFamily, prefix: IpAddr, prefix_len: u8, reserved: &[IpAddr]) -> Result<(), Error> {
+        let bits = if family == ResourceFamily::Ipv4 { 32 } else { 128 };
+        let first = address_bits(prefix, family).unwrap_or(0);
+        let last = first | u128::MAX.checked_shr(128 - bits + prefix_len as u32).unwrap_or(0);
+        let within = |addr: &IpAddr| address_bits(*addr, family).is_some_and(|addr| addr >= first && addr <= last);
+        for addr in self.range_start.iter().chain(&self.range_end).chain(&self.gateway) {
+            if !within(addr) {
+                return Err(Error::new(ErrorKind::Validation, format!("{} is not within the assignment", addr)));
+            }
+        }
+        if let (Some(start), Some(end)) = (self.range_start, self.range_end) {
+            let range = address_bits(start, family)..=address_bits(end, family);
+            if let Some(addr) = reserved.iter().chain(&self.gateway).find(|addr| range.contains(&address_bits(**addr, family))) {
+                return Err(Error::new(ErrorKind::Validation, format!("The dynamic range includes {}, which cannot be leased", addr)));
+            }
+        }
+        if let Some(gateway) = &self.gateway {
+            if reserved.contains(gateway) {
+                return Err(Error::new(ErrorKind::Validation, format!("{} cannot be the gateway", gateway)));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait DhcpStore {
+    /// DHCP service of an assignment, `None` if it has none
+    fn get_dhcp(&self, family: ResourceFamily, assignment_id: i32) -> Result<Option<DhcpSettings>, Error>;
+
+    /// DHCP service of all DHCP-enabled assignments of `family`, by assignment ID
+    fn get_all_dhcp(&self, family: ResourceFamily) -> Result<HashMap<i32, DhcpSettings>, Error>;
+
+    /// Enable DHCP for an assignment with `dhcp`, or disable it with `None`
+    fn set_dhcp(&self, family: ResourceFamily, assignment_id: i32, dhcp: Option<&DhcpSettings>) -> Result<(), Error>;
+}
+
+/// Address pool of a Kea subnet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeaPool {
+    /// Range as `first - last`
+    pub pool: String,
+}
+
+/// Option of a Kea subnet, such as `routers`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeaOption {
+    pub name: String,
+
+    /// Option value, with list items separated by `, `
+    pub data: String,
+}
+
+/// Host reservation of a Kea subnet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeaReservation {
+    pub hw_address: String,
+
+    /// Reserved address of a `subnet4` entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_address: Option<IpAddr>,
+
+    /// Reserved addresses of a `subnet6` entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ip_addresses: Vec<IpAddr>,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+}
+
+/// `subnet4` or `subnet6` entry of a Kea configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeaSubnet {
+    /// Assignment ID
+    pub id: i32,
+
+    /// Assignment prefix, such as `192.0.2.0/24`
+    pub subnet: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pools: Vec<KeaPool>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub option_data: Vec<KeaOption>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_lifetime: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_timer: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebind_timer: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_lifetime: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reservations: Vec<KeaReservation>,
+
+    /// Assignment name
+    pub comment: String,
+}
+
+/// Subnets of a Kea configuration, to be placed in the `Dhcp4` and `Dhcp6` sections
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeaConfig {
+    pub subnet4: Vec<KeaSubnet>,
+    pub subnet6: Vec<KeaSubnet>,
+}
+
+/// A DHCP-enabled assignment with its hosts, as (address, MAC address, host name, gateway)
+struct DhcpAssignment {
+    id: i32,
+    name: String,
+    prefix: IpAddr,
+    prefix_len: u8,
+    dhcp: DhcpSettings,
+    hosts: Vec<(IpAddr, Option<String>, String, bool)>,
+}
+
+fn kea_subnet(family: ResourceFamily, assignment: DhcpAssignment) -> Result<KeaSubnet, Error> {
+    let dhcp = assignment.dhcp;
+    let pools = match (dhcp.range_start, dhcp.range_end) {
+        (Some(start), Some(end)) => vec![KeaPool { pool: format!("{} - {}", start, end) }],
+        _ => Vec::new(),
+    };
+
+    let mut option_data = Vec::new();
+    if family == ResourceFamily::Ipv4 {
+        let gateway = dhcp.gateway.or_else(|| assignment.hosts.iter().find(|host| host.3).map(|host| host.0));
+        if let Some(gateway) = gateway {
+            option_data.push(KeaOption { name: "routers".to_string(), data: gateway.to_string() });
+        }
+    }
+    if !dhcp.dns_servers.is_empty() {
+        let name = if family == ResourceFamily::Ipv4 { "domain-name-servers" } else { "dns-servers" };
+        let data = dhcp.dns_servers.iter().map(|addr| addr.to_string()).collect::<Vec<_>>().join(", ");
+        option_data.push(KeaOption { name: name.to_string(), data });
+    }
+
+    let mut reservations = Vec::new();
+    let mut hw_addresses = HashSet::new();
+    for (addr, mac_address, hostname, _) in assignment.hosts {
+        let Some(hw_address) = mac_address else {
+            continue;
+        };
+        // Kea refuses to load a subnet with two reservations for the same client
+        if !hw_addresses.insert(hw_address.clone()) {
+            return Err(Error::new(ErrorKind::Conflict, format!("{} is reserved twice in {}/{}", hw_address, assignment.prefix, assignment.prefix_len)));
+        }
+        let (ip_address, ip_addresses) = if family == ResourceFamily::Ipv4 { (Some(addr), Vec::new()) } else { (None, vec![addr]) };
+        reservations.push(KeaReservation { hw_address, ip_address, ip_addresses, hostname });
+    }
+
+    Ok(KeaSubnet {
+        id: assignment.id,
+        subnet: format!("{}/{}", assignment.prefix, assignment.prefix_len),
+        pools,
+        option_data,
+        valid_lifetime: dhcp.valid_lifetime,
+        renew_timer: dhcp.renew_timer,
+        rebind_timer: dhcp.rebind_timer,
+        preferred_lifetime: dhcp.preferred_lifetime,
+        reservations,
+        comment: assignment.name,
+    })
+}
+
+/// Kea subnets of all DHCP-enabled assignments, ordered by ID
+pub fn kea_config<T>(store: &Store<T>) -> Result<KeaConfig, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut config = KeaConfig::default();
+
+    let mut dhcp = store.dhcp().get_all_dhcp(ResourceFamily::Ipv4)?.into_iter().collect::<Vec<_>>();
+    dhcp.sort_by_key(|(id, _)| *id);
+    let ipv4_store = store.ipv4_assignments();
+    for (assignment_id, settings) in dhcp {
+        let assignment = ipv4_store.get_assignment(assignment_id)?;
+        let prefix_len = assignment.ipv4_prefix_len as u8;
+        let hosts = ipv4_store.get_hosts(assignment_id)?.into_iter()
+            .map(|host| (IpAddr::V4(Ipv4Addr::from(host.ipv4_address)), host.mac_address, host.hostname, host.gateway))
+            .collect();
+        config.subnet4.push(kea_subnet(ResourceFamily::Ipv4, DhcpAssignment {
+            id: assignment_id,
+            name: assignment.name,
+            prefix: IpAddr::V4(Ipv4Addr::from(crate::ipv4::ipv4_network_address(assignment.ipv4_prefix, prefix_len))),
+            prefix_len,
+            dhcp: settings,
+            hosts,
+        })?);
+    }
+
+    let mut dhcp = store.dhcp().get_all_dhcp(ResourceFamily::Ipv6)?.into_iter().collect::<Vec<_>>();
+    dhcp.sort_by_key(|(id, _)| *id);
+    let ipv6_store = store.ipv6_assignments();
+    for (assignment_id, settings) in dhcp {
+        let assignment = ipv6_store.get_assignment(assignment_id)?;
+        let prefix_len = assignment.ipv6_prefix_len as u8;
+        let hosts = ipv6_store.get_hosts(assignment_id)?.into_iter()
+            .map(|host| (IpAddr::V6(Ipv6Addr::from(host.ipv6_address)), host.mac_address, host.hostname, host.gateway))
+            .collect();
+        config.subnet6.push(kea_subnet(ResourceFamily::Ipv6, DhcpAssignment {
+            id: assignment_id,
+            name: assignment.name,
+            prefix: IpAddr::V6(Ipv6Addr::from(crate::ipv6::ipv6_network_address(assignment.ipv6_prefix, prefix_len))),
+            prefix_len,
+            dhcp: settings,
+            hosts,
+        })?);
+    }
+    Ok(config)
+}
@@ -0,0 +1,675 @@
+//! DNS data for our address space, as BIND zone files
+//!
+//! Reverse zones are cut per assignment space on octet (IPv4) or nibble (IPv6) boundaries.
+//! An assignment can have its reverse DNS delegated to other nameservers, or get a PTR
+//! record for each address from a template. IPv4 delegations smaller than a /24 use RFC 2317
+//! classless delegation: an NS record set at `<first>/<length>` and a CNAME for each address.
+//!
+//! Assignment spaces can also have their reverse zones kept up to date on a primary server
+//! with dynamic updates; see [`crate::dns_update`].
+//!
+//! Forward A and AAAA records of the hosts within assignments are written as zone file
+//! fragments, one per configured domain, to be included in zones maintained elsewhere.
+
+use crate::types::{Error, ErrorKind};
+use crate::store::{DbConnection, Store};
+use crate::user::ResourceFamily;
+use crate::dns_update::{DnsUpdateConfig, TsigKey};
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+
+/// Largest assignment, in address bits, that can have a PTR template
+pub const MAX_PTR_TEMPLATE_BITS: u8 = 16;
+
+fn default_hostmaster() -> String {
+    "hostmaster.localhost.".to_string()
+}
+
+fn default_ttl() -> u32 {
+    3600
+}
+
+/// Apex records of the generated zones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsConfig {
+    /// Nameservers of the zones; the first is the primary in the SOA record
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+
+    /// Mailbox of the zone administrator, in SOA form such as `hostmaster.example.net.`
+    #[serde(default = "default_hostmaster")]
+    pub hostmaster: String,
+
+    /// TTL of all records, also used for negative caching
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+
+    /// TSIG keys for dynamic updates, by key name
+    #[serde(default)]
+    pub keys: BTreeMap<String, TsigKey>,
+
+    /// Retries of dynamic updates
+    #[serde(default)]
+    pub update: DnsUpdateConfig,
+
+    /// Domains of the forward records of hosts; each host goes to the longest one its name is in
+    #[serde(default)]
+    pub forward_domains: Vec<String>,
+
+    /// Domain appended to host names without a dot, such as `web1`
+    #[serde(default)]
+    pub default_domain: Option<String>,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig {
+            nameservers: Vec::new(),
+            hostmaster: default_hostmaster(),
+            ttl: default_ttl(),
+            keys: BTreeMap::new(),
+            update: DnsUpdateConfig::default(),
+            forward_domains: Vec::new(),
+            default_domain: None,
+        }
+    }
+}
+
+impl DnsConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        for nameserver in &self.nameservers {
+            absolute_name(nameserver)?;
+        }
+        absolute_name(&self.hostmaster)?;
+        for (name, key) in &self.keys {
+            absolute_name(name)?;
+            key.validate().map_err(|e| Error::new(ErrorKind::Validation, format!("TSIG key {}: {}", name, e)))?;
+        }
+        self.update.validate()?;
+        for domain in self.forward_domains.iter().chain(&self.default_domain) {
+            absolute_name(domain)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reverse DNS of an IPv4 or IPv6 assignment: delegated, or PTR records from a template.
+/// With neither, the assignment has no reverse DNS.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReverseDns {
+    /// Nameservers serving the reverse zone of the assignment
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+
+    /// PTR target for every address, such as `{ip}.{name}.example.net.`. `{ip}` is replaced
+    /// by the address with `-` between its parts, `{name}` by the assignment name as a label.
+    #[serde(default)]
+    pub ptr_template: Option<String>,
+}
+
+impl ReverseDns {
+    pub fn is_empty(&self) -> bool {
+        self.nameservers.is_empty() && self.ptr_template.is_none()
+    }
+
+    /// Check the names, and return them as absolute names
+    pub fn normalize(&self) -> Result<ReverseDns, Error> {
+        let nameservers = self.nameservers.iter().map(|name| absolute_name(name)).collect::<Result<Vec<_>, _>>()?;
+        let ptr_template = match self.ptr_template.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(template) => {
+                let example = expand_ptr_template(template, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), "example");
+                absolute_name(&example).map_err(|_| Error::new(ErrorKind::Validation, format!("Invalid PTR template: {}", template)))?;
+                Some(if template.ends_with('.') { template.to_string() } else { format!("{}.", template) })
+            },
+        };
+        if !nameservers.is_empty() && ptr_template.is_some() {
+            return Err(Error::new(ErrorKind::Validation, "Set either nameservers or a PTR template, not both".to_string()));
+        }
+        Ok(ReverseDns { nameservers, ptr_template })
+    }
+}
+
+/// Dynamic updates (RFC 2136) of the reverse zones of an assignment space
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DnsUpdate {
+    /// Zone to update, such as `2.0.192.in-addr.arpa`; by default each record goes to its
+    /// generated reverse zone
+    #[serde(default)]
+    pub zone: Option<String>,
+
+    /// Primary server accepting the updates, as `host` or `host:port`
+    pub server: String,
+
+    /// Name of the TSIG key in `dns.keys` signing the updates
+    pub key: String,
+}
+
+impl DnsUpdate {
+    /// Check the names, and return the zone without the trailing dot
+    pub fn normalize(&self) -> Result<DnsUpdate, Error> {
+        let zone = match self.zone.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(zone) => {
+                let relative = zone.strip_suffix('.').unwrap_or(zone);
+                // RFC 2317 zone names have a `/` in their first label
+                let valid = relative.len() <= 253 && relative.split('.').all(|label| {
+                    !label.is_empty() && label.len() <= 63 && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'/')
+                });
+                if !valid {
+                    return Err(Error::new(ErrorKind::Validation, format!("Invalid zone name: {}", zone)));
+                }
+                Some(relative.to_ascii_lowercase())
+            },
+        };
+        let server = self.server.trim();
+        if server.is_empty() || server.contains(char::is_whitespace) {
+            return Err(Error::new(ErrorKind::Validation, format!("Invalid server: {}", self.server)));
+        }
+        let key = absolute_name(&self.key)?;
+        Ok(DnsUpdate {
+            zone,
+            server: server.to_string(),
+            key: key.trim_end_matches('.').to_ascii_lowercase(),
+        })
+    }
+}
+
+pub trait ReverseDnsStore {
+    /// Reverse DNS of an assignment, empty if not set
+    fn get_reverse_dns(&self, family: ResourceFamily, assignment_id: i32) -> Result<ReverseDns, Error>;
+
+    /// Reverse DNS of all assignments of a family that have any, by assignment ID
+    fn get_all_reverse_dns(&self, family: ResourceFamily) -> Result<HashMap<i32, ReverseDns>, Error>;
+
+    /// Replace the reverse DNS of an assignment; an empty value removes it.
+    /// PTR templates are limited to assignments of at most 2^16 addresses.
+    fn set_reverse_dns(&self, family: ResourceFamily, assignment_id: i32, reverse_dns: &ReverseDns) -> Result<(), Error>;
+
+    /// SOA serial of the reverse zones, incremented (modulo 2^32) by every change of the
+    /// spaces, pools, assignments or reverse DNS they are made of
+    fn get_serial(&self) -> Result<u32, Error>;
+
+    /// Dynamic updates of the reverse zones of an assignment space, if enabled
+    fn get_dns_update(&self, family: ResourceFamily, space_id: i32) -> Result<Option<DnsUpdate>, Error>;
+
+    /// Enable dynamic updates for an assignment space, or disable them with `None`
+    fn set_dns_update(&self, family: ResourceFamily, space_id: i32, dns_update: Option<&DnsUpdate>) -> Result<(), Error>;
+}
+
+/// `name` with a trailing dot, if it is a valid host name
+pub fn absolute_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    let relative = name.strip_suffix('.').unwrap_or(name);
+    let valid = !relative.is_empty() && relative.len() <= 253 && relative.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    });
+    if !valid {
+        return Err(Error::new(ErrorKind::Validation, format!("Invalid host name: {}", name)));
+    }
+    Ok(format!("{}.", relative))
+}
+
+/// `name` as a single DNS label: lower case letters, digits and hyphens
+pub fn label(name: &str) -> String {
+    let mut label = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            label.push(c.to_ascii_lowercase());
+        } else if !label.is_empty() && !label.ends_with('-') {
+            label.push('-');
+        }
+    }
+    label.truncate(63);
+    match label.trim_end_matches('-') {
+        "" => "unnamed".to_string(),
+        label => label.to_string(),
+    }
+}
+
+/// The address with `-` between its parts, such as `192-0-2-1` or `2001-db8-0-0-0-0-0-1`
+pub fn address_label(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().map(|octet| octet.to_string()).join("-"),
+        IpAddr::V6(addr) => addr.segments().map(|segment| format!("{:x}", segment)).join("-"),
+    }
+}
+
+/// PTR target of `addr` in an assignment named `name`
+pub fn expand_ptr_template(template: &str, addr: IpAddr, name: &str) -> String {
+    template.replace("{ip}", &address_label(addr)).replace("{name}", &label(name))
+}
+
+/// Address as an integer, with the number of bits of its family
+fn to_bits(addr: IpAddr) -> (u128, u8) {
+    match addr {
+        IpAddr::V4(addr) => (u32::from(addr) as u128, 32),
+        IpAddr::V6(addr) => (u128::from(addr), 128),
+    }
+}
+
+fn from_bits(value: u128, bits: u8) -> IpAddr {
+    if bits == 32 {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(value))
+    }
+}
+
+/// Number of addresses in a prefix, saturating for `::/0`
+fn size(prefix_len: u8, bits: u8) -> u128 {
+    1u128.checked_shl((bits - prefix_len) as u32).unwrap_or(u128::MAX)
+}
+
+/// Reverse name of a prefix, without the trailing dot. Lengths are rounded down to an octet
+/// or nibble boundary, except for IPv4 prefixes longer than /24, which get RFC 2317 names
+/// such as `0/26.2.0.192.in-addr.arpa`.
+pub fn reverse_name(prefix: IpAddr, prefix_len: u8) -> String {
+    match prefix {
+        IpAddr::V4(addr) => {
+            let octets = addr.octets();
+            let mut labels: Vec<String> = octets[..(prefix_len / 8) as usize].iter().rev().map(|octet| octet.to_string()).collect();
+            if prefix_len > 24 && !prefix_len.is_multiple_of(8) {
+                labels.insert(0, format!("{}/{}", octets[3], prefix_len));
+            }
+            labels.push("in-addr.arpa".to_string());
+            labels.join(".")
+        },
+        IpAddr::V6(addr) => {
+            let value = u128::from(addr);
+            let mut labels: Vec<String> = (0..(prefix_len / 4) as u32)
+                .map(|i| format!("{:x}", (value >> (124 - 4 * i)) & 0xf))
+                .collect();
+            labels.reverse();
+            labels.push("ip6.arpa".to_string());
+            labels.join(".")
+        },
+    }
+}
+
+/// `name` relative to `origin`
+fn relative(name: &str, origin: &str) -> String {
+    if name == origin {
+        "@".to_string()
+    } else {
+        name.strip_suffix(origin).and_then(|name| name.strip_suffix('.')).unwrap_or(name).to_string()
+    }
+}
+
+/// A generated zone file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Zone {
+    /// Zone name, without the trailing dot
+    pub name: String,
+
+    /// Zone file in BIND format
+    pub content: String,
+}
+
+impl Zone {
+    /// Name of the zone file, `/` of RFC 2317 names replaced by `-`
+    pub fn file_name(&self) -> String {
+        format!("{}.zone", self.name.replace('/', "-"))
+    }
+}
+
+/// An assignment with reverse DNS
+#[derive(Debug, Clone)]
+struct ReverseAssignment {
+    prefix: u128,
+    prefix_len: u8,
+    name: String,
+    reverse_dns: ReverseDns,
+}
+
+/// A record of a reverse zone
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReverseRecord {
+    /// Owner name
+    pub name: String,
+
+    /// `NS`, `CNAME` or `PTR`
+    pub rtype: &'static str,
+
+    /// Target name, relative to the owner's zone unless it ends with a dot
+    pub target: String,
+}
+
+/// Records of the zone `zone_prefix/zone_len`, or `None` if an assignment delegates all of it
+/// Records are sorted by address, delegations first, with names relative to the zone.
+fn reverse_records(zone_prefix: u128, zone_len: u8, bits: u8, assignments: &[ReverseAssignment]) -> Option<Vec<(u128, u8, ReverseRecord)>> {
+    let zone_end = zone_prefix + (size(zone_len, bits) - 1);
+    let classless_zone = bits == 32 && zone_len > 24;
+    // Names in RFC 2317 zones are relative to the /24 they are cut from
+    let origin = reverse_name(from_bits(zone_prefix, bits), if classless_zone { 24 } else { zone_len });
+    let boundary = if bits == 32 { 8 } else { 4 };
+
+    let mut records = Vec::new();
+    for assignment in assignments {
+        let end = assignment.prefix + (size(assignment.prefix_len, bits) - 1);
+        if end < zone_prefix || assignment.prefix > zone_end {
+            continue;
+        }
+        if !assignment.reverse_dns.nameservers.is_empty() {
+            if assignment.prefix_len <= zone_len {
+                return None;
+            }
+            let ns = |owner: &str| assignment.reverse_dns.nameservers.iter().map(|nameserver| ReverseRecord {
+                name: owner.to_string(),
+                rtype: "NS",
+                target: nameserver.clone(),
+            }).collect::<Vec<_>>();
+            if bits == 32 && assignment.prefix_len > 24 && !assignment.prefix_len.is_multiple_of(boundary) {
+                let delegated = reverse_name(from_bits(assignment.prefix, bits), assignment.prefix_len);
+                let owner = relative(&delegated, &origin);
+                records.extend(ns(&owner).into_iter().map(|record| (assignment.prefix, 0, record)));
+                for addr in assignment.prefix..=end {
+                    let last_octet = addr & 0xff;
+                    let name = relative(&reverse_name(from_bits(addr, bits), bits), &origin);
+                    records.push((addr, 1, ReverseRecord { name, rtype: "CNAME", target: format!("{}.{}", last_octet, owner) }));
+                }
+            } else {
+                let delegated_len = assignment.prefix_len.div_ceil(boundary) * boundary;
+                let step = size(delegated_len, bits);
+                let mut prefix = assignment.prefix;
+                while prefix <= end {
+                    let owner = relative(&reverse_name(from_bits(prefix, bits), delegated_len), &origin);
+                    records.extend(ns(&owner).into_iter().map(|record| (prefix, 0, record)));
+                    prefix = match prefix.checked_add(step) {
+                        Some(prefix) => prefix,
+                        None => break,
+                    };
+                }
+            }
+        } else if let Some(template) = &assignment.reverse_dns.ptr_template {
+            if bits - assignment.prefix_len > MAX_PTR_TEMPLATE_BITS {
+                continue;
+            }
+            for addr in assignment.prefix.max(zone_prefix)..=end.min(zone_end) {
+                let addr_ip = from_bits(addr, bits);
+                let name = relative(&reverse_name(addr_ip, bits), &origin);
+                records.push((addr, 1, ReverseRecord { name, rtype: "PTR", target: expand_ptr_template(template, addr_ip, &assignment.name) }));
+            }
+        }
+    }
+    records.sort();
+    records.dedup();
+    Some(records)
+}
+
+/// Length of the reverse zones of a space: the space rounded up to an octet or nibble boundary,
+/// or the space itself for IPv4 spaces longer than /24
+fn zone_len(space_len: u8, bits: u8) -> u8 {
+    if bits == 32 && space_len > 24 {
+        space_len
+    } else {
+        let boundary = if bits == 32 { 8 } else { 4 };
+        space_len.div_ceil(boundary) * boundary
+    }
+}
+
+/// Reverse zones of one assignment space
+fn space_zones(space_name: &str, space_prefix: IpAddr, space_len: u8, assignments: &[ReverseAssignment], config: &DnsConfig, serial: u32) -> Vec<Zone> {
+    let (space_prefix, bits) = to_bits(space_prefix);
+    let zone_len = zone_len(space_len, bits);
+    let nameservers: Vec<String> = config.nameservers.iter().map(|name| absolute_name(name).unwrap_or_else(|_| name.clone())).collect();
+    let hostmaster = absolute_name(&config.hostmaster).unwrap_or_else(|_| config.hostmaster.clone());
+
+    let mut zones = Vec::new();
+    for i in 0..(1u128 << (zone_len - space_len)) {
+        let zone_prefix = space_prefix + i * size(zone_len, bits);
+        let Some(records) = reverse_records(zone_prefix, zone_len, bits, assignments) else {
+            continue;
+        };
+        let name = reverse_name(from_bits(zone_prefix, bits), zone_len);
+        let mut content = format!("; Reverse zone for assignment space {} ({}/{})\n", space_name, from_bits(space_prefix, bits), space_len);
+        content += &format!("$ORIGIN {}.\n$TTL {}\n", name, config.ttl);
+        content += &format!("@\tIN\tSOA\t{} {} {} 3600 900 1209600 {}\n", nameservers[0], hostmaster, serial, config.ttl);
+        for nameserver in &nameservers {
+            content += &format!("@\tIN\tNS\t{}\n", nameserver);
+        }
+        for (_, _, record) in records {
+            content += &format!("{}\tIN\t{}\t{}\n", record.name, record.rtype, record.target);
+        }
+        zones.push(Zone { name, content });
+    }
+    zones
+}
+
+/// Records of one assignment in the reverse zones of the space `space_prefix/space_len`, by zone
+/// name, with absolute names. Zones the assignment delegates as a whole are left out, since
+/// their NS records belong in the parent zone.
+pub fn assignment_reverse_records(space_prefix: IpAddr, space_len: u8, prefix: IpAddr, prefix_len: u8, name: &str, reverse_dns: &ReverseDns) -> Vec<(String, Vec<ReverseRecord>)> {
+    if reverse_dns.is_empty() {
+        return Vec::new();
+    }
+    let (space_prefix, bits) = to_bits(space_prefix);
+    let zone_len = zone_len(space_len, bits);
+    let zone_size = size(zone_len, bits);
+    let assignment = ReverseAssignment {
+        prefix: to_bits(prefix).0,
+        prefix_len,
+        name: name.to_string(),
+        reverse_dns: reverse_dns.normalize().unwrap_or_else(|_| reverse_dns.clone()),
+    };
+    let end = assignment.prefix + (size(prefix_len, bits) - 1);
+
+    let mut zones = Vec::new();
+    let mut zone_prefix = space_prefix.max(assignment.prefix - (assignment.prefix - space_prefix) % zone_size);
+    while zone_prefix <= end {
+        if let Some(records) = reverse_records(zone_prefix, zone_len, bits, std::slice::from_ref(&assignment)) {
+            let zone = reverse_name(from_bits(zone_prefix, bits), zone_len);
+            let absolute = |name: &str| if name == "@" { zone.clone() } else { format!("{}.{}", name, zone) };
+            let records: Vec<ReverseRecord> = records.into_iter().map(|(_, _, record)| ReverseRecord {
+                name: absolute(&record.name),
+                rtype: record.rtype,
+                target: if record.target.ends_with('.') { record.target } else { format!("{}.", absolute(&record.target)) },
+            }).collect();
+            if !records.is_empty() {
+                zones.push((zone, records));
+            }
+        }
+        zone_prefix = match zone_prefix.checked_add(zone_size) {
+            Some(prefix) => prefix,
+            None => break,
+        };
+    }
+    zones
+}
+
+/// Reverse zones of all IPv4 spaces, then all IPv6 spaces, with SOA `serial`
+pub fn reverse_zones<T>(store: &Store<T>, config: &DnsConfig, serial: u32) -> Result<Vec<Zone>, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if config.nameservers.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "No nameservers configured for the zones (dns.nameservers)".to_string()));
+    }
+    config.validate().map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    let mut zones = Vec::new();
+
+    let reverse_dns = store.reverse_dns().get_all_reverse_dns(ResourceFamily::Ipv4)?;
+    let ipv4_store = store.ipv4_assignments();
+    for space in ipv4_store.get_spaces()? {
+        let mut assignments = Vec::new();
+        for pool in ipv4_store.get_pools(space.id)? {
+            for assignment in ipv4_store.get_assignments(pool.id)? {
+                if let Some(settings) = reverse_dns.get(&assignment.id) {
+                    let prefix_len = assignment.ipv4_prefix_len as u8;
+                    assignments.push(ReverseAssignment {
+                        prefix: u32::from_be_bytes(crate::ipv4::ipv4_network_address(assignment.ipv4_prefix, prefix_len)) as u128,
+                        prefix_len,
+                        name: assignment.name,
+                        reverse_dns: settings.clone(),
+                    });
+                }
+            }
+        }
+        let prefix = IpAddr::V4(Ipv4Addr::from(crate::ipv4::ipv4_network_address(space.ipv4_prefix, space.ipv4_prefix_len as u8)));
+        zones.extend(space_zones(&space.name, prefix, space.ipv4_prefix_len as u8, &assignments, config, serial));
+    }
+
+    let reverse_dns = store.reverse_dns().get_all_reverse_dns(ResourceFamily::Ipv6)?;
+    let ipv6_store = store.ipv6_assignments();
+    for space in ipv6_store.get_spaces()? {
+        let mut assignments = Vec::new();
+        for pool in ipv6_store.get_pools(space.id)? {
+            for assignment in ipv6_store.get_assignments(pool.id)? {
+                if let Some(settings) = reverse_dns.get(&assignment.id) {
+                    let prefix_len = assignment.ipv6_prefix_len as u8;
+                    assignments.push(ReverseAssignment {
+                        prefix: u128::from_be_bytes(crate::ipv6::ipv6_network_address(assignment.ipv6_prefix, prefix_len)),
+                        prefix_len,
+                        name: assignment.name,
+                        reverse_dns: settings.clone(),
+                    });
+                }
+            }
+        }
+        let prefix = IpAddr::V6(Ipv6Addr::from(crate::ipv6::ipv6_network_address(space.ipv6_prefix, space.ipv6_prefix_len as u8)));
+        zones.extend(space_zones(&space.name, prefix, space.ipv6_prefix_len as u8, &assignments, config, serial));
+    }
+    Ok(zones)
+}
+
+/// Forward records of the hosts, and where they disagree with the reverse DNS
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ForwardDns {
+    /// One zone file fragment per forward domain, with A and AAAA records but no SOA or NS
+    /// records, named after the domain
+    pub fragments: Vec<Zone>,
+
+    /// Hosts outside the forward domains, or without a PTR record pointing back to their name
+    pub issues: Vec<String>,
+}
+
+/// A named host, with the reverse DNS of its assignment
+struct ForwardHost {
+    addr: IpAddr,
+    hostname: String,
+    prefix_len: u8,
+    assignment_name: String,
+    reverse_dns: Option<ReverseDns>,
+}
+
+/// Why the reverse DNS of `host`, named `fqdn`, does not point back to it, if it does not
+fn reverse_issue(host: &ForwardHost, fqdn: &str) -> Option<String> {
+    let bits = to_bits(host.addr).1;
+    let reverse_dns = host.reverse_dns.as_ref().filter(|reverse_dns| !reverse_dns.is_empty());
+    match reverse_dns.map(|reverse_dns| (reverse_dns.nameservers.is_empty(), &reverse_dns.ptr_template)) {
+        // Delegated reverse DNS is not ours to check
+        Some((false, _)) => None,
+        Some((true, Some(template))) if bits - host.prefix_len <= MAX_PTR_TEMPLATE_BITS => {
+            let target = expand_ptr_template(template, host.addr, &host.assignment_name);
+            if target.trim_end_matches('.').eq_ignore_ascii_case(fqdn) {
+                None
+            } else {
+                Some(format!("{} ({}): PTR record points to {}", host.addr, fqdn, target))
+            }
+        },
+        _ => Some(format!("{} ({}): no PTR record", host.addr, fqdn)),
+    }
+}
+
+/// Named hosts of all IPv4 assignments, then all IPv6 assignments
+fn forward_hosts<T>(store: &Store<T>) -> Result<Vec<ForwardHost>, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut hosts = Vec::new();
+
+    let reverse_dns = store.reverse_dns().get_all_reverse_dns(ResourceFamily::Ipv4)?;
+    let ipv4_store = store.ipv4_assignments();
+    for space in ipv4_store.get_spaces()? {
+        for pool in ipv4_store.get_pools(space.id)? {
+            for assignment in ipv4_store.get_assignments(pool.id)? {
+                for host in ipv4_store.get_hosts(assignment.id)? {
+                    if host.hostname.is_empty() {
+                        continue;
+                    }
+                    hosts.push(ForwardHost {
+                        addr: IpAddr::V4(Ipv4Addr::from(host.ipv4_address)),
+                        hostname: host.hostname,
+                        prefix_len: assignment.ipv4_prefix_len as u8,
+                        assignment_name: assignment.name.clone(),
+                        reverse_dns: reverse_dns.get(&assignment.id).cloned(),
+                    });
+                }
+            }
+        }
+    }
+
+    let reverse_dns = store.reverse_dns().get_all_reverse_dns(ResourceFamily::Ipv6)?;
+    let ipv6_store = store.ipv6_assignments();
+    for space in ipv6_store.get_spaces()? {
+        for pool in ipv6_store.get_pools(space.id)? {
+            for assignment in ipv6_store.get_assignments(pool.id)? {
+                for host in ipv6_store.get_hosts(assignment.id)? {
+                    if host.hostname.is_empty() {
+                        continue;
+                    }
+                    hosts.push(ForwardHost {
+                        addr: IpAddr::V6(Ipv6Addr::from(host.ipv6_address)),
+                        hostname: host.hostname,
+                        prefix_len: assignment.ipv6_prefix_len as u8,
+                        assignment_name: assignment.name.clone(),
+                        reverse_dns: reverse_dns.get(&assignment.id).cloned(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// A and AAAA records of all named hosts, one fragment per domain in `dns.forward_domains`,
+/// checked against the reverse DNS of their assignments
+pub fn forward_dns<T>(store: &Store<T>, config: &DnsConfig) -> Result<ForwardDns, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if config.forward_domains.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "No forward domains configured (dns.forward_domains)".to_string()));
+    }
+    config.validate().map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    let domain_name = |domain: &str| domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let default_domain = config.default_domain.as_deref().map(domain_name);
+
+    let mut records: BTreeMap<String, Vec<(String, IpAddr)>> = config.forward_domains.iter().map(|domain| (domain_name(domain), Vec::new())).collect();
+    let mut issues = Vec::new();
+    for host in forward_hosts(store)? {
+        let fqdn = match &default_domain {
+            Some(domain) if !host.hostname.contains('.') => format!("{}.{}", host.hostname, domain),
+            _ => host.hostname.clone(),
+        };
+        let domain = records.keys()
+            .filter(|domain| fqdn == **domain || fqdn.ends_with(&format!(".{}", domain)))
+            .max_by_key(|domain| domain.len())
+            .cloned();
+        let Some(domain) = domain else {
+            issues.push(format!("{} ({}): not in any forward domain", host.addr, fqdn));
+            continue;
+        };
+        issues.extend(reverse_issue(&host, &fqdn));
+        let owner = relative(&fqdn, &domain);
+        records.get_mut(&domain).unwrap().push((owner, host.addr));
+    }
+
+    let fragments = records.into_iter().map(|(domain, mut records)| {
+        // By owner name, A records before AAAA records
+        records.sort_by_key(|(owner, addr)| (owner.clone(), addr.is_ipv6(), *addr));
+        records.dedup();
+        let mut content = format!("; Forward records for {}\n$ORIGIN {}.\n", domain, domain);
+        for (owner, addr) in records {
+            let rtype = if addr.is_ipv4() { "A" } else { "AAAA" };
+            content += &format!("{}\t{}\tIN\t{}\t{}\n", owner, config.ttl, rtype, addr);
+        }
+        Zone { name: domain, content }
+    }).collect();
+    Ok(ForwardDns { fragments, issues })
+}
@@ -0,0 +1,636 @@
+//! Dynamic updates (RFC 2136) of reverse zones, signed with TSIG (RFC 8945)
+//!
+//! When an assignment is created, renamed or deleted, along with its pool or space or on its
+//! own, or its reverse DNS changes, the changed record sets are pushed to the primary server configured for its assignment space. Each
+//! changed record set is deleted and its current records added, so an update can be repeated
+//! safely. Updates are sent over TCP by a background task for each server in the order they were
+//! pushed, and retried at growing intervals while the server cannot be reached or refuses them,
+//! without holding up the updates of other servers.
+
+use crate::dns::{DnsConfig, ReverseRecord};
+use crate::store::{DbConnection, Store};
+use crate::types::{Error, ErrorKind};
+use crate::user::ResourceFamily;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Deserialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv6Addr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Port of servers given without one
+const DEFAULT_PORT: u16 = 53;
+
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_PTR: u16 = 12;
+const TYPE_TSIG: u16 = 250;
+
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+const OPCODE_UPDATE: u16 = 5;
+
+/// Allowed difference between our clock and the server's, in seconds
+const TSIG_FUDGE: u16 = 300;
+
+/// Owner names per UPDATE message, keeping messages well below the 64 KiB limit of DNS over TCP
+const MAX_NAMES_PER_MESSAGE: usize = 128;
+
+fn default_algorithm() -> String {
+    "hmac-sha256".to_string()
+}
+
+/// A TSIG key shared with DNS servers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TsigKey {
+    /// `hmac-sha256`, `hmac-sha512` or `hmac-sha1`
+    #[serde(default = "default_algorithm")]
+    pub algorithm: String,
+
+    /// Secret in base64, as printed by `tsig-keygen` or `keymgr`
+    pub secret: String,
+}
+
+impl TsigKey {
+    pub fn validate(&self) -> Result<(), Error> {
+        self.algorithm_name()?;
+        self.secret_bytes()?;
+        Ok(())
+    }
+
+    /// Algorithm name in TSIG records
+    fn algorithm_name(&self) -> Result<&'static str, Error> {
+        match self.algorithm.trim().trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok("hmac-sha256"),
+            "hmac-sha512" => Ok("hmac-sha512"),
+            "hmac-sha1" => Ok("hmac-sha1"),
+            _ => Err(Error::new(ErrorKind::Validation, format!("Unsupported TSIG algorithm: {}", self.algorithm))),
+        }
+    }
+
+    fn secret_bytes(&self) -> Result<Vec<u8>, Error> {
+        base64::engine::general_purpose::STANDARD.decode(self.secret.trim())
+            .map_err(|_| Error::new(ErrorKind::Validation, "TSIG secret is not valid base64".to_string()))
+    }
+
+    fn mac(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let secret = self.secret_bytes()?;
+        let mac = match self.algorithm_name()? {
+            "hmac-sha512" => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(&secret).expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            },
+            "hmac-sha1" => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(&secret).expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            },
+            _ => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&secret).expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            },
+        };
+        Ok(mac)
+    }
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+fn default_retry_interval() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+/// Retries of dynamic updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsUpdateConfig {
+    /// Retries of a failed update before it is given up
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+
+    /// Seconds before the first retry, doubled for each further retry
+    #[serde(default = "default_retry_interval")]
+    pub retry_interval: u64,
+
+    /// Seconds to wait for the server to accept the connection or answer
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for DnsUpdateConfig {
+    fn default() -> Self {
+        DnsUpdateConfig {
+            retries: default_retries(),
+            retry_interval: default_retry_interval(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+impl DnsUpdateConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.timeout == 0 {
+            return Err(Error::new(ErrorKind::Validation, "dns.update.timeout must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Changes of some record sets of a zone
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneUpdate {
+    /// Zone name, without the trailing dot
+    pub zone: String,
+
+    /// Record sets to delete, by owner name and type
+    pub delete: BTreeSet<(String, &'static str)>,
+
+    /// Records to add after the deletions
+    pub add: Vec<ReverseRecord>,
+
+    /// TTL of the added records
+    pub ttl: u32,
+}
+
+impl ZoneUpdate {
+    /// UPDATE messages making the changes, unsigned, with at most
+    /// `MAX_NAMES_PER_MESSAGE` owner names each
+    pub fn messages(&self) -> Result<Vec<Vec<u8>>, Error> {
+        // Deletions and additions by owner name
+        let mut names: BTreeMap<&str, (Vec<&'static str>, Vec<&ReverseRecord>)> = BTreeMap::new();
+        for (name, rtype) in &self.delete {
+            names.entry(name.as_str()).or_default().0.push(rtype);
+        }
+        for record in &self.add {
+            names.entry(record.name.as_str()).or_default().1.push(record);
+        }
+        let names: Vec<_> = names.into_iter().collect();
+
+        let mut messages = Vec::new();
+        for chunk in names.chunks(MAX_NAMES_PER_MESSAGE) {
+            let delete: Vec<(&str, &str)> = chunk.iter().flat_map(|(name, (rtypes, _))| rtypes.iter().map(move |rtype| (*name, *rtype))).collect();
+            let add: Vec<&ReverseRecord> = chunk.iter().flat_map(|(_, (_, records))| records.iter().copied()).collect();
+            let count = u16::try_from(delete.len() + add.len())
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "Too many records in an update".to_string()))?;
+
+            let mut message = Vec::new();
+            put_u16(&mut message, rand::random());
+            put_u16(&mut message, OPCODE_UPDATE << 11);
+            for section_count in [1, 0, count, 0] {
+                put_u16(&mut message, section_count);
+            }
+            put_name(&mut message, &self.zone)?;
+            put_u16(&mut message, TYPE_SOA);
+            put_u16(&mut message, CLASS_IN);
+            for (name, rtype) in delete {
+                put_name(&mut message, name)?;
+                put_u16(&mut message, type_code(rtype));
+                put_u16(&mut message, CLASS_ANY);
+                put_u32(&mut message, 0);
+                put_u16(&mut message, 0);
+            }
+            for record in add {
+                let mut rdata = Vec::new();
+                put_name(&mut rdata, &record.target)?;
+                put_name(&mut message, &record.name)?;
+                put_u16(&mut message, type_code(record.rtype));
+                put_u16(&mut message, CLASS_IN);
+                put_u32(&mut message, self.ttl);
+                put_u16(&mut message, rdata.len() as u16);
+                message.extend_from_slice(&rdata);
+            }
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
+
+fn type_code(rtype: &str) -> u16 {
+    match rtype {
+        "NS" => TYPE_NS,
+        "CNAME" => TYPE_CNAME,
+        _ => TYPE_PTR,
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// `name` in wire format, uncompressed
+fn put_name(buf: &mut Vec<u8>, name: &str) -> Result<(), Error> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid DNS name: {}", name)));
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Ok(())
+}
+
+fn malformed() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Malformed DNS message".to_string())
+}
+
+fn get_u16(message: &[u8], pos: usize) -> Result<u16, Error> {
+    match message.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(malformed()),
+    }
+}
+
+/// Name at `pos`, in lower case, and the position after it
+fn read_name(message: &[u8], mut pos: usize) -> Result<(String, usize), Error> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds the pointers followed, in case they loop
+    for _ in 0..128 {
+        let len = *message.get(pos).ok_or_else(malformed)? as usize;
+        if len == 0 {
+            return Ok((labels.join("."), end.unwrap_or(pos + 1)));
+        } else if len & 0xc0 == 0xc0 {
+            let low = *message.get(pos + 1).ok_or_else(malformed)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = ((len & 0x3f) << 8) | low;
+        } else {
+            let label = message.get(pos + 1..pos + 1 + len).ok_or_else(malformed)?;
+            labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+            pos += 1 + len;
+        }
+    }
+    Err(malformed())
+}
+
+/// Position after the resource record at `pos`
+fn skip_record(message: &[u8], pos: usize) -> Result<usize, Error> {
+    let (_, pos) = read_name(message, pos)?;
+    let end = pos + 10 + get_u16(message, pos + 8)? as usize;
+    if end > message.len() {
+        return Err(malformed());
+    }
+    Ok(end)
+}
+
+/// Name of a response code, or of a TSIG error
+fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        rcode => format!("RCODE{}", rcode),
+    }
+}
+
+/// TSIG variables covered by the MAC, after the message
+fn put_tsig_variables(data: &mut Vec<u8>, key_name: &str, algorithm: &str, time: u64, fudge: u16, error: u16, other: &[u8]) -> Result<(), Error> {
+    put_name(data, &key_name.to_ascii_lowercase())?;
+    put_u16(data, CLASS_ANY);
+    put_u32(data, 0);
+    put_name(data, algorithm)?;
+    data.extend_from_slice(&time.to_be_bytes()[2..]);
+    put_u16(data, fudge);
+    put_u16(data, error);
+    put_u16(data, other.len() as u16);
+    data.extend_from_slice(other);
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Sign `message` with a TSIG record at time `time`, and return the MAC. `request_mac` is
+/// the MAC of the request when signing a response.
+pub fn sign(message: &mut Vec<u8>, key_name: &str, key: &TsigKey, time: u64, request_mac: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    if message.len() < 12 {
+        return Err(malformed());
+    }
+    let algorithm = key.algorithm_name()?;
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        put_u16(&mut data, request_mac.len() as u16);
+        data.extend_from_slice(request_mac);
+    }
+    data.extend_from_slice(message);
+    put_tsig_variables(&mut data, key_name, algorithm, time, TSIG_FUDGE, 0, &[])?;
+    let mac = key.mac(&data)?;
+
+    let mut rdata = Vec::new();
+    put_name(&mut rdata, algorithm)?;
+    rdata.extend_from_slice(&time.to_be_bytes()[2..]);
+    put_u16(&mut rdata, TSIG_FUDGE);
+    put_u16(&mut rdata, mac.len() as u16);
+    rdata.extend_from_slice(&mac);
+    rdata.extend_from_slice(&message[0..2]);
+    put_u16(&mut rdata, 0);
+    put_u16(&mut rdata, 0);
+
+    put_name(message, &key_name.to_ascii_lowercase())?;
+    put_u16(message, TYPE_TSIG);
+    put_u16(message, CLASS_ANY);
+    put_u32(message, 0);
+    put_u16(message, rdata.len() as u16);
+    message.extend_from_slice(&rdata);
+    let additional = get_u16(message, 10)? + 1;
+    message[10..12].copy_from_slice(&additional.to_be_bytes());
+    Ok(mac)
+}
+
+/// Check the TSIG record at the end of `message` against `key` at time `now`, and return its
+/// MAC. `request_mac` is the MAC of the request when checking a response.
+pub fn verify(message: &[u8], key_name: &str, key: &TsigKey, now: u64, request_mac: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let additional = get_u16(message, 10)?;
+    if additional == 0 {
+        return Err(Error::new(ErrorKind::Unauthorized, "DNS message is not signed".to_string()));
+    }
+    let mut pos = 12;
+    for _ in 0..get_u16(message, 4)? {
+        pos = read_name(message, pos)?.1 + 4;
+    }
+    let records = get_u16(message, 6)? as usize + get_u16(message, 8)? as usize + additional as usize - 1;
+    for _ in 0..records {
+        pos = skip_record(message, pos)?;
+    }
+
+    let tsig_start = pos;
+    let (owner, pos) = read_name(message, pos)?;
+    if get_u16(message, pos)? != TYPE_TSIG {
+        return Err(Error::new(ErrorKind::Unauthorized, "DNS message is not signed".to_string()));
+    }
+    if owner != key_name.trim_end_matches('.').to_ascii_lowercase() {
+        return Err(Error::new(ErrorKind::Unauthorized, format!("DNS message is signed with another key: {}", owner)));
+    }
+    let (algorithm, pos) = read_name(message, pos + 10)?;
+    if algorithm != key.algorithm_name()? {
+        return Err(Error::new(ErrorKind::Unauthorized, format!("DNS message is signed with another algorithm: {}", algorithm)));
+    }
+    let time_bytes = message.get(pos..pos + 6).ok_or_else(malformed)?;
+    let time = time_bytes.iter().fold(0u64, |time, byte| (time << 8) | *byte as u64);
+    let fudge = get_u16(message, pos + 6)?;
+    let mac_len = get_u16(message, pos + 8)? as usize;
+    let mac = message.get(pos + 10..pos + 10 + mac_len).ok_or_else(malformed)?;
+    let pos = pos + 10 + mac_len;
+    let original_id = message.get(pos..pos + 2).ok_or_else(malformed)?;
+    let error = get_u16(message, pos + 2)?;
+    let other_len = get_u16(message, pos + 4)? as usize;
+    let other = message.get(pos + 6..pos + 6 + other_len).ok_or_else(malformed)?;
+    if error != 0 {
+        return Err(Error::new(ErrorKind::Unauthorized, format!("TSIG error {}", rcode_name(error))));
+    }
+
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        put_u16(&mut data, request_mac.len() as u16);
+        data.extend_from_slice(request_mac);
+    }
+    let unsigned_start = data.len();
+    data.extend_from_slice(&message[..tsig_start]);
+    data[unsigned_start..unsigned_start + 2].copy_from_slice(original_id);
+    data[unsigned_start + 10..unsigned_start + 12].copy_from_slice(&(additional - 1).to_be_bytes());
+    put_tsig_variables(&mut data, key_name, &algorithm, time, fudge, error, other)?;
+    let expected = key.mac(&data)?;
+    // Constant time, so that the comparison does not reveal how much of the MAC is right
+    let difference = expected.iter().zip(mac).fold(0u8, |difference, (a, b)| difference | (a ^ b));
+    if expected.len() != mac.len() || difference != 0 {
+        return Err(Error::new(ErrorKind::Unauthorized, "Wrong TSIG signature".to_string()));
+    }
+    if now.abs_diff(time) > fudge as u64 {
+        return Err(Error::new(ErrorKind::Unauthorized, "TSIG signature time is off".to_string()));
+    }
+    Ok(mac.to_vec())
+}
+
+/// `server` with the default port if it has none
+fn server_address(server: &str) -> String {
+    if server.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", server, DEFAULT_PORT)
+    } else if server.starts_with('[') || server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:{}", server, DEFAULT_PORT)
+    }
+}
+
+/// Send one message over DNS over TCP, and read the answer
+async fn exchange(stream: &mut TcpStream, message: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::with_capacity(message.len() + 2);
+    put_u16(&mut request, message.len() as u16);
+    request.extend_from_slice(message);
+    stream.write_all(&request).await?;
+    let len = stream.read_u16().await?;
+    let mut response = vec![0; len as usize];
+    stream.read_exact(&mut response).await?;
+    Ok(response)
+}
+
+/// Send `update` to `server`, signed with the key `key_name`, and wait until the server has
+/// applied all of it
+pub async fn send_update(server: &str, update: &ZoneUpdate, key_name: &str, key: &TsigKey, timeout: Duration) -> Result<(), Error> {
+    let messages = update.messages()?;
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let address = server_address(server);
+    let mut stream = match tokio::time::timeout(timeout, TcpStream::connect(&address)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(Error::new(ErrorKind::Other, format!("Cannot connect to {}: {}", address, e))),
+        Err(_) => return Err(Error::new(ErrorKind::Other, format!("Timed out connecting to {}", address))),
+    };
+    for mut message in messages {
+        let id = [message[0], message[1]];
+        let request_mac = sign(&mut message, key_name, key, now(), None)?;
+        let response = match tokio::time::timeout(timeout, exchange(&mut stream, &message)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err(Error::new(ErrorKind::Other, format!("Error talking to {}: {}", address, e))),
+            Err(_) => return Err(Error::new(ErrorKind::Other, format!("Timed out waiting for {}", address))),
+        };
+        if response.len() < 12 || response[0..2] != id || response[2] & 0x80 == 0 {
+            return Err(Error::new(ErrorKind::Other, format!("Unexpected answer from {}", address)));
+        }
+        let rcode = (response[3] & 0x0f) as u16;
+        // Servers do not sign answers to requests they cannot verify
+        if rcode == 0 || get_u16(&response, 10)? > 0 {
+            verify(&response, key_name, key, now(), Some(&request_mac))?;
+        }
+        if rcode != 0 {
+            return Err(Error::new(ErrorKind::Other, format!("{} answered {}", address, rcode_name(rcode))));
+        }
+    }
+    Ok(())
+}
+
+/// Targets of a record set before a change, and its records after, by zone, owner name and type
+type RecordSetChanges = BTreeMap<(String, String, &'static str), (Vec<String>, Vec<ReverseRecord>)>;
+
+/// An update waiting to be sent
+#[derive(Debug)]
+struct Job {
+    server: String,
+    key: String,
+    update: ZoneUpdate,
+}
+
+/// Sends dynamic updates in the background, in the order they were pushed to each server
+#[derive(Debug)]
+pub struct DnsUpdater {
+    keys: BTreeMap<String, TsigKey>,
+    config: DnsUpdateConfig,
+    ttl: u32,
+
+    /// Queues by server, each started by the first update to its server
+    queues: Mutex<BTreeMap<String, mpsc::UnboundedSender<Job>>>,
+}
+
+impl DnsUpdater {
+    pub fn new(config: &DnsConfig) -> Self {
+        DnsUpdater {
+            keys: config.keys.iter().map(|(name, key)| (name.trim_end_matches('.').to_ascii_lowercase(), key.clone())).collect(),
+            config: config.update.clone(),
+            ttl: config.ttl,
+            queues: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether the TSIG key `name` is configured
+    pub fn has_key(&self, name: &str) -> bool {
+        self.keys.contains_key(&name.trim_end_matches('.').to_ascii_lowercase())
+    }
+
+    /// Push the change of an assignment's records from `before` to `after`, as returned by
+    /// [`crate::dns::assignment_reverse_records`], if its space has dynamic updates
+    pub async fn push<T>(&self, store: &Store<T>, family: ResourceFamily, space_id: i32, before: Vec<(String, Vec<ReverseRecord>)>, after: Vec<(String, Vec<ReverseRecord>)>)
+    where
+        T: DbConnection + Clone + Send + Sync + 'static,
+    {
+        if before == after {
+            return;
+        }
+        let store = store.clone();
+        let settings = match tokio::task::spawn_blocking(move || store.reverse_dns().get_dns_update(family, space_id)).await {
+            Ok(Ok(Some(settings))) => settings,
+            Ok(Ok(None)) => return,
+            Ok(Err(e)) => {
+                log::error!("Error getting dynamic DNS update settings of space {}: {}", space_id, e);
+                return;
+            },
+            Err(e) => {
+                log::error!("Error getting dynamic DNS update settings of space {}: {}", space_id, e);
+                return;
+            },
+        };
+
+        let mut record_sets = RecordSetChanges::new();
+        for (is_after, zones) in [(false, before), (true, after)] {
+            for (zone, records) in zones {
+                let zone = settings.zone.clone().unwrap_or(zone);
+                for record in records {
+                    if record.name != zone && !record.name.ends_with(&format!(".{}", zone)) {
+                        log::warn!("Not updating {} {}: outside the zone {}", record.name, record.rtype, zone);
+                        continue;
+                    }
+                    let record_set = record_sets.entry((zone.clone(), record.name.clone(), record.rtype)).or_default();
+                    if is_after {
+                        record_set.1.push(record);
+                    } else {
+                        record_set.0.push(record.target);
+                    }
+                }
+            }
+        }
+
+        let mut updates: BTreeMap<String, ZoneUpdate> = BTreeMap::new();
+        for ((zone, name, rtype), (before, after)) in record_sets {
+            if before == after.iter().map(|record| record.target.clone()).collect::<Vec<_>>() {
+                continue;
+            }
+            let update = updates.entry(zone.clone()).or_insert_with(|| ZoneUpdate { zone, ttl: self.ttl, ..Default::default() });
+            update.delete.insert((name, rtype));
+            update.add.extend(after);
+        }
+        for update in updates.into_values() {
+            self.submit(Job {
+                server: settings.server.clone(),
+                key: settings.key.clone(),
+                update,
+            });
+        }
+    }
+
+    fn submit(&self, job: Job) {
+        let mut queues = self.queues.lock().unwrap();
+        let sender = queues.entry(job.server.clone()).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(run(receiver, self.keys.clone(), self.config.clone()));
+            sender
+        });
+        let _ = sender.send(job);
+    }
+}
+
+/// Send the jobs of one server one at a time, retrying each before going on to the next
+async fn run(mut receiver: mpsc::UnboundedReceiver<Job>, keys: BTreeMap<String, TsigKey>, config: DnsUpdateConfig) {
+    while let Some(job) = receiver.recv().await {
+        let Some(key) = keys.get(&job.key) else {
+            log::error!("Not updating zone {} on {}: no TSIG key {} in dns.keys", job.update.zone, job.server, job.key);
+            continue;
+        };
+        let mut retries = 0;
+        loop {
+            match send_update(&job.server, &job.update, &job.key, key, Duration::from_secs(config.timeout)).await {
+                Ok(()) => {
+                    log::info!("Updated zone {} on {}: {} record sets replaced by {} records", job.update.zone, job.server, job.update.delete.len(), job.update.add.len());
+                    break;
+                },
+                Err(e) if retries < config.retries => {
+                    let delay = config.retry_interval.saturating_mul(1 << retries.min(16));
+                    log::warn!("Updating zone {} on {} failed, retrying in {} s: {}", job.update.zone, job.server, delay, e);
+                    tokio::time::sleep(Duration::from_secs(delay)).await;
+                    retries += 1;
+                },
+                Err(e) => {
+                    log::error!("Updating zone {} on {} failed, giving up: {}", job.update.zone, job.server, e);
+                    break;
+                },
+            }
+        }
+    }
+}
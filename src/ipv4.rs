@@ -1,6 +1,7 @@
 
 use crate::types::Error;
-use crate::types::ObjectVisibility;
+use crate::types::{HasVisibility, ObjectVisibility, normalize_mac_address};
+use crate::dns::{absolute_name, ReverseDns};
 
 use serde::{Serialize, Deserialize};
 
@@ -41,6 +42,15 @@ pub fn ipv4_broadcast_address(ip: RawIpv4Addr, prefix_len: u8) -> RawIpv4Addr {
     addr
 }
 
+/// Addresses of a network that cannot be given to hosts: the network and broadcast addresses,
+/// except in /31 and /32 networks, which have none (RFC 3021)
+pub fn ipv4_reserved_addresses(ip: RawIpv4Addr, prefix_len: u8) -> Vec<RawIpv4Addr> {
+    if prefix_len >= 31 {
+        return Vec::new();
+    }
+    vec![ipv4_network_address(ip, prefix_len), ipv4_broadcast_address(ip, prefix_len)]
+}
+
 /// IPv4 assignment space. Can contain multiple pools.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentSpaceIpv4 {
@@ -63,6 +73,12 @@ pub struct AssignmentSpaceIpv4 {
     pub ipv4_prefix_len: i32,
 }
 
+impl HasVisibility for AssignmentSpaceIpv4 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.space_visibility
+    }
+}
+
 /// IPv4 assignment pool. Can contain multiple assignments.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentPoolIpv4 {
@@ -88,6 +104,12 @@ pub struct AssignmentPoolIpv4 {
     pub ipv4_prefix_len: i32,
 }
 
+impl HasVisibility for AssignmentPoolIpv4 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.pool_visibility
+    }
+}
+
 /// IPv4 assignment to a specific entity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentIpv4 {
@@ -111,8 +133,63 @@ pub struct AssignmentIpv4 {
 
     /// Length of the IPv4 prefix
     pub ipv4_prefix_len: i32,
+
+    /// ASN assignment originating the prefix, for route objects
+    #[serde(default)]
+    pub origin_asn_id: Option<i32>,
 }
 
+impl HasVisibility for AssignmentIpv4 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.assignment_visibility
+    }
+}
+
+
+/// An address within an IPv4 assignment, such as a server in a server subnet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostIpv4 {
+    #[serde(default)]
+    pub id: i32,
+
+    /// Parent assignment ID
+    pub assignment_id: i32,
+
+    /// IPv4 address of the host, in big-endian bit and byte order
+    pub ipv4_address: [u8; 4],
+
+    /// Host name, such as `web1.example.net`; may be empty
+    #[serde(default)]
+    pub hostname: String,
+
+    /// MAC address, such as `00:00:5e:00:53:01`
+    #[serde(default)]
+    pub mac_address: Option<String>,
+
+    /// Document the purpose of the host, its owner, etc.
+    #[serde(default)]
+    pub description: String,
+
+    /// The host is the default gateway of the assignment
+    #[serde(default)]
+    pub gateway: bool,
+}
+
+impl HostIpv4 {
+    /// Check the host name and MAC address, and return the host with them normalized
+    pub fn normalize(&self) -> Result<HostIpv4, Error> {
+        let mut host = self.clone();
+        host.hostname = match self.hostname.trim() {
+            "" => String::new(),
+            name => absolute_name(name)?.trim_end_matches('.').to_ascii_lowercase(),
+        };
+        host.mac_address = match self.mac_address.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(mac) => Some(normalize_mac_address(mac)?),
+        };
+        Ok(host)
+    }
+}
 
 pub trait Ipv4AssignmentStore {
     /// Get an assignment space by ID
@@ -121,6 +198,9 @@ pub trait Ipv4AssignmentStore {
     /// Get all assignment spaces
     fn get_spaces(&self) -> Result<Vec<AssignmentSpaceIpv4>, Error>;
 
+    /// Get the assignment spaces containing `prefix/prefix_len`
+    fn get_spaces_containing(&self, prefix: RawIpv4Addr, prefix_len: u8) -> Result<Vec<AssignmentSpaceIpv4>, Error>;
+
     /// Create a new assignment space
     /// Returns the ID of the new assignment space
     /// ID in input is ignored
@@ -139,6 +219,9 @@ pub trait Ipv4AssignmentStore {
     /// Get all assignment pools in a space
     fn get_pools(&self, space_id: i32) -> Result<Vec<AssignmentPoolIpv4>, Error>;
 
+    /// Get the assignment pools in a space containing `prefix/prefix_len`
+    fn get_pools_containing(&self, space_id: i32, prefix: RawIpv4Addr, prefix_len: u8) -> Result<Vec<AssignmentPoolIpv4>, Error>;
+
     /// Create a new assignment pool
     /// Returns the ID of the new assignment pool
     /// ID in input is ignored
@@ -157,14 +240,49 @@ pub trait Ipv4AssignmentStore {
     /// Get all assignments in a pool
     fn get_assignments(&self, pool_id: i32) -> Result<Vec<AssignmentIpv4>, Error>;
 
+    /// Get the assignments in a pool containing `prefix/prefix_len`
+    fn get_assignments_containing(&self, pool_id: i32, prefix: RawIpv4Addr, prefix_len: u8) -> Result<Vec<AssignmentIpv4>, Error>;
+
     /// Create a new assignment
     /// Returns the ID of the new assignment
     /// ID in input is ignored
     fn create_assignment(&self, assignment: &AssignmentIpv4) -> Result<i32, Error>;
 
+    /// Create a new assignment along with its reverse DNS, or neither
+    /// Returns the ID of the new assignment
+    fn create_assignment_with_reverse_dns(&self, assignment: &AssignmentIpv4, reverse_dns: &ReverseDns) -> Result<i32, Error>;
+
     /// Update metadata for an assignment
     fn update_assignment(&self, id: i32, name: &str, description: &str) -> Result<(), Error>;
 
+    /// Set or clear the origin ASN assignment of an assignment
+    fn update_assignment_origin_asn(&self, id: i32, origin_asn_id: Option<i32>) -> Result<(), Error>;
+
     /// Delete an assignment
+    /// Also deletes all hosts in the assignment
     fn delete_assignment(&self, assignment_id: i32) -> Result<(), Error>;
+
+    /// Get a host by ID
+    fn get_host(&self, host_id: i32) -> Result<HostIpv4, Error>;
+
+    /// Get all hosts in an assignment, ordered by address
+    fn get_hosts(&self, assignment_id: i32) -> Result<Vec<HostIpv4>, Error>;
+
+    /// Create a new host
+    /// The address must be within the assignment, not reserved and not taken by another host,
+    /// and an assignment has at most one gateway
+    /// Returns the ID of the new host
+    /// ID in input is ignored
+    fn create_host(&self, host: &HostIpv4) -> Result<i32, Error>;
+
+    /// Update a host, with the same checks as `create_host`
+    /// The host stays in its assignment
+    fn update_host(&self, id: i32, host: &HostIpv4) -> Result<(), Error>;
+
+    /// Delete a host
+    fn delete_host(&self, host_id: i32) -> Result<(), Error>;
+
+    /// First address of an assignment that is free for a new host, if any
+    /// Unless the assignment has a gateway, its first usable address is kept for one
+    fn next_free_host(&self, assignment_id: i32) -> Result<Option<RawIpv4Addr>, Error>;
 }
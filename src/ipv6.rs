@@ -1,6 +1,7 @@
 
 use crate::types::Error;
-use crate::types::ObjectVisibility;
+use crate::types::{HasVisibility, ObjectVisibility, normalize_mac_address};
+use crate::dns::{absolute_name, ReverseDns};
 
 use serde::{Serialize, Deserialize};
 
@@ -41,6 +42,14 @@ pub fn ipv6_broadcast_address(ip: RawIpv6Addr, prefix_len: u8) -> RawIpv6Addr {
     addr
 }
 
+/// Addresses of a network that cannot be given to hosts: the Subnet-Router anycast address
+/// (the first address), except in /127 and /128 networks, which have none (RFC 6164)
+pub fn ipv6_reserved_addresses(ip: RawIpv6Addr, prefix_len: u8) -> Vec<RawIpv6Addr> {
+    if prefix_len >= 127 {
+        return Vec::new();
+    }
+    vec![ipv6_network_address(ip, prefix_len)]
+}
 
 /// IPv6 assignment space. Can contain multiple pools.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,6 +73,12 @@ pub struct AssignmentSpaceIpv6 {
     pub ipv6_prefix_len: i32,
 }
 
+impl HasVisibility for AssignmentSpaceIpv6 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.space_visibility
+    }
+}
+
 /// IPv6 assignment pool. Can contain multiple assignments.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentPoolIpv6 {
@@ -89,6 +104,12 @@ pub struct AssignmentPoolIpv6 {
     pub ipv6_prefix_len: i32,
 }
 
+impl HasVisibility for AssignmentPoolIpv6 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.pool_visibility
+    }
+}
+
 /// IPv6 assignment to a specific entity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentIpv6 {
@@ -112,6 +133,61 @@ pub struct AssignmentIpv6 {
 
     /// Length of the IPv6 prefix
     pub ipv6_prefix_len: i32,
+
+    /// ASN assignment originating the prefix, for route objects
+    #[serde(default)]
+    pub origin_asn_id: Option<i32>,
+}
+
+impl HasVisibility for AssignmentIpv6 {
+    fn visibility(&self) -> ObjectVisibility {
+        self.assignment_visibility
+    }
+}
+
+/// An address within an IPv6 assignment, such as a server in a server subnet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostIpv6 {
+    #[serde(default)]
+    pub id: i32,
+
+    /// Parent assignment ID
+    pub assignment_id: i32,
+
+    /// IPv6 address of the host, in big-endian bit and byte order
+    pub ipv6_address: [u8; 16],
+
+    /// Host name, such as `web1.example.net`; may be empty
+    #[serde(default)]
+    pub hostname: String,
+
+    /// MAC address, such as `00:00:5e:00:53:01`
+    #[serde(default)]
+    pub mac_address: Option<String>,
+
+    /// Document the purpose of the host, its owner, etc.
+    #[serde(default)]
+    pub description: String,
+
+    /// The host is the default gateway of the assignment
+    #[serde(default)]
+    pub gateway: bool,
+}
+
+impl HostIpv6 {
+    /// Check the host name and MAC address, and return the host with them normalized
+    pub fn normalize(&self) -> Result<HostIpv6, Error> {
+        let mut host = self.clone();
+        host.hostname = match self.hostname.trim() {
+            "" => String::new(),
+            name => absolute_name(name)?.trim_end_matches('.').to_ascii_lowercase(),
+        };
+        host.mac_address = match self.mac_address.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(mac) => Some(normalize_mac_address(mac)?),
+        };
+        Ok(host)
+    }
 }
 
 pub trait Ipv6AssignmentStore {
@@ -121,6 +197,9 @@ pub trait Ipv6AssignmentStore {
     /// Get all assignment spaces
     fn get_spaces(&self) -> Result<Vec<AssignmentSpaceIpv6>, Error>;
 
+    /// Get the assignment spaces containing `prefix/prefix_len`
+    fn get_spaces_containing(&self, prefix: RawIpv6Addr, prefix_len: u8) -> Result<Vec<AssignmentSpaceIpv6>, Error>;
+
     /// Create a new assignment space
     /// Returns the ID of the new assignment space
     /// ID in input is ignored
@@ -139,6 +218,9 @@ pub trait Ipv6AssignmentStore {
     /// Get all assignment pools in a space
     fn get_pools(&self, space_id: i32) -> Result<Vec<AssignmentPoolIpv6>, Error>;
 
+    /// Get the assignment pools in a space containing `prefix/prefix_len`
+    fn get_pools_containing(&self, space_id: i32, prefix: RawIpv6Addr, prefix_len: u8) -> Result<Vec<AssignmentPoolIpv6>, Error>;
+
     /// Create a new assignment pool
     /// Returns the ID of the new assignment pool
     /// ID in input is ignored
@@ -157,14 +239,49 @@ pub trait Ipv6AssignmentStore {
     /// Get all assignments in a pool
     fn get_assignments(&self, pool_id: i32) -> Result<Vec<AssignmentIpv6>, Error>;
 
+    /// Get the assignments in a pool containing `prefix/prefix_len`
+    fn get_assignments_containing(&self, pool_id: i32, prefix: RawIpv6Addr, prefix_len: u8) -> Result<Vec<AssignmentIpv6>, Error>;
+
     /// Create a new assignment
     /// Returns the ID of the new assignment
     /// ID in input is ignored
     fn create_assignment(&self, assignment: &AssignmentIpv6) -> Result<i32, Error>;
 
+    /// Create a new assignment along with its reverse DNS, or neither
+    /// Returns the ID of the new assignment
+    fn create_assignment_with_reverse_dns(&self, assignment: &AssignmentIpv6, reverse_dns: &ReverseDns) -> Result<i32, Error>;
+
     /// Update metadata for an assignment
     fn update_assignment(&self, id: i32, name: &str, description: &str) -> Result<(), Error>;
 
+    /// Set or clear the origin ASN assignment of an assignment
+    fn update_assignment_origin_asn(&self, id: i32, origin_asn_id: Option<i32>) -> Result<(), Error>;
+
     /// Delete an assignment
+    /// Also deletes all hosts in the assignment
     fn delete_assignment(&self, assignment_id: i32) -> Result<(), Error>;
+
+    /// Get a host by ID
+    fn get_host(&self, host_id: i32) -> Result<HostIpv6, Error>;
+
+    /// Get all hosts in an assignment, ordered by address
+    fn get_hosts(&self, assignment_id: i32) -> Result<Vec<HostIpv6>, Error>;
+
+    /// Create a new host
+    /// The address must be within the assignment, not reserved and not taken by another host,
+    /// and an assignment has at most one gateway
+    /// Returns the ID of the new host
+    /// ID in input is ignored
+    fn create_host(&self, host: &HostIpv6) -> Result<i32, Error>;
+
+    /// Update a host, with the same checks as `create_host`
+    /// The host stays in its assignment
+    fn update_host(&self, id: i32, host: &HostIpv6) -> Result<(), Error>;
+
+    /// Delete a host
+    fn delete_host(&self, host_id: i32) -> Result<(), Error>;
+
+    /// First address of an assignment that is free for a new host, if any
+    /// Unless the assignment has a gateway, its first usable address is kept for one
+    fn next_free_host(&self, assignment_id: i32) -> Result<Option<RawIpv6Addr>, Error>;
 }
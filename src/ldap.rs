@@ -0,0 +1,363 @@
+//! LDAP password authentication
+//!
+//! [`LdapConnection`] wraps another [`DbConnection`] and checks passwords against an LDAP
+//! directory. Everything else (users, roles, API tokens, 2FA, login attempts) stays in the
+//! wrapped store. Users who log in through LDAP get a local user on their first login if
+//! `create_users` is set; their role follows their directory groups if `admin_groups` is set.
+
+use crate::store::{DbConnection, DbConnectionWrapper};
+use crate::types::{Error, ErrorKind};
+use crate::user::*;
+
+use ldap3::{LdapConn, LdapConnSettings, Scope, SearchEntry};
+use ldap3::{dn_escape, ldap_escape};
+
+use serde::{Serialize, Deserialize};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+
+/// LDAP result code for a failed bind
+const RC_INVALID_CREDENTIALS: u32 = 49;
+
+fn default_user_filter() -> String {
+    "(uid={username})".to_string()
+}
+
+fn default_group_filter() -> String {
+    "(|(member={dn})(uniqueMember={dn})(memberUid={username}))".to_string()
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+/// How the DN of a user is found before binding with their password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "mode")]
+pub enum LdapBindMode {
+    /// Bind directly to a DN built from a template, such as
+    /// `uid={username},ou=people,dc=example,dc=org`
+    Direct {
+        user_dn_template: String,
+    },
+
+    /// Search for the user (optionally as a service account), then bind to the DN found
+    Search {
+        base_dn: String,
+
+        /// `{username}` is replaced with the escaped username
+        #[serde(default = "default_user_filter")]
+        filter: String,
+
+        #[serde(default)]
+        bind_dn: Option<String>,
+
+        #[serde(default)]
+        bind_password: Option<String>,
+    },
+}
+
+/// LDAP directory settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the server
+    pub url: String,
+
+    /// Upgrade `ldap://` connections with StartTLS
+    #[serde(default)]
+    pub starttls: bool,
+
+    /// Connection timeout in seconds
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+
+    #[serde(flatten)]
+    pub bind: LdapBindMode,
+
+    /// Base DN for group searches. Groups are not looked up if unset.
+    #[serde(default)]
+    pub group_base_dn: Option<String>,
+
+    /// `{dn}` and `{username}` are replaced with the escaped user DN and username
+    #[serde(default = "default_group_filter")]
+    pub group_filter: String,
+
+    /// Groups (DN or cn) whose members are admins. If empty, roles are managed locally.
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+
+    /// Groups (DN or cn) whose members may log in. If empty, every directory user may.
+    /// Members of `admin_groups` may always log in.
+    #[serde(default)]
+    pub user_groups: Vec<String>,
+
+    /// Create local users on their first login
+    #[serde(default)]
+    pub create_users: bool,
+
+    /// Users that log in with their local password instead, e.g. a break-glass admin
+    #[serde(default)]
+    pub local_users: Vec<String>,
+}
+
+impl LdapConfig {
+    /// DN to bind to for `username` in direct bind mode
+    pub fn user_dn(&self, username: &str) -> Option<String> {
+        match &self.bind {
+            LdapBindMode::Direct { user_dn_template } => Some(user_dn_template.replace("{username}", &dn_escape(username))),
+            LdapBindMode::Search { .. } => None,
+        }
+    }
+
+    /// Search filter for `username` in search mode
+    pub fn user_filter(&self, username: &str) -> Option<String> {
+        match &self.bind {
+            LdapBindMode::Direct { .. } => None,
+            LdapBindMode::Search { filter, .. } => Some(filter.replace("{username}", &ldap_escape(username))),
+        }
+    }
+
+    pub fn group_filter(&self, dn: &str, username: &str) -> String {
+        self.group_filter
+            .replace("{dn}", &ldap_escape(dn))
+            .replace("{username}", &ldap_escape(username))
+    }
+
+    /// Role for a user in `groups` (DNs and cns), or `None` if the user may not log in
+    pub fn map_groups(&self, groups: &[String]) -> Option<Option<UserRole>> {
+        let member_of = |wanted: &[String]| wanted.iter().any(|wanted| groups.iter().any(|group| group.eq_ignore_ascii_case(wanted)));
+        let is_admin = member_of(&self.admin_groups);
+        if !is_admin && !self.user_groups.is_empty() && !member_of(&self.user_groups) {
+            return None;
+        }
+        if self.admin_groups.is_empty() {
+            Some(None)
+        } else if is_admin {
+            Some(Some(UserRole::Admin))
+        } else {
+            Some(Some(UserRole::User))
+        }
+    }
+
+    pub fn is_local_user(&self, username: &str) -> bool {
+        self.local_users.iter().any(|local| local == username)
+    }
+}
+
+/// A user authenticated by the directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapIdentity {
+    pub dn: String,
+
+    /// DNs and cns of the groups of the user
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct LdapAuthenticator {
+    config: LdapConfig,
+}
+
+impl LdapAuthenticator {
+    pub fn new(config: LdapConfig) -> Self {
+        LdapAuthenticator { config }
+    }
+
+    pub fn config(&self) -> &LdapConfig {
+        &self.config
+    }
+
+    fn connect(&self) -> Result<LdapConn, Error> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(self.config.timeout))
+            .set_starttls(self.config.starttls);
+        Ok(LdapConn::with_settings(settings, &self.config.url)?)
+    }
+
+    /// Bind, returning `false` for invalid credentials
+    fn bind(ldap: &mut LdapConn, dn: &str, password: &str) -> Result<bool, Error> {
+        let res = ldap.simple_bind(dn, password)?;
+        if res.rc == RC_INVALID_CREDENTIALS {
+            return Ok(false);
+        }
+        res.success()?;
+        Ok(true)
+    }
+
+    /// Check a password against the directory. `Ok(None)` means the user does not exist
+    /// or the password is wrong.
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<Option<LdapIdentity>, Error> {
+        // An empty password would make an unauthenticated bind, which always succeeds.
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ldap = self.connect()?;
+        let res = self.authenticate_with(&mut ldap, username, password);
+        let _ = ldap.unbind();
+        res
+    }
+
+    fn authenticate_with(&self, ldap: &mut LdapConn, username: &str, password: &str) -> Result<Option<LdapIdentity>, Error> {
+        let dn = match &self.config.bind {
+            LdapBindMode::Direct { .. } => self.config.user_dn(username).unwrap(),
+            LdapBindMode::Search { base_dn, bind_dn, bind_password, .. } => {
+                if let Some(bind_dn) = bind_dn {
+                    if !Self::bind(ldap, bind_dn, bind_password.as_deref().unwrap_or_default())? {
+                        return Err(Error::new(ErrorKind::Other, "LDAP service account bind failed".to_string()));
+                    }
+                }
+                let filter = self.config.user_filter(username).unwrap();
+                let (entries, _) = ldap.search(base_dn, Scope::Subtree, &filter, vec!["1.1"])?.success()?;
+                match entries.len() {
+                    0 => return Ok(None),
+                    1 => SearchEntry::construct(entries.into_iter().next().unwrap()).dn,
+                    _ => return Err(Error::new(ErrorKind::Other, format!("LDAP search for {} matched more than one entry", username))),
+                }
+            },
+        };
+
+        if !Self::bind(ldap, &dn, password)? {
+            return Ok(None);
+        }
+
+        // Search groups as the service account if there is one; otherwise as the user.
+        if let LdapBindMode::Search { bind_dn: Some(bind_dn), bind_password, .. } = &self.config.bind {
+            if !Self::bind(ldap, bind_dn, bind_password.as_deref().unwrap_or_default())? {
+                return Err(Error::new(ErrorKind::Other, "LDAP service account bind failed".to_string()));
+            }
+        }
+
+        let mut groups = Vec::new();
+        if let Some(group_base_dn) = &self.config.group_base_dn {
+            let filter = self.config.group_filter(&dn, username);
+            let (entries, _) = ldap.search(group_base_dn, Scope::Subtree, &filter, vec!["cn"])?.success()?;
+            for entry in entries {
+                let entry = SearchEntry::construct(entry);
+                groups.extend(entry.attrs.get("cn").cloned().unwrap_or_default());
+                groups.push(entry.dn);
+            }
+        }
+
+        Ok(Some(LdapIdentity { dn, groups }))
+    }
+}
+
+/// A [`DbConnection`] whose user stores check passwords against LDAP
+#[derive(Debug, Clone)]
+pub struct LdapConnection<T>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    db: T,
+    ldap: Arc<LdapAuthenticator>,
+}
+
+impl<T> LdapConnection<T>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    pub fn new(db: T, config: LdapConfig) -> Self {
+        LdapConnection {
+            db,
+            ldap: Arc::new(LdapAuthenticator::new(config)),
+        }
+    }
+}
+
+impl<T> DbConnectionWrapper for LdapConnection<T>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    type Inner = T;
+
+    fn inner(&self) -> &T {
+        &self.db
+    }
+
+    fn user_store(&self) -> Box<dyn UserStore> {
+        Box::new(LdapUserStore {
+            inner: self.db.user_store(),
+            ldap: self.ldap.clone(),
+        })
+    }
+}
+
+/// User store that checks passwords against LDAP and delegates everything else
+pub struct LdapUserStore {
+    inner: Box<dyn UserStore>,
+    ldap: Arc<LdapAuthenticator>,
+}
+
+impl LdapUserStore {
+    /// Make sure the local user exists and has the role of its groups
+    fn sync_user(&self, username: &str, identity: &LdapIdentity) -> Result<bool, Error> {
+        let config = self.ldap.config();
+        let role = match config.map_groups(&identity.groups) {
+            Some(role) => role,
+            None => {
+                log::info!("LDAP user {} is not in any allowed group", username);
+                return Ok(false);
+            },
+        };
+
+        match self.inner.get_user_role(username) {
+            Ok(current) => {
+                if let Some(role) = role {
+                    if role != current {
+                        self.inner.set_role(username, role)?;
+                    }
+                }
+                Ok(true)
+            },
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound) => {
+                if !config.create_users {
+                    log::info!("LDAP user {} has no local user", username);
+                    return Ok(false);
+                }
+                // The local password is never used for directory users.
+                let mut password = [0u8; 32];
+                rand::Rng::fill(&mut rand::thread_rng(), &mut password);
+                self.inner.create_user(username, &hex::encode(password), role.unwrap_or_default())?;
+                Ok(true)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn check_managed_password(&self, username: &str) -> Result<(), Error> {
+        if self.ldap.config().is_local_user(username) {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::Forbidden, "Password is managed by the LDAP directory".to_string()))
+        }
+    }
+}
+
+impl UserStoreWrapper for LdapUserStore {
+    fn inner(&self) -> &dyn UserStore {
+        self.inner.as_ref()
+    }
+
+    fn check_password(&self, username: &str, password: &str) -> Result<bool, Error> {
+        if self.ldap.config().is_local_user(username) {
+            return self.inner.check_password(username, password);
+        }
+        match self.ldap.authenticate(username, password)? {
+            Some(identity) => self.sync_user(username, &identity),
+            None => Ok(false),
+        }
+    }
+
+    fn set_password(&self, username: &str, password: &str) -> Result<(), Error> {
+        self.check_managed_password(username)?;
+        self.inner.set_password(username, password)
+    }
+
+    fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error> {
+        self.check_managed_password(username)?;
+        self.inner.change_password(username, password, keep_token_id)
+    }
+}
@@ -10,6 +10,15 @@ pub mod ipv6;
 pub mod ipv4;
 pub mod asn;
 pub mod user;
+pub mod totp;
+pub mod ldap;
+pub mod config;
+pub mod lookup;
+pub mod rpsl;
+pub mod roa;
+pub mod dns;
+pub mod dns_update;
+pub mod dhcp;
 
 pub use store::Store;
 pub use types::Error;
@@ -80,6 +89,7 @@ pub mod example_data {
             assignment_visibility: types::ObjectVisibility::Public,
             ipv4_prefix: [192, 168, 1, 1],
             ipv4_prefix_len: 32,
+            origin_asn_id: None,
         };
         ipv4_store.create_assignment(&assignment).unwrap();
 
@@ -110,6 +120,7 @@ pub mod example_data {
             assignment_visibility: types::ObjectVisibility::Public,
             ipv6_prefix: "2001:db8:1:1::".parse::<std::net::Ipv6Addr>().unwrap().octets(),
             ipv6_prefix_len: 64,
+            origin_asn_id: None,
         };
         ipv6_store.create_assignment(&assignment).unwrap();
     }
@@ -121,6 +132,61 @@ pub mod example_data {
 mod tests {
     use super::*;
 
+    /// Router of a test server, with the API token of its `admin` user
+    #[derive(Clone)]
+    struct TestRouter {
+        router: axum::Router,
+        token: String,
+    }
+
+    /// Build the router of `server`, creating an `admin` user to send requests as
+    fn test_router(server: server::Server<db_sqlite::SqliteConnection>) -> TestRouter {
+        let users = server.store().users();
+        users.set_password("admin", "password").unwrap();
+        let token = users.create_api_token("admin", "test", None, &user::TokenScope::default()).unwrap();
+        TestRouter {
+            router: server.build_router(),
+            token: token.secret,
+        }
+    }
+
+    impl TestRouter {
+        /// Send a request with a JSON body, as the admin if `authorized`
+        async fn send(&self, method: &str, path: &str, authorized: bool, body: serde_json::Value) -> http::Response<axum::body::Body> {
+            use tower::ServiceExt;
+
+            let mut request = http::Request::builder()
+                .method(method)
+                .uri(path)
+                .header("Content-Type", "application/json");
+            if authorized {
+                request = request.header("Authorization", format!("Bearer {}", self.token));
+            }
+            let request = request.body(axum::body::Body::from(body.to_string())).unwrap();
+            self.router.clone().oneshot(request).await.unwrap()
+        }
+
+        /// Send a request, as the admin if `authorized`, returning the status and the text of the response
+        async fn text_request(&self, method: &str, path: &str, authorized: bool, body: serde_json::Value) -> (u16, String) {
+            let res = self.send(method, path, authorized, body).await;
+            let status = res.status().as_u16();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+
+        /// Send a request as the admin, returning the status and the JSON response
+        async fn api_request(&self, method: &str, path: &str, body: serde_json::Value) -> (u16, serde_json::Value) {
+            let (status, body) = self.text_request(method, path, true, body).await;
+            (status, serde_json::from_str(&body).unwrap())
+        }
+
+        /// Send a request without credentials, returning the status and the JSON response
+        async fn anonymous_request(&self, method: &str, path: &str, body: serde_json::Value) -> (u16, serde_json::Value) {
+            let (status, body) = self.text_request(method, path, false, body).await;
+            (status, serde_json::from_str(&body).unwrap())
+        }
+    }
+
     #[test]
     fn user_store() {
         let db = db_sqlite::SqliteConnection::open_memory().unwrap();
@@ -133,6 +199,2500 @@ mod tests {
         assert_eq!(user_store.get_user_from_api_key(&key).unwrap(), Some("alice".to_string()));
     }
 
+    #[tokio::test]
+    async fn api_tokens() {
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db.clone());
+        let user_store = store.users();
+        user_store.set_password("alice", "password").unwrap();
+        let key1 = user_store.generate_api_key("alice").unwrap();
+        let key2 = user_store.generate_api_key("alice").unwrap();
+        assert_ne!(key1, key2);
+
+        let scope = user::TokenScope {
+            read_only: true,
+            families: Some(vec![user::ResourceFamily::Ipv4]),
+        };
+        let token = user_store.create_api_token("alice", "read-only", None, &scope).unwrap();
+        let (username, info) = user_store.get_api_token(&token.secret).unwrap().unwrap();
+        assert_eq!(username, "alice");
+        assert_eq!(info.scope, scope);
+        assert!(info.last_used_at.is_some());
+        assert!(scope.allows(&[user::ResourceFamily::Ipv4], false));
+        assert!(!scope.allows(&[user::ResourceFamily::Ipv4], true));
+        assert!(!scope.allows(&[user::ResourceFamily::Asn], false));
+        assert!(!scope.allows(&[user::ResourceFamily::Ipv4, user::ResourceFamily::Ipv6], false));
+        assert!(scope.allows(&[], false));
+
+        let expired = user_store.create_api_token("alice", "expired", Some(user::unix_time_now() - 1), &user::TokenScope::default()).unwrap();
+        assert_eq!(user_store.get_user_from_api_key(&expired.secret).unwrap(), None);
+
+        // Expired tokens are neither listed nor counted, and are deleted once another is created
+        assert_eq!(user_store.list_api_tokens("alice").unwrap().len(), 3);
+        assert_eq!(user_store.get_user("alice").unwrap().api_token_count, 3);
+        let stored_tokens = || db.get_conn().unwrap().query_row("SELECT COUNT(*) FROM api_key", [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(stored_tokens(), 4);
+        user_store.create_api_token("alice", "login", Some(user::unix_time_now() + 60), &user::TokenScope::default()).unwrap();
+        assert_eq!(stored_tokens(), 4);
+        user_store.revoke_api_token("alice", token.token.id).unwrap();
+        assert_eq!(user_store.get_user_from_api_key(&token.secret).unwrap(), None);
+        assert!(user_store.revoke_api_token("alice", token.token.id).is_err());
+        assert_eq!(user_store.get_user_from_api_key(&key1).unwrap(), Some("alice".to_string()));
+
+        // Exports and ROAs expose data of several families, so family-scoped tokens cannot read them
+        let asn_scope = user::TokenScope {
+            read_only: false,
+            families: Some(vec![user::ResourceFamily::Asn]),
+        };
+        let asn_token = user_store.create_api_token("alice", "asn-only", None, &asn_scope).unwrap();
+        let admin = test_router(server::Server::new(store.clone()));
+        let asn_only = TestRouter { token: asn_token.secret, ..admin.clone() };
+        for path in ["/api/v1/export/kea", "/api/v1/export/dns-reverse", "/api/v1/export/dns-forward", "/api/v1/roa"] {
+            assert_ne!(admin.text_request("GET", path, true, serde_json::json!({})).await.0, 403, "{}", path);
+            assert_eq!(asn_only.text_request("GET", path, true, serde_json::json!({})).await.0, 403, "{}", path);
+        }
+        assert_eq!(asn_only.text_request("GET", "/api/v1/asn/assignment_space", true, serde_json::json!({})).await.0, 200);
+        assert_eq!(asn_only.text_request("GET", "/api/v1/user/tokens", true, serde_json::json!({})).await.0, 200);
+
+        // Revoking a token of another user is refused as if it did not exist
+        let path = format!("/api/v1/user/tokens/{}", asn_token.token.id);
+        assert_eq!(admin.text_request("DELETE", &path, true, serde_json::json!({})).await.0, 404);
+        assert_eq!(asn_only.text_request("DELETE", &path, true, serde_json::json!({})).await.0, 403);
+        let alice = TestRouter { token: key1.clone(), ..admin.clone() };
+        assert_eq!(alice.text_request("DELETE", &path, true, serde_json::json!({})).await.0, 200);
+        assert_eq!(alice.text_request("DELETE", &path, true, serde_json::json!({})).await.0, 404);
+    }
+
+    #[tokio::test]
+    async fn password_change_revokes_tokens() {
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let user_store = store.users();
+        user_store.set_password("alice", "password").unwrap();
+        let key1 = user_store.generate_api_key("alice").unwrap();
+        let key2 = user_store.generate_api_key("alice").unwrap();
+        let (_, token2) = user_store.get_api_token(&key2).unwrap().unwrap();
+
+        user_store.change_password("alice", "password2", Some(token2.id)).unwrap();
+        assert!(user_store.check_password("alice", "password2").unwrap());
+        assert!(!user_store.check_password("alice", "password").unwrap());
+        assert_eq!(user_store.get_user_from_api_key(&key1).unwrap(), None);
+        assert_eq!(user_store.get_user_from_api_key(&key2).unwrap(), Some("alice".to_string()));
+
+        user_store.set_password("alice", "password3").unwrap();
+        assert!(user_store.check_password("alice", "password3").unwrap());
+        assert_eq!(user_store.get_user_from_api_key(&key2).unwrap(), None);
+        assert_eq!(user_store.list_users().unwrap(), vec!["alice".to_string()]);
+
+        // Guesses of the current password are throttled like logins
+        let api = test_router(server::Server::new(store.clone()));
+        let change = |old_password: &str| serde_json::json!({ "old_password": old_password, "new_password": "password2" });
+        assert_eq!(api.text_request("PUT", "/api/v1/user/self/password", true, change("wrong")).await.0, 403);
+        assert_eq!(user_store.get_login_failures(&user::LoginThrottleKey::Username("admin".to_string()), 0).unwrap().count, 1);
+        for _ in 0..10 {
+            user_store.record_login_attempt("admin", None, user::LoginOutcome::Failure).unwrap();
+        }
+        let res = api.send("PUT", "/api/v1/user/self/password", true, change("password")).await;
+        assert_eq!(res.status(), 429);
+        assert!(res.headers().contains_key(http::header::RETRY_AFTER));
+        assert!(user_store.check_password("admin", "password").unwrap());
+    }
+
+    #[test]
+    fn user_roles() {
+        use user::UserRole;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let user_store = store.users();
+        user_store.set_password("alice", "password").unwrap();
+        user_store.set_password("bob", "password").unwrap();
+        assert_eq!(user_store.get_user_role("alice").unwrap(), UserRole::Admin);
+        assert_eq!(user_store.get_user_role("bob").unwrap(), UserRole::User);
+
+        assert!(user_store.create_user("bob", "password", UserRole::User).is_err());
+        user_store.create_user("carol", "password", UserRole::User).unwrap();
+        assert!(user_store.check_password("carol", "password").unwrap());
+
+        // The last admin can be neither deleted nor demoted.
+        assert!(user_store.delete_user("alice").is_err());
+        assert!(user_store.set_role("alice", UserRole::User).is_err());
+        user_store.set_role("bob", UserRole::Admin).unwrap();
+        user_store.delete_user("alice").unwrap();
+        assert!(user_store.delete_user("bob").is_err());
+
+        user_store.generate_api_key("bob").unwrap();
+        let users = user_store.list_users_with_metadata().unwrap();
+        assert_eq!(users.iter().map(|u| u.username.as_str()).collect::<Vec<_>>(), vec!["bob", "carol"]);
+        assert_eq!(users[0].role, UserRole::Admin);
+        assert_eq!(users[0].api_token_count, 1);
+        assert_eq!(users[1].api_token_count, 0);
+        assert!(user_store.get_user("alice").is_err());
+    }
+
+    #[test]
+    fn user_roles_migration() {
+        use r2d2_sqlite::rusqlite;
+        use user::UserRole;
+
+        // A database from before roles, at schema version 1
+        let create = |name: &str, users: &[&str]| {
+            let path = std::env::temp_dir().join(format!("mirams-{}-{}.db", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute_batch(db_sqlite::MIGRATIONS[0]).unwrap();
+            conn.execute_batch("CREATE TABLE schema_version (id INTEGER PRIMARY KEY, version INTEGER NOT NULL UNIQUE); INSERT INTO schema_version VALUES (1, 1);").unwrap();
+            for user in users {
+                conn.execute("INSERT INTO user (name, hashed_password) VALUES (?, '')", [user]).unwrap();
+            }
+            path
+        };
+
+        let path = create("roles", &["alice", "bob"]);
+        let store = Store::new(db_sqlite::SqliteConnection::open_file(path.to_str().unwrap()).unwrap());
+        assert_eq!(store.users().get_user_role("alice").unwrap(), UserRole::Admin);
+        assert_eq!(store.users().get_user_role("bob").unwrap(), UserRole::User);
+        std::fs::remove_file(&path).unwrap();
+
+        // Duplicate users stop the upgrade rather than being deleted
+        let path = create("duplicate-users", &["alice", "bob", "bob"]);
+        let error = db_sqlite::SqliteConnection::open_file(path.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("bob"), "{}", error);
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        assert_eq!(conn.query_row("SELECT COUNT(*) FROM user", [], |row| row.get::<_, i64>(0)).unwrap(), 3);
+        assert_eq!(conn.query_row("SELECT version FROM schema_version", [], |row| row.get::<_, i64>(0)).unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn login_throttling() {
+        use user::{LoginFailures, LoginOutcome, LoginThrottleKey, LoginThrottlePolicy};
+
+        let policy = LoginThrottlePolicy::USERNAME;
+        let key = LoginThrottleKey::Username("alice".to_string());
+        let failures = |count| LoginFailures { key: key.clone(), count, last_failure_at: 1000 };
+        assert_eq!(policy.blocked_until(&failures(3)), 0);
+        assert_eq!(policy.blocked_until(&failures(4)), 1001);
+        assert_eq!(policy.blocked_until(&failures(6)), 1004);
+        assert_eq!(policy.blocked_until(&failures(10)), 1000 + policy.lockout_duration);
+        assert!(policy.lockout(&failures(10), 1001).unwrap().locked_out);
+        assert!(policy.lockout(&failures(10), 1000 + policy.lockout_duration).is_none());
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db.clone());
+        let user_store = store.users();
+        for _ in 0..3 {
+            user_store.record_login_attempt("alice", Some("192.0.2.1"), LoginOutcome::Failure).unwrap();
+        }
+        user_store.record_login_attempt("bob", Some("192.0.2.1"), LoginOutcome::Failure).unwrap();
+        assert_eq!(user_store.get_login_failures(&key, 0).unwrap().count, 3);
+        assert_eq!(user_store.get_login_failures(&LoginThrottleKey::Source("192.0.2.1".to_string()), 0).unwrap().count, 4);
+        assert_eq!(user_store.list_login_failures(0).unwrap().len(), 3);
+
+        // A success resets failures against the username but not against the source.
+        user_store.record_login_attempt("alice", Some("192.0.2.1"), LoginOutcome::Success).unwrap();
+        assert_eq!(user_store.get_login_failures(&key, 0).unwrap().count, 0);
+        assert_eq!(user_store.get_login_failures(&LoginThrottleKey::Source("192.0.2.1".to_string()), 0).unwrap().count, 4);
+
+        user_store.clear_login_failures(&LoginThrottleKey::Source("192.0.2.1".to_string())).unwrap();
+        assert_eq!(user_store.get_login_failures(&LoginThrottleKey::Source("192.0.2.1".to_string()), 0).unwrap().count, 0);
+        assert_eq!(user_store.list_login_attempts(Some("alice"), None, 10).unwrap().len(), 4);
+        assert_eq!(user_store.list_login_attempts(None, None, 2).unwrap()[0].outcome, LoginOutcome::Success);
+
+        // Attempts in progress count as failures, so parallel attempts cannot all pass the check
+        let attempts: Vec<_> = (0..5).map(|_| user_store.begin_login_attempt("carol", None).unwrap()).collect();
+        assert!(attempts[..4].iter().all(|attempt| matches!(attempt, user::LoginAttemptStart::Allowed(_))));
+        assert!(matches!(&attempts[4], user::LoginAttemptStart::Throttled(lockout) if lockout.failures == 4));
+        let carol = LoginThrottleKey::Username("carol".to_string());
+        let user::LoginAttemptStart::Allowed(attempt_id) = attempts[0] else { unreachable!() };
+        user_store.finish_login_attempt(attempt_id, None).unwrap();
+        assert_eq!(user_store.get_login_failures(&carol, 0).unwrap().count, 3);
+        let user::LoginAttemptStart::Allowed(attempt_id) = attempts[3] else { unreachable!() };
+        user_store.finish_login_attempt(attempt_id, Some(LoginOutcome::Success)).unwrap();
+        assert_eq!(user_store.get_login_failures(&carol, 0).unwrap().count, 0);
+
+        // Logins fail closed when throttling cannot be checked
+        let api = test_router(server::Server::new(store.clone()));
+        let login = serde_json::json!({ "username": "admin", "password": "password" });
+        assert_eq!(api.text_request("POST", "/api/v1/login", false, login.clone()).await.0, 200);
+
+        // Errors on our side are reported as such and do not count as failures
+        let admin = LoginThrottleKey::Username("admin".to_string());
+        db.get_conn().unwrap().execute_batch("ALTER TABLE user RENAME TO user_unavailable").unwrap();
+        assert_eq!(api.text_request("POST", "/api/v1/login", false, login.clone()).await.0, 500);
+        db.get_conn().unwrap().execute_batch("ALTER TABLE user_unavailable RENAME TO user").unwrap();
+        assert_eq!(user_store.get_login_failures(&admin, 0).unwrap().count, 0);
+        let wrong = serde_json::json!({ "username": "admin", "password": "wrong" });
+        assert_eq!(api.text_request("POST", "/api/v1/login", false, wrong).await.0, 401);
+        assert_eq!(user_store.get_login_failures(&admin, 0).unwrap().count, 1);
+
+        db.get_conn().unwrap().execute_batch("DROP TABLE login_attempt").unwrap();
+        let (status, body) = api.anonymous_request("POST", "/api/v1/login", login).await;
+        assert_eq!(status, 503);
+        assert!(body["result"].is_null(), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn totp() {
+        // RFC 6238 test vectors (SHA-1), truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(totp::code_at_step(secret, totp::time_step(59)), 287082);
+        assert_eq!(totp::code_at_step(secret, totp::time_step(1111111109)), 81804);
+        assert_eq!(totp::verify_code(secret, "081804", 1111111109, None), Some(totp::time_step(1111111109)));
+        assert_eq!(totp::verify_code(secret, "081804", 1111111109, Some(totp::time_step(1111111109))), None);
+        assert_eq!(totp::encode_secret(b"foo"), "MZXW6");
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let user_store = db_sqlite::model::SqliteUserStore::new(db.clone());
+        user_store.set_password("alice", "password").unwrap();
+        assert!(!user_store.verify_second_factor("alice", "000000").unwrap());
+
+        let enrolment = user_store.begin_totp_enrolment("alice").unwrap();
+        assert!(enrolment.otpauth_uri.starts_with("otpauth://totp/MIRAMS%3Aalice?secret="));
+        assert!(user_store.get_totp_status("alice").unwrap().pending);
+        let secret = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, &enrolment.secret).unwrap();
+        assert!(user_store.confirm_totp_enrolment("alice", "abcdef").is_err());
+
+        // Confirm with the code of the previous step, so that the current one is still unused.
+        let now = 1111111109;
+        let code = |step| format!("{:06}", totp::code_at_step(&secret, step));
+        let recovery_codes = user_store.confirm_totp_enrolment_at("alice", &code(totp::time_step(now) - 1), now).unwrap();
+        assert_eq!(recovery_codes.len(), totp::RECOVERY_CODE_COUNT);
+        assert!(user_store.begin_totp_enrolment("alice").is_err());
+
+        assert!(!user_store.verify_second_factor_at("alice", &code(totp::time_step(now) - 1), now).unwrap());
+        assert!(user_store.verify_second_factor_at("alice", &code(totp::time_step(now) + 1), now).unwrap());
+        assert!(user_store.verify_second_factor("alice", &recovery_codes[0].to_uppercase()).unwrap());
+        assert!(!user_store.verify_second_factor("alice", &recovery_codes[0]).unwrap());
+        assert_eq!(user_store.get_totp_status("alice").unwrap().recovery_codes_remaining, totp::RECOVERY_CODE_COUNT as i64 - 1);
+
+        // Replacing recovery codes needs the password, which is checked like at login
+        let api = test_router(server::Server::new(Store::new(db)));
+        let alice = TestRouter { token: user_store.generate_api_key("alice").unwrap(), ..api };
+        let confirm = |password: &str| serde_json::json!({ "password": password });
+        assert_eq!(alice.text_request("POST", "/api/v1/user/self/totp/recovery_codes", true, confirm("wrong")).await.0, 403);
+        assert_eq!(alice.text_request("POST", "/api/v1/user/self/totp/disable", true, confirm("wrong")).await.0, 403);
+        assert_eq!(user_store.get_login_failures(&user::LoginThrottleKey::Username("alice".to_string()), 0).unwrap().count, 2);
+        assert_eq!(alice.text_request("POST", "/api/v1/user/self/totp/recovery_codes", true, confirm("password")).await.0, 200);
+        assert_eq!(user_store.get_totp_status("alice").unwrap().recovery_codes_remaining, totp::RECOVERY_CODE_COUNT as i64);
+
+        user_store.disable_totp("alice").unwrap();
+        assert!(!user_store.get_totp_status("alice").unwrap().enabled);
+    }
+
+    #[test]
+    fn api_keys_are_hashed() {
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db.clone());
+        let user_store = store.users();
+        user_store.set_password("alice", "password").unwrap();
+        let key = user_store.generate_api_key("alice").unwrap();
+
+        let conn = db.get_conn().unwrap();
+        let (prefix, hash): (String, String) = conn.query_row("SELECT key_prefix, key_hash FROM api_key", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert!(key.starts_with(&prefix));
+        assert_ne!(hash, key);
+        assert!(!hash.contains(&key[prefix.len()..]));
+
+        let mut wrong_key = key.clone();
+        wrong_key.pop();
+        wrong_key.push(if key.ends_with('0') { '1' } else { '0' });
+        assert_eq!(user_store.get_user_from_api_key(&wrong_key).unwrap(), None);
+        assert_eq!(user_store.get_user_from_api_key(&key).unwrap(), Some("alice".to_string()));
+
+        // A configured secret takes over, and keys hashed before are rehashed on use
+        let path = std::env::temp_dir().join(format!("mirams-api-key-secret-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let secret = user::load_api_key_secret(&path).unwrap();
+        assert_eq!(user::load_api_key_secret(&path).unwrap(), secret);
+        std::fs::remove_file(&path).unwrap();
+        let configured_store = Store::new(db.clone().with_api_key_secret(secret));
+        assert_eq!(configured_store.users().get_user_from_api_key(&key).unwrap(), Some("alice".to_string()));
+        let rehashed: String = conn.query_row("SELECT key_hash FROM api_key", [], |row| row.get(0)).unwrap();
+        assert_ne!(rehashed, hash);
+        assert_eq!(user_store.get_user_from_api_key(&key).unwrap(), None);
+        let new_key = configured_store.users().generate_api_key("alice").unwrap();
+        assert_eq!(user_store.get_user_from_api_key(&new_key).unwrap(), None);
+        assert_eq!(configured_store.users().get_user_from_api_key(&new_key).unwrap(), Some("alice".to_string()));
+    }
+
+    #[test]
+    fn oidc_claim_mapping() {
+        let config: server::oidc::OidcConfig = serde_json::from_value(serde_json::json!({
+            "issuer_url": "https://idp.example",
+            "client_id": "mirams",
+            "redirect_url": "https://mirams.example/api/v1/oidc/callback",
+            "role_claim": "groups",
+            "admin_values": ["noc"],
+        })).unwrap();
+        let claims = |value: serde_json::Value| value.as_object().unwrap().clone();
+
+        let identity = config.map_claims(&claims(serde_json::json!({"iss": "https://idp.example", "sub": "1234", "preferred_username": "alice", "groups": ["staff", "noc"]}))).unwrap();
+        assert_eq!((identity.issuer.as_str(), identity.subject.as_str()), ("https://idp.example", "1234"));
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.role, Some(user::UserRole::Admin));
+        let identity = config.map_claims(&claims(serde_json::json!({"iss": "https://idp.example", "sub": "5678", "preferred_username": "bob", "groups": "staff"}))).unwrap();
+        assert_eq!(identity.role, Some(user::UserRole::User));
+        assert!(config.map_claims(&claims(serde_json::json!({"iss": "https://idp.example", "sub": "1234"}))).is_err());
+        assert!(config.map_claims(&claims(serde_json::json!({"iss": "https://idp.example", "sub": "1234", "preferred_username": " "}))).is_err());
+        assert!(config.map_claims(&claims(serde_json::json!({"iss": "https://idp.example", "preferred_username": "alice"}))).is_err());
+    }
+
+    /// A fresh RSA key (PKCS#1 PEM) for the mock identity provider in `oidc_login`
+    fn oidc_test_key() -> String {
+        use rsa::pkcs1::EncodeRsaPrivateKey;
+        use rsa::pkcs8::DecodePrivateKey;
+
+        let key = rcgen::KeyPair::generate_for(&rcgen::PKCS_RSA_SHA256).unwrap();
+        let key = rsa::RsaPrivateKey::from_pkcs8_der(&key.serialize_der()).unwrap();
+        key.to_pkcs1_pem(rsa::pkcs8::LineEnding::LF).unwrap().to_string()
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    struct GroupsClaim {
+        groups: Vec<String>,
+    }
+
+    impl openidconnect::AdditionalClaims for GroupsClaim {}
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn oidc_login() {
+        use axum::extract::{Form, State};
+        use base64::Engine;
+        use openidconnect::core::*;
+        use openidconnect::{JsonWebKeyId, PrivateSigningKey};
+        use sha2::Digest;
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+        use tower::ServiceExt;
+
+        /// Code of a pending login, with its PKCE challenge, nonce and subject
+        type PendingCodes = HashMap<String, (String, String, String)>;
+
+        #[derive(Clone)]
+        struct MockIdp {
+            issuer: String,
+            /// ID and PEM of the current signing key
+            key: Arc<Mutex<(String, String)>>,
+            codes: Arc<Mutex<PendingCodes>>,
+        }
+
+        impl MockIdp {
+            fn signing_key(&self) -> CoreRsaPrivateSigningKey {
+                let (id, pem) = self.key.lock().unwrap().clone();
+                CoreRsaPrivateSigningKey::from_pem(&pem, Some(JsonWebKeyId::new(id))).unwrap()
+            }
+        }
+
+        async fn token(State(idp): State<MockIdp>, Form(form): Form<HashMap<String, String>>) -> axum::response::Response {
+            use axum::response::IntoResponse;
+            let (challenge, nonce, subject) = match idp.codes.lock().unwrap().remove(&form["code"]) {
+                Some(pending) => pending,
+                None => return (http::StatusCode::BAD_REQUEST, axum::Json(serde_json::json!({"error": "invalid_grant"}))).into_response(),
+            };
+            let verifier_hash = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sha2::Sha256::digest(form["code_verifier"].as_bytes()));
+            if verifier_hash != challenge {
+                return (http::StatusCode::BAD_REQUEST, axum::Json(serde_json::json!({"error": "invalid_grant"}))).into_response();
+            }
+
+            let now = user::unix_time_now();
+            let claims: openidconnect::IdTokenClaims<GroupsClaim, CoreGenderClaim> = serde_json::from_value(serde_json::json!({
+                "iss": idp.issuer,
+                "aud": ["mirams"],
+                "sub": subject,
+                "exp": now + 300,
+                "iat": now,
+                "nonce": nonce,
+                "preferred_username": "carol",
+                "groups": ["noc"],
+            })).unwrap();
+            let key = idp.signing_key();
+            let id_token = openidconnect::IdToken::<GroupsClaim, CoreGenderClaim, CoreJweContentEncryptionAlgorithm, CoreJwsSigningAlgorithm, CoreJsonWebKeyType>::new(
+                claims, &key, CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256, None, None,
+            ).unwrap();
+            axum::Json(serde_json::json!({
+                "access_token": "mock-access-token",
+                "token_type": "Bearer",
+                "expires_in": 300,
+                "id_token": id_token.to_string(),
+            })).into_response()
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let idp = MockIdp {
+            issuer: issuer.clone(),
+            key: Arc::new(Mutex::new(("test".to_string(), oidc_test_key()))),
+            codes: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let discovery = serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+        });
+        let jwks = |State(idp): State<MockIdp>| async move { axum::Json(CoreJsonWebKeySet::new(vec![idp.signing_key().as_verification_key()])) };
+        let idp_router = axum::Router::new()
+            .route("/.well-known/openid-configuration", axum::routing::get(move || async move { axum::Json(discovery) }))
+            .route("/jwks", axum::routing::get(jwks))
+            .route("/token", axum::routing::post(token))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, idp_router).await.unwrap() });
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let config: server::oidc::OidcConfig = serde_json::from_value(serde_json::json!({
+            "issuer_url": issuer,
+            "client_id": "mirams",
+            "client_secret": "secret",
+            "redirect_url": "https://mirams.example/api/v1/oidc/callback",
+            "role_claim": "groups",
+            "admin_values": ["noc"],
+            "create_users": true,
+        })).unwrap();
+        let router = server::Server::new(store.clone()).with_oidc(config).build_router();
+        let get = |uri: String| http::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+        let location = |res: &http::Response<axum::body::Body>| res.headers()[http::header::LOCATION].to_str().unwrap().to_string();
+
+        // Start a login that the provider answers with `code` for `subject`, returning the state and its cookie
+        let begin = |code: &'static str, subject: &'static str| {
+            let router = router.clone();
+            let idp = idp.clone();
+            let issuer = issuer.clone();
+            async move {
+                let res = router.oneshot(get("/api/v1/oidc/login".to_string())).await.unwrap();
+                assert_eq!(res.status(), 302);
+                let cookie = res.headers()[http::header::SET_COOKIE].to_str().unwrap().to_string();
+                assert!(cookie.contains("; HttpOnly; SameSite=Lax; Secure"), "{}", cookie);
+                let cookie = cookie.split(';').next().unwrap().to_string();
+                let auth_url = openidconnect::url::Url::parse(&location(&res)).unwrap();
+                assert!(auth_url.as_str().starts_with(&format!("{}/authorize?", issuer)));
+                let params: HashMap<String, String> = auth_url.query_pairs().into_owned().collect();
+                assert_eq!(params["code_challenge_method"], "S256");
+                idp.codes.lock().unwrap().insert(code.to_string(), (params["code_challenge"].clone(), params["nonce"].clone(), subject.to_string()));
+                assert_eq!(cookie, format!("mirams_oidc_state={}", params["state"]));
+                (params["state"].clone(), cookie)
+            }
+        };
+        let callback = |code: &str, state: &str, cookie: &str| {
+            let mut request = get(format!("/api/v1/oidc/callback?code={}&state={}", code, state));
+            request.headers_mut().insert(http::header::COOKIE, format!("theme=dark; {}", cookie).parse().unwrap());
+            let router = router.clone();
+            async move {
+                let res = router.oneshot(request).await.unwrap();
+                assert_eq!(res.status(), 302);
+                let fragment = location(&res);
+                let fragment: HashMap<String, String> = openidconnect::url::form_urlencoded::parse(fragment.trim_start_matches("/login/#").as_bytes()).into_owned().collect();
+                fragment
+            }
+        };
+
+        let (state, cookie) = begin("code-1", "1234").await;
+
+        // Unknown state is refused
+        assert!(callback("code-1", "bogus", "mirams_oidc_state=bogus").await.contains_key("error"));
+
+        // So is a callback in a browser that did not start the login, without using up the state
+        let (_, other_cookie) = begin("code-0", "1234").await;
+        assert!(callback("code-1", &state, &other_cookie).await.contains_key("error"));
+        assert!(callback("code-1", &state, "").await.contains_key("error"));
+
+        let fragment = callback("code-1", &state, &cookie).await;
+        assert_eq!(fragment["username"], "carol");
+        let (username, _) = store.users().get_api_token(&fragment["api_token"]).unwrap().unwrap();
+        assert_eq!(username, "carol");
+        assert_eq!(store.users().get_user_role("carol").unwrap(), user::UserRole::Admin);
+        assert_eq!(store.users().get_oidc_user(&issuer, "1234").unwrap(), Some("carol".to_string()));
+
+        // The state is single-use
+        assert!(callback("code-1", &state, &cookie).await.contains_key("error"));
+
+        // Another identity claiming the same username is not attached to the existing user
+        let (state, cookie) = begin("code-2", "5678").await;
+        assert!(callback("code-2", &state, &cookie).await.contains_key("error"));
+        assert_eq!(store.users().get_oidc_user(&issuer, "5678").unwrap(), None);
+
+        // ...unless an admin links it
+        store.users().set_password("dave", "password").unwrap();
+        store.users().link_oidc_user("dave", &issuer, "5678").unwrap();
+        assert!(matches!(store.users().link_oidc_user("carol", &issuer, "5678").unwrap_err().kind(), types::ErrorKind::Conflict));
+        let (state, cookie) = begin("code-3", "5678").await;
+        let fragment = callback("code-3", &state, &cookie).await;
+        assert_eq!(fragment["username"], "dave");
+        let (username, _) = store.users().get_api_token(&fragment["api_token"]).unwrap().unwrap();
+        assert_eq!(username, "dave");
+
+        // ID tokens signed with a rotated key are verified after discovering the provider again
+        *idp.key.lock().unwrap() = ("rotated".to_string(), oidc_test_key());
+        let (state, cookie) = begin("code-4", "5678").await;
+        assert_eq!(callback("code-4", &state, &cookie).await["username"], "dave");
+
+        store.users().unlink_oidc_user("dave", &issuer, "5678").unwrap();
+        assert!(matches!(store.users().unlink_oidc_user("dave", &issuer, "5678").unwrap_err().kind(), types::ErrorKind::NotFound));
+        store.users().set_role("carol", user::UserRole::User).unwrap();
+        store.users().delete_user("carol").unwrap();
+        assert_eq!(store.users().get_oidc_user(&issuer, "1234").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn error_kinds() {
+        use r2d2_sqlite::rusqlite;
+
+        let not_found: types::Error = rusqlite::Error::QueryReturnedNoRows.into();
+        assert!(matches!(not_found.kind(), types::ErrorKind::NotFound));
+        let constraint = rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE), None);
+        assert!(matches!(types::Error::from(constraint).kind(), types::ErrorKind::Conflict));
+        let io = std::io::Error::other("io");
+        assert!(matches!(types::Error::from(io).kind(), types::ErrorKind::Other));
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let api = test_router(server::Server::new(store));
+
+        let space = serde_json::json!({
+            "name": "space",
+            "description": "",
+            "space_visibility": "public",
+            "ipv4_prefix": [192, 0, 2, 0],
+            "ipv4_prefix_len": 24,
+        });
+        let (status, body) = api.api_request("POST", "/api/v1/ipv4/assignment_space", space.clone()).await;
+        assert_eq!(status, 200);
+        assert!(body.get("code").is_none());
+        let space_id = body["ipv4_assignment_space"]["id"].as_i64().unwrap();
+
+        let (status, body) = api.api_request("POST", "/api/v1/ipv4/assignment_space", space).await;
+        assert_eq!((status, body["code"].as_str()), (409, Some("conflict")));
+        assert_eq!(body["error"], "Error creating assignment space: Overlapping space exists");
+
+        let (status, body) = api.api_request("POST", &format!("/api/v1/ipv4/assignment_space/{}/pool", space_id), serde_json::json!({
+            "assignment_space_id": space_id,
+            "name": "pool",
+            "description": "",
+            "pool_visibility": "public",
+            "ipv4_prefix": [198, 51, 100, 0],
+            "ipv4_prefix_len": 25,
+        })).await;
+        assert_eq!((status, body["code"].as_str()), (422, Some("validation")));
+
+        let (status, body) = api.api_request("GET", "/api/v1/ipv4/assignment_space/999", serde_json::Value::Null).await;
+        assert_eq!((status, body["code"].as_str()), (404, Some("not_found")));
+
+        let (status, body) = api.api_request("DELETE", "/api/v1/users/admin", serde_json::Value::Null).await;
+        assert_eq!((status, body["code"].as_str()), (409, Some("conflict")));
+
+        let (status, body) = api.api_request("GET", "/api/v1/users", serde_json::Value::Null).await;
+        assert_eq!(status, 200);
+        assert!(body.get("code").is_none());
+    }
+
+    #[tokio::test]
+    async fn nested_route_ancestry() {
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let api = test_router(server::Server::new(store));
+
+        // Resource fields of space (0), pool (1) and assignment (2) number `n`
+        let resource = |family: &str, n: u8, level: u8| -> serde_json::Value {
+            match family {
+                "ipv4" => serde_json::json!({ "ipv4_prefix": [10, n, 0, 0], "ipv4_prefix_len": 16 + 6 * level }),
+                "ipv6" => serde_json::json!({
+                    "ipv6_prefix": [0x20, 0x01, 0x0d, 0xb8, 0, n, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                    "ipv6_prefix_len": 48 + 8 * level,
+                }),
+                _ => {
+                    let from = 64500 + 100 * n as u32;
+                    match level {
+                        2 => serde_json::json!({ "asn": from }),
+                        _ => serde_json::json!({ "asn_from": from, "asn_to": from + 9 }),
+                    }
+                },
+            }
+        };
+        let with = |mut value: serde_json::Value, fields: serde_json::Value| {
+            value.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+            value
+        };
+
+        for family in ["ipv4", "ipv6", "asn"] {
+            let base = format!("/api/v1/{}/assignment_space", family);
+            let mut ids = Vec::new();
+            for n in 1..=2 {
+                let (status, body) = api.api_request("POST", &base, with(serde_json::json!({
+                    "name": "space", "description": "", "space_visibility": "public",
+                }), resource(family, n, 0))).await;
+                assert_eq!(status, 200, "{}", body);
+                let space_id = body[format!("{}_assignment_space", family)]["id"].as_i64().unwrap();
+
+                let (status, body) = api.api_request("POST", &format!("{}/{}/pool", base, space_id), with(serde_json::json!({
+                    "assignment_space_id": space_id, "name": "pool", "description": "", "pool_visibility": "public",
+                }), resource(family, n, 1))).await;
+                assert_eq!(status, 200, "{}", body);
+                let pool_id = body[format!("{}_assignment_pool", family)]["id"].as_i64().unwrap();
+
+                let (status, body) = api.api_request("POST", &format!("{}/{}/pool/{}/assignment", base, space_id, pool_id), with(serde_json::json!({
+                    "assignment_pool_id": pool_id, "name": "assignment", "description": "", "assignment_visibility": "public",
+                }), resource(family, n, 2))).await;
+                assert_eq!(status, 200, "{}", body);
+                let assignment_id = body[format!("{}_assignment", family)]["id"].as_i64().unwrap();
+                ids.push((space_id, pool_id, assignment_id));
+            }
+            let [(space_a, pool_a, assignment_a), (space_b, pool_b, assignment_b)] = ids[..] else {
+                unreachable!();
+            };
+            let update = serde_json::json!({ "name": "renamed", "description": "" });
+
+            // Pool A through space B
+            let pool = format!("{}/{}/pool/{}", base, space_b, pool_a);
+            for (method, body) in [("GET", serde_json::Value::Null), ("PUT", update.clone()), ("DELETE", serde_json::Value::Null)] {
+                let (status, body) = api.api_request(method, &pool, body).await;
+                assert_eq!((status, body["code"].as_str()), (404, Some("not_found")), "{} {} {}", family, method, pool);
+            }
+            let (status, _) = api.api_request("GET", &format!("{}/assignment", pool), serde_json::Value::Null).await;
+            assert_eq!(status, 404, "{} list {}", family, pool);
+            let (status, _) = api.api_request("POST", &format!("{}/assignment", pool), with(serde_json::json!({
+                "assignment_pool_id": pool_a, "name": "other", "description": "", "assignment_visibility": "public",
+            }), resource(family, 1, 2))).await;
+            assert_eq!(status, 404, "{} create {}", family, pool);
+
+            // Assignment A through pool B, and through space B
+            for assignment in [
+                format!("{}/{}/pool/{}/assignment/{}", base, space_b, pool_b, assignment_a),
+                format!("{}/{}/pool/{}/assignment/{}", base, space_b, pool_a, assignment_a),
+                format!("{}/{}/pool/{}/assignment/{}", base, space_a, pool_b, assignment_b),
+            ] {
+                for (method, body) in [("GET", serde_json::Value::Null), ("PUT", update.clone()), ("DELETE", serde_json::Value::Null)] {
+                    let (status, body) = api.api_request(method, &assignment, body).await;
+                    assert_eq!((status, body["code"].as_str()), (404, Some("not_found")), "{} {} {}", family, method, assignment);
+                }
+            }
+
+            // Nothing was changed, and the full paths still work
+            let assignment = format!("{}/{}/pool/{}/assignment/{}", base, space_a, pool_a, assignment_a);
+            let (status, body) = api.api_request("GET", &assignment, serde_json::Value::Null).await;
+            assert_eq!(status, 200);
+            assert_eq!(body[format!("{}_assignment", family)]["name"], "assignment");
+            let (status, body) = api.api_request("GET", &format!("{}/{}/pool/{}", base, space_a, pool_a), serde_json::Value::Null).await;
+            assert_eq!(status, 200);
+            assert_eq!(body[format!("{}_assignment_pool", family)]["name"], "pool");
+            let (status, body) = api.api_request("GET", &format!("{}/{}/pool/{}/assignment", base, space_a, pool_a), serde_json::Value::Null).await;
+            assert_eq!(status, 200);
+            assert_eq!(body[format!("{}_assignments", family)].as_array().unwrap().len(), 1);
+            let (status, _) = api.api_request("PUT", &assignment, update.clone()).await;
+            assert_eq!(status, 200);
+            let (status, _) = api.api_request("DELETE", &assignment, serde_json::Value::Null).await;
+            assert_eq!(status, 200);
+            let (status, _) = api.api_request("GET", &assignment, serde_json::Value::Null).await;
+            assert_eq!(status, 404);
+        }
+    }
+
+    #[tokio::test]
+    async fn hierarchical_visibility() {
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let api = test_router(server::Server::new(store));
+
+        // A public pool with a public assignment, in a private space or in a public one
+        let base = "/api/v1/ipv4/assignment_space";
+        let mut paths = Vec::new();
+        for (n, space_visibility) in [(1u8, "private"), (2, "public")] {
+            let (_, body) = api.api_request("POST", base, serde_json::json!({
+                "name": "space", "description": "", "space_visibility": space_visibility,
+                "ipv4_prefix": [10, n, 0, 0], "ipv4_prefix_len": 16,
+            })).await;
+            let space = format!("{}/{}", base, body["ipv4_assignment_space"]["id"]);
+            let mut pools = Vec::new();
+            for (m, pool_visibility) in [(0u8, "public"), (1, "private")] {
+                let (_, body) = api.api_request("POST", &format!("{}/pool", space), serde_json::json!({
+                    "assignment_space_id": body["ipv4_assignment_space"]["id"], "name": "pool", "description": "",
+                    "pool_visibility": pool_visibility, "ipv4_prefix": [10, n, m, 0], "ipv4_prefix_len": 24,
+                })).await;
+                let pool_id = body["ipv4_assignment_pool"]["id"].clone();
+                let pool = format!("{}/pool/{}", space, pool_id);
+                let (status, body) = api.api_request("POST", &format!("{}/assignment", pool), serde_json::json!({
+                    "assignment_pool_id": pool_id, "name": "assignment", "description": "",
+                    "assignment_visibility": "public", "ipv4_prefix": [10, n, m, 0], "ipv4_prefix_len": 28,
+                })).await;
+                assert_eq!(status, 200, "{}", body);
+                let assignment = format!("{}/assignment/{}", pool, body["ipv4_assignment"]["id"]);
+                pools.push((pool, assignment));
+            }
+            paths.push((space, pools));
+        }
+
+        let (_, body) = api.anonymous_request("GET", base, serde_json::Value::Null).await;
+        assert_eq!(body["ipv4_assignment_spaces"].as_array().unwrap().len(), 1);
+        let (_, body) = api.api_request("GET", base, serde_json::Value::Null).await;
+        assert_eq!(body["ipv4_assignment_spaces"].as_array().unwrap().len(), 2);
+
+        let (private_space, private_space_pools) = &paths[0];
+        let (public_space, public_space_pools) = &paths[1];
+        for uri in [
+            private_space.clone(),
+            format!("{}/pool", private_space),
+            private_space_pools[0].0.clone(),
+            format!("{}/assignment", private_space_pools[0].0),
+            private_space_pools[0].1.clone(),
+            public_space_pools[1].0.clone(),
+            format!("{}/assignment", public_space_pools[1].0),
+            public_space_pools[1].1.clone(),
+        ] {
+            let (status, _) = api.anonymous_request("GET", &uri, serde_json::Value::Null).await;
+            assert_eq!(status, 404, "{}", uri);
+            let (status, _) = api.api_request("GET", &uri, serde_json::Value::Null).await;
+            assert_eq!(status, 200, "{}", uri);
+        }
+
+        let (status, body) = api.anonymous_request("GET", &format!("{}/pool", public_space), serde_json::Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["ipv4_assignment_pools"].as_array().unwrap().len(), 1);
+        for uri in [public_space_pools[0].0.clone(), public_space_pools[0].1.clone()] {
+            let (status, _) = api.anonymous_request("GET", &uri, serde_json::Value::Null).await;
+            assert_eq!(status, 200, "{}", uri);
+        }
+        let (_, body) = api.anonymous_request("GET", &format!("{}/assignment", public_space_pools[0].0), serde_json::Value::Null).await;
+        assert_eq!(body["ipv4_assignments"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rdap() {
+        use types::ObjectVisibility::{Public, Private};
+        use tower::ServiceExt;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let mut pool_ids = Vec::new();
+        for (n, visibility) in [(0u8, Public), (128, Private)] {
+            let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+                id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: visibility,
+                ipv4_prefix: [192, 0, 2, n], ipv4_prefix_len: 25,
+            }).unwrap();
+            ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+                id: 0, assignment_pool_id: pool_id, name: "EXAMPLE-NET".to_string(), description: "Example\nNetwork".to_string(),
+                assignment_visibility: Public, ipv4_prefix: [192, 0, 2, n], ipv4_prefix_len: 28, origin_asn_id: None,
+            }).unwrap();
+            pool_ids.push(pool_id);
+        }
+        // Only the objects containing a prefix are selected for looking it up
+        assert_eq!(ipv4_store.get_spaces_containing([192, 0, 2, 200], 32).unwrap().len(), 1);
+        assert!(ipv4_store.get_spaces_containing([192, 0, 2, 0], 23).unwrap().is_empty());
+        assert_eq!(ipv4_store.get_pools_containing(space_id, [192, 0, 2, 200], 30).unwrap()[0].id, pool_ids[1]);
+        assert!(ipv4_store.get_pools_containing(space_id, [192, 0, 2, 0], 24).unwrap().is_empty());
+        assert_eq!(ipv4_store.get_assignments_containing(pool_ids[0], [192, 0, 2, 15], 32).unwrap().len(), 1);
+        assert!(ipv4_store.get_assignments_containing(pool_ids[0], [192, 0, 2, 16], 32).unwrap().is_empty());
+        let ipv6_store = store.ipv6_assignments();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 32,
+        }).unwrap();
+        ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 48,
+        }).unwrap();
+        let asn_store = store.asn_assignments();
+        let space_id = asn_store.create_space(&asn::AssignmentSpaceAsn {
+            id: 0, name: "asn space".to_string(), description: "".to_string(), space_visibility: Private, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let pool_id = asn_store.create_pool(&asn::AssignmentPoolAsn {
+            id: 0, assignment_space_id: space_id, name: "asn pool".to_string(), description: "".to_string(), pool_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        asn_store.create_assignment(&asn::AssignmentAsn {
+            id: 0, assignment_pool_id: pool_id, name: "EXAMPLE-NET".to_string(), description: "".to_string(), assignment_visibility: Public, asn: 64500,
+        }).unwrap();
+        assert_eq!(asn_store.get_spaces_containing(64511).unwrap().len(), 1);
+        assert!(asn_store.get_spaces_containing(64512).unwrap().is_empty());
+        assert_eq!(asn_store.get_pools_containing(space_id, 64496).unwrap()[0].id, pool_id);
+        assert_eq!(asn_store.get_assignments_containing(pool_id, 64500).unwrap().len(), 1);
+        assert!(asn_store.get_assignments_containing(pool_id, 64501).unwrap().is_empty());
+
+        let router = server::Server::new(store).build_router();
+        let get = |uri: &str| {
+            let router = router.clone();
+            let request = http::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+            async move {
+                let res = router.oneshot(request).await.unwrap();
+                let status = res.status().as_u16();
+                assert_eq!(res.headers()["Content-Type"], "application/rdap+json");
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let (status, body) = get("/rdap/ip/192.0.2.5").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["objectClassName"], "ip network");
+        assert_eq!(body["rdapConformance"][0], "rdap_level_0");
+        assert_eq!((body["startAddress"].as_str(), body["endAddress"].as_str()), (Some("192.0.2.0"), Some("192.0.2.15")));
+        assert_eq!(body["type"], "ASSIGNMENT");
+        assert_eq!(body["parentHandle"], format!("IPV4-POOL-{}", pool_ids[0]));
+        assert_eq!(body["remarks"][0]["description"], serde_json::json!(["Example", "Network"]));
+        assert_eq!(body["entities"][0]["handle"], "EXAMPLE-NET");
+
+        let (_, body) = get("/rdap/ip/192.0.2.64/26").await;
+        assert_eq!((body["type"].as_str(), body["endAddress"].as_str()), (Some("SUB-ALLOCATION"), Some("192.0.2.127")));
+        // The assignment in the private pool is hidden; its space is public
+        let (_, body) = get("/rdap/ip/192.0.2.130").await;
+        assert_eq!((body["type"].as_str(), body["endAddress"].as_str()), (Some("ALLOCATION"), Some("192.0.2.255")));
+        let (status, body) = get("/rdap/ip/198.51.100.1").await;
+        assert_eq!((status, body["errorCode"].as_u64()), (404, Some(404)));
+        let (_, body) = get("/rdap/ip/2001:db8::1").await;
+        assert_eq!((body["ipVersion"].as_str(), body["endAddress"].as_str()), (Some("v6"), Some("2001:db8:0:ffff:ffff:ffff:ffff:ffff")));
+        assert_eq!(get("/rdap/ip/2001:db8::/16").await.0, 404);
+        assert_eq!(get("/rdap/ip/192.0.2.1/33").await.0, 400);
+        assert_eq!(get("/rdap/ip/example").await.0, 400);
+
+        // The ASN space is private, so the public pool and assignment in it are hidden
+        assert_eq!(get("/rdap/autnum/64500").await.0, 404);
+        assert_eq!(get("/rdap/autnum/AS64500").await.0, 400);
+
+        let (status, body) = get("/rdap/entity/EXAMPLE-NET").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["objectClassName"], "entity");
+        assert_eq!(body["networks"].as_array().unwrap().len(), 1);
+        assert!(body.get("autnums").is_none());
+        assert_eq!(get("/rdap/entity/UNKNOWN").await.0, 404);
+
+        let (status, body) = get("/rdap/help").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["notices"][0]["title"], "Help");
+        assert_eq!(get("/rdap/domain/example.com").await.0, 400);
+    }
+
+    #[tokio::test]
+    async fn whois() {
+        use types::ObjectVisibility::{Public, Private};
+        use server::listener::{ListenAddr, Listener};
+        use server::whois::{self, WhoisConfig};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        assert_eq!(rpsl::object_name("Example network (Tokyo)", "NET"), "EXAMPLE-NETWORK-TOKYO");
+        assert_eq!(rpsl::object_name("123", "NET"), "NET-123");
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 25,
+        }).unwrap();
+        ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+            id: 0, assignment_pool_id: pool_id, name: "Example net".to_string(), description: "Example\nNetwork".to_string(),
+            assignment_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 28, origin_asn_id: None,
+        }).unwrap();
+        let ipv6_store = store.ipv6_assignments();
+        ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 32,
+        }).unwrap();
+        let asn_store = store.asn_assignments();
+        let space_id = asn_store.create_space(&asn::AssignmentSpaceAsn {
+            id: 0, name: "asn space".to_string(), description: "".to_string(), space_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let pool_id = asn_store.create_pool(&asn::AssignmentPoolAsn {
+            id: 0, assignment_space_id: space_id, name: "asn pool".to_string(), description: "".to_string(), pool_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        for (asn, visibility) in [(64500, Public), (64501, Private)] {
+            asn_store.create_assignment(&asn::AssignmentAsn {
+                id: 0, assignment_pool_id: pool_id, name: format!("example {}", asn), description: "".to_string(), assignment_visibility: visibility, asn,
+            }).unwrap();
+        }
+
+        let answer = |query: &str| whois::answer(&store, query, "TEST").unwrap();
+        let inetnum = answer("192.0.2.1");
+        assert!(inetnum.contains("inetnum:        192.0.2.0 - 192.0.2.15\n"), "{}", inetnum);
+        assert!(inetnum.contains("netname:        EXAMPLE-NET\ndescr:          Example\ndescr:          Network\nstatus:         ASSIGNED PA\nsource:         TEST\n"), "{}", inetnum);
+        assert!(answer("192.0.2.64/26").contains("status:         SUB-ALLOCATED PA\n"));
+        assert!(answer("2001:db8::/48").contains("inet6num:       2001:db8::/32\n"));
+        assert!(answer("AS64500").contains("aut-num:        AS64500\nas-name:        EXAMPLE-64500\n"));
+        assert!(answer("AS64501").contains("as-block:       AS64496 - AS64511\n"));
+        assert!(answer("198.51.100.1").contains("% No entries found."));
+        assert!(answer("example.com").contains("% Invalid query."));
+        assert!(answer("192.0.2.0/33").contains("% Invalid query."));
+        assert!(answer("192.0.2.0/abc").contains("% Invalid query."));
+        assert!(answer("2001:db8::/").contains("% Invalid query."));
+
+        let mut config = WhoisConfig::new(vec![ListenAddr::Tcp("127.0.0.1:0".to_string())]);
+        config.max_query_length = 32;
+        config.timeout = 1;
+        let std_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = std_listener.local_addr().unwrap();
+        let (shutdown, shutdown_receiver) = tokio::sync::watch::channel(false);
+        let task = tokio::spawn(whois::serve(Listener::Tcp(std_listener), store.clone(), std::sync::Arc::new(config), shutdown_receiver));
+
+        let query = |query: &'static [u8]| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(query).await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        assert!(query(b"AS64500\r\n").await.contains("aut-num:        AS64500\n"));
+        assert!(query(b"192.0.2.1").await.contains("inetnum:"));
+        assert!(query(&[b'1'; 100]).await.contains("% Query too long."));
+
+        // A client that sends nothing is disconnected after the timeout
+        let started = std::time::Instant::now();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+        assert!(started.elapsed() >= std::time::Duration::from_millis(900));
+
+        shutdown.send_replace(true);
+        task.await.unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rpsl_export() {
+        use types::ObjectVisibility::{Public, Private};
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let asn_store = store.asn_assignments();
+        let space_id = asn_store.create_space(&asn::AssignmentSpaceAsn {
+            id: 0, name: "asn space".to_string(), description: "".to_string(), space_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let pool_id = asn_store.create_pool(&asn::AssignmentPoolAsn {
+            id: 0, assignment_space_id: space_id, name: "asn pool".to_string(), description: "".to_string(), pool_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let mut asn_ids = Vec::new();
+        for (asn, visibility) in [(64501, Private), (64500, Public)] {
+            asn_ids.push(asn_store.create_assignment(&asn::AssignmentAsn {
+                id: 0, assignment_pool_id: pool_id, name: format!("example {}", asn), description: "".to_string(), assignment_visibility: visibility, asn,
+            }).unwrap());
+        }
+        let (private_asn_id, public_asn_id) = (asn_ids[0], asn_ids[1]);
+
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 25,
+        }).unwrap();
+        let assignment = |n: u8, visibility, origin_asn_id| ipv4::AssignmentIpv4 {
+            id: 0, assignment_pool_id: pool_id, name: format!("net {}", n), description: "".to_string(),
+            assignment_visibility: visibility, ipv4_prefix: [192, 0, 2, n], ipv4_prefix_len: 28, origin_asn_id,
+        };
+        let error = ipv4_store.create_assignment(&assignment(48, Public, Some(public_asn_id + 100))).unwrap_err();
+        assert_eq!(error.kind(), types::ErrorKind::Validation);
+        let routed_id = ipv4_store.create_assignment(&assignment(16, Public, Some(public_asn_id))).unwrap();
+        let assignment_id = ipv4_store.create_assignment(&assignment(0, Public, None)).unwrap();
+        ipv4_store.create_assignment(&assignment(32, Private, Some(public_asn_id))).unwrap();
+        // The origin of a route need not be public itself
+        ipv4_store.update_assignment_origin_asn(assignment_id, Some(private_asn_id)).unwrap();
+        assert_eq!(ipv4_store.get_assignment(assignment_id).unwrap().origin_asn_id, Some(private_asn_id));
+        let uri = format!("/api/v1/ipv4/assignment_space/{}/pool/{}/assignment/{}/origin_asn", space_id, pool_id, assignment_id);
+
+        let ipv6_store = store.ipv6_assignments();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 32,
+        }).unwrap();
+        let pool_id = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 40,
+        }).unwrap();
+        ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+            id: 0, assignment_pool_id: pool_id, name: "net6".to_string(), description: "IPv6\nnetwork".to_string(),
+            assignment_visibility: Public, ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 48,
+            origin_asn_id: Some(public_asn_id),
+        }).unwrap();
+
+        let config = rpsl::RpslConfig {
+            source: "TEST".to_string(),
+            maintainer: Some("MAINT-TEST".to_string()),
+        };
+        let text = rpsl::export(&store, &config).unwrap();
+        assert_eq!(text, "\
+aut-num:        AS64500
+as-name:        EXAMPLE-64500
+descr:          example 64500
+status:         ASSIGNED
+mnt-by:         MAINT-TEST
+source:         TEST
+
+inetnum:        192.0.2.0 - 192.0.2.15
+netname:        NET-0
+descr:          net 0
+status:         ASSIGNED PA
+mnt-by:         MAINT-TEST
+source:         TEST
+
+inetnum:        192.0.2.16 - 192.0.2.31
+netname:        NET-16
+descr:          net 16
+status:         ASSIGNED PA
+mnt-by:         MAINT-TEST
+source:         TEST
+
+inet6num:       2001:db8::/48
+netname:        NET6
+descr:          IPv6
+descr:          network
+status:         ASSIGNED
+mnt-by:         MAINT-TEST
+source:         TEST
+
+route:          192.0.2.0/28
+descr:          net 0
+origin:         AS64501
+mnt-by:         MAINT-TEST
+source:         TEST
+
+route:          192.0.2.16/28
+descr:          net 16
+origin:         AS64500
+mnt-by:         MAINT-TEST
+source:         TEST
+
+route6:         2001:db8::/48
+descr:          IPv6
+descr:          network
+origin:         AS64500
+mnt-by:         MAINT-TEST
+source:         TEST
+");
+
+        let api = test_router(server::Server::new(store.clone()).with_rpsl(config.clone()));
+        let res = api.send("GET", "/api/v1/export/rpsl", true, serde_json::Value::Null).await;
+        assert_eq!(res.headers()["Content-Type"], "text/plain; charset=utf-8");
+        assert_eq!(api.text_request("GET", "/api/v1/export/rpsl", true, serde_json::Value::Null).await, (200, text));
+        assert_eq!(api.text_request("GET", "/api/v1/export/rpsl", false, serde_json::Value::Null).await.0, 401);
+
+        let (status, body) = api.text_request("PUT", &uri, true, serde_json::json!({ "origin_asn_id": null })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert!(body.contains("\"origin_asn_id\":null"), "{}", body);
+        assert_eq!(api.text_request("PUT", &uri, true, serde_json::json!({ "origin_asn_id": public_asn_id + 100 })).await.0, 422);
+
+        // Deleting the ASN assignment withdraws its routes
+        asn_store.delete_assignment(public_asn_id).unwrap();
+        assert_eq!(ipv4_store.get_assignment(routed_id).unwrap().origin_asn_id, None);
+        let text = rpsl::export(&store, &config).unwrap();
+        assert!(!text.contains("route"), "{}", text);
+        assert!(!text.contains("aut-num"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn roas() {
+        use types::ObjectVisibility::Public;
+        use std::net::IpAddr;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let asn_store = store.asn_assignments();
+        let space_id = asn_store.create_space(&asn::AssignmentSpaceAsn {
+            id: 0, name: "asn space".to_string(), description: "".to_string(), space_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let pool_id = asn_store.create_pool(&asn::AssignmentPoolAsn {
+            id: 0, assignment_space_id: space_id, name: "asn pool".to_string(), description: "".to_string(), pool_visibility: Public, asn_from: 64496, asn_to: 64511,
+        }).unwrap();
+        let asn_ids: Vec<i32> = [64500, 64501].into_iter().map(|asn| asn_store.create_assignment(&asn::AssignmentAsn {
+            id: 0, assignment_pool_id: pool_id, name: format!("example {}", asn), description: "".to_string(), assignment_visibility: Public, asn,
+        }).unwrap()).collect();
+
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 25,
+        }).unwrap();
+        let mut assignment_ids = Vec::new();
+        for (n, origin_asn_id) in [(0, Some(asn_ids[0])), (16, None)] {
+            assignment_ids.push(ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+                id: 0, assignment_pool_id: pool_id, name: format!("net {}", n), description: "".to_string(),
+                assignment_visibility: Public, ipv4_prefix: [192, 0, 2, n], ipv4_prefix_len: 28, origin_asn_id,
+            }).unwrap());
+        }
+        let ipv6_store = store.ipv6_assignments();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 32,
+        }).unwrap();
+        let pool_id = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 40,
+        }).unwrap();
+        ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+            id: 0, assignment_pool_id: pool_id, name: "net6".to_string(), description: "".to_string(),
+            assignment_visibility: Public, ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 48,
+            origin_asn_id: None,
+        }).unwrap();
+
+        let roa_store = store.roas();
+        let roa = |prefix: &str, prefix_len, max_length, asn| roa::Roa {
+            id: 0, prefix: prefix.parse::<IpAddr>().unwrap(), prefix_len, max_length, asn, description: "".to_string(),
+        };
+        for invalid in [roa("198.51.100.0", 24, 24, 64500), roa("192.0.2.0", 24, 23, 64500), roa("192.0.2.0", 24, 33, 64500), roa("2001:db9::", 32, 48, 64500)] {
+            assert_eq!(roa_store.create_roa(&invalid).unwrap_err().kind(), types::ErrorKind::Validation, "{:?}", invalid);
+        }
+        let roa_id = roa_store.create_roa(&roa("192.0.2.5", 24, 24, 64500)).unwrap();
+        assert_eq!(roa_store.get_roa(roa_id).unwrap().prefix, "192.0.2.0".parse::<IpAddr>().unwrap());
+        let uncovered = |store: &Store<_>| roa::coverage(store).unwrap().into_iter().map(|assignment| assignment.prefix).collect::<Vec<_>>();
+        assert_eq!(uncovered(&store), ["192.0.2.0/28", "192.0.2.16/28", "2001:db8::/48"]);
+
+        // A longer maxLength covers both /28s; the first only for its origin AS64500
+        roa_store.update_roa(roa_id, &roa("192.0.2.0", 24, 28, 64500)).unwrap();
+        let roa6_id = roa_store.create_roa(&roa("2001:db8::", 32, 48, 64501)).unwrap();
+        assert!(uncovered(&store).is_empty());
+        ipv4_store.update_assignment_origin_asn(assignment_ids[0], Some(asn_ids[1])).unwrap();
+        let report = roa::coverage(&store).unwrap();
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].family, report[0].assignment_id, report[0].origin_asn), (user::ResourceFamily::Ipv4, assignment_ids[0], Some(64501)));
+        assert_eq!(roa_store.update_roa(roa_id + 100, &roa("192.0.2.0", 24, 28, 64500)).unwrap_err().kind(), types::ErrorKind::NotFound);
+
+        let mut described = roa("2001:db8::", 32, 32, 64502);
+        described.description = "Backup".to_string();
+        roa_store.create_roa(&described).unwrap();
+        let slurm = serde_json::to_value(roa::slurm(&roa_store.get_roas().unwrap())).unwrap();
+        assert_eq!(slurm, serde_json::json!({
+            "slurmVersion": 1,
+            "validationOutputFilters": { "prefixFilters": [], "bgpsecFilters": [] },
+            "locallyAddedAssertions": {
+                "prefixAssertions": [
+                    { "asn": 64500, "prefix": "192.0.2.0/24", "maxPrefixLength": 28 },
+                    { "asn": 64502, "prefix": "2001:db8::/32", "maxPrefixLength": 32, "comment": "Backup" },
+                    { "asn": 64501, "prefix": "2001:db8::/32", "maxPrefixLength": 48 },
+                ],
+                "bgpsecAssertions": [],
+            },
+        }));
+
+        let api = test_router(server::Server::new(store.clone()));
+        assert_eq!(api.anonymous_request("GET", "/api/v1/roa", serde_json::Value::Null).await.0, 401);
+        let (status, body) = api.api_request("GET", "/api/v1/roa", serde_json::Value::Null).await;
+        assert_eq!((status, body["roas"].as_array().unwrap().len()), (200, 3));
+        let (status, body) = api.api_request("POST", "/api/v1/roa", serde_json::json!({
+            "prefix": "192.0.2.128", "prefix_len": 25, "max_length": 25, "asn": 0,
+        })).await;
+        assert_eq!(status, 200, "{}", body);
+        let id = body["roa"]["id"].as_i64().unwrap();
+        let (status, body) = api.api_request("PUT", &format!("/api/v1/roa/{}", id), serde_json::json!({
+            "prefix": "192.0.2.128", "prefix_len": 25, "max_length": 24, "asn": 0,
+        })).await;
+        assert_eq!((status, body["code"].as_str()), (422, Some("validation")));
+        assert_eq!(api.api_request("DELETE", &format!("/api/v1/roa/{}", id), serde_json::Value::Null).await.0, 200);
+        assert_eq!(api.api_request("GET", &format!("/api/v1/roa/{}", id), serde_json::Value::Null).await.0, 404);
+        assert_eq!(api.api_request("DELETE", &format!("/api/v1/roa/{}", id), serde_json::Value::Null).await.0, 404);
+        let (status, body) = api.api_request("GET", "/api/v1/roa/coverage", serde_json::Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["uncovered_assignments"][0]["prefix"], "192.0.2.0/28");
+        let (status, body) = api.api_request("GET", "/api/v1/export/slurm", serde_json::Value::Null).await;
+        assert_eq!((status, body), (200, slurm));
+
+        roa_store.delete_roa(roa6_id).unwrap();
+        assert_eq!(uncovered(&store), ["192.0.2.0/28", "2001:db8::/48"]);
+    }
+
+    #[tokio::test]
+    async fn rtr() {
+        use types::ObjectVisibility::Public;
+        use server::listener::{ListenAddr, Listener};
+        use server::rtr::{self, Pdu, RtrCache, RtrConfig, Vrp};
+        use tokio::io::AsyncWriteExt;
+        use std::net::IpAddr;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        store.ipv4_assignments().create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        store.ipv6_assignments().create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 32,
+        }).unwrap();
+        let roa_store = store.roas();
+        let roa = |prefix: &str, prefix_len, max_length, asn| roa::Roa {
+            id: 0, prefix: prefix.parse::<IpAddr>().unwrap(), prefix_len, max_length, asn, description: "".to_string(),
+        };
+        let vrp = |prefix: &str, prefix_len, max_length, asn| Vrp { prefix: prefix.parse::<IpAddr>().unwrap(), prefix_len, max_length, asn };
+        let roa_id = roa_store.create_roa(&roa("192.0.2.0", 24, 24, 64500)).unwrap();
+        roa_store.create_roa(&roa("2001:db8::", 32, 48, 64501)).unwrap();
+        assert_eq!(roa_store.get_serial().unwrap(), 2);
+        roa_store.delete_roa(roa_id + 100).unwrap();
+        assert_eq!(roa_store.get_serial().unwrap(), 2);
+
+        let cache = RtrCache::load(&store).await.unwrap();
+        let session_id = cache.session_id();
+        let mut config = RtrConfig::new(vec![ListenAddr::Tcp("127.0.0.1:0".to_string())]);
+        config.refresh_interval = 900;
+        let std_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = std_listener.local_addr().unwrap();
+        let (shutdown, shutdown_receiver) = tokio::sync::watch::channel(false);
+        let task = tokio::spawn(rtr::serve(Listener::Tcp(std_listener), cache.clone(), std::sync::Arc::new(config), shutdown_receiver));
+
+        // A minimal router: send PDUs, then read up to End of Data, Cache Reset or Error Report
+        async fn send(stream: &mut tokio::net::TcpStream, version: u8, pdus: &[Pdu]) {
+            let mut buf = Vec::new();
+            for pdu in pdus {
+                pdu.encode(version, &mut buf);
+            }
+            stream.write_all(&buf).await.unwrap();
+        }
+        async fn receive(stream: &mut tokio::net::TcpStream) -> Vec<(u8, Pdu)> {
+            let mut pdus = Vec::new();
+            while let Some(raw) = rtr::read_pdu(stream).await.unwrap() {
+                let (version, pdu) = Pdu::decode(&raw).unwrap();
+                let last = matches!(pdu, Pdu::EndOfData { .. } | Pdu::CacheReset | Pdu::ErrorReport { .. });
+                pdus.push((version, pdu));
+                if last {
+                    break;
+                }
+            }
+            pdus
+        }
+        let end_of_data = |serial, version| Pdu::EndOfData {
+            session_id, serial,
+            refresh_interval: if version == 1 { 900 } else { 0 },
+            retry_interval: if version == 1 { 600 } else { 0 },
+            expire_interval: if version == 1 { 7200 } else { 0 },
+        };
+
+        let mut router = tokio::net::TcpStream::connect(addr).await.unwrap();
+        send(&mut router, 1, &[Pdu::ResetQuery]).await;
+        assert_eq!(receive(&mut router).await.into_iter().map(|(_, pdu)| pdu).collect::<Vec<_>>(), [
+            Pdu::CacheResponse { session_id },
+            Pdu::Prefix { announce: true, vrp: vrp("192.0.2.0", 24, 24, 64500) },
+            Pdu::Prefix { announce: true, vrp: vrp("2001:db8::", 32, 48, 64501) },
+            end_of_data(2, 1),
+        ]);
+
+        // A change through the store is announced, and served incrementally
+        roa_store.update_roa(roa_id, &roa("192.0.2.0", 24, 26, 64500)).unwrap();
+        assert!(cache.refresh(&store).await.unwrap());
+        assert!(!cache.refresh(&store).await.unwrap());
+        let notify = rtr::read_pdu(&mut router).await.unwrap().unwrap();
+        assert_eq!(Pdu::decode(&notify).unwrap(), (1, Pdu::SerialNotify { session_id, serial: 3 }));
+        send(&mut router, 1, &[Pdu::SerialQuery { session_id, serial: 2 }]).await;
+        assert_eq!(receive(&mut router).await.into_iter().map(|(_, pdu)| pdu).collect::<Vec<_>>(), [
+            Pdu::CacheResponse { session_id },
+            Pdu::Prefix { announce: false, vrp: vrp("192.0.2.0", 24, 24, 64500) },
+            Pdu::Prefix { announce: true, vrp: vrp("192.0.2.0", 24, 26, 64500) },
+            end_of_data(3, 1),
+        ]);
+        send(&mut router, 1, &[Pdu::SerialQuery { session_id, serial: 3 }]).await;
+        assert_eq!(receive(&mut router).await.len(), 2);
+
+        // Unknown serials and sessions require a reset
+        send(&mut router, 1, &[Pdu::SerialQuery { session_id, serial: 1 }]).await;
+        assert_eq!(receive(&mut router).await, [(1, Pdu::CacheReset)]);
+        send(&mut router, 1, &[Pdu::SerialQuery { session_id: session_id.wrapping_add(1), serial: 3 }]).await;
+        assert_eq!(receive(&mut router).await, [(1, Pdu::CacheReset)]);
+
+        // Changing the version within a session is an error, which closes the connection
+        send(&mut router, 0, &[Pdu::ResetQuery]).await;
+        let response = receive(&mut router).await;
+        assert!(matches!(response[..], [(1, Pdu::ErrorReport { code: rtr::ERROR_UNEXPECTED_PROTOCOL_VERSION, .. })]), "{:?}", response);
+        assert_eq!(rtr::read_pdu(&mut router).await.unwrap(), None);
+
+        // Version 0 routers get the short End of Data
+        let mut router = tokio::net::TcpStream::connect(addr).await.unwrap();
+        roa_store.delete_roa(roa_id).unwrap();
+        cache.refresh(&store).await.unwrap();
+        send(&mut router, 0, &[Pdu::ResetQuery]).await;
+        assert_eq!(receive(&mut router).await, [
+            (0, Pdu::CacheResponse { session_id }),
+            (0, Pdu::Prefix { announce: true, vrp: vrp("2001:db8::", 32, 48, 64501) }),
+            (0, end_of_data(4, 0)),
+        ]);
+
+        // Unsupported versions are refused with the highest version we speak
+        let mut router = tokio::net::TcpStream::connect(addr).await.unwrap();
+        router.write_all(&[2, 2, 0, 0, 0, 0, 0, 8]).await.unwrap();
+        let response = receive(&mut router).await;
+        assert!(matches!(response[..], [(1, Pdu::ErrorReport { code: rtr::ERROR_UNSUPPORTED_PROTOCOL_VERSION, .. })]), "{:?}", response);
+
+        shutdown.send_replace(true);
+        task.await.unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reverse_dns() {
+        use types::ObjectVisibility::Public;
+        use user::ResourceFamily::{Ipv4, Ipv6};
+        use dns::ReverseDns;
+
+        assert_eq!(dns::reverse_name("192.0.2.64".parse().unwrap(), 26), "64/26.2.0.192.in-addr.arpa");
+        assert_eq!(dns::reverse_name("2001:db8::".parse().unwrap(), 32), "8.b.d.0.1.0.0.2.ip6.arpa");
+        assert_eq!(dns::label("Web servers (Tokyo)"), "web-servers-tokyo");
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let mut ipv4_ids = Vec::new();
+        for (prefix, prefix_len, assignments) in [([192, 0, 2, 0], 24, vec![([192, 0, 2, 0], 26), ([192, 0, 2, 64], 30)]), ([198, 51, 100, 0], 23, vec![([198, 51, 101, 0], 24)])] {
+            let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+                id: 0, name: format!("space {}", prefix[0]), description: "".to_string(), space_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: prefix_len,
+            }).unwrap();
+            let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+                id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: prefix_len,
+            }).unwrap();
+            for (prefix, prefix_len) in assignments {
+                ipv4_ids.push(ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+                    id: 0, assignment_pool_id: pool_id, name: "Web servers".to_string(), description: "".to_string(),
+                    assignment_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: prefix_len, origin_asn_id: None,
+                }).unwrap());
+            }
+        }
+        let ipv6_store = store.ipv6_assignments();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 30,
+        }).unwrap();
+        let pool_id = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public,
+            ipv6_prefix: "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: 30,
+        }).unwrap();
+        let ipv6_ids: Vec<i32> = [("2001:db8:100::", 40), ("2001:db8:200::", 39), ("2001:db8::", 126), ("2001:db9::", 64)].into_iter().map(|(prefix, prefix_len)| {
+            ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+                id: 0, assignment_pool_id: pool_id, name: "net6".to_string(), description: "".to_string(), assignment_visibility: Public,
+                ipv6_prefix: prefix.parse::<std::net::Ipv6Addr>().unwrap().octets(), ipv6_prefix_len: prefix_len, origin_asn_id: None,
+            }).unwrap()
+        }).collect();
+
+        let delegated = |nameservers: &[&str]| ReverseDns { nameservers: nameservers.iter().map(|name| name.to_string()).collect(), ptr_template: None };
+        let template = |template: &str| ReverseDns { nameservers: Vec::new(), ptr_template: Some(template.to_string()) };
+        let reverse_dns = store.reverse_dns();
+        reverse_dns.set_reverse_dns(Ipv4, ipv4_ids[0], &delegated(&["ns1.customer.example", "ns2.customer.example."])).unwrap();
+        reverse_dns.set_reverse_dns(Ipv4, ipv4_ids[1], &template("{ip}.{name}.example.net")).unwrap();
+        reverse_dns.set_reverse_dns(Ipv4, ipv4_ids[2], &delegated(&["ns.customer.example"])).unwrap();
+        reverse_dns.set_reverse_dns(Ipv6, ipv6_ids[0], &delegated(&["ns.customer.example"])).unwrap();
+        reverse_dns.set_reverse_dns(Ipv6, ipv6_ids[1], &delegated(&["ns.customer.example"])).unwrap();
+        reverse_dns.set_reverse_dns(Ipv6, ipv6_ids[2], &template("host-{ip}.example.net.")).unwrap();
+        assert_eq!(reverse_dns.get_reverse_dns(Ipv4, ipv4_ids[1]).unwrap().ptr_template.as_deref(), Some("{ip}.{name}.example.net."));
+        for (family, id, invalid, kind) in [
+            (Ipv6, ipv6_ids[3], template("{ip}.example.net"), types::ErrorKind::Validation),
+            (Ipv4, ipv4_ids[1], template("{ip}..example.net"), types::ErrorKind::Validation),
+            (Ipv4, ipv4_ids[1], ReverseDns { nameservers: vec!["ns.example".to_string()], ptr_template: Some("{ip}.example.net".to_string()) }, types::ErrorKind::Validation),
+            (Ipv4, ipv4_ids[1] + 100, delegated(&["ns.example"]), types::ErrorKind::NotFound),
+            (user::ResourceFamily::Asn, ipv4_ids[1], delegated(&["ns.example"]), types::ErrorKind::Validation),
+        ] {
+            assert_eq!(reverse_dns.set_reverse_dns(family, id, &invalid).unwrap_err().kind(), kind, "{:?}", invalid);
+        }
+
+        let mut config = dns::DnsConfig::default();
+        assert_eq!(dns::reverse_zones(&store, &config, 1).unwrap_err().kind(), types::ErrorKind::InvalidInput);
+        config.nameservers = vec!["ns1.example.net".to_string(), "ns2.example.net.".to_string()];
+        config.hostmaster = "hostmaster.example.net".to_string();
+        let zones = dns::reverse_zones(&store, &config, 2024010100).unwrap();
+        assert_eq!(zones.iter().map(|zone| zone.name.as_str()).collect::<Vec<_>>(), [
+            "2.0.192.in-addr.arpa",
+            "100.51.198.in-addr.arpa",
+            "8.b.d.0.1.0.0.2.ip6.arpa", "9.b.d.0.1.0.0.2.ip6.arpa", "a.b.d.0.1.0.0.2.ip6.arpa", "b.b.d.0.1.0.0.2.ip6.arpa",
+        ]);
+        let mut expected = "; Reverse zone for assignment space space 192 (192.0.2.0/24)
+$ORIGIN 2.0.192.in-addr.arpa.
+$TTL 3600
+@\tIN\tSOA\tns1.example.net. hostmaster.example.net. 2024010100 3600 900 1209600 3600
+@\tIN\tNS\tns1.example.net.
+@\tIN\tNS\tns2.example.net.
+0/26\tIN\tNS\tns1.customer.example.
+0/26\tIN\tNS\tns2.customer.example.
+".to_string();
+        for i in 0..64 {
+            expected += &format!("{}\tIN\tCNAME\t{}.0/26\n", i, i);
+        }
+        for i in 64..68 {
+            expected += &format!("{}\tIN\tPTR\t192-0-2-{}.web-servers.example.net.\n", i, i);
+        }
+        assert_eq!(zones[0].content, expected);
+        assert!(zones[2].content.ends_with("1.0\tIN\tNS\tns.customer.example.\n2.0\tIN\tNS\tns.customer.example.\n3.0\tIN\tNS\tns.customer.example.\n"), "{}", zones[2].content);
+        assert!(zones[2].content.contains(&format!("1{}\tIN\tPTR\thost-2001-db8-0-0-0-0-0-1.example.net.\n", ".0".repeat(23))), "{}", zones[2].content);
+        assert!(!zones[1].content.contains("\tIN\tNS\tns.customer.example."), "{}", zones[1].content);
+
+        // Deleting an assignment removes its reverse DNS
+        ipv4_store.delete_assignment(ipv4_ids[2]).unwrap();
+        assert!(reverse_dns.get_reverse_dns(Ipv4, ipv4_ids[2]).unwrap().is_empty());
+        assert_eq!(dns::reverse_zones(&store, &config, 1).unwrap().len(), 7);
+
+        let api = test_router(server::Server::new(store.clone()).with_dns(config.clone()));
+        let uri = format!("/api/v1/ipv6/assignment_space/{}/pool/{}/assignment/{}/reverse_dns", space_id, pool_id, ipv6_ids[2]);
+        assert_eq!(api.text_request("GET", &uri, false, serde_json::Value::Null).await.0, 401);
+        let (status, body) = api.text_request("PUT", &uri, true, serde_json::json!({ "nameservers": ["ns.example.org"] })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert!(body.contains("\"nameservers\":[\"ns.example.org.\"]"), "{}", body);
+        assert_eq!(api.text_request("PUT", &uri, true, serde_json::json!({ "nameservers": ["bad name"] })).await.0, 422);
+
+        // An assignment is not kept without the reverse DNS it was created with
+        let (status, body) = api.text_request("POST", &format!("/api/v1/ipv6/assignment_space/{}/pool/{}/assignment", space_id, pool_id), true, serde_json::json!({
+            "assignment_pool_id": pool_id, "name": "net6", "description": "", "assignment_visibility": "public",
+            "ipv6_prefix": "2001:db8:1000::".parse::<std::net::Ipv6Addr>().unwrap().octets(), "ipv6_prefix_len": 48,
+            "reverse_dns": { "ptr_template": "{ip}.example.net" },
+        })).await;
+        assert_eq!(status, 422, "{}", body);
+        assert_eq!(ipv6_store.get_assignments(pool_id).unwrap().len(), ipv6_ids.len());
+        assert_eq!(api.text_request("GET", &uri.replace(&format!("/pool/{}/", pool_id), &format!("/pool/{}/", pool_id + 100)), true, serde_json::Value::Null).await.0, 404);
+
+        let (status, body) = api.text_request("GET", "/api/v1/export/dns-reverse", true, serde_json::Value::Null).await;
+        assert_eq!(status, 200, "{}", body);
+        assert!(body.contains("\"name\":\"2.0.192.in-addr.arpa\""), "{}", body);
+        let (status, body) = api.text_request("GET", "/api/v1/export/dns-reverse/8.b.d.0.1.0.0.2.ip6.arpa.", true, serde_json::Value::Null).await;
+        assert_eq!(status, 200);
+        assert!(body.starts_with("; Reverse zone for assignment space space6 (2001:db8::/30)\n$ORIGIN 8.b.d.0.1.0.0.2.ip6.arpa.\n"), "{}", body);
+        assert!(body.contains("\tIN\tNS\tns.example.org.\n"), "{}", body);
+        assert_eq!(api.text_request("GET", "/api/v1/export/dns-reverse/example.com", true, serde_json::Value::Null).await.0, 404);
+        assert_eq!(api.text_request("GET", "/api/v1/export/dns-reverse", false, serde_json::Value::Null).await.0, 401);
+
+        // The SOA serial only changes along with the data
+        let serial = reverse_dns.get_serial().unwrap();
+        let zone = "/api/v1/export/dns-reverse/8.b.d.0.1.0.0.2.ip6.arpa";
+        for _ in 0..2 {
+            let body = api.text_request("GET", zone, true, serde_json::Value::Null).await.1;
+            assert!(body.contains(&format!(". {} ", serial)), "{}", body);
+        }
+        ipv6_store.update_assignment(ipv6_ids[0], "renamed", "").unwrap();
+        let body = api.text_request("GET", zone, true, serde_json::Value::Null).await.1;
+        assert!(body.contains(&format!(". {} ", serial + 1)), "{}", body);
+
+        // So does deleting the space of an assignment
+        ipv6_store.delete_space(space_id).unwrap();
+        assert!(reverse_dns.get_all_reverse_dns(Ipv6).unwrap().is_empty());
+        assert_eq!(reverse_dns.get_serial().unwrap(), serial + 2);
+        let (status, body) = api.text_request("GET", "/api/v1/export/dns-reverse", true, serde_json::Value::Null).await;
+        assert_eq!(status, 200);
+        assert!(!body.contains("ns.example.org"), "{}", body);
+    }
+
+    /// A DNS server on a local port that checks TSIG signatures, answers updates with `rcode`
+    /// (signed if it is NOERROR), and passes on the updates it receives
+    async fn mock_dns_server(key: dns_update::TsigKey, rcode: u8) -> (std::net::SocketAddr, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let key = key.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    while let Ok(len) = stream.read_u16().await {
+                        let mut message = vec![0; len as usize];
+                        stream.read_exact(&mut message).await.unwrap();
+                        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+                        let request_mac = dns_update::verify(&message, "mirams-test", &key, now, None).unwrap();
+                        let mut response = vec![message[0], message[1], 0xa8, rcode, 0, 0, 0, 0, 0, 0, 0, 0];
+                        if rcode == 0 {
+                            dns_update::sign(&mut response, "mirams-test", &key, now, Some(&request_mac)).unwrap();
+                        }
+                        let _ = sender.send(message);
+                        stream.write_all(&(response.len() as u16).to_be_bytes()).await.unwrap();
+                        stream.write_all(&response).await.unwrap();
+                    }
+                });
+            }
+        });
+        (addr, receiver)
+    }
+
+    #[tokio::test]
+    async fn dns_update() {
+        use types::ObjectVisibility::Public;
+        use dns::{ReverseDns, ReverseRecord};
+        use dns_update::{TsigKey, ZoneUpdate};
+        use std::time::Duration;
+
+        let records = dns::assignment_reverse_records("192.0.2.0".parse().unwrap(), 24, "192.0.2.64".parse().unwrap(), 26, "customer", &ReverseDns {
+            nameservers: vec!["ns.customer.example".to_string()],
+            ptr_template: None,
+        });
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, "2.0.192.in-addr.arpa");
+        assert_eq!(records[0].1.len(), 65);
+        assert_eq!(records[0].1[0], ReverseRecord { name: "64/26.2.0.192.in-addr.arpa".to_string(), rtype: "NS", target: "ns.customer.example.".to_string() });
+        assert_eq!(records[0].1[1], ReverseRecord { name: "64.2.0.192.in-addr.arpa".to_string(), rtype: "CNAME", target: "64.64/26.2.0.192.in-addr.arpa.".to_string() });
+        // A delegation of a whole zone belongs in the parent zone
+        assert!(dns::assignment_reverse_records("198.51.100.0".parse().unwrap(), 23, "198.51.101.0".parse().unwrap(), 24, "", &ReverseDns {
+            nameservers: vec!["ns.customer.example".to_string()],
+            ptr_template: None,
+        }).is_empty());
+
+        // Signatures cover the whole message
+        let key = TsigKey { algorithm: "hmac-sha256".to_string(), secret: "bWlyYW1zLXRlc3Qtc2VjcmV0".to_string() };
+        let update = ZoneUpdate {
+            zone: "2.0.192.in-addr.arpa".to_string(),
+            delete: [("1.2.0.192.in-addr.arpa".to_string(), "PTR")].into_iter().collect(),
+            add: vec![ReverseRecord { name: "1.2.0.192.in-addr.arpa".to_string(), rtype: "PTR", target: "host.example.net.".to_string() }],
+            ttl: 3600,
+        };
+        let mut message = update.messages().unwrap().remove(0);
+        let mac = dns_update::sign(&mut message, "mirams-test.", &key, 1700000000, None).unwrap();
+        assert_eq!(mac.len(), 32);
+        assert_eq!(dns_update::verify(&message, "mirams-test", &key, 1700000100, None).unwrap(), mac);
+        assert!(dns_update::verify(&message, "mirams-test", &key, 1700001000, None).is_err());
+        assert!(dns_update::verify(&message, "other-key", &key, 1700000000, None).is_err());
+        let other = TsigKey { algorithm: "hmac-sha256".to_string(), secret: "b3RoZXItc2VjcmV0".to_string() };
+        assert!(dns_update::verify(&message, "mirams-test", &other, 1700000000, None).is_err());
+        message[20] ^= 1;
+        assert!(dns_update::verify(&message, "mirams-test", &key, 1700000000, None).is_err());
+        assert!(TsigKey { algorithm: "hmac-md5".to_string(), secret: "c2VjcmV0".to_string() }.validate().is_err());
+
+        // Refusals are reported
+        let (refusing, _) = mock_dns_server(key.clone(), 5).await;
+        let error = dns_update::send_update(&refusing.to_string(), &update, "mirams-test", &key, Duration::from_secs(5)).await.unwrap_err();
+        assert!(error.to_string().contains("REFUSED"), "{}", error);
+
+        let (server_addr, mut updates) = mock_dns_server(key.clone(), 0).await;
+        dns_update::send_update(&server_addr.to_string(), &update, "mirams-test", &key, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(updates.recv().await.unwrap()[8..10], [0, 2]);
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db.clone());
+        let space_id = store.ipv4_assignments().create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = store.ipv4_assignments().create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let mut config = dns::DnsConfig::default();
+        config.keys.insert("mirams-test".to_string(), key.clone());
+        let api = test_router(server::Server::new(store.clone()).with_dns(config));
+        let contains = |message: &[u8], bytes: &[u8]| message.windows(bytes.len()).any(|window| window == bytes);
+
+        let uri = format!("/api/v1/ipv4/assignment_space/{}/dns_update", space_id);
+        assert_eq!(api.text_request("GET", &uri, true, serde_json::Value::Null).await, (200, "{\"error\":null,\"dns_update\":null}".to_string()));
+        assert_eq!(api.text_request("PUT", &uri, true, serde_json::json!({ "server": server_addr.to_string(), "key": "unknown" })).await.0, 422);
+        assert_eq!(api.text_request("PUT", &uri.replace(&format!("/{}/", space_id), "/100/"), true, serde_json::json!({ "server": server_addr.to_string(), "key": "mirams-test" })).await.0, 404);
+        let (status, body) = api.text_request("PUT", &uri, true, serde_json::json!({ "server": server_addr.to_string(), "key": "Mirams-Test." })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert!(body.contains("\"key\":\"mirams-test\""), "{}", body);
+
+        // Creating an assignment adds its records: 4 record sets deleted, 4 records added
+        let assignments = format!("/api/v1/ipv4/assignment_space/{}/pool/{}/assignment", space_id, pool_id);
+        let (status, body) = api.text_request("POST", &assignments, true, serde_json::json!({
+            "assignment_pool_id": pool_id, "name": "Web", "description": "", "assignment_visibility": "public",
+            "ipv4_prefix": [192, 0, 2, 8], "ipv4_prefix_len": 30,
+            "reverse_dns": { "ptr_template": "{ip}.{name}.example.net" },
+        })).await;
+        assert_eq!(status, 200, "{}", body);
+        let assignment: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let assignment_id = assignment["ipv4_assignment"]["id"].as_i64().unwrap();
+        let message = tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(message[8..10], [0, 8]);
+        assert!(contains(&message, b"\x012\x010\x03192\x07in-addr\x04arpa\x00"));
+        assert!(contains(&message, b"\x09192-0-2-9\x03web\x07example\x03net\x00"));
+
+        // Renaming changes the PTR records that use the name
+        let (status, body) = api.text_request("PUT", &format!("{}/{}", assignments, assignment_id), true, serde_json::json!({ "name": "Mail", "description": "" })).await;
+        assert_eq!(status, 200, "{}", body);
+        let message = tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(message[8..10], [0, 8]);
+        assert!(contains(&message, b"\x09192-0-2-9\x04mail\x07example\x03net\x00"));
+
+        // Unchanged records are not sent again
+        let (status, body) = api.text_request("PUT", &format!("{}/{}/reverse_dns", assignments, assignment_id), true, serde_json::json!({ "ptr_template": "{ip}.static.example.net" })).await;
+        assert_eq!(status, 200, "{}", body);
+        let (status, body) = api.text_request("PUT", &format!("{}/{}/reverse_dns", assignments, assignment_id), true, serde_json::json!({ "ptr_template": "{ip}.static.example.net" })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap()[8..10], [0, 8]);
+
+        // Changes are refused when the records before them cannot be read
+        db.get_conn().unwrap().execute_batch("ALTER TABLE reverse_dns_ipv4 RENAME TO reverse_dns_ipv4_unavailable").unwrap();
+        assert_eq!(api.text_request("PUT", &format!("{}/{}", assignments, assignment_id), true, serde_json::json!({ "name": "Web", "description": "" })).await.0, 500);
+        assert_eq!(api.text_request("DELETE", &format!("{}/{}", assignments, assignment_id), true, serde_json::Value::Null).await.0, 500);
+        db.get_conn().unwrap().execute_batch("ALTER TABLE reverse_dns_ipv4_unavailable RENAME TO reverse_dns_ipv4").unwrap();
+        assert_eq!(store.ipv4_assignments().get_assignment(assignment_id as i32).unwrap().name, "Mail");
+        assert!(updates.try_recv().is_err());
+
+        // Deleting only deletes
+        assert_eq!(api.text_request("DELETE", &format!("{}/{}", assignments, assignment_id), true, serde_json::Value::Null).await.0, 200);
+        assert_eq!(tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap()[8..10], [0, 4]);
+        assert!(updates.try_recv().is_err());
+
+        assert_eq!(api.text_request("DELETE", &uri, true, serde_json::Value::Null).await.0, 200);
+        assert!(store.reverse_dns().get_dns_update(user::ResourceFamily::Ipv4, space_id).unwrap().is_none());
+
+        // Deleting a pool or a space deletes the records of its assignments, and the settings
+        // are deleted along with their space
+        assert_eq!(api.text_request("PUT", &uri, true, serde_json::json!({ "server": server_addr.to_string(), "key": "mirams-test" })).await.0, 200);
+        let pools = format!("/api/v1/ipv4/assignment_space/{}/pool", space_id);
+        for delete_space in [false, true] {
+            let pool_id = match delete_space {
+                false => pool_id,
+                true => store.ipv4_assignments().create_pool(&ipv4::AssignmentPoolIpv4 {
+                    id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+                }).unwrap(),
+            };
+            let (status, body) = api.text_request("POST", &format!("{}/{}/assignment", pools, pool_id), true, serde_json::json!({
+                "assignment_pool_id": pool_id, "name": "Web", "description": "", "assignment_visibility": "public",
+                "ipv4_prefix": [192, 0, 2, 8], "ipv4_prefix_len": 30,
+                "reverse_dns": { "ptr_template": "{ip}.{name}.example.net" },
+            })).await;
+            assert_eq!(status, 200, "{}", body);
+            assert_eq!(tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap()[8..10], [0, 8]);
+            let delete_uri = match delete_space {
+                false => format!("{}/{}", pools, pool_id),
+                true => format!("/api/v1/ipv4/assignment_space/{}", space_id),
+            };
+            assert_eq!(api.text_request("DELETE", &delete_uri, true, serde_json::Value::Null).await.0, 200);
+            assert_eq!(tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap()[8..10], [0, 4]);
+        }
+        assert!(store.reverse_dns().get_dns_update(user::ResourceFamily::Ipv4, space_id).unwrap().is_none());
+        assert_eq!(api.text_request("DELETE", &format!("/api/v1/ipv4/assignment_space/{}", space_id), true, serde_json::Value::Null).await.0, 404);
+
+        // A server that refuses updates, retried after 10 s, does not hold up other servers
+        let (refusing, mut refused) = mock_dns_server(key.clone(), 5).await;
+        for (prefix, server_addr) in [([198, 51, 100, 0], refusing), ([203, 0, 113, 0], server_addr)] {
+            let space_id = store.ipv4_assignments().create_space(&ipv4::AssignmentSpaceIpv4 {
+                id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: 24,
+            }).unwrap();
+            let pool_id = store.ipv4_assignments().create_pool(&ipv4::AssignmentPoolIpv4 {
+                id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: 24,
+            }).unwrap();
+            let uri = format!("/api/v1/ipv4/assignment_space/{}/dns_update", space_id);
+            assert_eq!(api.text_request("PUT", &uri, true, serde_json::json!({ "server": server_addr.to_string(), "key": "mirams-test" })).await.0, 200);
+            let (status, body) = api.text_request("POST", &format!("/api/v1/ipv4/assignment_space/{}/pool/{}/assignment", space_id, pool_id), true, serde_json::json!({
+                "assignment_pool_id": pool_id, "name": "Web", "description": "", "assignment_visibility": "public",
+                "ipv4_prefix": [prefix[0], prefix[1], prefix[2], 8], "ipv4_prefix_len": 30,
+                "reverse_dns": { "ptr_template": "{ip}.{name}.example.net" },
+            })).await;
+            assert_eq!(status, 200, "{}", body);
+        }
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), refused.recv()).await.unwrap().unwrap()[8..10], [0, 8]);
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), updates.recv()).await.unwrap().unwrap()[8..10], [0, 8]);
+    }
+
+    /// Runs against a local BIND or Knot primary for `2.0.192.in-addr.arpa` that accepts updates
+    /// signed with the hmac-sha256 key `mirams-test`, such as from `tsig-keygen mirams-test` and
+    /// `update-policy { grant mirams-test zonesub ANY; };` in `named.conf`:
+    ///
+    /// ```sh
+    /// MIRAMS_TEST_DNS_SERVER=127.0.0.1:53 MIRAMS_TEST_DNS_SECRET=<secret> cargo test dns_update_server -- --ignored
+    /// ```
+    #[tokio::test]
+    #[ignore]
+    async fn dns_update_server() {
+        use dns::ReverseRecord;
+        use dns_update::{TsigKey, ZoneUpdate};
+
+        let server = std::env::var("MIRAMS_TEST_DNS_SERVER").unwrap_or("127.0.0.1:53".to_string());
+        let key = TsigKey { algorithm: "hmac-sha256".to_string(), secret: std::env::var("MIRAMS_TEST_DNS_SECRET").unwrap() };
+        let mut update = ZoneUpdate {
+            zone: "2.0.192.in-addr.arpa".to_string(),
+            delete: [("1.2.0.192.in-addr.arpa".to_string(), "PTR")].into_iter().collect(),
+            add: vec![ReverseRecord { name: "1.2.0.192.in-addr.arpa".to_string(), rtype: "PTR", target: "host.example.net.".to_string() }],
+            ttl: 3600,
+        };
+        dns_update::send_update(&server, &update, "mirams-test", &key, std::time::Duration::from_secs(5)).await.unwrap();
+        update.add.clear();
+        dns_update::send_update(&server, &update, "mirams-test", &key, std::time::Duration::from_secs(5)).await.unwrap();
+
+        let wrong_key = TsigKey { algorithm: "hmac-sha256".to_string(), secret: "d3Jvbmc=".to_string() };
+        assert!(dns_update::send_update(&server, &update, "mirams-test", &wrong_key, std::time::Duration::from_secs(5)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hosts() {
+        use types::ObjectVisibility::Public;
+
+        assert_eq!(types::normalize_mac_address("00-00-5E-00-53-01").unwrap(), "00:00:5e:00:53:01");
+        assert_eq!(types::normalize_mac_address("0000.5e00.5301").unwrap(), "00:00:5e:00:53:01");
+        assert_eq!(types::normalize_mac_address("00:00:5e:00:53").unwrap_err().kind(), types::ErrorKind::Validation);
+        assert_eq!(ipv4::ipv4_reserved_addresses([192, 0, 2, 9], 29), [[192, 0, 2, 8], [192, 0, 2, 15]]);
+        assert!(ipv4::ipv4_reserved_addresses([192, 0, 2, 8], 31).is_empty());
+        assert_eq!(ipv6::ipv6_reserved_addresses("2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets(), 64).len(), 1);
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let assignment_ids: Vec<i32> = [([192, 0, 2, 0], 29), ([192, 0, 2, 8], 31)].into_iter().map(|(prefix, prefix_len)| {
+            ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+                id: 0, assignment_pool_id: pool_id, name: "Servers".to_string(), description: "".to_string(),
+                assignment_visibility: Public, ipv4_prefix: prefix, ipv4_prefix_len: prefix_len, origin_asn_id: None,
+            }).unwrap()
+        }).collect();
+        let host = |assignment_id: i32, address: [u8; 4], hostname: &str, gateway: bool| ipv4::HostIpv4 {
+            id: 0, assignment_id, ipv4_address: address, hostname: hostname.to_string(), mac_address: None, description: "".to_string(), gateway,
+        };
+
+        // The first usable address is kept for the gateway until there is one
+        assert_eq!(ipv4_store.next_free_host(assignment_ids[0]).unwrap(), Some([192, 0, 2, 2]));
+        let mut web = host(assignment_ids[0], [192, 0, 2, 2], "Web1.example.net.", false);
+        web.mac_address = Some("00-00-5E-00-53-01".to_string());
+        let web_id = ipv4_store.create_host(&web).unwrap();
+        let web = ipv4_store.get_host(web_id).unwrap();
+        assert_eq!((web.hostname.as_str(), web.mac_address.as_deref()), ("web1.example.net", Some("00:00:5e:00:53:01")));
+        assert_eq!(ipv4_store.next_free_host(assignment_ids[0]).unwrap(), Some([192, 0, 2, 3]));
+        ipv4_store.create_host(&host(assignment_ids[0], [192, 0, 2, 1], "gw.example.net", true)).unwrap();
+        assert_eq!(ipv4_store.next_free_host(assignment_ids[0]).unwrap(), Some([192, 0, 2, 3]));
+        for (invalid, kind) in [
+            (host(assignment_ids[0], [192, 0, 2, 9], "", false), types::ErrorKind::Validation),
+            (host(assignment_ids[0], [192, 0, 2, 7], "", false), types::ErrorKind::Validation),
+            (host(assignment_ids[0], [192, 0, 2, 0], "", false), types::ErrorKind::Validation),
+            (host(assignment_ids[0], [192, 0, 2, 3], "bad..name", false), types::ErrorKind::Validation),
+            (host(assignment_ids[0], [192, 0, 2, 2], "", false), types::ErrorKind::Conflict),
+            (host(assignment_ids[0], [192, 0, 2, 3], "", true), types::ErrorKind::Conflict),
+            (host(assignment_ids[0] + 100, [192, 0, 2, 3], "", false), types::ErrorKind::NotFound),
+        ] {
+            assert_eq!(ipv4_store.create_host(&invalid).unwrap_err().kind(), kind, "{:?}", invalid);
+        }
+        for address in 3..=6 {
+            ipv4_store.create_host(&host(assignment_ids[0], [192, 0, 2, address], "", false)).unwrap();
+        }
+        assert_eq!(ipv4_store.next_free_host(assignment_ids[0]).unwrap(), None);
+        let hosts = ipv4_store.get_hosts(assignment_ids[0]).unwrap();
+        assert_eq!(hosts.iter().map(|host| host.ipv4_address[3]).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+
+        // Point-to-point links have no reserved addresses
+        assert_eq!(ipv4_store.next_free_host(assignment_ids[1]).unwrap(), Some([192, 0, 2, 8]));
+        ipv4_store.create_host(&host(assignment_ids[1], [192, 0, 2, 9], "", false)).unwrap();
+
+        let api = test_router(server::Server::new(store.clone()));
+        let hosts_uri = format!("/api/v1/ipv4/assignment_space/{}/pool/{}/assignment/{}/host", space_id, pool_id, assignment_ids[1]);
+        assert_eq!(api.anonymous_request("GET", &hosts_uri, serde_json::Value::Null).await.0, 401);
+        let (status, body) = api.api_request("GET", &format!("{}/next_free", hosts_uri), serde_json::Value::Null).await;
+        assert_eq!((status, body["ipv4_address"].clone()), (200, serde_json::json!([192, 0, 2, 8])));
+        let (status, body) = api.api_request("POST", &hosts_uri, serde_json::json!({
+            "assignment_id": 0, "ipv4_address": [192, 0, 2, 8], "hostname": "router.example.net", "mac_address": "0000.5e00.5302", "description": "Uplink",
+        })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["ipv4_host"]["assignment_id"], assignment_ids[1]);
+        assert_eq!(body["ipv4_host"]["mac_address"], "00:00:5e:00:53:02");
+        let host_id = body["ipv4_host"]["id"].as_i64().unwrap();
+        let (status, body) = api.api_request("GET", &format!("{}/next_free", hosts_uri), serde_json::Value::Null).await;
+        assert_eq!((status, body["ipv4_address"].clone()), (200, serde_json::Value::Null));
+        let (status, body) = api.api_request("PUT", &format!("{}/{}", hosts_uri, host_id), serde_json::json!({
+            "assignment_id": assignment_ids[0], "ipv4_address": [192, 0, 2, 10], "hostname": "router.example.net",
+        })).await;
+        assert_eq!((status, body["code"].as_str()), (422, Some("validation")));
+        let (status, body) = api.api_request("PUT", &format!("{}/{}", hosts_uri, host_id), serde_json::json!({
+            "assignment_id": assignment_ids[0], "ipv4_address": [192, 0, 2, 8], "hostname": "gw.example.net", "gateway": true,
+        })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!((body["ipv4_host"]["assignment_id"].clone(), body["ipv4_host"]["mac_address"].clone()), (serde_json::json!(assignment_ids[1]), serde_json::Value::Null));
+        let (status, body) = api.api_request("GET", &hosts_uri, serde_json::Value::Null).await;
+        assert_eq!((status, body["ipv4_hosts"].as_array().unwrap().len()), (200, 2));
+        let other_uri = format!("/api/v1/ipv4/assignment_space/{}/pool/{}/assignment/{}/host/{}", space_id, pool_id, assignment_ids[0], host_id);
+        assert_eq!(api.api_request("GET", &other_uri, serde_json::Value::Null).await.0, 404);
+        assert_eq!(api.api_request("DELETE", &format!("{}/{}", hosts_uri, host_id), serde_json::Value::Null).await.0, 200);
+        assert_eq!(api.api_request("GET", &format!("{}/{}", hosts_uri, host_id), serde_json::Value::Null).await.0, 404);
+
+        // Hosts go with their assignment, pool and space
+        ipv4_store.delete_assignment(assignment_ids[0]).unwrap();
+        assert!(store.ipv4_assignments().get_hosts(assignment_ids[0]).unwrap().is_empty());
+        assert_eq!(ipv4_store.get_hosts(assignment_ids[1]).unwrap().len(), 1);
+        ipv4_store.delete_pool(pool_id).unwrap();
+        assert!(ipv4_store.get_hosts(assignment_ids[1]).unwrap().is_empty());
+
+        let ipv6_store = store.ipv6_assignments();
+        let prefix = "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let pool_id = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let assignment_id = ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+            id: 0, assignment_pool_id: pool_id, name: "Servers".to_string(), description: "".to_string(), assignment_visibility: Public,
+            ipv6_prefix: prefix, ipv6_prefix_len: 64, origin_asn_id: None,
+        }).unwrap();
+        let host6 = |address: &str| ipv6::HostIpv6 {
+            id: 0, assignment_id, ipv6_address: address.parse::<std::net::Ipv6Addr>().unwrap().octets(),
+            hostname: "".to_string(), mac_address: None, description: "".to_string(), gateway: false,
+        };
+        assert_eq!(ipv6_store.create_host(&host6("2001:db8::")).unwrap_err().kind(), types::ErrorKind::Validation);
+        assert_eq!(ipv6_store.create_host(&host6("2001:db8:0:1::1")).unwrap_err().kind(), types::ErrorKind::Validation);
+        ipv6_store.create_host(&host6("2001:db8::2")).unwrap();
+        let next = ipv6_store.next_free_host(assignment_id).unwrap().map(std::net::Ipv6Addr::from);
+        assert_eq!(next, Some("2001:db8::3".parse().unwrap()));
+        ipv6_store.delete_space(space_id).unwrap();
+        assert!(ipv6_store.get_hosts(assignment_id).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dns_forward() {
+        use types::ObjectVisibility::Public;
+        use user::ResourceFamily::{Ipv4, Ipv6};
+        use dns::ReverseDns;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let reverse_dns = [
+            Some(ReverseDns { nameservers: Vec::new(), ptr_template: Some("web1.example.net".to_string()) }),
+            Some(ReverseDns { nameservers: vec!["ns.example.org".to_string()], ptr_template: None }),
+            None,
+        ];
+        for (i, (reverse_dns, hosts)) in reverse_dns.into_iter().zip([vec![(2, "web1.example.net"), (3, "web2"), (4, "")], vec![(17, "mail.example.org")], vec![(33, "db.servers.example.net")]]).enumerate() {
+            let assignment_id = ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+                id: 0, assignment_pool_id: pool_id, name: "Servers".to_string(), description: "".to_string(),
+                assignment_visibility: Public, ipv4_prefix: [192, 0, 2, 16 * i as u8], ipv4_prefix_len: 28, origin_asn_id: None,
+            }).unwrap();
+            if let Some(reverse_dns) = reverse_dns {
+                store.reverse_dns().set_reverse_dns(Ipv4, assignment_id, &reverse_dns).unwrap();
+            }
+            for (address, hostname) in hosts {
+                ipv4_store.create_host(&ipv4::HostIpv4 {
+                    id: 0, assignment_id, ipv4_address: [192, 0, 2, address], hostname: hostname.to_string(), mac_address: None, description: "".to_string(), gateway: false,
+                }).unwrap();
+            }
+        }
+        let ipv6_store = store.ipv6_assignments();
+        let prefix = "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets();
+        let space_id = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let pool_id = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let assignment_id = ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+            id: 0, assignment_pool_id: pool_id, name: "Servers".to_string(), description: "".to_string(), assignment_visibility: Public,
+            ipv6_prefix: prefix, ipv6_prefix_len: 120, origin_asn_id: None,
+        }).unwrap();
+        store.reverse_dns().set_reverse_dns(Ipv6, assignment_id, &ReverseDns { nameservers: Vec::new(), ptr_template: Some("host-{ip}.example.net".to_string()) }).unwrap();
+        for (address, hostname) in [("2001:db8::2", "host-2001-db8-0-0-0-0-0-2.example.net"), ("2001:db8::3", "web1.example.net")] {
+            ipv6_store.create_host(&ipv6::HostIpv6 {
+                id: 0, assignment_id, ipv6_address: address.parse::<std::net::Ipv6Addr>().unwrap().octets(),
+                hostname: hostname.to_string(), mac_address: None, description: "".to_string(), gateway: false,
+            }).unwrap();
+        }
+
+        let mut config = dns::DnsConfig::default();
+        assert_eq!(dns::forward_dns(&store, &config).unwrap_err().kind(), types::ErrorKind::InvalidInput);
+        config.forward_domains = vec!["example.net.".to_string(), "Servers.example.net".to_string(), "example.com".to_string()];
+        config.default_domain = Some("servers.example.net".to_string());
+        let forward = dns::forward_dns(&store, &config).unwrap();
+        assert_eq!(forward.fragments.iter().map(|fragment| fragment.name.as_str()).collect::<Vec<_>>(), ["example.com", "example.net", "servers.example.net"]);
+        assert_eq!(forward.fragments[0].content, "; Forward records for example.com\n$ORIGIN example.com.\n");
+        assert_eq!(forward.fragments[1].content, "; Forward records for example.net
+$ORIGIN example.net.
+host-2001-db8-0-0-0-0-0-2\t3600\tIN\tAAAA\t2001:db8::2
+web1\t3600\tIN\tA\t192.0.2.2
+web1\t3600\tIN\tAAAA\t2001:db8::3
+");
+        assert_eq!(forward.fragments[2].content, "; Forward records for servers.example.net
+$ORIGIN servers.example.net.
+db\t3600\tIN\tA\t192.0.2.33
+web2\t3600\tIN\tA\t192.0.2.3
+");
+        assert_eq!(forward.issues, [
+            "192.0.2.3 (web2.servers.example.net): PTR record points to web1.example.net.",
+            "192.0.2.17 (mail.example.org): not in any forward domain",
+            "192.0.2.33 (db.servers.example.net): no PTR record",
+            "2001:db8::3 (web1.example.net): PTR record points to host-2001-db8-0-0-0-0-0-3.example.net.",
+        ]);
+
+        let api = test_router(server::Server::new(store.clone()).with_dns(config));
+        assert_eq!(api.text_request("GET", "/api/v1/export/dns-forward", false, serde_json::Value::Null).await.0, 401);
+        let (status, body) = api.text_request("GET", "/api/v1/export/dns-forward", true, serde_json::Value::Null).await;
+        assert_eq!(status, 200, "{}", body);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["dns_forward"]["issues"].as_array().unwrap().len(), 4);
+        let (status, body) = api.text_request("GET", "/api/v1/export/dns-forward/Servers.example.net.", true, serde_json::Value::Null).await;
+        assert_eq!((status, body), (200, forward.fragments[2].content.clone()));
+        assert_eq!(api.text_request("GET", "/api/v1/export/dns-forward/example.org", true, serde_json::Value::Null).await.0, 404);
+    }
+
+    #[tokio::test]
+    async fn kea_dhcp() {
+        use types::ObjectVisibility::Public;
+        use user::ResourceFamily::{Asn, Ipv4, Ipv6};
+        use dhcp::DhcpSettings;
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        let ipv4_store = store.ipv4_assignments();
+        let space_id = ipv4_store.create_space(&ipv4::AssignmentSpaceIpv4 {
+            id: 0, name: "space".to_string(), description: "".to_string(), space_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let pool_id = ipv4_store.create_pool(&ipv4::AssignmentPoolIpv4 {
+            id: 0, assignment_space_id: space_id, name: "pool".to_string(), description: "".to_string(), pool_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 24,
+        }).unwrap();
+        let assignment_id = ipv4_store.create_assignment(&ipv4::AssignmentIpv4 {
+            id: 0, assignment_pool_id: pool_id, name: "Office".to_string(), description: "".to_string(),
+            assignment_visibility: Public, ipv4_prefix: [192, 0, 2, 0], ipv4_prefix_len: 26, origin_asn_id: None,
+        }).unwrap();
+        for (address, hostname, mac_address, gateway) in [(1, "gw", None, true), (10, "printer.example.net", Some("00:00:5e:00:53:10"), false), (11, "nas", None, false)] {
+            ipv4_store.create_host(&ipv4::HostIpv4 {
+                id: 0, assignment_id, ipv4_address: [192, 0, 2, address], hostname: hostname.to_string(), mac_address: mac_address.map(str::to_string), description: "".to_string(), gateway,
+            }).unwrap();
+        }
+
+        let settings = DhcpSettings {
+            range_start: Some("192.0.2.32".parse().unwrap()),
+            range_end: Some("192.0.2.62".parse().unwrap()),
+            dns_servers: vec!["192.0.2.53".parse().unwrap(), "198.51.100.53".parse().unwrap()],
+            valid_lifetime: Some(3600),
+            renew_timer: Some(900),
+            ..Default::default()
+        };
+        for (invalid, kind) in [
+            (DhcpSettings { range_end: None, ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { range_end: Some("192.0.2.63".parse().unwrap()), ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { range_end: Some("192.0.2.70".parse().unwrap()), ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { gateway: Some("192.0.2.40".parse().unwrap()), ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { dns_servers: vec!["2001:db8::53".parse().unwrap()], ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { renew_timer: Some(7200), ..settings.clone() }, types::ErrorKind::Validation),
+            (DhcpSettings { preferred_lifetime: Some(1800), ..settings.clone() }, types::ErrorKind::Validation),
+        ] {
+            assert_eq!(store.dhcp().set_dhcp(Ipv4, assignment_id, Some(&invalid)).unwrap_err().kind(), kind, "{:?}", invalid);
+        }
+        assert_eq!(store.dhcp().set_dhcp(Ipv4, assignment_id + 100, Some(&settings)).unwrap_err().kind(), types::ErrorKind::NotFound);
+        assert_eq!(store.dhcp().set_dhcp(Asn, assignment_id, Some(&settings)).unwrap_err().kind(), types::ErrorKind::Validation);
+        store.dhcp().set_dhcp(Ipv4, assignment_id, Some(&settings)).unwrap();
+        assert_eq!(store.dhcp().get_dhcp(Ipv4, assignment_id).unwrap(), Some(settings.clone()));
+
+        let ipv6_store = store.ipv6_assignments();
+        let prefix = "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap().octets();
+        let space_id6 = ipv6_store.create_space(&ipv6::AssignmentSpaceIpv6 {
+            id: 0, name: "space6".to_string(), description: "".to_string(), space_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let pool_id6 = ipv6_store.create_pool(&ipv6::AssignmentPoolIpv6 {
+            id: 0, assignment_space_id: space_id6, name: "pool6".to_string(), description: "".to_string(), pool_visibility: Public, ipv6_prefix: prefix, ipv6_prefix_len: 32,
+        }).unwrap();
+        let assignment_id6 = ipv6_store.create_assignment(&ipv6::AssignmentIpv6 {
+            id: 0, assignment_pool_id: pool_id6, name: "Office".to_string(), description: "".to_string(), assignment_visibility: Public,
+            ipv6_prefix: prefix, ipv6_prefix_len: 64, origin_asn_id: None,
+        }).unwrap();
+        ipv6_store.create_host(&ipv6::HostIpv6 {
+            id: 0, assignment_id: assignment_id6, ipv6_address: "2001:db8::10".parse::<std::net::Ipv6Addr>().unwrap().octets(),
+            hostname: "printer.example.net".to_string(), mac_address: Some("00:00:5e:00:53:10".to_string()), description: "".to_string(), gateway: false,
+        }).unwrap();
+        let settings6 = DhcpSettings { preferred_lifetime: Some(1800), valid_lifetime: Some(3600), ..Default::default() };
+        assert_eq!(store.dhcp().set_dhcp(Ipv6, assignment_id6, Some(&DhcpSettings { gateway: Some("2001:db8::1".parse().unwrap()), ..settings6.clone() })).unwrap_err().kind(), types::ErrorKind::Validation);
+        store.dhcp().set_dhcp(Ipv6, assignment_id6, Some(&settings6)).unwrap();
+
+        let config = serde_json::to_value(dhcp::kea_config(&store).unwrap()).unwrap();
+        assert_eq!(config, serde_json::json!({
+            "subnet4": [{
+                "id": assignment_id,
+                "subnet": "192.0.2.0/26",
+                "pools": [{ "pool": "192.0.2.32 - 192.0.2.62" }],
+                "option-data": [
+                    { "name": "routers", "data": "192.0.2.1" },
+                    { "name": "domain-name-servers", "data": "192.0.2.53, 198.51.100.53" },
+                ],
+                "valid-lifetime": 3600,
+                "renew-timer": 900,
+                "reservations": [{ "hw-address": "00:00:5e:00:53:10", "ip-address": "192.0.2.10", "hostname": "printer.example.net" }],
+                "comment": "Office",
+            }],
+            "subnet6": [{
+                "id": assignment_id6,
+                "subnet": "2001:db8::/64",
+                "valid-lifetime": 3600,
+                "preferred-lifetime": 1800,
+                "reservations": [{ "hw-address": "00:00:5e:00:53:10", "ip-addresses": ["2001:db8::10"], "hostname": "printer.example.net" }],
+                "comment": "Office",
+            }],
+        }));
+
+        let api = test_router(server::Server::new(store.clone()));
+        let dhcp_uri = format!("/api/v1/ipv6/assignment_space/{}/pool/{}/assignment/{}/dhcp", space_id6, pool_id6, assignment_id6);
+        assert_eq!(api.anonymous_request("GET", &dhcp_uri, serde_json::Value::Null).await.0, 401);
+        let (status, body) = api.api_request("GET", &dhcp_uri, serde_json::Value::Null).await;
+        assert_eq!((status, body["dhcp"]["preferred_lifetime"].clone()), (200, serde_json::json!(1800)));
+        let (status, body) = api.api_request("PUT", &dhcp_uri, serde_json::json!({ "range_start": "2001:db8::1:0", "range_end": "2001:db8::1:ffff" })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["dhcp"]["range_end"], "2001:db8::1:ffff");
+        assert_eq!(body["dhcp"]["valid_lifetime"], serde_json::Value::Null);
+        let (status, body) = api.api_request("GET", "/api/v1/export/kea", serde_json::Value::Null).await;
+        assert_eq!((status, body["subnet6"][0]["pools"].clone()), (200, serde_json::json!([{ "pool": "2001:db8::1:0 - 2001:db8::1:ffff" }])));
+        assert_eq!(api.api_request("DELETE", &dhcp_uri, serde_json::Value::Null).await.0, 200);
+        let (status, body) = api.api_request("GET", &dhcp_uri, serde_json::Value::Null).await;
+        assert_eq!((status, body["dhcp"].clone()), (200, serde_json::Value::Null));
+
+        // DHCP goes with its assignment, pool and space
+        ipv4_store.delete_assignment(assignment_id).unwrap();
+        assert!(store.dhcp().get_all_dhcp(Ipv4).unwrap().is_empty());
+        store.dhcp().set_dhcp(Ipv6, assignment_id6, Some(&settings6)).unwrap();
+        ipv6_store.delete_pool(pool_id6).unwrap();
+        assert!(store.dhcp().get_all_dhcp(Ipv6).unwrap().is_empty());
+        let (status, body) = api.api_request("GET", "/api/v1/export/kea", serde_json::Value::Null).await;
+        assert_eq!((status, body["subnet6"].clone()), (200, serde_json::json!([])));
+    }
+
+    #[tokio::test]
+    async fn external_authentication() {
+        use axum::extract::ConnectInfo;
+        use server::auth::{AddressPrefix, AttributeValue, ClientCertAuth, ClientCertAuthConfig, ClientCertificate, DistinguishedName, ProxyAuthConfig};
+        use std::net::SocketAddr;
+        use tower::ServiceExt;
+
+        let prefix: AddressPrefix = "192.0.2.0/24".parse().unwrap();
+        assert!(prefix.contains("192.0.2.10".parse().unwrap()));
+        assert!(prefix.contains("::ffff:192.0.2.10".parse().unwrap()));
+        assert!(!prefix.contains("192.0.3.10".parse().unwrap()));
+        assert!("2001:db8::/129".parse::<AddressPrefix>().is_err());
+
+        // Subjects are compared as RFC 4514 DNs
+        let config = ClientCertAuth::new(ClientCertAuthConfig {
+            users: [(r"CN=Smith\, John+UID=js, O=Example".to_string(), "john".to_string())].into(),
+            common_name_as_username: false,
+        }).unwrap();
+        let username = |subject: &str| config.username(&ClientCertificate { subject: subject.parse().unwrap(), common_name: None });
+        assert_eq!(username(r"uid=JS+2.5.4.3=smith\2c John,o=example").as_deref(), Some("john"));
+        assert_eq!(username(r"CN=Smith\, John \ +UID=js,O=Example"), None);
+        assert!("CN=Smith, John+UID=js,O=Example".parse::<DistinguishedName>().is_err());
+        assert!(r"CN=Smith\, John+UID=js,O=Example\".parse::<DistinguishedName>().is_err());
+        assert!("NOSUCHTYPE=x".parse::<DistinguishedName>().is_err());
+        assert!(ClientCertAuth::new(ClientCertAuthConfig { users: [("NOSUCHTYPE=x".to_string(), "john".to_string())].into(), common_name_as_username: false }).is_err());
+
+        // X.509 names list RDNs in the reverse order, and values are escaped when printed
+        let string = |value: &str| AttributeValue::String(value.to_string());
+        let subject = DistinguishedName::from_asn1_rdns(vec![
+            vec![("2.5.4.6".to_string(), string("JP"))],
+            vec![("2.5.4.3".to_string(), string(" alice,O=Example#"))],
+        ]);
+        assert_eq!(subject.to_string(), r"CN=\ alice\,O=Example#,C=JP");
+        assert_eq!(subject.to_string().parse::<DistinguishedName>().unwrap(), subject);
+        assert_ne!("CN=alice,O=Example,C=JP".parse::<DistinguishedName>().unwrap(), subject);
+        let encoded = DistinguishedName::from_asn1_rdns(vec![vec![("1.2.3.4".to_string(), AttributeValue::Encoded(vec![0x04, 0x01, 0xab]))]]);
+        assert_eq!(encoded.to_string(), "1.2.3.4=#0401ab");
+        assert_eq!("OID.1.2.3.4=#0401AB".parse::<DistinguishedName>().unwrap(), encoded);
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        store.users().set_password("admin", "password").unwrap();
+        store.users().set_password("alice", "password").unwrap();
+        let router = server::Server::new(store.clone())
+            .with_proxy_auth(ProxyAuthConfig {
+                header: "X-Remote-User".to_string(),
+                trusted_proxies: vec!["127.0.0.1".parse().unwrap()],
+                trust_unix_socket: false,
+            })
+            .with_client_cert_auth(ClientCertAuthConfig {
+                users: [("CN=deploy-script, O=Example".to_string(), "alice".to_string())].into(),
+                common_name_as_username: false,
+            })
+            .unwrap()
+            .build_router();
+        let request = |source: &str, user: Option<&str>, cert: Option<&str>| {
+            let mut request = http::Request::get("/api/v1/users");
+            if let Some(user) = user {
+                request = request.header("X-Remote-User", user);
+            }
+            let mut request = request.body(axum::body::Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(source.parse::<SocketAddr>().unwrap()));
+            if let Some(subject) = cert {
+                request.extensions_mut().insert(ClientCertificate { subject: subject.parse().unwrap(), common_name: None });
+            }
+            request
+        };
+
+        let status = |request: http::Request<axum::body::Body>| {
+            let router = router.clone();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(status(request("127.0.0.1:1234", Some("admin"), None)).await, 200);
+        assert_eq!(status(request("127.0.0.1:1234", Some("alice"), None)).await, 403);
+        assert_eq!(status(request("127.0.0.1:1234", Some("mallory"), None)).await, 401);
+        assert_eq!(status(request("198.51.100.1:1234", Some("admin"), None)).await, 401);
+        assert_eq!(status(request("198.51.100.1:1234", None, Some("cn=deploy-script,o=Example"))).await, 403);
+        assert_eq!(status(request("198.51.100.1:1234", None, Some("CN=unknown"))).await, 401);
+
+        let res = router.clone().oneshot({
+            let mut request = request("198.51.100.1:1234", None, Some("CN=deploy-script,O=Example"));
+            *request.uri_mut() = "/api/v1/user/self".parse().unwrap();
+            request
+        }).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["user"]["username"], "alice");
+
+        // Requests through trusted proxies come from the nearest untrusted address they forward
+        let proxy = ProxyAuthConfig {
+            header: "X-Remote-User".to_string(),
+            trusted_proxies: vec!["127.0.0.1".parse().unwrap(), "2001:db8::/32".parse().unwrap()],
+            trust_unix_socket: true,
+        };
+        let client = |peer: Option<&str>, header: &'static str, value: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert(header, value.parse().unwrap());
+            proxy.client_address(peer.map(|peer| peer.parse().unwrap()), peer.is_none(), &headers).map(|addr| addr.to_string())
+        };
+        assert_eq!(client(Some("198.51.100.1"), "x-forwarded-for", "203.0.113.1").as_deref(), Some("198.51.100.1"));
+        assert_eq!(client(Some("127.0.0.1"), "x-forwarded-for", "203.0.113.1, 2001:db8::1").as_deref(), Some("203.0.113.1"));
+        assert_eq!(client(Some("127.0.0.1"), "x-forwarded-for", "203.0.113.1, 198.51.100.7").as_deref(), Some("198.51.100.7"));
+        assert_eq!(client(None, "forwarded", "for=192.0.2.60;proto=http, for=\"[2001:db8::1]:4711\"").as_deref(), Some("192.0.2.60"));
+        assert_eq!(client(Some("127.0.0.1"), "x-forwarded-for", "unknown"), None);
+        assert_eq!(client(Some("127.0.0.1"), "x-remote-user", "admin"), None);
+
+        // ...which is what logins are throttled by
+        let mut login = http::Request::post("/api/v1/login")
+            .header("Content-Type", "application/json")
+            .header("X-Forwarded-For", "203.0.113.9")
+            .body(axum::body::Body::from(serde_json::json!({ "username": "alice", "password": "wrong" }).to_string()))
+            .unwrap();
+        login.extensions_mut().insert(ConnectInfo("127.0.0.1:1234".parse::<SocketAddr>().unwrap()));
+        assert_eq!(router.clone().oneshot(login).await.unwrap().status(), 401);
+        assert_eq!(store.users().list_login_attempts(None, Some("203.0.113.9"), 10).unwrap().len(), 1);
+        assert!(store.users().list_login_attempts(None, Some("127.0.0.1"), 10).unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tls_listener() {
+        use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+        use server::auth::ClientCertAuthConfig;
+        use server::tls::{TlsConfig, TlsState};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tower::ServiceExt;
+
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "Test CA");
+        let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+        let issue = |names: Vec<String>, subject: &[(DnType, &str)], usage: ExtendedKeyUsagePurpose| {
+            let mut params = CertificateParams::new(names).unwrap();
+            params.distinguished_name = rcgen::DistinguishedName::new();
+            for (dn_type, value) in subject {
+                params.distinguished_name.push(dn_type.clone(), *value);
+            }
+            params.extended_key_usages = vec![usage];
+            let key = KeyPair::generate().unwrap();
+            (params.signed_by(&key, &ca).unwrap(), key)
+        };
+        let (server_cert, server_key) = issue(vec!["localhost".to_string()], &[(DnType::CommonName, "localhost")], ExtendedKeyUsagePurpose::ServerAuth);
+        let client = issue(vec![], &[(DnType::CommonName, "deploy-script")], ExtendedKeyUsagePurpose::ClientAuth);
+        // Issued in ASN.1 order: C, O, CN
+        let multi_rdn_client = issue(vec![], &[
+            (DnType::CountryName, "JP"),
+            (DnType::OrganizationName, "Example"),
+            (DnType::CommonName, "ops"),
+        ], ExtendedKeyUsagePurpose::ClientAuth);
+        let spoofing_client = issue(vec![], &[(DnType::CommonName, "ops,O=Example,C=JP")], ExtendedKeyUsagePurpose::ClientAuth);
+
+        let dir = std::env::temp_dir().join(format!("mirams-tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cert.pem"), server_cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), server_key.serialize_pem()).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        let config = TlsConfig {
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+            client_ca_path: Some(dir.join("ca.pem")),
+            redirect_http_addr: None,
+        };
+        let state = TlsState::new(config.clone()).unwrap();
+        assert!(TlsState::new(TlsConfig { key_path: dir.join("missing.pem"), ..config.clone() }).is_err());
+        state.reload().unwrap();
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        store.users().set_password("alice", "password").unwrap();
+        store.users().set_password("bob", "password").unwrap();
+        let server = server::Server::new(store)
+            .with_client_cert_auth(ClientCertAuthConfig {
+                users: [
+                    ("CN=deploy-script".to_string(), "alice".to_string()),
+                    ("CN=ops,O=Example,C=JP".to_string(), "bob".to_string()),
+                ].into(),
+                common_name_as_username: false,
+            })
+            .unwrap()
+            .with_tls(config)
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            server.serve(vec![server::listener::Listener::Tcp(listener)], std::future::pending()).await
+        });
+
+        let get_self = |client: Option<&(rcgen::Certificate, KeyPair)>| {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(ca.der().clone()).unwrap();
+            let builder = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions().unwrap()
+                .with_root_certificates(roots);
+            let config = if let Some((client_cert, client_key)) = client {
+                let key = rustls::pki_types::PrivateKeyDer::try_from(client_key.serialize_der()).unwrap();
+                builder.with_client_auth_cert(vec![client_cert.der().clone()], key).unwrap()
+            } else {
+                builder.with_no_client_auth()
+            };
+            async move {
+                let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+                let mut stream = connector.connect("localhost".try_into().unwrap(), stream).await.unwrap();
+                stream.write_all(b"GET /api/v1/user/self HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                response
+            }
+        };
+        let response = get_self(Some(&client)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("\"username\":\"alice\""));
+        let response = get_self(Some(&multi_rdn_client)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("\"username\":\"bob\""));
+        // A common name containing `,` and `=` is not read as further RDNs
+        assert!(get_self(Some(&spoofing_client)).await.starts_with("HTTP/1.1 401"));
+        assert!(get_self(None).await.starts_with("HTTP/1.1 401"));
+
+        let res = server::tls::redirect_router(8443).oneshot(
+            http::Request::get("/ipv4/?page=2").header("Host", "mirams.example:8080").body(axum::body::Body::empty()).unwrap()
+        ).await.unwrap();
+        assert_eq!(res.status(), 308);
+        assert_eq!(res.headers()[http::header::LOCATION], "https://mirams.example:8443/ipv4/?page=2");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn listeners() {
+        use server::auth::ProxyAuthConfig;
+        use server::listener::{ListenAddr, Listener};
+        use std::os::unix::fs::PermissionsExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        assert_eq!("unix:/run/mirams.sock".parse::<ListenAddr>().unwrap(), ListenAddr::Unix("/run/mirams.sock".into()));
+        assert_eq!("[::1]:3001".parse::<ListenAddr>().unwrap().to_string(), "[::1]:3001");
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("localhost".parse::<ListenAddr>().is_err());
+
+        let dir = std::env::temp_dir().join(format!("mirams-listener-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("mirams.sock");
+        // A stale socket is replaced
+        drop(std::os::unix::net::UnixListener::bind(&socket_path).unwrap());
+
+        let mut listeners = Listener::bind(&"127.0.0.1:0".parse().unwrap(), None).unwrap();
+        let tcp_addr = match &listeners[0] {
+            Listener::Tcp(listener) => listener.local_addr().unwrap(),
+            _ => unreachable!(),
+        };
+        // IPv6 listeners do not take the IPv4 port, so both families can be listed
+        if let Ok(v6) = Listener::bind(&ListenAddr::Tcp(format!("[::1]:{}", tcp_addr.port())), None) {
+            listeners.extend(v6);
+        }
+        listeners.extend(Listener::bind(&ListenAddr::Unix(socket_path.clone()), Some(0o600)).unwrap());
+        assert_eq!(std::fs::metadata(&socket_path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(Listener::from_systemd().unwrap().is_empty());
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let store = Store::new(db);
+        store.users().set_password("admin", "password").unwrap();
+        let server = server::Server::new(store)
+            .with_proxy_auth(ProxyAuthConfig {
+                header: "X-Remote-User".to_string(),
+                trusted_proxies: vec![],
+                trust_unix_socket: true,
+            })
+            .with_shutdown_timeout(std::time::Duration::from_secs(10));
+        let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(async move {
+            server.serve(listeners, async { shutdown_receiver.await.unwrap() }).await
+        });
+
+        let request = b"GET /api/v1/users HTTP/1.1\r\nHost: localhost\r\nX-Remote-User: admin\r\nConnection: close\r\n\r\n";
+        let mut stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        // The header is not trusted over TCP
+        let mut stream = tokio::net::TcpStream::connect(tcp_addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+
+        // An idle keep-alive connection does not hold up the shutdown
+        let mut idle = tokio::net::TcpStream::connect(tcp_addr).await.unwrap();
+        idle.write_all(b"GET /api/v1/version HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 1024];
+        assert!(idle.read(&mut buf).await.unwrap() > 0);
+
+        let started = std::time::Instant::now();
+        shutdown_sender.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(tokio::net::TcpStream::connect(tcp_addr).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn config_file() {
+        use tower::ServiceExt;
+
+        let config: config::Config = r#"
+            [database]
+            path = "/var/lib/mirams/mirams.db"
+
+            [server]
+            listen = ["127.0.0.1:3001", "[::1]:3001", "unix:/run/mirams/mirams.sock"]
+            unix_socket_mode = 0o660
+            request_timeout = 10
+            cors_origins = ["https://noc.example.com"]
+
+            [auth.ldap]
+            url = "ldap://ldap.example.com"
+            mode = "search"
+            base_dn = "ou=people,dc=example,dc=com"
+
+            [auth.proxy]
+            trusted_proxies = ["127.0.0.1", "2001:db8::/32"]
+
+            [logging]
+            level = "debug"
+            syslog = false
+        "#.parse().unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.server.request_timeout, 10);
+        assert_eq!(config.server.listen[2], server::listener::ListenAddr::Unix("/run/mirams/mirams.sock".into()));
+        assert_eq!(config.server.unix_socket_mode, Some(0o660));
+        assert_eq!(config.auth.proxy.as_ref().unwrap().header, "X-Remote-User");
+        assert_eq!(config.logging.level().unwrap(), log::Level::Debug);
+        assert!(config.logging.stderr);
+
+        let defaults: config::Config = "".parse().unwrap();
+        defaults.validate().unwrap();
+        assert_eq!(defaults.server.request_timeout, 30);
+        assert_eq!(defaults.server.cors_origins, vec![config::ANY_ORIGIN.to_string()]);
+        assert!(defaults.server.listen.is_empty());
+        let single: config::Config = "[server]\nlisten = \"127.0.0.1:3001\"".parse().unwrap();
+        assert_eq!(single.server.listen, vec![server::listener::ListenAddr::Tcp("127.0.0.1:3001".to_string())]);
+
+        assert!("[server]\nlisten_addr = \"127.0.0.1:3001\"".parse::<config::Config>().is_err());
+        assert!("[logging]\nlevel = \"loud\"".parse::<config::Config>().unwrap().validate().is_err());
+        assert!("[server]\nlisten = \"127.0.0.1\"".parse::<config::Config>().is_err());
+        assert!("[server]\nunix_socket_mode = 0o1777".parse::<config::Config>().unwrap().validate().is_err());
+        assert!("[server]\nrequest_timeout = 0".parse::<config::Config>().unwrap().validate().is_err());
+        let whois = "[whois]\nlisten = \"[::1]:43\"".parse::<config::Config>().unwrap().whois.unwrap();
+        assert_eq!((whois.max_query_length, whois.timeout, whois.source.as_str()), (256, 10, "MIRAMS"));
+        assert!("[whois]\nlisten = []".parse::<config::Config>().unwrap().validate().is_err());
+        let rtr = "[rtr]\nlisten = \"[::1]:323\"".parse::<config::Config>().unwrap().rtr.unwrap();
+        assert_eq!((rtr.refresh_interval, rtr.retry_interval, rtr.expire_interval, rtr.poll_interval), (3600, 600, 7200, 5));
+        assert!("[rtr]\nlisten = \"[::1]:323\"\nexpire_interval = 600".parse::<config::Config>().unwrap().validate().is_err());
+        let rpsl = "[rpsl]\nmaintainer = \"MAINT-EXAMPLE\"".parse::<config::Config>().unwrap().rpsl;
+        assert_eq!((rpsl.source.as_str(), rpsl.maintainer.as_deref()), ("MIRAMS", Some("MAINT-EXAMPLE")));
+        assert!("[rpsl]\nsource = \"\"".parse::<config::Config>().unwrap().validate().is_err());
+        let dns = "[dns]\nnameservers = [\"ns1.example.net.\"]".parse::<config::Config>().unwrap().dns;
+        assert_eq!((dns.hostmaster.as_str(), dns.ttl), ("hostmaster.localhost.", 3600));
+        assert!("[dns]\nnameservers = [\"ns 1\"]".parse::<config::Config>().unwrap().validate().is_err());
+        assert!("[dns]\nforward_domains = [\"example..net\"]".parse::<config::Config>().unwrap().validate().is_err());
+        let dns = "[dns.keys.mirams]\nsecret = \"c2VjcmV0\"\n[dns.update]\nretries = 2".parse::<config::Config>().unwrap().dns;
+        assert_eq!((dns.keys["mirams"].algorithm.as_str(), dns.update.retries, dns.update.retry_interval), ("hmac-sha256", 2, 10));
+        assert!("[dns.keys.mirams]\nsecret = \"not base64!\"".parse::<config::Config>().unwrap().validate().is_err());
+        assert!("[auth.client_cert.users]\n\"CN=alice,\" = \"alice\"".parse::<config::Config>().unwrap().validate().is_err());
+        assert!("[auth.client_cert.users]\n\"CN=alice, O=Example\" = \"alice\"".parse::<config::Config>().unwrap().validate().is_ok());
+        assert!("[tls]\ncert_path = \"/nonexistent/cert.pem\"\nkey_path = \"/nonexistent/key.pem\"".parse::<config::Config>().unwrap().validate().is_err());
+
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        let router = server::Server::new(Store::new(db))
+            .with_cors_origins(config.server.cors_origins.clone())
+            .build_router();
+        let preflight = |origin: &str| http::Request::builder()
+            .method("OPTIONS")
+            .uri("/api/v1/version")
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "GET")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(preflight("https://noc.example.com")).await.unwrap();
+        assert_eq!(res.headers()["access-control-allow-origin"], "https://noc.example.com");
+        let res = router.clone().oneshot(preflight("https://evil.example")).await.unwrap();
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+    }
+
+    #[test]
+    fn ldap_config() {
+        let config: ldap::LdapConfig = serde_json::from_value(serde_json::json!({
+            "url": "ldap://127.0.0.1:1389",
+            "mode": "direct",
+            "user_dn_template": "uid={username},ou=people,dc=example,dc=org",
+            "admin_groups": ["noc"],
+            "user_groups": ["cn=staff,ou=groups,dc=example,dc=org"],
+        })).unwrap();
+        assert_eq!(config.user_dn("alice"), Some("uid=alice,ou=people,dc=example,dc=org".to_string()));
+        assert_eq!(config.user_dn("a,b=c"), Some("uid=a\\2cb\\3dc,ou=people,dc=example,dc=org".to_string()));
+        assert_eq!(config.user_filter("alice"), None);
+        assert_eq!(config.group_filter("uid=x*,dc=org", "x*"), "(|(member=uid=x\\2a,dc=org)(uniqueMember=uid=x\\2a,dc=org)(memberUid=x\\2a))");
+
+        assert_eq!(config.map_groups(&["NOC".to_string()]), Some(Some(user::UserRole::Admin)));
+        assert_eq!(config.map_groups(&["CN=Staff,ou=groups,dc=example,dc=org".to_string()]), Some(Some(user::UserRole::User)));
+        assert_eq!(config.map_groups(&["other".to_string()]), None);
+
+        let config: ldap::LdapConfig = serde_json::from_value(serde_json::json!({
+            "url": "ldap://127.0.0.1:1389",
+            "mode": "search",
+            "base_dn": "dc=example,dc=org",
+        })).unwrap();
+        assert_eq!(config.user_filter("al)ice"), Some("(uid=al\\29ice)".to_string()));
+        assert_eq!(config.map_groups(&[]), Some(None));
+
+        // Empty passwords are refused without contacting the server.
+        assert_eq!(ldap::LdapAuthenticator::new(config).authenticate("alice", "").unwrap(), None);
+    }
+
+    /// Runs against a local OpenLDAP with the defaults of the bitnami/openldap image:
+    ///
+    /// ```sh
+    /// docker run --rm -p 1389:1389 bitnami/openldap
+    /// MIRAMS_TEST_LDAP_URL=ldap://127.0.0.1:1389 cargo test ldap_login -- --ignored
+    /// ```
+    #[test]
+    #[ignore]
+    fn ldap_login() {
+        let url = std::env::var("MIRAMS_TEST_LDAP_URL").unwrap_or("ldap://127.0.0.1:1389".to_string());
+        let db = db_sqlite::SqliteConnection::open_memory().unwrap();
+        Store::new(db.clone()).users().set_password("admin", "local-password").unwrap();
+
+        for mode in [
+            serde_json::json!({"mode": "direct", "user_dn_template": "cn={username},ou=users,dc=example,dc=org"}),
+            serde_json::json!({"mode": "search", "base_dn": "ou=users,dc=example,dc=org", "bind_dn": "cn=admin,dc=example,dc=org", "bind_password": "adminpassword"}),
+        ] {
+            let mut config = serde_json::json!({
+                "url": url,
+                "group_base_dn": "dc=example,dc=org",
+                "admin_groups": ["readers"],
+                "create_users": true,
+                "local_users": ["admin"],
+            });
+            config.as_object_mut().unwrap().extend(mode.as_object().unwrap().clone());
+            let config: ldap::LdapConfig = serde_json::from_value(config).unwrap();
+            let store = Store::new(ldap::LdapConnection::new(db.clone(), config));
+            let users = store.users();
+
+            assert!(!users.check_password("user01", "wrong").unwrap());
+            assert!(!users.check_password("nobody", "bitnami1").unwrap());
+            assert!(users.check_password("user01", "bitnami1").unwrap());
+            assert_eq!(users.get_user_role("user01").unwrap(), user::UserRole::Admin);
+            assert!(users.change_password("user01", "new-password", None).is_err());
+
+            // Local users and everything but passwords stay in SQLite
+            assert!(users.check_password("admin", "local-password").unwrap());
+            assert!(users.generate_api_key("user01").is_ok());
+        }
+    }
+
     #[test]
     fn ipv4_masks() {
         use std::net::Ipv4Addr;
@@ -256,6 +2816,7 @@ mod tests {
             assignment_visibility: types::ObjectVisibility::Public,
             ipv4_prefix: Ipv4Addr::new(192, 168, 0, 1).octets(),
             ipv4_prefix_len: 32,
+            origin_asn_id: None,
         };
         let assignment_id = ipv4_store.create_assignment(&assignment).unwrap();
         assert!(ipv4_store.get_space(space_id).is_ok());
@@ -370,6 +2931,7 @@ mod tests {
             assignment_visibility: types::ObjectVisibility::Public,
             ipv6_prefix: "2001:db8:0:1::".parse::<Ipv6Addr>().unwrap().octets(),
             ipv6_prefix_len: 64,
+            origin_asn_id: None,
         };
         let assignment_id = ipv6_store.create_assignment(&assignment).unwrap();
         assert!(ipv6_store.get_space(space_id).is_ok());
@@ -0,0 +1,275 @@
+//! Finding resources in the assignment stores, for the public query services
+//! (RDAP and the like). Objects are returned together with their ancestors, and
+//! with `public_only` only objects that are public along with all their ancestors
+//! (see `ObjectVisibility::effective`) are considered.
+
+use crate::types::{Error, HasVisibility, ObjectVisibility};
+use crate::ipv4::{AssignmentSpaceIpv4, AssignmentPoolIpv4, AssignmentIpv4, Ipv4AssignmentStore, RawIpv4Addr};
+use crate::ipv6::{AssignmentSpaceIpv6, AssignmentPoolIpv6, AssignmentIpv6, Ipv6AssignmentStore, RawIpv6Addr};
+use crate::asn::{AssignmentSpaceAsn, AssignmentPoolAsn, AssignmentAsn, AsnAssignmentStore};
+
+
+/// An object along with its ancestors
+#[derive(Debug, Clone)]
+pub enum Found<S, P, A> {
+    Space(S),
+    Pool(S, P),
+    Assignment(S, P, A),
+}
+
+pub type FoundIpv4 = Found<AssignmentSpaceIpv4, AssignmentPoolIpv4, AssignmentIpv4>;
+pub type FoundIpv6 = Found<AssignmentSpaceIpv6, AssignmentPoolIpv6, AssignmentIpv6>;
+pub type FoundAsn = Found<AssignmentSpaceAsn, AssignmentPoolAsn, AssignmentAsn>;
+
+impl<S, P, A> Found<S, P, A>
+where
+    S: HasVisibility,
+    P: HasVisibility,
+    A: HasVisibility,
+{
+    /// The object followed by its ancestors
+    pub fn ancestry(&self) -> Vec<&dyn HasVisibility> {
+        match self {
+            Found::Space(space) => vec![space],
+            Found::Pool(space, pool) => vec![pool, space],
+            Found::Assignment(space, pool, assignment) => vec![assignment, pool, space],
+        }
+    }
+
+    pub fn visibility(&self) -> ObjectVisibility {
+        ObjectVisibility::effective(&self.ancestry())
+    }
+
+    /// 0 for spaces, 1 for pools and 2 for assignments
+    pub fn depth(&self) -> usize {
+        match self {
+            Found::Space(_) => 0,
+            Found::Pool(..) => 1,
+            Found::Assignment(..) => 2,
+        }
+    }
+}
+
+/// Fields shared by spaces, pools and assignments
+pub trait Object: HasVisibility {
+    fn id(&self) -> i32;
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+}
+
+macro_rules! impl_object {
+    ($($ty:ty),*) => {
+        $(
+            impl Object for $ty {
+                fn id(&self) -> i32 {
+                    self.id
+                }
+
+                fn name(&self) -> &str {
+                    &self.name
+                }
+
+                fn description(&self) -> &str {
+                    &self.description
+                }
+            }
+        )*
+    };
+}
+
+impl_object!(
+    AssignmentSpaceIpv4, AssignmentPoolIpv4, AssignmentIpv4,
+    AssignmentSpaceIpv6, AssignmentPoolIpv6, AssignmentIpv6,
+    AssignmentSpaceAsn, AssignmentPoolAsn, AssignmentAsn
+);
+
+impl<S, P, A> Found<S, P, A>
+where
+    S: Object,
+    P: Object,
+    A: Object,
+{
+    /// The object itself, without its ancestors
+    pub fn object(&self) -> &dyn Object {
+        match self {
+            Found::Space(space) => space,
+            Found::Pool(_, pool) => pool,
+            Found::Assignment(_, _, assignment) => assignment,
+        }
+    }
+
+    /// ID of the parent pool or space
+    pub fn parent_id(&self) -> Option<i32> {
+        match self {
+            Found::Space(_) => None,
+            Found::Pool(space, _) => Some(space.id()),
+            Found::Assignment(_, pool, _) => Some(pool.id()),
+        }
+    }
+}
+
+fn keep(ancestry: &[&dyn HasVisibility], public_only: bool) -> bool {
+    !public_only || ObjectVisibility::effective(ancestry) == ObjectVisibility::Public
+}
+
+/// Spaces, pools and assignments of one resource family, as read from its store
+trait ObjectStore {
+    type Space: Object + Clone;
+    type Pool: Object + Clone;
+    type Assignment: Object;
+
+    fn spaces(&self) -> Result<Vec<Self::Space>, Error>;
+    fn pools(&self, space_id: i32) -> Result<Vec<Self::Pool>, Error>;
+    fn assignments(&self, pool_id: i32) -> Result<Vec<Self::Assignment>, Error>;
+}
+
+macro_rules! impl_object_store {
+    ($($store:path => ($space:ty, $pool:ty, $assignment:ty)),*) => {
+        $(
+            impl ObjectStore for dyn $store + '_ {
+                type Space = $space;
+                type Pool = $pool;
+                type Assignment = $assignment;
+
+                fn spaces(&self) -> Result<Vec<Self::Space>, Error> {
+                    self.get_spaces()
+                }
+
+                fn pools(&self, space_id: i32) -> Result<Vec<Self::Pool>, Error> {
+                    self.get_pools(space_id)
+                }
+
+                fn assignments(&self, pool_id: i32) -> Result<Vec<Self::Assignment>, Error> {
+                    self.get_assignments(pool_id)
+                }
+            }
+        )*
+    };
+}
+
+impl_object_store!(
+    Ipv4AssignmentStore => (AssignmentSpaceIpv4, AssignmentPoolIpv4, AssignmentIpv4),
+    Ipv6AssignmentStore => (AssignmentSpaceIpv6, AssignmentPoolIpv6, AssignmentIpv6),
+    AsnAssignmentStore => (AssignmentSpaceAsn, AssignmentPoolAsn, AssignmentAsn)
+);
+
+/// An object of the family of `T`
+type FoundIn<T> = Found<<T as ObjectStore>::Space, <T as ObjectStore>::Pool, <T as ObjectStore>::Assignment>;
+
+/// `spaces` along with the pools of each that `pools` returns and the assignments of each pool
+/// that `assignments` returns, each space followed by its pools and each pool by its assignments
+fn walk<S, P, A>(spaces: Vec<S>, pools: impl Fn(i32) -> Result<Vec<P>, Error>, assignments: impl Fn(i32) -> Result<Vec<A>, Error>, public_only: bool) -> Result<Vec<Found<S, P, A>>, Error>
+where
+    S: Object + Clone,
+    P: Object + Clone,
+    A: Object,
+{
+    let mut objects = Vec::new();
+    for space in spaces {
+        if !keep(&[&space], public_only) {
+            continue;
+        }
+        objects.push(Found::Space(space.clone()));
+        for pool in pools(space.id())? {
+            if !keep(&[&pool, &space], public_only) {
+                continue;
+            }
+            objects.push(Found::Pool(space.clone(), pool.clone()));
+            for assignment in assignments(pool.id())? {
+                if keep(&[&assignment, &pool, &space], public_only) {
+                    objects.push(Found::Assignment(space.clone(), pool.clone(), assignment));
+                }
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// All objects of `store`, each space followed by its pools and each pool by its assignments
+fn objects<T>(store: &T, public_only: bool) -> Result<Vec<FoundIn<T>>, Error>
+where
+    T: ObjectStore + ?Sized,
+{
+    walk(store.spaces()?, |space_id| store.pools(space_id), |pool_id| store.assignments(pool_id), public_only)
+}
+
+/// All IPv4 objects, each space followed by its pools and each pool by its assignments
+pub fn ipv4_objects(store: &dyn Ipv4AssignmentStore, public_only: bool) -> Result<Vec<FoundIpv4>, Error> {
+    objects(store, public_only)
+}
+
+/// All IPv6 objects, each space followed by its pools and each pool by its assignments
+pub fn ipv6_objects(store: &dyn Ipv6AssignmentStore, public_only: bool) -> Result<Vec<FoundIpv6>, Error> {
+    objects(store, public_only)
+}
+
+/// All ASN objects, each space followed by its pools and each pool by its assignments
+pub fn asn_objects(store: &dyn AsnAssignmentStore, public_only: bool) -> Result<Vec<FoundAsn>, Error> {
+    objects(store, public_only)
+}
+
+impl FoundIpv4 {
+    /// Prefix and prefix length of the object
+    pub fn prefix(&self) -> (RawIpv4Addr, u8) {
+        match self {
+            Found::Space(space) => (space.ipv4_prefix, space.ipv4_prefix_len as u8),
+            Found::Pool(_, pool) => (pool.ipv4_prefix, pool.ipv4_prefix_len as u8),
+            Found::Assignment(_, _, assignment) => (assignment.ipv4_prefix, assignment.ipv4_prefix_len as u8),
+        }
+    }
+}
+
+impl FoundIpv6 {
+    /// Prefix and prefix length of the object
+    pub fn prefix(&self) -> (RawIpv6Addr, u8) {
+        match self {
+            Found::Space(space) => (space.ipv6_prefix, space.ipv6_prefix_len as u8),
+            Found::Pool(_, pool) => (pool.ipv6_prefix, pool.ipv6_prefix_len as u8),
+            Found::Assignment(_, _, assignment) => (assignment.ipv6_prefix, assignment.ipv6_prefix_len as u8),
+        }
+    }
+}
+
+impl FoundAsn {
+    /// First and last ASN of the object
+    pub fn range(&self) -> (u32, u32) {
+        match self {
+            Found::Space(space) => (space.asn_from, space.asn_to),
+            Found::Pool(_, pool) => (pool.asn_from, pool.asn_to),
+            Found::Assignment(_, _, assignment) => (assignment.asn, assignment.asn),
+        }
+    }
+}
+
+/// The most specific IPv4 object containing `prefix/prefix_len`
+pub fn lookup_ipv4(store: &dyn Ipv4AssignmentStore, prefix: RawIpv4Addr, prefix_len: u8, public_only: bool) -> Result<Option<FoundIpv4>, Error> {
+    let found = walk(
+        store.get_spaces_containing(prefix, prefix_len)?,
+        |space_id| store.get_pools_containing(space_id, prefix, prefix_len),
+        |pool_id| store.get_assignments_containing(pool_id, prefix, prefix_len),
+        public_only,
+    )?;
+    Ok(found.into_iter().max_by_key(|found| (found.depth(), found.prefix().1)))
+}
+
+/// The most specific IPv6 object containing `prefix/prefix_len`
+pub fn lookup_ipv6(store: &dyn Ipv6AssignmentStore, prefix: RawIpv6Addr, prefix_len: u8, public_only: bool) -> Result<Option<FoundIpv6>, Error> {
+    let found = walk(
+        store.get_spaces_containing(prefix, prefix_len)?,
+        |space_id| store.get_pools_containing(space_id, prefix, prefix_len),
+        |pool_id| store.get_assignments_containing(pool_id, prefix, prefix_len),
+        public_only,
+    )?;
+    Ok(found.into_iter().max_by_key(|found| (found.depth(), found.prefix().1)))
+}
+
+/// The most specific ASN object containing `asn`
+pub fn lookup_asn(store: &dyn AsnAssignmentStore, asn: u32, public_only: bool) -> Result<Option<FoundAsn>, Error> {
+    let found = walk(
+        store.get_spaces_containing(asn)?,
+        |space_id| store.get_pools_containing(space_id, asn),
+        |pool_id| store.get_assignments_containing(pool_id, asn),
+        public_only,
+    )?;
+    Ok(found.into_iter().max_by_key(|found| (found.depth(), u32::MAX - (found.range().1 - found.range().0))))
+}
@@ -0,0 +1,238 @@
+//! Route Origin Authorisations (RFC 6482) for prefixes in our spaces, exported as local
+//! exceptions in SLURM (RFC 8416)
+
+use crate::types::{Error, ErrorKind};
+use crate::store::{DbConnection, Store};
+use crate::lookup::{self, Found};
+use crate::user::ResourceFamily;
+use crate::ipv4;
+use crate::ipv6;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+
+/// Authorisation for an AS to originate a prefix and its more specifics up to `max_length`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Roa {
+    #[serde(default)]
+    pub id: i32,
+
+    /// IPv4 or IPv6 prefix, which must be within one of our assignment spaces
+    pub prefix: IpAddr,
+
+    /// Length of the prefix
+    pub prefix_len: u8,
+
+    /// Longest prefix that may be announced
+    pub max_length: u8,
+
+    /// Origin AS
+    pub asn: u32,
+
+    /// Document the purpose, such as the announcing network
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Roa {
+    pub fn family(&self) -> ResourceFamily {
+        match self.prefix {
+            IpAddr::V4(_) => ResourceFamily::Ipv4,
+            IpAddr::V6(_) => ResourceFamily::Ipv6,
+        }
+    }
+
+    /// Check the lengths, and return the ROA with the host bits of the prefix cleared
+    pub fn normalize(&self) -> Result<Roa, Error> {
+        let bits = match self.prefix {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if self.prefix_len > bits {
+            return Err(Error::new(ErrorKind::Validation, format!("Prefix length must be at most {}", bits)));
+        }
+        if self.max_length < self.prefix_len || self.max_length > bits {
+            return Err(Error::new(ErrorKind::Validation, format!("maxLength must be between the prefix length and {}", bits)));
+        }
+        let prefix = match self.prefix {
+            IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(ipv4::ipv4_network_address(addr.octets(), self.prefix_len))),
+            IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(ipv6::ipv6_network_address(addr.octets(), self.prefix_len))),
+        };
+        Ok(Roa {
+            prefix,
+            ..self.clone()
+        })
+    }
+
+    /// Whether an announcement of `prefix/prefix_len` from `asn` (any AS if `None`) is valid
+    /// under this ROA
+    pub fn covers(&self, prefix: IpAddr, prefix_len: u8, asn: Option<u32>) -> bool {
+        if prefix_len < self.prefix_len || prefix_len > self.max_length {
+            return false;
+        }
+        if asn.is_some_and(|asn| asn != self.asn) {
+            return false;
+        }
+        match (self.prefix, prefix) {
+            (IpAddr::V4(roa), IpAddr::V4(prefix)) => {
+                ipv4::ipv4_network_address(prefix.octets(), self.prefix_len) == ipv4::ipv4_network_address(roa.octets(), self.prefix_len)
+            },
+            (IpAddr::V6(roa), IpAddr::V6(prefix)) => {
+                ipv6::ipv6_network_address(prefix.octets(), self.prefix_len) == ipv6::ipv6_network_address(roa.octets(), self.prefix_len)
+            },
+            _ => false,
+        }
+    }
+}
+
+pub trait RoaStore {
+    /// Get a ROA by ID
+    fn get_roa(&self, roa_id: i32) -> Result<Roa, Error>;
+
+    /// Get all ROAs, IPv4 before IPv6, ordered by prefix
+    fn get_roas(&self) -> Result<Vec<Roa>, Error>;
+
+    /// Create a new ROA
+    /// Returns the ID of the new ROA
+    /// ID in input is ignored
+    fn create_roa(&self, roa: &Roa) -> Result<i32, Error>;
+
+    /// Replace a ROA. The same checks apply as for new ROAs.
+    fn update_roa(&self, id: i32, roa: &Roa) -> Result<(), Error>;
+
+    /// Delete a ROA
+    fn delete_roa(&self, roa_id: i32) -> Result<(), Error>;
+
+    /// Serial number of the ROA set, incremented (modulo 2^32) by every change
+    fn get_serial(&self) -> Result<u32, Error>;
+}
+
+/// SLURM file (RFC 8416) with our ROAs as locally added assertions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Slurm {
+    pub slurm_version: u32,
+    pub validation_output_filters: ValidationOutputFilters,
+    pub locally_added_assertions: LocallyAddedAssertions,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationOutputFilters {
+    pub prefix_filters: Vec<serde_json::Value>,
+    pub bgpsec_filters: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocallyAddedAssertions {
+    pub prefix_assertions: Vec<PrefixAssertion>,
+    pub bgpsec_assertions: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefixAssertion {
+    pub asn: u32,
+    pub prefix: String,
+    pub max_prefix_length: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// SLURM assertions for `roas`. The order only depends on the ROAs themselves, so that
+/// exports can be diffed.
+pub fn slurm(roas: &[Roa]) -> Slurm {
+    let mut roas = roas.to_vec();
+    roas.sort_by_key(|roa| (roa.prefix, roa.prefix_len, roa.max_length, roa.asn));
+    let prefix_assertions = roas.into_iter()
+        .map(|roa| PrefixAssertion {
+            asn: roa.asn,
+            prefix: format!("{}/{}", roa.prefix, roa.prefix_len),
+            max_prefix_length: roa.max_length,
+            comment: Some(roa.description.trim().to_string()).filter(|comment| !comment.is_empty()),
+        })
+        .collect();
+    Slurm {
+        slurm_version: 1,
+        validation_output_filters: ValidationOutputFilters::default(),
+        locally_added_assertions: LocallyAddedAssertions {
+            prefix_assertions,
+            bgpsec_assertions: Vec::new(),
+        },
+    }
+}
+
+/// An assignment that no ROA authorises
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UncoveredAssignment {
+    pub family: ResourceFamily,
+    pub assignment_space_id: i32,
+    pub assignment_pool_id: i32,
+    pub assignment_id: i32,
+    pub name: String,
+
+    /// Such as `192.0.2.0/28`
+    pub prefix: String,
+
+    /// ASN of the origin ASN assignment, if any
+    pub origin_asn: Option<u32>,
+}
+
+/// IPv4 and IPv6 assignments that cannot be announced as a whole with a valid origin: no ROA
+/// covers the prefix with a long enough maxLength, or none names the origin ASN of the
+/// assignment.
+pub fn coverage<T>(store: &Store<T>) -> Result<Vec<UncoveredAssignment>, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let roas = store.roas().get_roas()?;
+    let origins: HashMap<i32, u32> = lookup::asn_objects(store.asn_assignments().as_ref(), false)?.into_iter()
+        .filter_map(|found| match found {
+            Found::Assignment(_, _, assignment) => Some((assignment.id, assignment.asn)),
+            _ => None,
+        })
+        .collect();
+    let origin = |origin_asn_id: Option<i32>| origin_asn_id.and_then(|id| origins.get(&id).copied());
+    let covered = |prefix: IpAddr, prefix_len: u8, asn: Option<u32>| roas.iter().any(|roa| roa.covers(prefix, prefix_len, asn));
+
+    let mut uncovered = Vec::new();
+    for found in lookup::ipv4_objects(store.ipv4_assignments().as_ref(), false)? {
+        if let Found::Assignment(space, pool, assignment) = found {
+            let prefix = IpAddr::V4(Ipv4Addr::from(ipv4::ipv4_network_address(assignment.ipv4_prefix, assignment.ipv4_prefix_len as u8)));
+            let origin_asn = origin(assignment.origin_asn_id);
+            if !covered(prefix, assignment.ipv4_prefix_len as u8, origin_asn) {
+                uncovered.push(UncoveredAssignment {
+                    family: ResourceFamily::Ipv4,
+                    assignment_space_id: space.id,
+                    assignment_pool_id: pool.id,
+                    assignment_id: assignment.id,
+                    name: assignment.name,
+                    prefix: format!("{}/{}", prefix, assignment.ipv4_prefix_len),
+                    origin_asn,
+                });
+            }
+        }
+    }
+    for found in lookup::ipv6_objects(store.ipv6_assignments().as_ref(), false)? {
+        if let Found::Assignment(space, pool, assignment) = found {
+            let prefix = IpAddr::V6(Ipv6Addr::from(ipv6::ipv6_network_address(assignment.ipv6_prefix, assignment.ipv6_prefix_len as u8)));
+            let origin_asn = origin(assignment.origin_asn_id);
+            if !covered(prefix, assignment.ipv6_prefix_len as u8, origin_asn) {
+                uncovered.push(UncoveredAssignment {
+                    family: ResourceFamily::Ipv6,
+                    assignment_space_id: space.id,
+                    assignment_pool_id: pool.id,
+                    assignment_id: assignment.id,
+                    name: assignment.name,
+                    prefix: format!("{}/{}", prefix, assignment.ipv6_prefix_len),
+                    origin_asn,
+                });
+            }
+        }
+    }
+    Ok(uncovered)
+}
@@ -0,0 +1,259 @@
+//! RPSL (RFC 2622) objects describing public resources, as served over WHOIS and
+//! exported for submission to an IRR
+
+use crate::lookup::{self, Found, FoundIpv4, FoundIpv6, FoundAsn, Object};
+use crate::store::{DbConnection, Store};
+use crate::types::{Error, ErrorKind, ObjectVisibility};
+use crate::ipv4;
+use crate::ipv6;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+
+/// Column at which attribute values start
+const VALUE_COLUMN: usize = 16;
+
+/// Default `source:` of objects
+pub const DEFAULT_SOURCE: &str = "MIRAMS";
+
+fn default_source() -> String {
+    DEFAULT_SOURCE.to_string()
+}
+
+/// Attributes shared by all generated objects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpslConfig {
+    /// `source:` of the objects, the name of the IRR database
+    #[serde(default = "default_source")]
+    pub source: String,
+
+    /// `mnt-by:` of the objects; omitted if unset
+    #[serde(default)]
+    pub maintainer: Option<String>,
+}
+
+impl Default for RpslConfig {
+    fn default() -> Self {
+        RpslConfig {
+            source: default_source(),
+            maintainer: None,
+        }
+    }
+}
+
+/// An RPSL object: attributes in order, the first one naming the class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpslObject {
+    attributes: Vec<(String, String)>,
+}
+
+impl RpslObject {
+    pub fn new(class: &str, key: impl ToString) -> Self {
+        RpslObject {
+            attributes: vec![(class.to_string(), key.to_string())],
+        }
+    }
+
+    /// Add an attribute. A value with several lines becomes one attribute per non-empty line.
+    pub fn add(&mut self, name: &str, value: impl ToString) -> &mut Self {
+        for line in value.to_string().lines().map(str::trim).filter(|line| !line.is_empty()) {
+            self.attributes.push((name.to_string(), line.to_string()));
+        }
+        self
+    }
+
+    pub fn class(&self) -> &str {
+        &self.attributes[0].0
+    }
+
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// Add `mnt-by:` and `source:`, which end every object
+    fn finish(&mut self, config: &RpslConfig) {
+        if let Some(maintainer) = &config.maintainer {
+            self.add("mnt-by", maintainer);
+        }
+        self.add("source", &config.source);
+    }
+}
+
+impl Display for RpslObject {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (name, value) in &self.attributes {
+            writeln!(f, "{:<width$}{}", format!("{}:", name), value, width = VALUE_COLUMN)?;
+        }
+        Ok(())
+    }
+}
+
+/// An object name (`netname:`, `as-name:`) from a free-form name: letters, digits, `-` and `_`,
+/// starting with a letter
+pub fn object_name(name: &str, prefix: &str) -> String {
+    let mut result = String::new();
+    for c in name.trim().to_ascii_uppercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            result.push(c);
+        } else if !result.ends_with('-') {
+            result.push('-');
+        }
+    }
+    let result = result.trim_matches('-');
+    if result.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        result.to_string()
+    } else {
+        format!("{}-{}", prefix, result).trim_end_matches('-').to_string()
+    }
+}
+
+/// `descr:` lines: the description, or the name if there is none
+fn descr(object: &dyn Object) -> &str {
+    if object.description().trim().is_empty() {
+        object.name()
+    } else {
+        object.description()
+    }
+}
+
+pub fn inetnum(found: &FoundIpv4, config: &RpslConfig) -> RpslObject {
+    let (prefix, prefix_len) = found.prefix();
+    let first = Ipv4Addr::from(ipv4::ipv4_network_address(prefix, prefix_len));
+    let last = Ipv4Addr::from(ipv4::ipv4_broadcast_address(prefix, prefix_len));
+    let status = match found {
+        Found::Space(..) => "ALLOCATED PA",
+        Found::Pool(..) => "SUB-ALLOCATED PA",
+        Found::Assignment(..) => "ASSIGNED PA",
+    };
+    let object = found.object();
+    let mut rpsl = RpslObject::new("inetnum", format!("{} - {}", first, last));
+    rpsl.add("netname", object_name(object.name(), "NET"))
+        .add("descr", descr(object))
+        .add("status", status);
+    rpsl.finish(config);
+    rpsl
+}
+
+pub fn inet6num(found: &FoundIpv6, config: &RpslConfig) -> RpslObject {
+    let (prefix, prefix_len) = found.prefix();
+    let network = Ipv6Addr::from(ipv6::ipv6_network_address(prefix, prefix_len));
+    let status = match found {
+        Found::Space(..) => "ALLOCATED-BY-RIR",
+        Found::Pool(..) => "ALLOCATED-BY-LIR",
+        Found::Assignment(..) => "ASSIGNED",
+    };
+    let object = found.object();
+    let mut rpsl = RpslObject::new("inet6num", format!("{}/{}", network, prefix_len));
+    rpsl.add("netname", object_name(object.name(), "NET"))
+        .add("descr", descr(object))
+        .add("status", status);
+    rpsl.finish(config);
+    rpsl
+}
+
+/// `aut-num` for assignments, `as-block` for spaces and pools
+pub fn aut_num(found: &FoundAsn, config: &RpslConfig) -> RpslObject {
+    let (from, to) = found.range();
+    let object = found.object();
+    let mut rpsl = match found {
+        Found::Assignment(..) => {
+            let mut rpsl = RpslObject::new("aut-num", format!("AS{}", from));
+            rpsl.add("as-name", object_name(object.name(), "AS"));
+            rpsl
+        },
+        _ => RpslObject::new("as-block", format!("AS{} - AS{}", from, to)),
+    };
+    rpsl.add("descr", descr(object));
+    if let Found::Assignment(..) = found {
+        rpsl.add("status", "ASSIGNED");
+    }
+    rpsl.finish(config);
+    rpsl
+}
+
+/// `route` announcing an IPv4 assignment from `origin`
+pub fn route(assignment: &ipv4::AssignmentIpv4, origin: u32, config: &RpslConfig) -> RpslObject {
+    let prefix_len = assignment.ipv4_prefix_len as u8;
+    let network = Ipv4Addr::from(ipv4::ipv4_network_address(assignment.ipv4_prefix, prefix_len));
+    let mut rpsl = RpslObject::new("route", format!("{}/{}", network, prefix_len));
+    rpsl.add("descr", descr(assignment))
+        .add("origin", format!("AS{}", origin));
+    rpsl.finish(config);
+    rpsl
+}
+
+/// `route6` announcing an IPv6 assignment from `origin`
+pub fn route6(assignment: &ipv6::AssignmentIpv6, origin: u32, config: &RpslConfig) -> RpslObject {
+    let prefix_len = assignment.ipv6_prefix_len as u8;
+    let network = Ipv6Addr::from(ipv6::ipv6_network_address(assignment.ipv6_prefix, prefix_len));
+    let mut rpsl = RpslObject::new("route6", format!("{}/{}", network, prefix_len));
+    rpsl.add("descr", descr(assignment))
+        .add("origin", format!("AS{}", origin));
+    rpsl.finish(config);
+    rpsl
+}
+
+/// Objects for all public assignments, to be submitted to an IRR: `aut-num` for ASN
+/// assignments, `inetnum` and `inet6num` for IP assignments, and `route` and `route6` for
+/// IP assignments with an origin ASN.
+///
+/// The output only depends on the data: objects are ordered by class, then by ASN or address,
+/// and separated by blank lines.
+pub fn export<T>(store: &Store<T>, config: &RpslConfig) -> Result<String, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if config.source.trim().is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "RPSL source must not be empty".to_string()));
+    }
+
+    let asn_objects = lookup::asn_objects(store.asn_assignments().as_ref(), false)?;
+    // Origins are needed even for ASN assignments that are not public themselves
+    let origins: HashMap<i32, u32> = asn_objects.iter()
+        .filter_map(|found| match found {
+            Found::Assignment(_, _, assignment) => Some((assignment.id, assignment.asn)),
+            _ => None,
+        })
+        .collect();
+
+    let mut aut_nums: Vec<_> = asn_objects.into_iter()
+        .filter(|found| matches!(found, Found::Assignment(..)) && found.visibility() == ObjectVisibility::Public)
+        .collect();
+    aut_nums.sort_by_key(|found| found.range());
+
+    let mut inetnums: Vec<_> = lookup::ipv4_objects(store.ipv4_assignments().as_ref(), true)?.into_iter()
+        .filter(|found| matches!(found, Found::Assignment(..)))
+        .collect();
+    inetnums.sort_by_key(|found| found.prefix());
+
+    let mut inet6nums: Vec<_> = lookup::ipv6_objects(store.ipv6_assignments().as_ref(), true)?.into_iter()
+        .filter(|found| matches!(found, Found::Assignment(..)))
+        .collect();
+    inet6nums.sort_by_key(|found| found.prefix());
+
+    let mut objects = Vec::new();
+    objects.extend(aut_nums.iter().map(|found| aut_num(found, config)));
+    objects.extend(inetnums.iter().map(|found| inetnum(found, config)));
+    objects.extend(inet6nums.iter().map(|found| inet6num(found, config)));
+    for found in &inetnums {
+        if let Found::Assignment(_, _, assignment) = found {
+            if let Some(origin) = assignment.origin_asn_id.and_then(|id| origins.get(&id)) {
+                objects.push(route(assignment, *origin, config));
+            }
+        }
+    }
+    for found in &inet6nums {
+        if let Found::Assignment(_, _, assignment) = found {
+            if let Some(origin) = assignment.origin_asn_id.and_then(|id| origins.get(&id)) {
+                objects.push(route6(assignment, *origin, config));
+            }
+        }
+    }
+
+    Ok(objects.iter().map(|object| object.to_string()).collect::<Vec<_>>().join("\n"))
+}
@@ -1,21 +1,33 @@
 
 mod v1_login;
 mod v1_user;
+mod v1_users;
+mod v1_login_attempts;
+mod v1_oidc;
 mod v1_asn;
 mod v1_ipv4;
 mod v1_ipv6;
+mod v1_roa;
+mod v1_export;
 
 use crate::store::DbConnection;
+use crate::types::{Error, ErrorKind, HasVisibility, ObjectVisibility};
 
 use super::Server;
+use super::auth::ClientCertificate;
+use super::listener::UnixSocketPeer;
+
+use crate::user::{ApiToken, LoginAttempt, LoginLockout, NewApiToken, ResourceFamily, TotpEnrolment, TotpStatus, UserInfo, UserRole};
 
 use axum::handler::Handler;
 use axum::Router;
 use axum::body::Body;
 use axum::routing::{get, post};
+use axum::extract::OriginalUri;
+use axum::extract::ConnectInfo;
 
 use tower_http::auth::{AsyncRequireAuthorizationLayer, AsyncAuthorizeRequest};
-use http::{Request, Response, header::AUTHORIZATION, Method};
+use http::{Request, Response, header::AUTHORIZATION, HeaderValue, Method};
 use tower::ServiceBuilder;
 use futures_util::future::BoxFuture;
 use tower_http::cors;
@@ -23,7 +35,7 @@ use tower_http::timeout::TimeoutLayer;
 
 use serde::{Serialize, Deserialize};
 
-use std::time::Duration;
+use std::net::SocketAddr;
 use std::marker::PhantomData;
 use std::any::TypeId;
 
@@ -32,6 +44,10 @@ use std::any::TypeId;
 pub struct ApiResponse {
     pub error: Option<String>,
 
+    /// Machine-readable kind of the error, such as `"conflict"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorKind>,
+
     #[serde(flatten)] 
     pub result: Option<ApiResponseVariant>,
 }
@@ -42,6 +58,17 @@ pub enum ApiResponseVariant {
     User(User),
     Users(Vec<User>),
     ApiToken(String),
+    ApiTokens(Vec<ApiToken>),
+    NewApiToken(NewApiToken),
+    TotpRequired(bool),
+    TotpStatus(TotpStatus),
+    TotpEnrolment(TotpEnrolment),
+    RecoveryCodes(Vec<String>),
+    LoginMethods(LoginMethods),
+    UserInfo(UserInfo),
+    UserInfos(Vec<UserInfo>),
+    LoginAttempts(Vec<LoginAttempt>),
+    LoginLockouts(Vec<LoginLockout>),
 
     AsnAssignmentSpace(crate::asn::AssignmentSpaceAsn),
     AsnAssignmentPool(crate::asn::AssignmentPoolAsn),
@@ -62,11 +89,37 @@ pub enum ApiResponseVariant {
     Ipv6AssignmentSpaces(Vec<crate::ipv6::AssignmentSpaceIpv6>),
     Ipv6AssignmentPools(Vec<crate::ipv6::AssignmentPoolIpv6>),
     Ipv6Assignments(Vec<crate::ipv6::AssignmentIpv6>),
+
+    Ipv4Host(crate::ipv4::HostIpv4),
+    Ipv4Hosts(Vec<crate::ipv4::HostIpv4>),
+    Ipv4Address(Option<crate::ipv4::RawIpv4Addr>),
+    Ipv6Host(crate::ipv6::HostIpv6),
+    Ipv6Hosts(Vec<crate::ipv6::HostIpv6>),
+    Ipv6Address(Option<crate::ipv6::RawIpv6Addr>),
+
+    Roa(crate::roa::Roa),
+    Roas(Vec<crate::roa::Roa>),
+    UncoveredAssignments(Vec<crate::roa::UncoveredAssignment>),
+    ReverseDns(crate::dns::ReverseDns),
+    DnsZones(Vec<crate::dns::Zone>),
+    DnsUpdate(Option<crate::dns::DnsUpdate>),
+    DnsForward(crate::dns::ForwardDns),
+    Dhcp(Option<crate::dhcp::DhcpSettings>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub username: String,
+
+    #[serde(default)]
+    pub role: UserRole,
+}
+
+/// Login methods offered by this server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoginMethods {
+    pub password: bool,
+    pub oidc: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -75,6 +128,22 @@ pub struct MetadataUpdateRequest {
     pub description: String,
 }
 
+/// A new assignment, with its reverse DNS
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssignmentCreateRequest<A> {
+    #[serde(flatten)]
+    pub assignment: A,
+
+    #[serde(default)]
+    pub reverse_dns: Option<crate::dns::ReverseDns>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OriginAsnUpdateRequest {
+    /// ID of the ASN assignment originating the prefix, or `null` to clear it
+    pub origin_asn_id: Option<i32>,
+}
+
 pub fn build_json_response(response: ApiResponse, status: u16) -> Response<Body> {
     Response::builder()
         .status(status)
@@ -84,7 +153,7 @@ pub fn build_json_response(response: ApiResponse, status: u16) -> Response<Body>
 }
 
 
-pub fn build_api_v1_router<T>() -> Router<Server<T>>
+pub fn build_api_v1_router<T>(server: &Server<T>) -> Router<Server<T>>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
@@ -94,22 +163,36 @@ where
 
     router = router.route("/login", post(v1_login::api_v1_login));
 
+    router = router.route("/login/methods", get(v1_login::api_v1_login_methods));
+
+    router = router.nest("/oidc", v1_oidc::build_router());
+
+    router = router.route("/logout", post(v1_login::api_v1_logout).layer(AuthHandler::<T>::new_auth_required_layer()));
+
     router = router.nest("/user", v1_user::build_router());
 
+    router = router.nest("/users", v1_users::build_router());
+
+    router = router.nest("/login_attempts", v1_login_attempts::build_router());
+
     router = router.nest("/asn", v1_asn::build_router());
 
     router = router.nest("/ipv4", v1_ipv4::build_router());
 
     router = router.nest("/ipv6", v1_ipv6::build_router());
 
+    router = router.nest("/roa", v1_roa::build_router());
+
+    router = router.nest("/export", v1_export::build_router());
+
     // at the end, define the default route
     router = router.fallback(fallback_handler());
 
     let cors = cors::CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_origin(cors::Any);
+        .allow_origin(cors_allow_origin(server.cors_origins()));
 
-    let timeout = TimeoutLayer::new(Duration::from_secs(30));
+    let timeout = TimeoutLayer::new(server.request_timeout());
 
     let service = ServiceBuilder::new()
         .layer(cors)
@@ -120,6 +203,20 @@ where
     router
 }
 
+fn cors_allow_origin(origins: &[String]) -> cors::AllowOrigin {
+    if origins.iter().any(|origin| origin == crate::config::ANY_ORIGIN) {
+        return cors::Any.into();
+    }
+    let origins: Vec<HeaderValue> = origins.iter().filter_map(|origin| match HeaderValue::from_str(origin) {
+        Ok(origin) => Some(origin),
+        Err(_) => {
+            log::warn!("Ignoring invalid CORS origin {}", origin);
+            None
+        },
+    }).collect();
+    cors::AllowOrigin::list(origins)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct AuthHandler<T, AuthRequired: ?Sized = ()> {
     _phantom1: PhantomData<T>,
@@ -129,6 +226,9 @@ pub(crate) struct AuthHandler<T, AuthRequired: ?Sized = ()> {
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct AuthRequired;
 
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AdminRequired;
+
 #[allow(dead_code)]
 impl<T, R> AuthHandler<T, R> {
     const fn new_internal() -> Self {
@@ -146,6 +246,10 @@ impl<T, R> AuthHandler<T, R> {
         AuthHandler::<T, AuthRequired>::new_internal()
     }
 
+    const fn new_admin_required() -> AuthHandler<T, AdminRequired> {
+        AuthHandler::<T, AdminRequired>::new_internal()
+    }
+
     pub fn new_layer() -> AsyncRequireAuthorizationLayer<AuthHandler<T, ()>> {
         Self::new().into_layer()
     }
@@ -154,6 +258,10 @@ impl<T, R> AuthHandler<T, R> {
         Self::new_auth_required().into_layer()
     }
 
+    pub fn new_admin_required_layer() -> AsyncRequireAuthorizationLayer<AuthHandler<T, AdminRequired>> {
+        Self::new_admin_required().into_layer()
+    }
+
     pub fn into_layer(self) -> AsyncRequireAuthorizationLayer<Self> {
         AsyncRequireAuthorizationLayer::new(self)
     }
@@ -183,21 +291,58 @@ where
             let authorization = request.headers().get(AUTHORIZATION)
                 .map(|header| header.to_str().unwrap().trim().to_owned());
 
+            let mut bearer_used = false;
             if let Some(authorization) = authorization {
                 let parts: Vec<&str> = authorization.split_whitespace().collect();
                 if parts.len() == 2 && parts[0] == "Bearer" {
+                    bearer_used = true;
                     let token = parts[1].to_owned();
                     let store = server.store().clone();
                     let user = tokio::task::spawn_blocking(move || {
                         let user_store = store.users();
-                        match user_store.get_user_from_api_key(&token) {
-                            Ok(Some(username)) => {
-                                Some(User { username })
+                        match user_store.get_api_token(&token) {
+                            Ok(Some((username, token))) => {
+                                let role = user_store.get_user_role(&username).ok()?;
+                                Some((User { username, role }, token))
                             },
                             _ => None,
                         }
                     }).await.unwrap();
+                    if let Some((user, token)) = user {
+                        let path = request.extensions().get::<OriginalUri>()
+                            .map(|uri| uri.path().to_owned())
+                            .unwrap_or_else(|| request.uri().path().to_owned());
+                        let write = !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+                        if !token.scope.allows(resource_families_from_path(&path), write) {
+                            return Err(response_forbidden());
+                        }
+                        if TypeId::of::<R>() == TypeId::of::<AdminRequired>() && user.role != UserRole::Admin {
+                            return Err(response_forbidden());
+                        }
+                        request.extensions_mut().insert(user);
+                        request.extensions_mut().insert(token);
+                        return Ok(request);
+                    }
+                }
+            }
+
+            // Without a bearer token, a trusted proxy or a client certificate may identify the user.
+            if !bearer_used {
+                if let Some(username) = external_username(&server, &request) {
+                    let store = server.store().clone();
+                    let user = tokio::task::spawn_blocking(move || {
+                        match store.users().get_user_role(&username) {
+                            Ok(role) => Some(User { username, role }),
+                            Err(e) => {
+                                log::info!("Externally authenticated user {} is unknown: {}", username, e);
+                                None
+                            },
+                        }
+                    }).await.unwrap();
                     if let Some(user) = user {
+                        if TypeId::of::<R>() == TypeId::of::<AdminRequired>() && user.role != UserRole::Admin {
+                            return Err(response_forbidden());
+                        }
                         request.extensions_mut().insert(user);
                         return Ok(request);
                     }
@@ -206,7 +351,8 @@ where
 
             let type_id = TypeId::of::<R>();
             let auth_required_type_id = TypeId::of::<AuthRequired>();
-            if auth_required_type_id == type_id {
+            let admin_required_type_id = TypeId::of::<AdminRequired>();
+            if auth_required_type_id == type_id || admin_required_type_id == type_id {
                 let response = response_unauthorized();
                 return Err(response);
             }
@@ -216,20 +362,112 @@ where
     }
 }
 
-pub(crate) fn response_unauthorized() -> Response<Body> {
+/// Address of the client of a request, for login throttling and auditing. Behind a trusted
+/// proxy, this is the address it forwards; `None` if there is none.
+pub(crate) fn client_address<T>(server: &Server<T>, extensions: &http::Extensions, headers: &http::HeaderMap) -> Option<std::net::IpAddr>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let peer = extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    match server.proxy_auth() {
+        Some(proxy_auth) => proxy_auth.client_address(peer, extensions.get::<UnixSocketPeer>().is_some(), headers),
+        None => peer,
+    }
+}
+
+/// Username asserted by a trusted reverse proxy header or a verified client certificate
+fn external_username<T>(server: &Server<T>, request: &Request<Body>) -> Option<String>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(proxy_auth) = server.proxy_auth() {
+        let username = if request.extensions().get::<UnixSocketPeer>().is_some() {
+            proxy_auth.unix_socket_username(request.headers())
+        } else {
+            let source = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+            proxy_auth.username(source, request.headers())
+        };
+        if username.is_some() {
+            return username;
+        }
+    }
+    if let Some(client_cert_auth) = server.client_cert_auth() {
+        if let Some(cert) = request.extensions().get::<ClientCertificate>() {
+            return client_cert_auth.username(cert);
+        }
+    }
+    None
+}
+
+/// Resource families whose data an API path such as `/api/v1/ipv4/...` exposes
+fn resource_families_from_path(path: &str) -> &'static [ResourceFamily] {
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    let mut segments = path.trim_start_matches('/').split('/');
+    match (segments.next().unwrap_or(""), segments.next().unwrap_or("")) {
+        ("asn", _) => &[ResourceFamily::Asn],
+        ("ipv4", _) => &[ResourceFamily::Ipv4],
+        ("ipv6", _) => &[ResourceFamily::Ipv6],
+        ("export", "dns-reverse" | "dns-forward" | "kea") => &[ResourceFamily::Ipv4, ResourceFamily::Ipv6],
+        ("roa", _) | ("export", _) => &[ResourceFamily::Asn, ResourceFamily::Ipv4, ResourceFamily::Ipv6],
+        _ => &[],
+    }
+}
+
+/// HTTP status of each kind of error
+pub(crate) fn error_status(kind: ErrorKind) -> u16 {
+    match kind {
+        ErrorKind::InvalidInput => 400,
+        ErrorKind::Unauthorized => 401,
+        ErrorKind::Forbidden => 403,
+        ErrorKind::NotFound => 404,
+        ErrorKind::Conflict => 409,
+        ErrorKind::Validation => 422,
+        ErrorKind::RateLimited => 429,
+        ErrorKind::DatabaseError | ErrorKind::InternalError | ErrorKind::Other => 500,
+    }
+}
+
+/// Error response with the status and code of `kind`
+pub(crate) fn response_error_kind(kind: ErrorKind, message: impl ToString) -> Response<Body> {
     let response = ApiResponse {
-        error: Some("Unauthorized".to_string()),
+        error: Some(message.to_string()),
+        code: Some(kind),
         result: None,
     };
-    build_json_response(response, 401)
+    build_json_response(response, error_status(kind))
+}
+
+/// Error response for a failed operation, e.g. `response_error("Error creating pool", &e)`.
+/// Details of server-side errors are logged instead of returned.
+pub(crate) fn response_error(context: &str, e: &Error) -> Response<Body> {
+    if error_status(e.kind()) >= 500 {
+        log::error!("{}: {}", context, e);
+        return response_error_kind(e.kind(), context);
+    }
+    response_error_kind(e.kind(), format!("{}: {}", context, e.message()))
+}
+
+pub(crate) fn response_unauthorized() -> Response<Body> {
+    response_error_kind(ErrorKind::Unauthorized, "Unauthorized")
+}
+
+pub(crate) fn response_forbidden() -> Response<Body> {
+    response_error_kind(ErrorKind::Forbidden, "Forbidden")
 }
 
 pub(crate) fn response_not_found() -> Response<Body> {
-    let response = ApiResponse {
-        error: Some("Not found".to_string()),
-        result: None,
-    };
-    build_json_response(response, 404)
+    response_error_kind(ErrorKind::NotFound, "Not found")
+}
+
+pub(crate) fn response_internal_error() -> Response<Body> {
+    response_error_kind(ErrorKind::InternalError, "Internal Server Error")
+}
+
+/// Whether an object, given followed by its ancestors, can be shown to `user`.
+/// Logged-in users see everything; anonymous users see an object only if it and
+/// all its ancestors are public (see `ObjectVisibility::effective`).
+pub(crate) fn is_visible(user: &Option<axum::extract::Extension<User>>, ancestry: &[&dyn HasVisibility]) -> bool {
+    user.is_some() || ObjectVisibility::effective(ancestry) == ObjectVisibility::Public
 }
 
 pub(crate) fn fallback_handler<S>() -> impl Handler<(), S>
@@ -16,12 +16,12 @@
 //! - `PUT /api/v1/asn/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id` - Update metadata for an assignment by ID
 //! - `DELETE /api/v1/asn/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id` - Delete an assignment by ID
 //! 
-//! GET endpoints accept unauthenticated requests if object visibility is set to `ObjectVisibility::Public`.
+//! GET endpoints accept unauthenticated requests for objects that are public along with all their ancestors.
 
 
-use crate::store::DbConnection;
+use crate::store::{DbConnection, Store};
 use crate::server::Server;
-use crate::types::ObjectVisibility;
+use crate::types::ErrorKind;
 use super::AuthHandler;
 use super::fallback_handler;
 use super::build_json_response;
@@ -30,6 +30,8 @@ use super::ApiResponseVariant;
 use super::ApiResponse;
 use super::MetadataUpdateRequest;
 use super::run_blocking_task;
+use super::is_visible;
+use super::{response_error, response_error_kind, response_internal_error};
 
 use crate::asn::{
     AssignmentSpaceAsn,
@@ -46,6 +48,46 @@ use axum::extract::Path as PathExtractor;
 
 use http::Response;
 
+/// The space `space_id`, or a 404 response
+async fn get_space<T>(store: &Store<T>, space_id: i32) -> Result<AssignmentSpaceAsn, Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
+        Ok(space) => Ok(space),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Err(response_error_kind(ErrorKind::NotFound, "Assignment space not found")),
+        Err(e) => Err(response_error("Error getting assignment space", &e)),
+    }
+}
+
+/// The space `space_id` and its pool `pool_id`, or a 404 response if the pool is not in that space
+async fn get_pool_in_space<T>(store: &Store<T>, space_id: i32, pool_id: i32) -> Result<(AssignmentSpaceAsn, AssignmentPoolAsn), Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let pool = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id)).await {
+        Ok(pool) if pool.assignment_space_id == space_id => pool,
+        Ok(_) => return Err(response_error_kind(ErrorKind::NotFound, "Pool not found")),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Err(response_error_kind(ErrorKind::NotFound, "Pool not found")),
+        Err(e) => return Err(response_error("Error getting pool", &e)),
+    };
+    let space = get_space(store, space_id).await?;
+    Ok((space, pool))
+}
+
+/// The space `space_id`, its pool `pool_id` and the assignment `assignment_id` in it, or a 404 response if they do not match
+async fn get_assignment_in_pool<T>(store: &Store<T>, space_id: i32, pool_id: i32, assignment_id: i32) -> Result<(AssignmentSpaceAsn, AssignmentPoolAsn, AssignmentAsn), Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let (space, pool) = get_pool_in_space(store, space_id, pool_id).await?;
+    match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_assignment(assignment_id)).await {
+        Ok(assignment) if assignment.assignment_pool_id == pool_id => Ok((space, pool, assignment)),
+        Ok(_) => Err(response_error_kind(ErrorKind::NotFound, "Assignment not found")),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Err(response_error_kind(ErrorKind::NotFound, "Assignment not found")),
+        Err(e) => Err(response_error("Error getting assignment", &e)),
+    }
+}
 
 async fn api_v1_asn_assignment_space_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
@@ -55,32 +97,19 @@ where
         let store = ext.0.store();
         let res = match run_blocking_task(store.clone(), |store| store.asn_assignments().get_spaces()).await {
             Ok(mut spaces) => {
-                if let None = user {
-                    spaces.retain(|space| {
-                        space.space_visibility == ObjectVisibility::Public
-                    });
-                }
+                spaces.retain(|space| is_visible(&user, &[space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::AsnAssignmentSpaces(spaces)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing assignment spaces: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing assignment spaces", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -95,32 +124,19 @@ where
                 if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignmentSpace(space)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error creating assignment space".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating assignment space")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error creating assignment space: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error creating assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -132,34 +148,22 @@ where
         let store = ext.0.store();
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
             Ok(space) => {
-                if user.is_none() && space.space_visibility != ObjectVisibility::Public {
-                    let res = ApiResponse {
-                        error: Some("Assignment space not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
+                if !is_visible(&user, &[&space]) {
+                    return response_error_kind(ErrorKind::NotFound, "Assignment space not found");
                 }
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::AsnAssignmentSpace(space)),
                 };
                 build_json_response(res, 200)
             },
-            Err(_) => {
-                let res = ApiResponse {
-                    error: Some("Assignment space not found".to_string()),
-                    result: None,
-                };
-                build_json_response(res, 404)
-            },
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound) => response_error_kind(ErrorKind::NotFound, "Assignment space not found"),
+            Err(e) => response_error("Error getting assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -174,32 +178,19 @@ where
                 if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignmentSpace(space)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating assignment space".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error updating assignment space")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error updating assignment space: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error updating assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -213,25 +204,16 @@ where
             Ok(_) => {
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error deleting assignment space: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error deleting assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -242,51 +224,29 @@ where
     if let Some(ext) = ext {
         let store = ext.0.store();
 
-        let space_id_copy = space_id;
-        if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id_copy)).await {
-            if user.is_none() && space.space_visibility != ObjectVisibility::Public {
-                let res = ApiResponse {
-                    error: Some("Assignment space not found".to_string()),
-                    result: None,
-                };
-                return build_json_response(res, 404);
-            }
-        } else {
-            let res = ApiResponse {
-                error: Some("Assignment space not found".to_string()),
-                result: None,
-            };
-            return build_json_response(res, 404);
+        let space = match get_space(store, space_id).await {
+            Ok(space) => space,
+            Err(res) => return res,
+        };
+        if !is_visible(&user, &[&space]) {
+            return response_error_kind(ErrorKind::NotFound, "Assignment space not found");
         }
 
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pools(space_id)).await {
             Ok(mut pools) => {
-                if let None = user {
-                    pools.retain(|pool| {
-                        pool.pool_visibility == ObjectVisibility::Public
-                    });
-                }
+                pools.retain(|pool| is_visible(&user, &[pool, &space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::AsnAssignmentPools(pools)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing pools: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing pools", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -295,11 +255,7 @@ where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if req.assignment_space_id != space_id {
-        let res = ApiResponse {
-            error: Some("Assignment space ID mismatch".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 400);
+        return response_error_kind(ErrorKind::InvalidInput, "Assignment space ID mismatch");
     }
     if let Some(ext) = ext {
         let store = ext.0.store();
@@ -308,36 +264,22 @@ where
                 if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignmentPool(pool)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error creating pool".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating pool")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error creating pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error creating pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
@@ -345,131 +287,80 @@ where
     if let Some(ext) = ext {
         let store = ext.0.store();
 
-        let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id)).await {
-            Ok(pool) => {
-                let space_id = pool.assignment_space_id;
-                if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
-                    if user.is_none() && space.space_visibility != ObjectVisibility::Public {
-                        let res = ApiResponse {
-                            error: Some("Pool not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-                } else {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-
-                if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-                let res = ApiResponse {
-                    error: None,
-                    result: Some(ApiResponseVariant::AsnAssignmentPool(pool)),
-                };
-                build_json_response(res, 200)
-            },
-            Err(_) => {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                build_json_response(res, 404)
-            },
+        let (space, pool) = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
         };
-        return res;
-    } else {
+        if !is_visible(&user, &[&pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Pool not found");
+        }
         let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+            error: None,
+            code: None,
+            result: Some(ApiResponseVariant::AsnAssignmentPool(pool)),
         };
-        return build_json_response(res, 500);
+        return build_json_response(res, 200);
+    } else {
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, JsonExtractor(req): JsonExtractor<MetadataUpdateRequest>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_pool_in_space(store, space_id, pool_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().update_pool(pool_id, &req.name, &req.description)).await {
             Ok(_) => {
                 if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignmentPool(pool)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating pool".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error updating pool")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error updating pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error updating pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_pool_in_space(store, space_id, pool_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().delete_pool(pool_id)).await {
             Ok(_) => {
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error deleting pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error deleting pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_assignment_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
@@ -477,266 +368,143 @@ where
     if let Some(ext) = ext {
         let store = ext.0.store();
 
-        let pool_id_copy = pool_id;
-        if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id_copy)).await {
-            if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                return build_json_response(res, 404);
-            }
-
-            let space_id = pool.assignment_space_id;
-
-            if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
-                if space.id != pool.assignment_space_id {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-            } else {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                return build_json_response(res, 404);
-            }
-        } else {
-            let res = ApiResponse {
-                error: Some("Pool not found".to_string()),
-                result: None,
-            };
-            return build_json_response(res, 404);
+        let (space, pool) = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        if !is_visible(&user, &[&pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Pool not found");
         }
 
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_assignments(pool_id)).await {
             Ok(mut assignments) => {
-                if let None = user {
-                    assignments.retain(|assignment| {
-                        assignment.assignment_visibility == ObjectVisibility::Public
-                    });
-                }
+                assignments.retain(|assignment| is_visible(&user, &[assignment, &pool, &space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::AsnAssignments(assignments)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing assignments: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing assignments", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_assignment_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, JsonExtractor(req): JsonExtractor<AssignmentAsn>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if req.assignment_pool_id != pool_id {
-        let res = ApiResponse {
-            error: Some("Assignment pool ID mismatch".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 400);
+        return response_error_kind(ErrorKind::InvalidInput, "Assignment pool ID mismatch");
     }
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_pool_in_space(store, space_id, pool_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().create_assignment(&req)).await {
             Ok(assignment_id) => {
                 if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_assignment(assignment_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignment(assignment)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error creating assignment".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating assignment")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error creating assignment: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error creating assignment", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_assignment_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
-        let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().get_assignment(assignment_id)).await {
-            Ok(assignment) => {
-                let pool_id = assignment.assignment_pool_id;
-                if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_pool(pool_id)).await {
-                    if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                        let res = ApiResponse {
-                            error: Some("Assignment not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-
-                    let space_id = pool.assignment_space_id;
-
-                    if let Ok(space) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_space(space_id)).await {
-                        if space.id != pool.assignment_space_id {
-                            let res = ApiResponse {
-                                error: Some("Assignment not found".to_string()),
-                                result: None,
-                            };
-                            return build_json_response(res, 404);
-                        }
-                    } else {
-                        let res = ApiResponse {
-                            error: Some("Assignment not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-                } else {
-                    let res = ApiResponse {
-                        error: Some("Assignment not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
 
-                if user.is_none() && assignment.assignment_visibility != ObjectVisibility::Public {
-                    let res = ApiResponse {
-                        error: Some("Assignment not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-                let res = ApiResponse {
-                    error: None,
-                    result: Some(ApiResponseVariant::AsnAssignment(assignment)),
-                };
-                build_json_response(res, 200)
-            },
-            Err(_) => {
-                let res = ApiResponse {
-                    error: Some("Assignment not found".to_string()),
-                    result: None,
-                };
-                build_json_response(res, 404)
-            },
+        let (space, pool, assignment) = match get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
         };
-        return res;
-    } else {
+        if !is_visible(&user, &[&assignment, &pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Assignment not found");
+        }
         let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+            error: None,
+            code: None,
+            result: Some(ApiResponseVariant::AsnAssignment(assignment)),
         };
-        return build_json_response(res, 500);
+        return build_json_response(res, 200);
+    } else {
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_assignment_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<MetadataUpdateRequest>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().update_assignment(assignment_id, &req.name, &req.description)).await {
             Ok(_) => {
                 if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.asn_assignments().get_assignment(assignment_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::AsnAssignment(assignment)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating assignment".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error updating assignment")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error updating assignment: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error updating assignment", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_asn_assignment_space_pool_assignment_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.asn_assignments().delete_assignment(assignment_id)).await {
             Ok(_) => {
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error deleting assignment: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error deleting assignment", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -0,0 +1,218 @@
+//!
+//! Endpoints for exports of the assignment data
+//! - `GET /api/v1/export/rpsl` - RPSL objects for public assignments, as plain text for submission to an IRR
+//! - `GET /api/v1/export/slurm` - ROAs as locally added assertions in an RFC 8416 SLURM file
+//! - `GET /api/v1/export/dns-reverse` - Reverse zones of all spaces, as names and BIND zone files
+//! - `GET /api/v1/export/dns-reverse/:zone` - One reverse zone as a BIND zone file; `/` in RFC 2317 names can be given as `-`
+//! - `GET /api/v1/export/dns-forward` - Forward records of hosts as BIND zone file fragments by domain, with their inconsistencies with the reverse DNS
+//! - `GET /api/v1/export/dns-forward/:domain` - The zone file fragment of one forward domain
+//! - `GET /api/v1/export/kea` - `subnet4` and `subnet6` lists of DHCP-enabled assignments for ISC Kea
+//!
+//! All endpoints require authentication.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::rpsl;
+use crate::roa;
+use crate::dns;
+use crate::dhcp;
+use crate::types::{Error, ErrorKind};
+use super::AuthHandler;
+use super::ApiResponse;
+use super::ApiResponseVariant;
+use super::build_json_response;
+use super::fallback_handler;
+use super::run_blocking_task;
+use super::{response_error, response_error_kind, response_internal_error};
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::get;
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Path as PathExtractor;
+
+use http::Response;
+
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/rpsl", get(export_rpsl::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/slurm", get(export_slurm::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/dns-reverse", get(export_dns_reverse::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/dns-reverse/:zone", get(export_dns_reverse_zone::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/dns-forward", get(export_dns_forward::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/dns-forward/:domain", get(export_dns_forward_domain::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/kea", get(export_kea::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+
+    router = router.fallback(fallback_handler());
+
+    router
+}
+
+fn build_text_response(text: String) -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(Body::from(text))
+        .unwrap()
+}
+
+async fn export_rpsl<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let config = ext.0.rpsl().clone();
+        match run_blocking_task(store.clone(), move |store| rpsl::export(&store, &config)).await {
+            Ok(text) => build_text_response(text),
+            Err(e) => response_error("Error exporting RPSL objects", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+async fn export_slurm<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        match run_blocking_task(store.clone(), |store| store.roas().get_roas()).await {
+            Ok(roas) => {
+                Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(serde_json::to_string_pretty(&roa::slurm(&roas)).unwrap() + "\n"))
+                    .unwrap()
+            },
+            Err(e) => response_error("Error exporting SLURM file", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+/// Reverse zones with the serial of their data as SOA serial
+async fn reverse_zones<T>(server: &Server<T>) -> Result<Vec<dns::Zone>, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let config = server.dns().clone();
+    run_blocking_task(server.store().clone(), move |store| {
+        let serial = store.reverse_dns().get_serial()?;
+        dns::reverse_zones(&store, &config, serial)
+    }).await
+}
+
+async fn export_dns_reverse<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        match reverse_zones(&ext.0).await {
+            Ok(zones) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::DnsZones(zones)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error generating reverse zones", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+async fn export_dns_reverse_zone<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(zone): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+        match reverse_zones(&ext.0).await {
+            Ok(zones) => match zones.into_iter().find(|found| found.name == zone || found.name.replace('/', "-") == zone) {
+                Some(found) => build_text_response(found.content),
+                None => response_error_kind(ErrorKind::NotFound, "Zone not found"),
+            },
+            Err(e) => response_error("Error generating reverse zones", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+async fn forward_dns<T>(server: &Server<T>) -> Result<dns::ForwardDns, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let config = server.dns().clone();
+    run_blocking_task(server.store().clone(), move |store| dns::forward_dns(&store, &config)).await
+}
+
+async fn export_dns_forward<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        match forward_dns(&ext.0).await {
+            Ok(forward) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::DnsForward(forward)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error generating forward records", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+async fn export_dns_forward_domain<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(domain): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        match forward_dns(&ext.0).await {
+            Ok(forward) => match forward.fragments.into_iter().find(|found| found.name == domain) {
+                Some(found) => build_text_response(found.content),
+                None => response_error_kind(ErrorKind::NotFound, "Domain not found"),
+            },
+            Err(e) => response_error("Error generating forward records", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
+
+async fn export_kea<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        match run_blocking_task(store.clone(), |store| dhcp::kea_config(&store)).await {
+            Ok(config) => {
+                Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(serde_json::to_string_pretty(&config).unwrap() + "\n"))
+                    .unwrap()
+            },
+            Err(e) => response_error("Error exporting Kea subnets", &e),
+        }
+    } else {
+        response_internal_error()
+    }
+}
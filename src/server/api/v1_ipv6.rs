@@ -5,25 +5,40 @@
 //! - `GET /api/v1/ipv6/assignment_space/:space_id` - Get an assignment space by ID
 //! - `PUT /api/v1/ipv6/assignment_space/:space_id` - Update metadata for an assignment space by ID
 //! - `DELETE /api/v1/ipv6/assignment_space/:space_id` - Delete an assignment space by ID
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/dns_update` - Get the dynamic DNS updates of an assignment space (authenticated)
+//! - `PUT /api/v1/ipv6/assignment_space/:space_id/dns_update` - Push reverse DNS changes in an assignment space to a DNS server
+//! - `DELETE /api/v1/ipv6/assignment_space/:space_id/dns_update` - Stop pushing reverse DNS changes in an assignment space
 //! - `GET /api/v1/ipv6/assignment_space/:space_id/pool` - List all pools in an assignment space
 //! - `POST /api/v1/ipv6/assignment_space/:space_id/pool` - Create a new pool in an assignment space
 //! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id` - Get a pool by ID
 //! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id` - Update metadata for a pool by ID
 //! - `DELETE /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id` - Delete a pool by ID
 //! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment` - List all assignments in a pool
-//! - `POST /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment` - Create a new assignment in a pool
+//! - `POST /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment` - Create a new assignment in a pool, optionally with its `reverse_dns`
 //! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id` - Get an assignment by ID
 //! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id` - Update metadata for an assignment by ID
+//! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/origin_asn` - Set or clear the origin ASN of an assignment
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/reverse_dns` - Get the reverse DNS of an assignment (authenticated)
+//! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/reverse_dns` - Replace the reverse DNS of an assignment
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp` - Get the DHCP service of an assignment, or `null` if it has none (authenticated)
+//! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp` - Enable DHCP for an assignment, or replace its settings
+//! - `DELETE /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp` - Disable DHCP for an assignment
 //! - `DELETE /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id` - Delete an assignment by ID
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host` - List all hosts in an assignment (authenticated)
+//! - `POST /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host` - Create a new host in an assignment
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/next_free` - Get the first free address in an assignment for a new host, or `null` if it is full (authenticated)
+//! - `GET /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id` - Get a host by ID (authenticated)
+//! - `PUT /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id` - Update a host by ID
+//! - `DELETE /api/v1/ipv6/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id` - Delete a host by ID
 //! 
-//! GET endpoints accept unauthenticated requests if object visibility is set to `ObjectVisibility::Public`.
+//! GET endpoints accept unauthenticated requests for objects that are public along with all their ancestors.
 
 
 
 
-use crate::store::DbConnection;
+use crate::store::{DbConnection, Store};
 use crate::server::Server;
-use crate::types::ObjectVisibility;
+use crate::types::ErrorKind;
 use super::AuthHandler;
 use super::fallback_handler;
 use super::build_json_response;
@@ -31,12 +46,20 @@ use super::User;
 use super::ApiResponseVariant;
 use super::ApiResponse;
 use super::MetadataUpdateRequest;
+use super::AssignmentCreateRequest;
+use super::OriginAsnUpdateRequest;
 use super::run_blocking_task;
+use crate::dns::{assignment_reverse_records, DnsUpdate, ReverseDns};
+use crate::dhcp::DhcpSettings;
+use crate::user::ResourceFamily;
+use super::is_visible;
+use super::{response_error, response_error_kind, response_internal_error};
 
 use crate::ipv6::{
     AssignmentSpaceIpv6,
     AssignmentPoolIpv6,
     AssignmentIpv6,
+    HostIpv6,
 };
 
 use axum::Router;
@@ -48,6 +71,116 @@ use axum::extract::Path as PathExtractor;
 
 use http::Response;
 
+use std::net::IpAddr;
+
+/// The space `space_id`, or a 404 response
+async fn get_space<T>(store: &Store<T>, space_id: i32) -> Result<AssignmentSpaceIpv6, Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
+        Ok(space) => Ok(space),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Err(response_error_kind(ErrorKind::NotFound, "Assignment space not found")),
+        Err(e) => Err(response_error("Error getting assignment space", &e)),
+    }
+}
+
+/// The space `space_id` and its pool `pool_id`, or a 404 response if the pool is not in that space
+async fn get_pool_in_space<T>(store: &Store<T>, space_id: i32, pool_id: i32) -> Result<(AssignmentSpaceIpv6, AssignmentPoolIpv6), Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let pool = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id)).await {
+        Ok(pool) if pool.assignment_space_id == space_id => pool,
+        Ok(_) => return Err(response_error_kind(ErrorKind::NotFound, "Pool not found")),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Err(response_error_kind(ErrorKind::NotFound, "Pool not found")),
+        Err(e) => return Err(response_error("Error getting pool", &e)),
+    };
+    let space = get_space(store, space_id).await?;
+    Ok((space, pool))
+}
+
+/// The space `space_id`, its pool `pool_id` and the assignment `assignment_id` in it, or a 404 response if they do not match
+async fn get_assignment_in_pool<T>(store: &Store<T>, space_id: i32, pool_id: i32, assignment_id: i32) -> Result<(AssignmentSpaceIpv6, AssignmentPoolIpv6, AssignmentIpv6), Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let (space, pool) = get_pool_in_space(store, space_id, pool_id).await?;
+    match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
+        Ok(assignment) if assignment.assignment_pool_id == pool_id => Ok((space, pool, assignment)),
+        Ok(_) => Err(response_error_kind(ErrorKind::NotFound, "Assignment not found")),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Err(response_error_kind(ErrorKind::NotFound, "Assignment not found")),
+        Err(e) => Err(response_error("Error getting assignment", &e)),
+    }
+}
+
+/// The host `host_id` of the assignment `assignment_id`, or a 404 response if they do not match
+async fn get_host_in_assignment<T>(store: &Store<T>, space_id: i32, pool_id: i32, assignment_id: i32, host_id: i32) -> Result<HostIpv6, Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    get_assignment_in_pool(store, space_id, pool_id, assignment_id).await?;
+    match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_host(host_id)).await {
+        Ok(host) if host.assignment_id == assignment_id => Ok(host),
+        Ok(_) => Err(response_error_kind(ErrorKind::NotFound, "Host not found")),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Err(response_error_kind(ErrorKind::NotFound, "Host not found")),
+        Err(e) => Err(response_error("Error getting host", &e)),
+    }
+}
+
+/// Push the change of the reverse DNS of an assignment from `before` to `after` to the DNS
+/// server of its space, if it has one
+async fn push_reverse_dns<T>(server: &Server<T>, space: &AssignmentSpaceIpv6, before: Option<(&AssignmentIpv6, &ReverseDns)>, after: Option<(&AssignmentIpv6, &ReverseDns)>)
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let space_len = space.ipv6_prefix_len as u8;
+    let space_prefix = IpAddr::from(crate::ipv6::ipv6_network_address(space.ipv6_prefix, space_len));
+    let records = |(assignment, reverse_dns): (&AssignmentIpv6, &ReverseDns)| {
+        let prefix_len = assignment.ipv6_prefix_len as u8;
+        let prefix = IpAddr::from(crate::ipv6::ipv6_network_address(assignment.ipv6_prefix, prefix_len));
+        assignment_reverse_records(space_prefix, space_len, prefix, prefix_len, &assignment.name, reverse_dns)
+    };
+    let before = before.map(records).unwrap_or_default();
+    let after = after.map(records).unwrap_or_default();
+    server.dns_updater().push(server.store(), ResourceFamily::Ipv6, space.id, before, after).await;
+}
+
+/// The assignments in the pool `pool_id`, or in all pools of the space `space_id`, that have
+/// reverse DNS, with it
+async fn get_reverse_dns_in_space<T>(store: &Store<T>, space_id: i32, pool_id: Option<i32>) -> Result<Vec<(AssignmentIpv6, ReverseDns)>, Response<Body>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let res = run_blocking_task(store.clone(), move |store| {
+        let pool_ids = match pool_id {
+            Some(pool_id) => vec![pool_id],
+            None => store.ipv6_assignments().get_pools(space_id)?.into_iter().map(|pool| pool.id).collect(),
+        };
+        let mut all_reverse_dns = store.reverse_dns().get_all_reverse_dns(ResourceFamily::Ipv6)?;
+        let mut assignments = Vec::new();
+        for pool_id in pool_ids {
+            for assignment in store.ipv6_assignments().get_assignments(pool_id)? {
+                if let Some(reverse_dns) = all_reverse_dns.remove(&assignment.id) {
+                    assignments.push((assignment, reverse_dns));
+                }
+            }
+        }
+        Ok::<_, crate::types::Error>(assignments)
+    }).await;
+    res.map_err(|e| response_error("Error getting reverse DNS", &e))
+}
+
+/// Push the removal of the reverse DNS of `assignments`, before they are deleted along with
+/// their pool or space
+async fn push_reverse_dns_removals<T>(server: &Server<T>, space: &AssignmentSpaceIpv6, assignments: &[(AssignmentIpv6, ReverseDns)])
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    for (assignment, reverse_dns) in assignments {
+        push_reverse_dns(server, space, Some((assignment, reverse_dns)), None).await;
+    }
+}
 
 async fn api_v1_ipv6_assignment_space_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
@@ -57,32 +190,19 @@ where
         let store = ext.0.store();
         let res = match run_blocking_task(store.clone(), |store| store.ipv6_assignments().get_spaces()).await {
             Ok(mut spaces) => {
-                if let None = user {
-                    spaces.retain(|space| {
-                        space.space_visibility == ObjectVisibility::Public
-                    });
-                }
+                spaces.retain(|space| is_visible(&user, &[space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::Ipv6AssignmentSpaces(spaces)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing assignment spaces: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing assignment spaces", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -97,32 +217,19 @@ where
                 if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::Ipv6AssignmentSpace(space)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error creating assignment space".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating assignment space")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error creating assignment space: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error creating assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -134,34 +241,22 @@ where
         let store = ext.0.store();
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
             Ok(space) => {
-                if user.is_none() && space.space_visibility != ObjectVisibility::Public {
-                    let res = ApiResponse {
-                        error: Some("Assignment space not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
+                if !is_visible(&user, &[&space]) {
+                    return response_error_kind(ErrorKind::NotFound, "Assignment space not found");
                 }
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::Ipv6AssignmentSpace(space)),
                 };
                 build_json_response(res, 200)
             },
-            Err(_) => {
-                let res = ApiResponse {
-                    error: Some("Assignment space not found".to_string()),
-                    result: None,
-                };
-                build_json_response(res, 404)
-            },
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound) => response_error_kind(ErrorKind::NotFound, "Assignment space not found"),
+            Err(e) => response_error("Error getting assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -176,32 +271,19 @@ where
                 if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::Ipv6AssignmentSpace(space)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating assignment space".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error updating assignment space")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error updating assignment space: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error updating assignment space", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -211,84 +293,148 @@ where
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        let space = match get_space(store, space_id).await {
+            Ok(space) => space,
+            Err(res) => return res,
+        };
+        let assignments = match get_reverse_dns_in_space(store, space_id, None).await {
+            Ok(assignments) => assignments,
+            Err(res) => return res,
+        };
+        // The settings of dynamic updates go away along with the space
+        push_reverse_dns_removals(&ext.0, &space, &assignments).await;
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().delete_space(space_id)).await {
             Ok(_) => {
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
+            Err(e) => response_error("Error deleting assignment space", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_dns_update_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(space_id): PathExtractor<i32>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_space(store, space_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.reverse_dns().get_dns_update(ResourceFamily::Ipv6, space_id)).await {
+            Ok(dns_update) => {
                 let res = ApiResponse {
-                    error: Some(format!("Error deleting assignment space: {}", e)),
-                    result: None,
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::DnsUpdate(dns_update)),
                 };
-                build_json_response(res, 500)
+                build_json_response(res, 200)
             },
+            Err(e) => response_error("Error getting dynamic DNS updates", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-async fn api_v1_ipv6_assignment_space_pool_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(space_id): PathExtractor<i32>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+async fn api_v1_ipv6_assignment_space_dns_update_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(space_id): PathExtractor<i32>, JsonExtractor(req): JsonExtractor<DnsUpdate>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
+        if !ext.0.dns_updater().has_key(&req.key) {
+            return response_error_kind(ErrorKind::Validation, "Unknown TSIG key; add it to dns.keys in the configuration");
+        }
         let store = ext.0.store();
+        if let Err(res) = get_space(store, space_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.reverse_dns().set_dns_update(ResourceFamily::Ipv6, space_id, Some(&req))).await {
+            Ok(_) => {
+                if let Ok(dns_update) = run_blocking_task(store.clone(), move |store| store.reverse_dns().get_dns_update(ResourceFamily::Ipv6, space_id)).await {
+                    let res = ApiResponse {
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::DnsUpdate(dns_update)),
+                    };
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating dynamic DNS updates")
+                }
+            },
+            Err(e) => response_error("Error updating dynamic DNS updates", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
 
-        let space_id_copy = space_id;
-        if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id_copy)).await {
-            if user.is_none() && space.space_visibility != ObjectVisibility::Public {
+async fn api_v1_ipv6_assignment_space_dns_update_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(space_id): PathExtractor<i32>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_space(store, space_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.reverse_dns().set_dns_update(ResourceFamily::Ipv6, space_id, None)).await {
+            Ok(_) => {
                 let res = ApiResponse {
-                    error: Some("Assignment space not found".to_string()),
+                    error: None,
+                    code: None,
                     result: None,
                 };
-                return build_json_response(res, 404);
-            }
-        } else {
-            let res = ApiResponse {
-                error: Some("Assignment space not found".to_string()),
-                result: None,
-            };
-            return build_json_response(res, 404);
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error deleting dynamic DNS updates", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(space_id): PathExtractor<i32>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+
+        let space = match get_space(store, space_id).await {
+            Ok(space) => space,
+            Err(res) => return res,
+        };
+        if !is_visible(&user, &[&space]) {
+            return response_error_kind(ErrorKind::NotFound, "Assignment space not found");
         }
 
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pools(space_id)).await {
             Ok(mut pools) => {
-                if let None = user {
-                    pools.retain(|pool| {
-                        pool.pool_visibility == ObjectVisibility::Public
-                    });
-                }
+                pools.retain(|pool| is_visible(&user, &[pool, &space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::Ipv6AssignmentPools(pools)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing pools: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing pools", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -297,11 +443,7 @@ where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if req.assignment_space_id != space_id {
-        let res = ApiResponse {
-            error: Some("Assignment space ID mismatch".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 400);
+        return response_error_kind(ErrorKind::InvalidInput, "Assignment space ID mismatch");
     }
     if let Some(ext) = ext {
         let store = ext.0.store();
@@ -310,36 +452,22 @@ where
                 if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::Ipv6AssignmentPool(pool)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error creating pool".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating pool")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error creating pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error creating pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_ipv6_assignment_space_pool_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
@@ -347,131 +475,86 @@ where
     if let Some(ext) = ext {
         let store = ext.0.store();
 
-        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id)).await {
-            Ok(pool) => {
-                let space_id = pool.assignment_space_id;
-                if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
-                    if user.is_none() && space.space_visibility != ObjectVisibility::Public {
-                        let res = ApiResponse {
-                            error: Some("Pool not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-                } else {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-
-                if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-                let res = ApiResponse {
-                    error: None,
-                    result: Some(ApiResponseVariant::Ipv6AssignmentPool(pool)),
-                };
-                build_json_response(res, 200)
-            },
-            Err(_) => {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                build_json_response(res, 404)
-            },
+        let (space, pool) = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
         };
-        return res;
-    } else {
+        if !is_visible(&user, &[&pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Pool not found");
+        }
         let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+            error: None,
+            code: None,
+            result: Some(ApiResponseVariant::Ipv6AssignmentPool(pool)),
         };
-        return build_json_response(res, 500);
+        return build_json_response(res, 200);
+    } else {
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_ipv6_assignment_space_pool_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, JsonExtractor(req): JsonExtractor<MetadataUpdateRequest>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        if let Err(res) = get_pool_in_space(store, space_id, pool_id).await {
+            return res;
+        }
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().update_pool(pool_id, &req.name, &req.description)).await {
             Ok(_) => {
                 if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id)).await {
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::Ipv6AssignmentPool(pool)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating pool".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error updating pool")
                 }
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error updating pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error updating pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_ipv6_assignment_space_pool_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
+        let space = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok((space, _)) => space,
+            Err(res) => return res,
+        };
+        let assignments = match get_reverse_dns_in_space(store, space_id, Some(pool_id)).await {
+            Ok(assignments) => assignments,
+            Err(res) => return res,
+        };
+        push_reverse_dns_removals(&ext.0, &space, &assignments).await;
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().delete_pool(pool_id)).await {
             Ok(_) => {
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error deleting pool: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 500)
-            },
+            Err(e) => response_error("Error deleting pool", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
 async fn api_v1_ipv6_assignment_space_pool_assignment_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
@@ -479,266 +562,499 @@ where
     if let Some(ext) = ext {
         let store = ext.0.store();
 
-        let pool_id_copy = pool_id;
-        if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id_copy)).await {
-            if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                return build_json_response(res, 404);
-            }
-
-            let space_id = pool.assignment_space_id;
-
-            if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
-                if space.id != pool.assignment_space_id {
-                    let res = ApiResponse {
-                        error: Some("Pool not found".to_string()),
-                        result: None,
-                    };
-                    return build_json_response(res, 404);
-                }
-            } else {
-                let res = ApiResponse {
-                    error: Some("Pool not found".to_string()),
-                    result: None,
-                };
-                return build_json_response(res, 404);
-            }
-        } else {
-            let res = ApiResponse {
-                error: Some("Pool not found".to_string()),
-                result: None,
-            };
-            return build_json_response(res, 404);
+        let (space, pool) = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        if !is_visible(&user, &[&pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Pool not found");
         }
 
         let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignments(pool_id)).await {
             Ok(mut assignments) => {
-                if let None = user {
-                    assignments.retain(|assignment| {
-                        assignment.assignment_visibility == ObjectVisibility::Public
-                    });
-                }
+                assignments.retain(|assignment| is_visible(&user, &[assignment, &pool, &space]));
                 let res = ApiResponse {
                     error: None,
+                    code: None,
                     result: Some(ApiResponseVariant::Ipv6Assignments(assignments)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
-                let res = ApiResponse {
-                    error: Some(format!("Error listing assignments: {}", e)),
-                    result: None,
-                };
-                build_json_response(res, 400)
-            },
+            Err(e) => response_error("Error listing assignments", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, JsonExtractor(req): JsonExtractor<AssignmentCreateRequest<AssignmentIpv6>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if req.assignment.assignment_pool_id != pool_id {
+        return response_error_kind(ErrorKind::InvalidInput, "Assignment pool ID mismatch");
+    }
+    let reverse_dns = match req.reverse_dns.as_ref().map(ReverseDns::normalize).transpose() {
+        Ok(reverse_dns) => reverse_dns.unwrap_or_default(),
+        Err(e) => return response_error("Error creating assignment", &e),
+    };
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let (space, _) = match get_pool_in_space(store, space_id, pool_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
         };
-        return build_json_response(res, 500);
+        let settings = reverse_dns.clone();
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().create_assignment_with_reverse_dns(&req.assignment, &settings)).await {
+            Ok(assignment_id) => {
+                if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
+                    push_reverse_dns(&ext.0, &space, None, Some((&assignment, &reverse_dns))).await;
+                    let res = ApiResponse {
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
+                    };
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error creating assignment")
+                }
+            },
+            Err(e) => response_error("Error creating assignment", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
-async fn api_v1_ipv6_assignment_space_pool_assignment_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id)): PathExtractor<(i32, i32)>, JsonExtractor(req): JsonExtractor<AssignmentIpv6>) -> Response<Body>
+async fn api_v1_ipv6_assignment_space_pool_assignment_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
-    if req.assignment_pool_id != pool_id {
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+
+        let (space, pool, assignment) = match get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        if !is_visible(&user, &[&assignment, &pool, &space]) {
+            return response_error_kind(ErrorKind::NotFound, "Assignment not found");
+        }
         let res = ApiResponse {
-            error: Some("Assignment pool ID mismatch".to_string()),
-            result: None,
+            error: None,
+            code: None,
+            result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
         };
-        return build_json_response(res, 400);
+        return build_json_response(res, 200);
+    } else {
+        return response_internal_error();
     }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<MetadataUpdateRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
     if let Some(ext) = ext {
         let store = ext.0.store();
-        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().create_assignment(&req)).await {
-            Ok(assignment_id) => {
+        let (space, _, before) = match get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        let reverse_dns = match run_blocking_task(store.clone(), move |store| store.reverse_dns().get_reverse_dns(ResourceFamily::Ipv6, assignment_id)).await {
+            Ok(reverse_dns) => reverse_dns,
+            Err(e) => return response_error("Error getting reverse DNS", &e),
+        };
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().update_assignment(assignment_id, &req.name, &req.description)).await {
+            Ok(_) => {
                 if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
+                    push_reverse_dns(&ext.0, &space, Some((&before, &reverse_dns)), Some((&assignment, &reverse_dns))).await;
                     let res = ApiResponse {
                         error: None,
+                        code: None,
                         result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
                     };
                     build_json_response(res, 200)
                 } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating assignment")
+                }
+            },
+            Err(e) => response_error("Error updating assignment", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_origin_asn_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<OriginAsnUpdateRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().update_assignment_origin_asn(assignment_id, req.origin_asn_id)).await {
+            Ok(_) => {
+                if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
                     let res = ApiResponse {
-                        error: Some("Error creating assignment".to_string()),
-                        result: None,
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
                     };
-                    build_json_response(res, 500)
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating assignment")
                 }
             },
-            Err(e) => {
+            Err(e) => response_error("Error updating assignment", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_reverse_dns_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.reverse_dns().get_reverse_dns(ResourceFamily::Ipv6, assignment_id)).await {
+            Ok(reverse_dns) => {
                 let res = ApiResponse {
-                    error: Some(format!("Error creating assignment: {}", e)),
-                    result: None,
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::ReverseDns(reverse_dns)),
                 };
-                build_json_response(res, 500)
+                build_json_response(res, 200)
             },
+            Err(e) => response_error("Error getting reverse DNS", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
-async fn api_v1_ipv6_assignment_space_pool_assignment_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+async fn api_v1_ipv6_assignment_space_pool_assignment_reverse_dns_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<ReverseDns>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
-        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
-            Ok(assignment) => {
-                let pool_id = assignment.assignment_pool_id;
-                if let Ok(pool) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_pool(pool_id)).await {
-                    if user.is_none() && pool.pool_visibility != ObjectVisibility::Public {
-                        let res = ApiResponse {
-                            error: Some("Assignment not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-
-                    let space_id = pool.assignment_space_id;
-
-                    if let Ok(space) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_space(space_id)).await {
-                        if space.id != pool.assignment_space_id {
-                            let res = ApiResponse {
-                                error: Some("Assignment not found".to_string()),
-                                result: None,
-                            };
-                            return build_json_response(res, 404);
-                        }
-                    } else {
-                        let res = ApiResponse {
-                            error: Some("Assignment not found".to_string()),
-                            result: None,
-                        };
-                        return build_json_response(res, 404);
-                    }
-                } else {
+        let (space, _, assignment) = match get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        let before = match run_blocking_task(store.clone(), move |store| store.reverse_dns().get_reverse_dns(ResourceFamily::Ipv6, assignment_id)).await {
+            Ok(reverse_dns) => reverse_dns,
+            Err(e) => return response_error("Error getting reverse DNS", &e),
+        };
+        let res = match run_blocking_task(store.clone(), move |store| store.reverse_dns().set_reverse_dns(ResourceFamily::Ipv6, assignment_id, &req)).await {
+            Ok(_) => {
+                if let Ok(reverse_dns) = run_blocking_task(store.clone(), move |store| store.reverse_dns().get_reverse_dns(ResourceFamily::Ipv6, assignment_id)).await {
+                    push_reverse_dns(&ext.0, &space, Some((&assignment, &before)), Some((&assignment, &reverse_dns))).await;
                     let res = ApiResponse {
-                        error: Some("Assignment not found".to_string()),
-                        result: None,
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::ReverseDns(reverse_dns)),
                     };
-                    return build_json_response(res, 404);
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating reverse DNS")
                 }
+            },
+            Err(e) => response_error("Error updating reverse DNS", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
 
-                if user.is_none() && assignment.assignment_visibility != ObjectVisibility::Public {
+async fn api_v1_ipv6_assignment_space_pool_assignment_dhcp_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.dhcp().get_dhcp(ResourceFamily::Ipv6, assignment_id)).await {
+            Ok(dhcp) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Dhcp(dhcp)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error getting DHCP settings", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_dhcp_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<DhcpSettings>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.dhcp().set_dhcp(ResourceFamily::Ipv6, assignment_id, Some(&req))).await {
+            Ok(_) => {
+                if let Ok(dhcp) = run_blocking_task(store.clone(), move |store| store.dhcp().get_dhcp(ResourceFamily::Ipv6, assignment_id)).await {
                     let res = ApiResponse {
-                        error: Some("Assignment not found".to_string()),
-                        result: None,
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Dhcp(dhcp)),
                     };
-                    return build_json_response(res, 404);
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating DHCP settings")
                 }
+            },
+            Err(e) => response_error("Error updating DHCP settings", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_dhcp_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.dhcp().set_dhcp(ResourceFamily::Ipv6, assignment_id, None)).await {
+            Ok(_) => {
                 let res = ApiResponse {
                     error: None,
-                    result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
+                    code: None,
+                    result: None,
                 };
                 build_json_response(res, 200)
             },
-            Err(_) => {
+            Err(e) => response_error("Error disabling DHCP", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let (space, _, assignment) = match get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            Ok(found) => found,
+            Err(res) => return res,
+        };
+        let reverse_dns = match run_blocking_task(store.clone(), move |store| store.reverse_dns().get_reverse_dns(ResourceFamily::Ipv6, assignment_id)).await {
+            Ok(reverse_dns) => reverse_dns,
+            Err(e) => return response_error("Error getting reverse DNS", &e),
+        };
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().delete_assignment(assignment_id)).await {
+            Ok(_) => {
+                push_reverse_dns(&ext.0, &space, Some((&assignment, &reverse_dns)), None).await;
                 let res = ApiResponse {
-                    error: Some("Assignment not found".to_string()),
+                    error: None,
+                    code: None,
                     result: None,
                 };
-                build_json_response(res, 404)
+                build_json_response(res, 200)
             },
+            Err(e) => response_error("Error deleting assignment", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_hosts(assignment_id)).await {
+            Ok(hosts) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Ipv6Hosts(hosts)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error getting hosts", &e),
         };
-        return build_json_response(res, 500);
+        return res;
+    } else {
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
-async fn api_v1_ipv6_assignment_space_pool_assignment_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<MetadataUpdateRequest>) -> Response<Body>
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>, JsonExtractor(req): JsonExtractor<HostIpv6>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
-        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().update_assignment(assignment_id, &req.name, &req.description)).await {
-            Ok(_) => {
-                if let Ok(assignment) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_assignment(assignment_id)).await {
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let mut host = req;
+        host.assignment_id = assignment_id;
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().create_host(&host)).await {
+            Ok(host_id) => {
+                if let Ok(host) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_host(host_id)).await {
                     let res = ApiResponse {
                         error: None,
-                        result: Some(ApiResponseVariant::Ipv6Assignment(assignment)),
+                        code: None,
+                        result: Some(ApiResponseVariant::Ipv6Host(host)),
                     };
                     build_json_response(res, 200)
                 } else {
-                    let res = ApiResponse {
-                        error: Some("Error updating assignment".to_string()),
-                        result: None,
-                    };
-                    build_json_response(res, 500)
+                    response_error_kind(ErrorKind::InternalError, "Error creating host")
                 }
             },
-            Err(e) => {
+            Err(e) => response_error("Error creating host", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_next_free<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_assignment_in_pool(store, space_id, pool_id, assignment_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().next_free_host(assignment_id)).await {
+            Ok(address) => {
                 let res = ApiResponse {
-                    error: Some(format!("Error updating assignment: {}", e)),
-                    result: None,
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Ipv6Address(address)),
                 };
-                build_json_response(res, 500)
+                build_json_response(res, 200)
             },
+            Err(e) => response_error("Error finding a free address", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
-#[allow(unused_variables)]
-async fn api_v1_ipv6_assignment_space_pool_assignment_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id)): PathExtractor<(i32, i32, i32)>) -> Response<Body>
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id, host_id)): PathExtractor<(i32, i32, i32, i32)>) -> Response<Body>
 where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     if let Some(ext) = ext {
         let store = ext.0.store();
-        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().delete_assignment(assignment_id)).await {
-            Ok(_) => {
+        let res = match get_host_in_assignment(store, space_id, pool_id, assignment_id, host_id).await {
+            Ok(host) => {
                 let res = ApiResponse {
                     error: None,
-                    result: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Ipv6Host(host)),
                 };
                 build_json_response(res, 200)
             },
-            Err(e) => {
+            Err(res) => res,
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id, host_id)): PathExtractor<(i32, i32, i32, i32)>, JsonExtractor(req): JsonExtractor<HostIpv6>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_host_in_assignment(store, space_id, pool_id, assignment_id, host_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().update_host(host_id, &req)).await {
+            Ok(_) => {
+                if let Ok(host) = run_blocking_task(store.clone(), move |store| store.ipv6_assignments().get_host(host_id)).await {
+                    let res = ApiResponse {
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Ipv6Host(host)),
+                    };
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating host")
+                }
+            },
+            Err(e) => response_error("Error updating host", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_ipv6_assignment_space_pool_assignment_host_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((space_id, pool_id, assignment_id, host_id)): PathExtractor<(i32, i32, i32, i32)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(res) = get_host_in_assignment(store, space_id, pool_id, assignment_id, host_id).await {
+            return res;
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.ipv6_assignments().delete_host(host_id)).await {
+            Ok(_) => {
                 let res = ApiResponse {
-                    error: Some(format!("Error deleting assignment: {}", e)),
+                    error: None,
+                    code: None,
                     result: None,
                 };
-                build_json_response(res, 500)
+                build_json_response(res, 200)
             },
+            Err(e) => response_error("Error deleting host", &e),
         };
         return res;
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
-        };
-        return build_json_response(res, 500);
+        return response_internal_error();
     }
 }
 
@@ -753,6 +1069,9 @@ where
     router = router.route("/assignment_space/:space_id", get(api_v1_ipv6_assignment_space_get::<T>).layer(AuthHandler::<T>::new_layer()));
     router = router.route("/assignment_space/:space_id", put(api_v1_ipv6_assignment_space_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
     router = router.route("/assignment_space/:space_id", delete(api_v1_ipv6_assignment_space_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/dns_update", get(api_v1_ipv6_assignment_space_dns_update_get::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/dns_update", put(api_v1_ipv6_assignment_space_dns_update_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/dns_update", delete(api_v1_ipv6_assignment_space_dns_update_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
 
     router = router.route("/assignment_space/:space_id/pool", get(api_v1_ipv6_assignment_space_pool_list::<T>).layer(AuthHandler::<T>::new_layer()));
     router = router.route("/assignment_space/:space_id/pool", post(api_v1_ipv6_assignment_space_pool_create::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
@@ -765,6 +1084,18 @@ where
     router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id", get(api_v1_ipv6_assignment_space_pool_assignment_get::<T>).layer(AuthHandler::<T>::new_layer()));
     router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id", put(api_v1_ipv6_assignment_space_pool_assignment_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
     router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id", delete(api_v1_ipv6_assignment_space_pool_assignment_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/origin_asn", put(api_v1_ipv6_assignment_space_pool_assignment_origin_asn_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/reverse_dns", get(api_v1_ipv6_assignment_space_pool_assignment_reverse_dns_get::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/reverse_dns", put(api_v1_ipv6_assignment_space_pool_assignment_reverse_dns_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp", get(api_v1_ipv6_assignment_space_pool_assignment_dhcp_get::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp", put(api_v1_ipv6_assignment_space_pool_assignment_dhcp_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/dhcp", delete(api_v1_ipv6_assignment_space_pool_assignment_dhcp_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host", get(api_v1_ipv6_assignment_space_pool_assignment_host_list::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host", post(api_v1_ipv6_assignment_space_pool_assignment_host_create::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/next_free", get(api_v1_ipv6_assignment_space_pool_assignment_host_next_free::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id", get(api_v1_ipv6_assignment_space_pool_assignment_host_get::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id", put(api_v1_ipv6_assignment_space_pool_assignment_host_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/assignment_space/:space_id/pool/:pool_id/assignment/:assignment_id/host/:host_id", delete(api_v1_ipv6_assignment_space_pool_assignment_host_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
 
     router = router.fallback(fallback_handler());
     router
@@ -1,49 +1,199 @@
 
 use super::build_json_response;
+use super::client_address;
+use super::{response_error, response_unauthorized};
 use crate::server::Server;
 use crate::store::DbConnection;
 use super::ApiResponse;
 use super::ApiResponseVariant;
+use super::LoginMethods;
+use super::User;
+use crate::user::{ApiToken, TokenScope, unix_time_now};
+use crate::user::{LoginAttemptStart, LoginOutcome};
+use crate::types::{Error, ErrorKind};
 
+use axum::body::Body;
 use axum::response::IntoResponse;
 use axum::extract::State as StateExtractor;
 use axum::extract::Json as JsonExtractor;
+use axum::extract::Extension as ExtensionExtractor;
+
+use http::{Extensions, HeaderMap, Response};
+use http::header::RETRY_AFTER;
 
 use serde::{Serialize, Deserialize};
 
 
+/// Lifetime of tokens issued by the login endpoint, in seconds
+pub const LOGIN_TOKEN_LIFETIME: i64 = 60 * 60 * 24;
+
 #[derive(Serialize, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+
+    /// TOTP or recovery code, required for users with two-factor authentication
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+enum LoginResult {
+    Success(String),
+    Failure,
+    TotpRequired,
+    Throttled(i64),
+    Unavailable,
+    Error(Error),
 }
 
-pub async fn api_v1_login<T: DbConnection + Clone + Send + Sync>(StateExtractor(s): StateExtractor<Server<T>>, JsonExtractor(req): JsonExtractor<LoginRequest>) -> impl IntoResponse {
+pub async fn api_v1_login<T: DbConnection + Clone + Send + Sync>(StateExtractor(s): StateExtractor<Server<T>>, extensions: Extensions, headers: HeaderMap, JsonExtractor(req): JsonExtractor<LoginRequest>) -> impl IntoResponse {
     let store = s.store().clone();
+    let source = client_address(&s, &extensions, &headers).map(|addr| addr.to_string());
+
+    let result = tokio::task::spawn_blocking(move || {
+        let users = store.users();
+        let source = source.as_deref();
+        let now = unix_time_now();
+
+        // Checked and counted before the (expensive) password verification
+        let attempt_id = match users.begin_login_attempt(&req.username, source) {
+            Ok(LoginAttemptStart::Allowed(attempt_id)) => attempt_id,
+            Ok(LoginAttemptStart::Throttled(lockout)) => {
+                log::warn!("Throttled login attempt for {} from {}", req.username, source.unwrap_or("unknown source"));
+                return LoginResult::Throttled(lockout.blocked_until - now);
+            },
+            // Fail closed: without throttling, passwords could be guessed at full speed.
+            Err(e) => {
+                log::error!("Error checking login throttling: {}", e);
+                return LoginResult::Unavailable;
+            },
+        };
+        let finish_attempt = |outcome| {
+            if let Err(e) = users.finish_login_attempt(attempt_id, outcome) {
+                log::error!("Error recording login attempt: {}", e);
+            }
+        };
+
+        // Errors on our side (such as an LDAP outage) are not the user's failures.
+        let error = |e| {
+            finish_attempt(None);
+            LoginResult::Error(e)
+        };
 
-    let token = tokio::task::spawn_blocking(move || {
-        if let Ok(true) = store.users().check_password(&req.username, &req.password) {
-            store.users().generate_api_key(&req.username).ok()
-        } else {
-            None
+        let password_ok = match users.check_password(&req.username, &req.password) {
+            Ok(ok) => ok,
+            // Unknown users and unusable password hashes
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::InvalidInput) => false,
+            Err(e) => return error(e),
+        };
+        let second_factor_ok = password_ok && match users.get_totp_status(&req.username) {
+            Ok(status) if status.enabled => match req.totp_code.as_deref() {
+                Some(code) => match users.verify_second_factor(&req.username, code) {
+                    Ok(ok) => ok,
+                    Err(e) => return error(e),
+                },
+                // Not a failure: the client is asked for the code in a second step.
+                None => {
+                    finish_attempt(None);
+                    return LoginResult::TotpRequired;
+                },
+            },
+            Ok(_) => true,
+            Err(e) => return error(e),
+        };
+        if !second_factor_ok {
+            finish_attempt(Some(LoginOutcome::Failure));
+            return LoginResult::Failure;
+        }
+
+        let expires_at = now + LOGIN_TOKEN_LIFETIME;
+        match users.create_api_token(&req.username, "login", Some(expires_at), &TokenScope::default()) {
+            Ok(token) => {
+                finish_attempt(Some(LoginOutcome::Success));
+                LoginResult::Success(token.secret)
+            },
+            Err(e) => error(e),
         }
     }).await.unwrap();
 
-    let (status, response) = match token {
-        Some(token) => (200, ApiResponse {
+    let (status, response) = match &result {
+        LoginResult::Success(token) => (200, ApiResponse {
             error: None,
-            result: Some(ApiResponseVariant::ApiToken(token)),
+            code: None,
+            result: Some(ApiResponseVariant::ApiToken(token.clone())),
         }),
-        None => (401, ApiResponse {
+        LoginResult::Failure => (401, ApiResponse {
             error: Some("Unauthorized".to_string()),
+            code: Some(ErrorKind::Unauthorized),
+            result: None,
+        }),
+        LoginResult::TotpRequired => (401, ApiResponse {
+            error: Some("Two-factor authentication code required".to_string()),
+            code: Some(ErrorKind::Unauthorized),
+            result: Some(ApiResponseVariant::TotpRequired(true)),
+        }),
+        LoginResult::Throttled(retry_after) => return response_throttled(*retry_after),
+        LoginResult::Unavailable => (503, ApiResponse {
+            error: Some("Login is temporarily unavailable".to_string()),
+            code: Some(ErrorKind::DatabaseError),
             result: None,
         }),
+        LoginResult::Error(e) => return response_error("Error logging in", e),
     };
 
-    let response = build_json_response(
+    build_json_response(
         response,
         status,
-    );
+    )
+}
 
+/// Response to a password check refused for `retry_after` seconds by login throttling
+pub(super) fn response_throttled(retry_after: i64) -> Response<Body> {
+    let mut response = build_json_response(ApiResponse {
+        error: Some(format!("Too many failed login attempts, try again in {} seconds", retry_after)),
+        code: Some(ErrorKind::RateLimited),
+        result: None,
+    }, 429);
+    response.headers_mut().insert(RETRY_AFTER, retry_after.into());
     response
 }
+
+/// Login methods offered to the frontend
+pub async fn api_v1_login_methods<T: DbConnection + Clone + Send + Sync>(StateExtractor(s): StateExtractor<Server<T>>) -> impl IntoResponse {
+    let methods = LoginMethods {
+        password: true,
+        oidc: s.oidc().is_some(),
+    };
+    build_json_response(ApiResponse {
+        error: None,
+        code: None,
+        result: Some(ApiResponseVariant::LoginMethods(methods)),
+    }, 200)
+}
+
+/// Revoke the token used to authenticate this request
+pub async fn api_v1_logout<T: DbConnection + Clone + Send + Sync>(StateExtractor(s): StateExtractor<Server<T>>, user: Option<ExtensionExtractor<User>>, token: Option<ExtensionExtractor<ApiToken>>) -> impl IntoResponse {
+    let (user, token) = match (user, token) {
+        (Some(user), Some(token)) => (user.0, token.0),
+        _ => return response_unauthorized(),
+    };
+
+    let store = s.store().clone();
+    let result = tokio::task::spawn_blocking(move || {
+        store.users().revoke_api_token(&user.username, token.id)
+    }).await.unwrap();
+
+    let (status, response) = match result {
+        Ok(_) => (200, ApiResponse {
+            error: None,
+            code: None,
+            result: None,
+        }),
+        Err(e) => return response_error("Error logging out", &e),
+    };
+
+    build_json_response(
+        response,
+        status,
+    )
+}
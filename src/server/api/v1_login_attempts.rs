@@ -0,0 +1,153 @@
+//!
+//! Endpoints for login attempts and lockouts
+//! - `GET /api/v1/login_attempts?username=&source=&limit=` - List recent login attempts, newest first
+//! - `GET /api/v1/login_attempts/lockouts` - List usernames and sources that are currently throttled or locked out
+//! - `DELETE /api/v1/login_attempts/lockouts?username=` or `?source=` - Clear the failures (and lockout) of a username or source
+//!
+//! All endpoints require an admin.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::types::ErrorKind;
+use crate::user::{LoginThrottleKey, LoginThrottlePolicy, unix_time_now};
+use super::AuthHandler;
+use super::fallback_handler;
+use super::build_json_response;
+use super::ApiResponseVariant;
+use super::ApiResponse;
+use super::run_blocking_task;
+use super::{response_error, response_error_kind, response_internal_error};
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::{get, delete};
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Query as QueryExtractor;
+
+use http::Response;
+
+use serde::{Serialize, Deserialize};
+
+
+/// Default and maximum number of attempts returned
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoginAttemptQuery {
+    pub username: Option<String>,
+    pub source: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockoutQuery {
+    pub username: Option<String>,
+    pub source: Option<String>,
+}
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/", get(login_attempts_list::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/lockouts", get(lockouts_list::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/lockouts", delete(lockouts_clear::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+
+    router = router.fallback(fallback_handler());
+
+    router
+}
+
+async fn login_attempts_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, QueryExtractor(query): QueryExtractor<LoginAttemptQuery>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        let res = match run_blocking_task(store.clone(), move |store| store.users().list_login_attempts(query.username.as_deref(), query.source.as_deref(), limit)).await {
+            Ok(attempts) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::LoginAttempts(attempts)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error listing login attempts", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn lockouts_list<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| {
+            let now = unix_time_now();
+            let since = LoginThrottlePolicy::USERNAME.window_start(now).min(LoginThrottlePolicy::SOURCE.window_start(now));
+            let failures = store.users().list_login_failures(since)?;
+            let lockouts = failures.iter().filter_map(|failures| {
+                let policy = LoginThrottlePolicy::for_key(&failures.key);
+                // Failures outside this policy's window may have been fetched for the other policy.
+                if failures.last_failure_at <= policy.window_start(now) {
+                    return None;
+                }
+                policy.lockout(failures, now)
+            }).collect::<Vec<_>>();
+            Ok::<_, crate::types::Error>(lockouts)
+        }).await {
+            Ok(lockouts) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::LoginLockouts(lockouts)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error listing lockouts", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn lockouts_clear<T>(ext: Option<ExtensionExtractor<Server<T>>>, QueryExtractor(query): QueryExtractor<LockoutQuery>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let key = match (query.username, query.source) {
+        (Some(username), None) => LoginThrottleKey::Username(username),
+        (None, Some(source)) => LoginThrottleKey::Source(source),
+        _ => {
+            return response_error_kind(ErrorKind::InvalidInput, "Exactly one of username or source is required");
+        },
+    };
+
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().clear_login_failures(&key)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error clearing lockout", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
@@ -0,0 +1,199 @@
+//!
+//! Endpoints for OpenID Connect login
+//! - `GET /api/v1/oidc/login` - Redirect to the provider to log in
+//! - `GET /api/v1/oidc/callback` - Redirect target of the provider; issues a login token and
+//!   redirects to the frontend with the token in the URL fragment (`/login/#api_token=...`)
+//!
+//! The login sets a cookie with its state, which the callback requires, so that a callback
+//! URL only completes the login in the browser that started it.
+//!
+//! Both respond with 404 if OpenID Connect is not configured.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::server::oidc::PENDING_LOGIN_LIFETIME;
+use crate::user::{LoginOutcome, TokenScope, unix_time_now};
+use super::client_address;
+use super::fallback_handler;
+use super::response_not_found;
+use super::v1_login::LOGIN_TOKEN_LIFETIME;
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::get;
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Query as QueryExtractor;
+
+use http::{Extensions, HeaderMap, HeaderValue, Response};
+use http::header::{COOKIE, LOCATION, SET_COOKIE};
+
+use openidconnect::url::form_urlencoded;
+
+use serde::{Serialize, Deserialize};
+
+
+/// Frontend route that receives the result of a login
+const FRONTEND_LOGIN_PATH: &str = "/login/";
+
+/// Cookie with the state of the login started by the browser
+const STATE_COOKIE: &str = "mirams_oidc_state";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/login", get(oidc_login::<T>));
+    router = router.route("/callback", get(oidc_callback::<T>));
+
+    router = router.fallback(fallback_handler());
+
+    router
+}
+
+fn redirect(location: &str) -> Response<Body> {
+    Response::builder()
+        .status(302)
+        .header(LOCATION, location)
+        .header("Cache-Control", "no-store")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Redirect to the frontend with `params` in the URL fragment, which is not sent to servers
+fn redirect_to_frontend(params: &[(&str, &str)]) -> Response<Body> {
+    let fragment = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish();
+    redirect(&format!("{}#{}", FRONTEND_LOGIN_PATH, fragment))
+}
+
+/// `Set-Cookie` value for the state cookie; an empty `state` with no `max_age` removes it
+fn state_cookie(state: &str, max_age: u64, secure: bool) -> HeaderValue {
+    let mut cookie = format!("{}={}; Path=/api/v1/oidc; Max-Age={}; HttpOnly; SameSite=Lax", STATE_COOKIE, state, max_age);
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    // The state is URL-safe base64.
+    HeaderValue::from_str(&cookie).unwrap()
+}
+
+/// Value of the cookie `name` sent with a request
+fn request_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get_all(COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+async fn oidc_login<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let provider = match ext.as_ref().and_then(|ext| ext.0.oidc()) {
+        Some(provider) => provider.clone(),
+        None => return response_not_found(),
+    };
+
+    match provider.begin_login().await {
+        Ok((url, state)) => {
+            let secure = provider.config().redirect_url.starts_with("https:");
+            let mut res = redirect(&url);
+            res.headers_mut().insert(SET_COOKIE, state_cookie(&state, PENDING_LOGIN_LIFETIME.as_secs(), secure));
+            res
+        },
+        Err(e) => {
+            log::error!("Failed to start OIDC login: {}", e);
+            redirect_to_frontend(&[("error", "Single sign-on is currently unavailable")])
+        },
+    }
+}
+
+async fn oidc_callback<T>(ext: Option<ExtensionExtractor<Server<T>>>, extensions: Extensions, headers: HeaderMap, QueryExtractor(query): QueryExtractor<CallbackQuery>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let secure = ext.as_ref()
+        .and_then(|ext| ext.0.oidc())
+        .is_some_and(|provider| provider.config().redirect_url.starts_with("https:"));
+    let mut res = oidc_callback_response(ext, &extensions, &headers, query).await;
+    res.headers_mut().insert(SET_COOKIE, state_cookie("", 0, secure));
+    res
+}
+
+async fn oidc_callback_response<T>(ext: Option<ExtensionExtractor<Server<T>>>, extensions: &Extensions, headers: &HeaderMap, query: CallbackQuery) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let server = match ext {
+        Some(ext) => ext.0,
+        None => return response_not_found(),
+    };
+    let provider = match server.oidc() {
+        Some(provider) => provider.clone(),
+        None => return response_not_found(),
+    };
+
+    if let Some(error) = query.error {
+        log::warn!("OIDC provider returned an error: {} {}", error, query.error_description.unwrap_or_default());
+        return redirect_to_frontend(&[("error", "Single sign-on failed")]);
+    }
+    let (code, state) = match (query.code, query.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return redirect_to_frontend(&[("error", "Single sign-on failed")]),
+    };
+    // Checked before the state is used up, so that a leaked callback URL cannot be completed elsewhere
+    if request_cookie(headers, STATE_COOKIE).as_deref() != Some(state.as_str()) {
+        log::warn!("OIDC callback without the state cookie of its login");
+        return redirect_to_frontend(&[("error", "Single sign-on failed")]);
+    }
+
+    let identity = match provider.finish_login(&code, &state).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::warn!("OIDC login failed: {}", e);
+            return redirect_to_frontend(&[("error", "Single sign-on failed")]);
+        },
+    };
+
+    let source = client_address(&server, extensions, headers).map(|addr| addr.to_string());
+    let store = server.store().clone();
+    let claimed = identity.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let users = store.users();
+        // Attempts that match no linked user are recorded under the claimed username.
+        let synced = provider.sync_user(&store, &identity);
+        let username = match &synced {
+            Ok(username) => username.clone(),
+            Err(_) => identity.username.clone(),
+        };
+        let token = synced.and_then(|username| {
+            let expires_at = unix_time_now() + LOGIN_TOKEN_LIFETIME;
+            users.create_api_token(&username, "login", Some(expires_at), &TokenScope::default())
+        });
+        let outcome = if token.is_ok() { LoginOutcome::Success } else { LoginOutcome::Failure };
+        if let Err(e) = users.record_login_attempt(&username, source.as_deref(), outcome) {
+            log::error!("Error recording login attempt: {}", e);
+        }
+        token.map(|token| (username, token))
+    }).await.unwrap();
+
+    match result {
+        Ok((username, token)) => redirect_to_frontend(&[("api_token", &token.secret), ("username", &username)]),
+        Err(e) => {
+            log::warn!("OIDC login of {} (issuer {}, subject {}) refused: {}", claimed.username, claimed.issuer, claimed.subject, e);
+            redirect_to_frontend(&[("error", "You are not allowed to use MIRAMS")])
+        },
+    }
+}
@@ -0,0 +1,199 @@
+//!
+//! Endpoints for Route Origin Authorisations
+//! - `GET /api/v1/roa` - List all ROAs
+//! - `POST /api/v1/roa` - Create a new ROA
+//! - `GET /api/v1/roa/coverage` - List IPv4 and IPv6 assignments without a ROA
+//! - `GET /api/v1/roa/:roa_id` - Get a ROA by ID
+//! - `PUT /api/v1/roa/:roa_id` - Replace a ROA by ID
+//! - `DELETE /api/v1/roa/:roa_id` - Delete a ROA by ID
+//!
+//! All endpoints require authentication.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::types::ErrorKind;
+use crate::roa::{self, Roa};
+use super::AuthHandler;
+use super::fallback_handler;
+use super::build_json_response;
+use super::ApiResponseVariant;
+use super::ApiResponse;
+use super::run_blocking_task;
+use super::{response_error, response_error_kind, response_internal_error};
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::{get, post, put, delete};
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Json as JsonExtractor;
+use axum::extract::Path as PathExtractor;
+
+use http::Response;
+
+
+async fn api_v1_roa_list<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), |store| store.roas().get_roas()).await {
+            Ok(roas) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Roas(roas)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error listing ROAs", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_roa_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, JsonExtractor(req): JsonExtractor<Roa>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.roas().create_roa(&req)).await {
+            Ok(roa_id) => {
+                if let Ok(roa) = run_blocking_task(store.clone(), move |store| store.roas().get_roa(roa_id)).await {
+                    let res = ApiResponse {
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Roa(roa)),
+                    };
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error creating ROA")
+                }
+            },
+            Err(e) => response_error("Error creating ROA", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_roa_coverage<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), |store| roa::coverage(&store)).await {
+            Ok(uncovered) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::UncoveredAssignments(uncovered)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error checking ROA coverage", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_roa_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(roa_id): PathExtractor<i32>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.roas().get_roa(roa_id)).await {
+            Ok(roa) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::Roa(roa)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error getting ROA", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_roa_update<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(roa_id): PathExtractor<i32>, JsonExtractor(req): JsonExtractor<Roa>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.roas().update_roa(roa_id, &req)).await {
+            Ok(_) => {
+                if let Ok(roa) = run_blocking_task(store.clone(), move |store| store.roas().get_roa(roa_id)).await {
+                    let res = ApiResponse {
+                        error: None,
+                        code: None,
+                        result: Some(ApiResponseVariant::Roa(roa)),
+                    };
+                    build_json_response(res, 200)
+                } else {
+                    response_error_kind(ErrorKind::InternalError, "Error updating ROA")
+                }
+            },
+            Err(e) => response_error("Error updating ROA", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn api_v1_roa_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(roa_id): PathExtractor<i32>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        if let Err(e) = run_blocking_task(store.clone(), move |store| store.roas().get_roa(roa_id)).await {
+            return response_error("Error deleting ROA", &e);
+        }
+        let res = match run_blocking_task(store.clone(), move |store| store.roas().delete_roa(roa_id)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error deleting ROA", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/", get(api_v1_roa_list::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/", post(api_v1_roa_create::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/coverage", get(api_v1_roa_coverage::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/:roa_id", get(api_v1_roa_get::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/:roa_id", put(api_v1_roa_update::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/:roa_id", delete(api_v1_roa_delete::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+
+    router = router.fallback(fallback_handler());
+
+    router
+}
@@ -1,20 +1,73 @@
+//!
+//! Endpoints for the user API
+//! - `GET /api/v1/user/self` - Get the current user
+//! - `PUT /api/v1/user/self/password` - Change the password of the current user, revoking all other sessions
+//! - `GET /api/v1/user/self/totp` - Get the two-factor authentication status of the current user
+//! - `POST /api/v1/user/self/totp` - Start TOTP enrolment, returning the secret and otpauth URI
+//! - `POST /api/v1/user/self/totp/confirm` - Confirm TOTP enrolment with a code, returning recovery codes
+//! - `POST /api/v1/user/self/totp/recovery_codes` - Replace the recovery codes (requires the password)
+//! - `POST /api/v1/user/self/totp/disable` - Disable two-factor authentication (requires the password)
+//! - `GET /api/v1/user/tokens` - List API tokens of the current user
+//! - `POST /api/v1/user/tokens` - Create a new API token for the current user
+//! - `DELETE /api/v1/user/tokens/:token_id` - Revoke an API token of the current user
+//!
+//! All endpoints require authentication.
 
 use crate::store::DbConnection;
 use crate::server::Server;
+use crate::types::ErrorKind;
+use crate::user::{ApiToken, LoginAttemptStart, LoginOutcome, TokenScope, UserStore, unix_time_now};
 use super::AuthHandler;
+use super::client_address;
 use super::fallback_handler;
 use super::build_json_response;
 use super::User;
 use super::ApiResponseVariant;
 use super::ApiResponse;
+use super::run_blocking_task;
+use super::{response_error, response_error_kind, response_internal_error};
+use super::v1_login::response_throttled;
 
 use axum::Router;
 use axum::body::Body;
-use axum::routing::get;
+use axum::routing::{get, post, put, delete};
 use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Json as JsonExtractor;
+use axum::extract::Path as PathExtractor;
 
-use http::Response;
+use http::{Extensions, HeaderMap, Response};
 
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenCreateRequest {
+    /// Token name
+    pub name: String,
+
+    /// Lifetime of the token in seconds. `None` means the token never expires.
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+
+    #[serde(default)]
+    pub scope: TokenScope,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordChangeRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TotpConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordConfirmRequest {
+    pub password: String,
+}
 
 pub fn build_router<T>() -> Router<Server<T>>
 where
@@ -23,6 +76,17 @@ where
     let mut router = Router::new();
 
     router = router.route("/self", get(user_self).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/self/password", put(user_self_password::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+
+    router = router.route("/self/totp", get(user_self_totp_status::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/self/totp", post(user_self_totp_begin::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/self/totp/confirm", post(user_self_totp_confirm::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/self/totp/recovery_codes", post(user_self_totp_recovery_codes::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/self/totp/disable", post(user_self_totp_disable::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+
+    router = router.route("/tokens", get(user_tokens_list::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/tokens", post(user_tokens_create::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
+    router = router.route("/tokens/:token_id", delete(user_tokens_revoke::<T>).layer(AuthHandler::<T>::new_auth_required_layer()));
 
     router = router.fallback(fallback_handler());
 
@@ -33,14 +97,288 @@ pub async fn user_self(ext: Option<ExtensionExtractor<User>>) -> Response<Body>
     if let Some(ext) = ext {
         let res = ApiResponse {
             error: None,
+            code: None,
             result: Some(ApiResponseVariant::User(ext.0)),
         };
         return build_json_response(res, 200);
     } else {
-        let res = ApiResponse {
-            error: Some("Internal Server Error".to_string()),
-            result: None,
+        return response_internal_error();
+    }
+}
+
+/// Check the password of `username` before a sensitive change, returning the response to send
+/// if it is not correct. Checks are throttled and recorded like logins, so that a stolen session
+/// cannot be used to guess the password.
+fn confirm_password(users: &dyn UserStore, username: &str, password: &str, source: Option<&str>) -> Result<(), Response<Body>> {
+    let attempt_id = match users.begin_login_attempt(username, source) {
+        Ok(LoginAttemptStart::Allowed(attempt_id)) => attempt_id,
+        Ok(LoginAttemptStart::Throttled(lockout)) => return Err(response_throttled(lockout.blocked_until - unix_time_now())),
+        Err(e) => return Err(response_error("Error checking login throttling", &e)),
+    };
+    let (outcome, result) = match users.check_password(username, password) {
+        Ok(true) => (Some(LoginOutcome::Success), Ok(())),
+        Ok(false) => (Some(LoginOutcome::Failure), Err(response_error_kind(ErrorKind::Forbidden, "Incorrect password"))),
+        Err(e) => (None, Err(response_error("Error checking password", &e))),
+    };
+    if let Err(e) = users.finish_login_attempt(attempt_id, outcome) {
+        log::error!("Error recording login attempt: {}", e);
+    }
+    result
+}
+
+async fn user_self_password<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, current_token: Option<ExtensionExtractor<ApiToken>>, extensions: Extensions, headers: HeaderMap, JsonExtractor(req): JsonExtractor<PasswordChangeRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if req.new_password.is_empty() {
+        return response_error_kind(ErrorKind::InvalidInput, "New password is required");
+    }
+
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let keep_token_id = current_token.map(|token| token.0.id);
+        let source = client_address(&ext.0, &extensions, &headers).map(|addr| addr.to_string());
+
+        let username_copy = username.clone();
+        let old_password = req.old_password;
+        if let Err(res) = run_blocking_task(store.clone(), move |store| confirm_password(&*store.users(), &username_copy, &old_password, source.as_deref())).await {
+            return res;
+        }
+
+        let new_password = req.new_password;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().change_password(&username, &new_password, keep_token_id)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error changing password", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_self_totp_status<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().get_totp_status(&username)).await {
+            Ok(status) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::TotpStatus(status)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error getting two-factor status", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_self_totp_begin<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().begin_totp_enrolment(&username)).await {
+            Ok(enrolment) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::TotpEnrolment(enrolment)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error starting two-factor enrolment", &e),
         };
-        return build_json_response(res, 500);
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_self_totp_confirm<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, JsonExtractor(req): JsonExtractor<TotpConfirmRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().confirm_totp_enrolment(&username, &req.code)).await {
+            Ok(codes) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::RecoveryCodes(codes)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error confirming two-factor enrolment", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_self_totp_recovery_codes<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, extensions: Extensions, headers: HeaderMap, JsonExtractor(req): JsonExtractor<PasswordConfirmRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let source = client_address(&ext.0, &extensions, &headers).map(|addr| addr.to_string());
+        let res = match run_blocking_task(store.clone(), move |store| {
+            confirm_password(&*store.users(), &username, &req.password, source.as_deref())?;
+            Ok(store.users().regenerate_recovery_codes(&username))
+        }).await {
+            Ok(Ok(codes)) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::RecoveryCodes(codes)),
+                };
+                build_json_response(res, 200)
+            },
+            Ok(Err(e)) => response_error("Error generating recovery codes", &e),
+            Err(res) => res,
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_self_totp_disable<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, extensions: Extensions, headers: HeaderMap, JsonExtractor(req): JsonExtractor<PasswordConfirmRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let source = client_address(&ext.0, &extensions, &headers).map(|addr| addr.to_string());
+        let res = match run_blocking_task(store.clone(), move |store| {
+            confirm_password(&*store.users(), &username, &req.password, source.as_deref())?;
+            Ok(store.users().disable_totp(&username))
+        }).await {
+            Ok(Ok(_)) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Ok(Err(e)) => response_error("Error disabling two-factor authentication", &e),
+            Err(res) => res,
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_tokens_list<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().list_api_tokens(&username)).await {
+            Ok(tokens) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::ApiTokens(tokens)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error listing API tokens", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_tokens_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, current_token: Option<ExtensionExtractor<ApiToken>>, JsonExtractor(req): JsonExtractor<TokenCreateRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if req.name.trim().is_empty() {
+        return response_error_kind(ErrorKind::InvalidInput, "Token name is required");
+    }
+    if let Some(expires_in) = req.expires_in {
+        if expires_in <= 0 {
+            return response_error_kind(ErrorKind::InvalidInput, "Token lifetime must be positive");
+        }
+    }
+
+    // A token cannot be used to create a token with a broader scope than its own.
+    if let Some(current_token) = &current_token {
+        if !req.scope.is_subset_of(&current_token.0.scope) {
+            return response_error_kind(ErrorKind::Forbidden, "Requested scope exceeds the scope of the current token");
+        }
+    }
+
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let expires_at = req.expires_in.map(|expires_in| unix_time_now().saturating_add(expires_in));
+        let res = match run_blocking_task(store.clone(), move |store| store.users().create_api_token(&username, req.name.trim(), expires_at, &req.scope)).await {
+            Ok(token) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::NewApiToken(token)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error creating API token", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn user_tokens_revoke<T>(ext: Option<ExtensionExtractor<Server<T>>>, user: Option<ExtensionExtractor<User>>, PathExtractor(token_id): PathExtractor<i32>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let (Some(ext), Some(user)) = (ext, user) {
+        let store = ext.0.store();
+        let username = user.0.username;
+        let res = match run_blocking_task(store.clone(), move |store| store.users().revoke_api_token(&username, token_id)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error revoking API token", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
     }
 }
@@ -0,0 +1,289 @@
+//!
+//! Endpoints for user administration
+//! - `GET /api/v1/users` - List all users with metadata
+//! - `POST /api/v1/users` - Create a new user
+//! - `GET /api/v1/users/:username` - Get a user with metadata
+//! - `DELETE /api/v1/users/:username` - Delete a user and all of its API tokens
+//! - `PUT /api/v1/users/:username/password` - Reset the password of a user, revoking all of its API tokens
+//! - `PUT /api/v1/users/:username/role` - Change the role of a user
+//! - `PUT /api/v1/users/:username/oidc` - Link a single sign-on identity to a user
+//! - `DELETE /api/v1/users/:username/oidc` - Unlink a single sign-on identity from a user
+//!
+//! All endpoints require an admin. The last admin can be neither deleted nor demoted.
+//! Single sign-on logs in only as the user its identity (issuer and subject) is linked to.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::types::ErrorKind;
+use crate::user::UserRole;
+use super::AuthHandler;
+use super::fallback_handler;
+use super::build_json_response;
+use super::ApiResponseVariant;
+use super::ApiResponse;
+use super::run_blocking_task;
+use super::{response_error, response_error_kind, response_internal_error};
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::{get, post, put, delete};
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Json as JsonExtractor;
+use axum::extract::Path as PathExtractor;
+
+use http::Response;
+
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserCreateRequest {
+    pub username: String,
+    pub password: String,
+
+    #[serde(default)]
+    pub role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordResetRequest {
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleUpdateRequest {
+    pub role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcLinkRequest {
+    pub issuer: String,
+    pub subject: String,
+}
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/", get(users_list::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/", post(users_create::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username", get(users_get::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username", delete(users_delete::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username/password", put(users_password::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username/role", put(users_role::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username/oidc", put(users_oidc_link::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+    router = router.route("/:username/oidc", delete(users_oidc_unlink::<T>).layer(AuthHandler::<T>::new_admin_required_layer()));
+
+    router = router.fallback(fallback_handler());
+
+    router
+}
+
+async fn users_list<T>(ext: Option<ExtensionExtractor<Server<T>>>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().list_users_with_metadata()).await {
+            Ok(users) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::UserInfos(users)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error listing users", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_create<T>(ext: Option<ExtensionExtractor<Server<T>>>, JsonExtractor(req): JsonExtractor<UserCreateRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let username = req.username.trim().to_string();
+    if username.is_empty() || req.password.is_empty() {
+        return response_error_kind(ErrorKind::InvalidInput, "Username and password are required");
+    }
+
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| {
+            store.users().create_user(&username, &req.password, req.role)?;
+            store.users().get_user(&username)
+        }).await {
+            Ok(user) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::UserInfo(user)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error creating user", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_get<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().get_user(&username)).await {
+            Ok(user) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::UserInfo(user)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error getting user", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_delete<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().delete_user(&username)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error deleting user", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_password<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>, JsonExtractor(req): JsonExtractor<PasswordResetRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if req.password.is_empty() {
+        return response_error_kind(ErrorKind::InvalidInput, "Password is required");
+    }
+
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| {
+            // Only reset existing users; `set_password` would create a missing one.
+            store.users().get_user(&username)?;
+            store.users().set_password(&username, &req.password)
+        }).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error resetting password", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_role<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>, JsonExtractor(req): JsonExtractor<RoleUpdateRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| {
+            store.users().set_role(&username, req.role)?;
+            store.users().get_user(&username)
+        }).await {
+            Ok(user) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: Some(ApiResponseVariant::UserInfo(user)),
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error changing role", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_oidc_link<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>, JsonExtractor(req): JsonExtractor<OidcLinkRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if req.issuer.is_empty() || req.subject.is_empty() {
+        return response_error_kind(ErrorKind::InvalidInput, "Issuer and subject are required");
+    }
+
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().link_oidc_user(&username, &req.issuer, &req.subject)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error linking identity", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
+
+async fn users_oidc_unlink<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(username): PathExtractor<String>, JsonExtractor(req): JsonExtractor<OidcLinkRequest>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    if let Some(ext) = ext {
+        let store = ext.0.store();
+        let res = match run_blocking_task(store.clone(), move |store| store.users().unlink_oidc_user(&username, &req.issuer, &req.subject)).await {
+            Ok(_) => {
+                let res = ApiResponse {
+                    error: None,
+                    code: None,
+                    result: None,
+                };
+                build_json_response(res, 200)
+            },
+            Err(e) => response_error("Error unlinking identity", &e),
+        };
+        return res;
+    } else {
+        return response_internal_error();
+    }
+}
@@ -0,0 +1,406 @@
+//! Authentication by a trusted reverse proxy header or a verified TLS client certificate
+
+use serde::{Serialize, Deserialize};
+
+use crate::types::{Error, ErrorKind};
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+
+fn default_header() -> String {
+    "X-Remote-User".to_string()
+}
+
+/// An address or a CIDR prefix such as `192.0.2.0/24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AddressPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl AddressPrefix {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        // Treat IPv4-mapped IPv6 sources (dual-stack listeners) as IPv4
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+            addr => addr,
+        };
+        match (self.addr, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.len as u32).unwrap_or(0);
+                u32::from(prefix) & mask == u32::from(addr) & mask
+            },
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.len as u32).unwrap_or(0);
+                u128::from(prefix) & mask == u128::from(addr) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AddressPrefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| format!("Invalid address: {}", s))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let len = match len {
+            Some(len) => len.trim().parse::<u8>().ok().filter(|len| *len <= max_len).ok_or_else(|| format!("Invalid prefix length: {}", s))?,
+            None => max_len,
+        };
+        Ok(AddressPrefix { addr, len })
+    }
+}
+
+impl TryFrom<String> for AddressPrefix {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for AddressPrefix {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl From<AddressPrefix> for String {
+    fn from(value: AddressPrefix) -> Self {
+        value.to_string()
+    }
+}
+
+/// Trust a username header set by an authenticating reverse proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyAuthConfig {
+    /// Header carrying the username
+    #[serde(default = "default_header")]
+    pub header: String,
+
+    /// Source addresses of the proxies. The header is ignored on requests from anywhere else.
+    #[serde(default)]
+    pub trusted_proxies: Vec<AddressPrefix>,
+
+    /// Also trust the header on connections to the Unix domain socket
+    #[serde(default)]
+    pub trust_unix_socket: bool,
+}
+
+impl ProxyAuthConfig {
+    pub fn is_trusted(&self, source: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|prefix| prefix.contains(source))
+    }
+
+    /// Username asserted for a request from `source`, if it comes from a trusted proxy
+    pub fn username(&self, source: Option<IpAddr>, headers: &http::HeaderMap) -> Option<String> {
+        headers.get(self.header.as_str())?;
+        if !source.map(|source| self.is_trusted(source)).unwrap_or(false) {
+            log::debug!("Ignoring {} header from untrusted source {:?}", self.header, source);
+            return None;
+        }
+        self.header_username(headers)
+    }
+
+    /// Username asserted for a request over the Unix domain socket, if that is trusted
+    pub fn unix_socket_username(&self, headers: &http::HeaderMap) -> Option<String> {
+        headers.get(self.header.as_str())?;
+        if !self.trust_unix_socket {
+            log::debug!("Ignoring {} header from the untrusted Unix socket", self.header);
+            return None;
+        }
+        self.header_username(headers)
+    }
+
+    /// Address of the client of a request from `peer` (`None` over the Unix socket). Requests
+    /// through trusted proxies are attributed to the nearest untrusted address they forward,
+    /// or to none, never to the proxy itself.
+    pub fn client_address(&self, peer: Option<IpAddr>, unix_socket: bool, headers: &http::HeaderMap) -> Option<IpAddr> {
+        let trusted = match peer {
+            Some(peer) => self.is_trusted(peer),
+            None => unix_socket && self.trust_unix_socket,
+        };
+        if !trusted {
+            return peer;
+        }
+        for addr in forwarded_addresses(headers).into_iter().rev() {
+            match addr {
+                Some(addr) if self.is_trusted(addr) => continue,
+                addr => return addr,
+            }
+        }
+        None
+    }
+
+    fn header_username(&self, headers: &http::HeaderMap) -> Option<String> {
+        let username = headers.get(self.header.as_str())?.to_str().ok()?.trim();
+        if username.is_empty() {
+            return None;
+        }
+        Some(username.to_string())
+    }
+}
+
+/// Addresses forwarded by proxies in `Forwarded` (RFC 7239), or else `X-Forwarded-For`,
+/// the client first. `None` stands for a node that is not an address, such as `unknown`.
+fn forwarded_addresses(headers: &http::HeaderMap) -> Vec<Option<IpAddr>> {
+    let elements = |name| headers.get_all(name).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| element.trim().to_string())
+        .collect::<Vec<_>>();
+    let forwarded = elements(http::header::FORWARDED);
+    if forwarded.is_empty() {
+        return elements(http::HeaderName::from_static("x-forwarded-for")).iter().map(|node| forwarded_node(node)).collect();
+    }
+    forwarded.iter().map(|element| {
+        element.split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+            .and_then(|(_, node)| forwarded_node(node))
+    }).collect()
+}
+
+/// Address of a forwarded node such as `192.0.2.1`, `"[2001:db8::1]:4711"` or `192.0.2.1:80`
+fn forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<SocketAddr>().map(|addr| addr.ip()).ok()
+        .or_else(|| node.trim_start_matches('[').trim_end_matches(']').parse().ok())
+}
+
+/// Attribute types that have a short name in DNs, as in RFC 4514 section 3
+/// plus those common in certificate subjects
+const ATTRIBUTE_TYPES: &[(&str, &str)] = &[
+    ("CN", "2.5.4.3"),
+    ("L", "2.5.4.7"),
+    ("ST", "2.5.4.8"),
+    ("O", "2.5.4.10"),
+    ("OU", "2.5.4.11"),
+    ("C", "2.5.4.6"),
+    ("STREET", "2.5.4.9"),
+    ("DC", "0.9.2342.19200300.100.1.25"),
+    ("UID", "0.9.2342.19200300.100.1.1"),
+    ("serialNumber", "2.5.4.5"),
+    ("emailAddress", "1.2.840.113549.1.9.1"),
+];
+
+/// Value of an attribute in a distinguished name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AttributeValue {
+    String(String),
+
+    /// BER encoding of a value that is not a string (`#` and hex digits in RFC 4514)
+    Encoded(Vec<u8>),
+}
+
+/// A distinguished name, with RDNs in RFC 4514 order (most specific first), each a set of
+/// attribute OIDs and values. Equality ignores the case of string values and the order of the
+/// values of a multi-valued RDN; `CN` and `2.5.4.3` name the same attribute type.
+#[derive(Debug, Clone)]
+pub struct DistinguishedName {
+    rdns: Vec<Vec<(String, AttributeValue)>>,
+}
+
+impl DistinguishedName {
+    /// From the RDN sequence of an X.509 name, which lists RDNs in the reverse order of RFC 4514
+    pub fn from_asn1_rdns(mut rdns: Vec<Vec<(String, AttributeValue)>>) -> Self {
+        rdns.reverse();
+        DistinguishedName { rdns }
+    }
+
+    fn canonical(&self) -> Vec<Vec<(&str, AttributeValue)>> {
+        self.rdns.iter().map(|rdn| {
+            let mut rdn = rdn.iter().map(|(oid, value)| {
+                let value = match value {
+                    AttributeValue::String(value) => AttributeValue::String(value.to_lowercase()),
+                    AttributeValue::Encoded(value) => AttributeValue::Encoded(value.clone()),
+                };
+                (oid.as_str(), value)
+            }).collect::<Vec<_>>();
+            rdn.sort();
+            rdn
+        }).collect()
+    }
+}
+
+impl PartialEq for DistinguishedName {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl Eq for DistinguishedName {}
+
+/// OID of an attribute type given by short name or in dotted form
+fn attribute_oid(attribute: &str) -> Option<String> {
+    let oid = attribute.strip_prefix("oid.").or_else(|| attribute.strip_prefix("OID.")).unwrap_or(attribute);
+    if !oid.is_empty() && oid.split('.').all(|arc| !arc.is_empty() && arc.bytes().all(|byte| byte.is_ascii_digit())) {
+        return Some(oid.to_string());
+    }
+    ATTRIBUTE_TYPES.iter().find(|(name, _)| name.eq_ignore_ascii_case(attribute)).map(|(_, oid)| oid.to_string())
+}
+
+impl FromStr for DistinguishedName {
+    type Err = String;
+
+    /// Parse an RFC 4514 string: escapes (`\,` or `\2C`) are resolved and unescaped spaces
+    /// around values are ignored
+    fn from_str(dn: &str) -> Result<Self, Self::Err> {
+        let malformed = || format!("Invalid distinguished name: {}", dn);
+        let bytes = dn.as_bytes();
+        let mut rdns = Vec::new();
+        let mut rdn = Vec::new();
+        let mut i = 0;
+        loop {
+            let start = i;
+            while i < bytes.len() && bytes[i] != b'=' {
+                i += 1;
+            }
+            let attribute = dn.get(start..i).ok_or_else(malformed)?.trim();
+            if i == bytes.len() {
+                return Err(malformed());
+            }
+            let oid = attribute_oid(attribute).ok_or_else(|| format!("Unknown attribute type {} in distinguished name: {}", attribute, dn))?;
+            i += 1;
+
+            while i < bytes.len() && bytes[i] == b' ' {
+                i += 1;
+            }
+            let value = if bytes.get(i) == Some(&b'#') {
+                let start = i + 1;
+                while i < bytes.len() && bytes[i] != b',' && bytes[i] != b'+' {
+                    i += 1;
+                }
+                let encoded = dn.get(start..i).ok_or_else(malformed)?.trim_end_matches(' ');
+                AttributeValue::Encoded(hex::decode(encoded).map_err(|_| malformed())?)
+            } else {
+                let mut value = Vec::new();
+                // Unescaped trailing spaces are not part of the value
+                let mut trailing_spaces = 0;
+                while i < bytes.len() && bytes[i] != b',' && bytes[i] != b'+' {
+                    if bytes[i] == b'\\' {
+                        let escaped = *bytes.get(i + 1).ok_or_else(malformed)?;
+                        if escaped.is_ascii_hexdigit() {
+                            value.push(dn.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()).ok_or_else(malformed)?);
+                            i += 3;
+                        } else {
+                            value.push(escaped);
+                            i += 2;
+                        }
+                        trailing_spaces = 0;
+                    } else {
+                        trailing_spaces = if bytes[i] == b' ' { trailing_spaces + 1 } else { 0 };
+                        value.push(bytes[i]);
+                        i += 1;
+                    }
+                }
+                value.truncate(value.len() - trailing_spaces);
+                AttributeValue::String(String::from_utf8(value).map_err(|_| malformed())?)
+            };
+            rdn.push((oid, value));
+
+            match bytes.get(i) {
+                Some(b'+') => {},
+                separator => {
+                    rdns.push(std::mem::take(&mut rdn));
+                    if separator.is_none() {
+                        return Ok(DistinguishedName { rdns });
+                    }
+                },
+            }
+            i += 1;
+        }
+    }
+}
+
+impl Display for DistinguishedName {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (i, rdn) in self.rdns.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            for (j, (oid, value)) in rdn.iter().enumerate() {
+                if j > 0 {
+                    write!(f, "+")?;
+                }
+                let name = ATTRIBUTE_TYPES.iter().find(|(_, known)| known == oid).map(|(name, _)| *name).unwrap_or(oid);
+                write!(f, "{}=", name)?;
+                match value {
+                    AttributeValue::String(value) => {
+                        let last = value.chars().count().saturating_sub(1);
+                        for (k, c) in value.chars().enumerate() {
+                            match c {
+                                '"' | '+' | ',' | ';' | '<' | '>' | '\\' => write!(f, "\\{}", c)?,
+                                '\0' => write!(f, "\\00")?,
+                                ' ' if k == 0 || k == last => write!(f, "\\ ")?,
+                                '#' if k == 0 => write!(f, "\\#")?,
+                                c => write!(f, "{}", c)?,
+                            }
+                        }
+                    },
+                    AttributeValue::Encoded(value) => write!(f, "#{}", hex::encode(value))?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Subject of a client certificate that the TLS listener has verified.
+/// Inserted as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub subject: DistinguishedName,
+
+    pub common_name: Option<String>,
+}
+
+/// Map verified client certificate subjects to users
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientCertAuthConfig {
+    /// Subject DN, as in RFC 4514 (e.g. `CN=alice,O=Example`), to username
+    #[serde(default)]
+    pub users: HashMap<String, String>,
+
+    /// Use the common name of subjects not listed in `users` as the username
+    #[serde(default)]
+    pub common_name_as_username: bool,
+}
+
+/// `ClientCertAuthConfig` with the subject DNs parsed
+#[derive(Debug, Clone)]
+pub struct ClientCertAuth {
+    users: Vec<(DistinguishedName, String)>,
+    common_name_as_username: bool,
+}
+
+impl ClientCertAuth {
+    /// Fails if a subject DN in `users` is malformed
+    pub fn new(config: ClientCertAuthConfig) -> Result<Self, Error> {
+        let users = config.users.into_iter().map(|(dn, username)| {
+            let dn = dn.parse::<DistinguishedName>().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+            Ok((dn, username))
+        }).collect::<Result<_, Error>>()?;
+        Ok(Self { users, common_name_as_username: config.common_name_as_username })
+    }
+
+    pub fn username(&self, cert: &ClientCertificate) -> Option<String> {
+        if let Some((_, username)) = self.users.iter().find(|(dn, _)| dn == &cert.subject) {
+            return Some(username.clone());
+        }
+        if self.common_name_as_username {
+            return cert.common_name.clone().filter(|cn| !cn.is_empty());
+        }
+        None
+    }
+}
@@ -0,0 +1,327 @@
+//! Listening sockets (TCP, Unix domain sockets and sockets passed in by systemd)
+//! and serving them until a graceful shutdown
+
+use crate::types::{Error, ErrorKind};
+use super::tls::{self, TlsState};
+
+use axum::Router;
+use axum::extract::ConnectInfo;
+
+use http::{Extensions, Request};
+
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder as ConnectionBuilder;
+use hyper_util::service::TowerToHyperService;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use tower::ServiceExt;
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt::{Display, Formatter};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+
+/// Prefix of Unix domain socket paths in listen addresses
+const UNIX_PREFIX: &str = "unix:";
+
+/// Connections waiting to be accepted
+const BACKLOG: i32 = 1024;
+
+/// Address to listen on: `host:port` (`[::1]:3001` for IPv6) or `unix:/path/to/socket`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(format!("Missing socket path: {}", s));
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        match s.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(ListenAddr::Tcp(s.to_string())),
+            _ => Err(format!("Invalid listen address (expected host:port or unix:path): {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for ListenAddr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+impl From<ListenAddr> for String {
+    fn from(value: ListenAddr) -> Self {
+        value.to_string()
+    }
+}
+
+/// A connection from a Unix domain socket.
+/// Inserted as a request extension in place of `ConnectInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixSocketPeer;
+
+/// A bound socket, not yet accepting connections
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    Unix(std::os::unix::net::UnixListener),
+}
+
+fn bind_tcp(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::STREAM, None)?;
+    // Like nginx, IPv6 addresses do not also accept IPv4, so that both can be listed.
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    Ok(socket.into())
+}
+
+impl Listener {
+    /// Bind `addr`. A host name gives one listener for each address it resolves to.
+    /// A socket file left at a Unix socket path is replaced, and the new one gets the permissions `unix_mode`.
+    pub fn bind(addr: &ListenAddr, unix_mode: Option<u32>) -> Result<Vec<Listener>, Error> {
+        match addr {
+            ListenAddr::Tcp(addr) => {
+                let addrs = addr.to_socket_addrs()
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Cannot resolve {}: {}", addr, e)))?;
+                let mut listeners = Vec::new();
+                for addr in addrs {
+                    let listener = bind_tcp(addr)
+                        .map_err(|e| Error::new(ErrorKind::Other, format!("Cannot listen on {}: {}", addr, e)))?;
+                    listeners.push(Listener::Tcp(listener));
+                }
+                Ok(listeners)
+            },
+            ListenAddr::Unix(path) => {
+                // Remove the socket of a previous run, but never a regular file
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        std::fs::remove_file(path)
+                            .map_err(|e| Error::new(ErrorKind::Other, format!("Cannot remove {}: {}", path.display(), e)))?;
+                    }
+                }
+                let listener = std::os::unix::net::UnixListener::bind(path)
+                    .map_err(|e| Error::new(ErrorKind::Other, format!("Cannot listen on {}: {}", path.display(), e)))?;
+                if let Some(mode) = unix_mode {
+                    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                        .map_err(|e| Error::new(ErrorKind::Other, format!("Cannot set permissions of {}: {}", path.display(), e)))?;
+                }
+                Ok(vec![Listener::Unix(listener)])
+            },
+        }
+    }
+
+    /// Sockets passed in by systemd socket activation (`LISTEN_FDS`)
+    pub fn from_systemd() -> Result<Vec<Listener>, Error> {
+        let mut fds = listenfd::ListenFd::from_env();
+        let mut listeners = Vec::new();
+        for i in 0..fds.len() {
+            if let Ok(Some(listener)) = fds.take_tcp_listener(i) {
+                listeners.push(Listener::Tcp(listener));
+                continue;
+            }
+            match fds.take_unix_listener(i) {
+                Ok(Some(listener)) => listeners.push(Listener::Unix(listener)),
+                Ok(None) => {},
+                Err(e) => return Err(Error::new(ErrorKind::InvalidInput, format!("Unsupported socket from systemd: {}", e))),
+            }
+        }
+        Ok(listeners)
+    }
+
+    /// Port of a TCP listener
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok().map(|addr| addr.port()),
+            Listener::Unix(_) => None,
+        }
+    }
+
+    pub(crate) fn into_tokio(self) -> std::io::Result<TokioListener> {
+        match self {
+            Listener::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(TokioListener::Tcp(tokio::net::TcpListener::from_std(listener)?))
+            },
+            Listener::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(TokioListener::Unix(tokio::net::UnixListener::from_std(listener)?))
+            },
+        }
+    }
+}
+
+impl Display for Listener {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "TCP socket"),
+            },
+            Listener::Unix(listener) => match listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(|path| path.to_path_buf())) {
+                Some(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+                None => write!(f, "Unix socket"),
+            },
+        }
+    }
+}
+
+pub(crate) enum TokioListener {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener),
+}
+
+pub(crate) enum Accepted {
+    Tcp(tokio::net::TcpStream, SocketAddr),
+    Unix(tokio::net::UnixStream),
+}
+
+impl TokioListener {
+    pub(crate) async fn accept(&self) -> std::io::Result<Accepted> {
+        match self {
+            TokioListener::Tcp(listener) => listener.accept().await.map(|(stream, addr)| Accepted::Tcp(stream, addr)),
+            TokioListener::Unix(listener) => listener.accept().await.map(|(stream, _)| Accepted::Unix(stream)),
+        }
+    }
+}
+
+/// Resolves once `shutdown` is set, or if its sender is gone
+pub(crate) async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|shutdown| *shutdown).await;
+}
+
+/// Serve `app` on `listener` until `shutdown` is set, then finish the requests in progress.
+/// With `tls`, TCP connections use HTTPS; Unix domain sockets are always plain HTTP.
+pub(crate) async fn serve(listener: Listener, app: Router, tls: Option<Arc<TlsState>>, mut shutdown: watch::Receiver<bool>) {
+    let name = listener.to_string();
+    let listener = match listener.into_tokio() {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Cannot use listener {}: {}", name, e);
+            return;
+        },
+    };
+
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = shutdown_requested(&mut shutdown) => break,
+        };
+        let accepted = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Such as running out of file descriptors; give connections time to close.
+                log::warn!("Failed to accept connection on {}: {}", name, e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            },
+        };
+
+        let app = app.clone();
+        let tls = tls.clone();
+        let shutdown = shutdown.clone();
+        connections.spawn(async move {
+            let mut extensions = Extensions::new();
+            match accepted {
+                Accepted::Tcp(stream, addr) => {
+                    extensions.insert(ConnectInfo(addr));
+                    match tls {
+                        Some(tls) => {
+                            let Some((stream, cert)) = tls::accept(&tls, stream, addr).await else {
+                                return;
+                            };
+                            if let Some(cert) = cert {
+                                extensions.insert(cert);
+                            }
+                            serve_connection(stream, app, extensions, shutdown).await;
+                        },
+                        None => serve_connection(stream, app, extensions, shutdown).await,
+                    }
+                },
+                Accepted::Unix(stream) => {
+                    extensions.insert(UnixSocketPeer);
+                    serve_connection(stream, app, extensions, shutdown).await;
+                },
+            }
+        });
+    }
+
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
+
+/// Serve one connection, closing it once the current request is done when `shutdown` is set
+async fn serve_connection<I>(io: I, app: Router, extensions: Extensions, mut shutdown: watch::Receiver<bool>)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = app.map_request(move |mut request: Request<hyper::body::Incoming>| {
+        request.extensions_mut().extend(extensions.clone());
+        request
+    });
+    let service = TowerToHyperService::new(service);
+    let builder = ConnectionBuilder::new(TokioExecutor::new());
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = shutdown_requested(&mut shutdown) => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        },
+    };
+    if let Err(e) = result {
+        log::debug!("Connection closed with error: {}", e);
+    }
+}
+
+/// Resolves on SIGTERM or SIGINT
+pub async fn shutdown_signal() {
+    let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            log::error!("Cannot listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        },
+    };
+    tokio::select! {
+        _ = terminate.recv() => log::info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
+    }
+}
@@ -1,10 +1,21 @@
 
 pub mod api;
+pub mod auth;
+pub mod listener;
+pub mod oidc;
+pub mod rdap;
+pub mod rtr;
+pub mod tls;
+pub mod whois;
 
 use crate::static_files::frontend_files;
 use crate::static_files::types_by_ext;
 use crate::store::Store;
 use crate::store::DbConnection;
+use crate::types::{Error, ErrorKind};
+use crate::rpsl::RpslConfig;
+use crate::dns::DnsConfig;
+use crate::dns_update::DnsUpdater;
 
 use axum::Router;
 use axum::http::HeaderMap;
@@ -14,8 +25,10 @@ use axum::extract::State as StateExtractor;
 
 use http::{Request, Response};
 
-use std::net::ToSocketAddrs;
+use std::future::Future;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 
 #[derive(Debug, Clone)]
@@ -24,6 +37,18 @@ where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     store: Store<T>,
+    oidc: Option<Arc<oidc::OidcProvider>>,
+    proxy_auth: Option<Arc<auth::ProxyAuthConfig>>,
+    client_cert_auth: Option<Arc<auth::ClientCertAuth>>,
+    tls: Option<Arc<tls::TlsState>>,
+    whois: Option<Arc<whois::WhoisConfig>>,
+    rtr: Option<Arc<rtr::RtrConfig>>,
+    rpsl: Arc<RpslConfig>,
+    dns: Arc<DnsConfig>,
+    dns_updater: Arc<DnsUpdater>,
+    request_timeout: Duration,
+    shutdown_timeout: Duration,
+    cors_origins: Vec<String>,
 }
 
 impl<T> Server<T>
@@ -31,32 +56,204 @@ where
     T: DbConnection + Clone + Send + Sync + 'static,
 {
     pub fn new(store: Store<T>) -> Self {
-        Server { store }
+        Server {
+            store,
+            oidc: None,
+            proxy_auth: None,
+            client_cert_auth: None,
+            tls: None,
+            whois: None,
+            rtr: None,
+            rpsl: Arc::new(RpslConfig::default()),
+            dns: Arc::new(DnsConfig::default()),
+            dns_updater: Arc::new(DnsUpdater::new(&DnsConfig::default())),
+            request_timeout: Duration::from_secs(30),
+            shutdown_timeout: Duration::from_secs(30),
+            cors_origins: vec![crate::config::ANY_ORIGIN.to_string()],
+        }
+    }
+
+    /// Abort API requests that take longer than `timeout`
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// On shutdown, wait at most `timeout` for requests in progress
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Allow browsers to call the API from these origins; `*` allows any
+    pub fn with_cors_origins(mut self, origins: Vec<String>) -> Self {
+        self.cors_origins = origins;
+        self
+    }
+
+    /// Enable OpenID Connect login
+    pub fn with_oidc(mut self, config: oidc::OidcConfig) -> Self {
+        self.oidc = Some(Arc::new(oidc::OidcProvider::new(config)));
+        self
+    }
+
+    /// Accept usernames from a header set by trusted reverse proxies
+    pub fn with_proxy_auth(mut self, config: auth::ProxyAuthConfig) -> Self {
+        self.proxy_auth = Some(Arc::new(config));
+        self
+    }
+
+    /// Accept verified TLS client certificates. Fails if a configured subject DN is malformed.
+    pub fn with_client_cert_auth(mut self, config: auth::ClientCertAuthConfig) -> Result<Self, Error> {
+        self.client_cert_auth = Some(Arc::new(auth::ClientCertAuth::new(config)?));
+        Ok(self)
+    }
+
+    /// Serve HTTPS. Fails if the certificate or key cannot be loaded.
+    pub fn with_tls(mut self, config: tls::TlsConfig) -> Result<Self, Error> {
+        self.tls = Some(Arc::new(tls::TlsState::new(config)?));
+        Ok(self)
+    }
+
+    /// Answer WHOIS queries on the addresses in `config`, next to the HTTP server
+    pub fn with_whois(mut self, config: whois::WhoisConfig) -> Self {
+        self.whois = Some(Arc::new(config));
+        self
+    }
+
+    /// Serve the ROAs to routers over RTR on the addresses in `config`
+    pub fn with_rtr(mut self, config: rtr::RtrConfig) -> Self {
+        self.rtr = Some(Arc::new(config));
+        self
+    }
+
+    /// Maintainer and source of exported RPSL objects
+    pub fn with_rpsl(mut self, config: RpslConfig) -> Self {
+        self.rpsl = Arc::new(config);
+        self
+    }
+
+    /// Apex records of generated zone files, and keys of dynamic updates
+    pub fn with_dns(mut self, config: DnsConfig) -> Self {
+        self.dns_updater = Arc::new(DnsUpdater::new(&config));
+        self.dns = Arc::new(config);
+        self
     }
 
     pub fn store(&self) -> &Store<T> {
         &self.store
     }
 
-    /// Start the server on the given address, in a new thread.
-    pub fn serve(&self, addr: impl ToSocketAddrs) {
-        let server = self.clone();
-        let addr = addr.to_socket_addrs().unwrap().next().unwrap();
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
-            rt.block_on(async move {
-                let mut app = build_frontend_router();
+    pub fn rpsl(&self) -> &RpslConfig {
+        &self.rpsl
+    }
+
+    pub fn dns(&self) -> &DnsConfig {
+        &self.dns
+    }
+
+    pub fn dns_updater(&self) -> &DnsUpdater {
+        &self.dns_updater
+    }
+
+    pub fn oidc(&self) -> Option<&Arc<oidc::OidcProvider>> {
+        self.oidc.as_ref()
+    }
+
+    pub fn proxy_auth(&self) -> Option<&Arc<auth::ProxyAuthConfig>> {
+        self.proxy_auth.as_ref()
+    }
+
+    pub fn client_cert_auth(&self) -> Option<&Arc<auth::ClientCertAuth>> {
+        self.client_cert_auth.as_ref()
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
 
-                let api_router = api::build_api_v1_router();
-                app = app.nest("/api/v1", api_router);
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    pub fn cors_origins(&self) -> &[String] {
+        &self.cors_origins
+    }
+
+    /// Build the router for the frontend and the API
+    pub fn build_router(&self) -> Router {
+        let mut app = build_frontend_router();
+
+        let api_router = api::build_api_v1_router(self);
+        app = app.nest("/api/v1", api_router);
+
+        app = app.nest("/rdap", rdap::build_router());
+
+        app = app.layer(axum::middleware::from_fn_with_state(self.clone(), add_state_extension::<Server<T>>));
+        app.with_state(self.clone())
+    }
 
-                app = app.layer(axum::middleware::from_fn_with_state(server.clone(), add_state_extension::<Server<T>>));
-                let app = app.with_state(server);
+    /// Serve on `listeners` until `shutdown` resolves, then stop accepting connections
+    /// and wait for requests in progress, at most for the shutdown timeout.
+    /// With TLS configured, the certificate is reloaded on SIGHUP.
+    pub async fn serve(&self, listeners: Vec<listener::Listener>, shutdown: impl Future<Output = ()>) -> Result<(), Error> {
+        let app = self.build_router();
+        let (shutdown_sender, shutdown_receiver) = tokio::sync::watch::channel(false);
+        let mut tasks = tokio::task::JoinSet::new();
+
+        if let Some(tls) = &self.tls {
+            tls::reload_on_sighup(tls.clone());
+            if let Some(redirect_addr) = &tls.config().redirect_http_addr {
+                let redirect_addr = redirect_addr.parse::<listener::ListenAddr>()
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+                let https_port = listeners.iter().find_map(|listener| listener.port()).unwrap_or(443);
+                for redirect_listener in listener::Listener::bind(&redirect_addr, None)? {
+                    log::info!("Redirecting HTTP on {} to HTTPS", redirect_listener);
+                    tasks.spawn(listener::serve(redirect_listener, tls::redirect_router(https_port), None, shutdown_receiver.clone()));
+                }
+            }
+        }
+        if let Some(config) = &self.whois {
+            for addr in &config.listen {
+                for whois_listener in listener::Listener::bind(addr, None)? {
+                    log::info!("Answering WHOIS queries on {}", whois_listener);
+                    tasks.spawn(whois::serve(whois_listener, self.store.clone(), config.clone(), shutdown_receiver.clone()));
+                }
+            }
+        }
+        if let Some(config) = &self.rtr {
+            let cache = rtr::RtrCache::load(&self.store).await?;
+            tasks.spawn(rtr::poll(cache.clone(), self.store.clone(), config.clone(), shutdown_receiver.clone()));
+            for addr in &config.listen {
+                for rtr_listener in listener::Listener::bind(addr, None)? {
+                    log::info!("Serving ROAs over RTR on {}", rtr_listener);
+                    tasks.spawn(rtr::serve(rtr_listener, cache.clone(), config.clone(), shutdown_receiver.clone()));
+                }
+            }
+        }
+        for server_listener in listeners {
+            log::info!("Listening on {}", server_listener);
+            tasks.spawn(listener::serve(server_listener, app.clone(), self.tls.clone(), shutdown_receiver.clone()));
+        }
+        let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
+
+        shutdown.await;
+        let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]);
+        log::info!("Shutting down; waiting up to {} seconds for requests in progress", self.shutdown_timeout.as_secs());
+        shutdown_sender.send_replace(true);
+        let drained = tokio::time::timeout(self.shutdown_timeout, async {
+            while tasks.join_next().await.is_some() {}
+        }).await;
+        if drained.is_err() {
+            log::warn!("Requests still in progress after {} seconds; closing them", self.shutdown_timeout.as_secs());
+        }
+        Ok(())
+    }
 
-                let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-                axum::serve(listener, app).await.unwrap();
-            });
-        });
+    /// Serve on `listeners` until SIGTERM or SIGINT. Blocks the calling thread.
+    pub fn run(&self, listeners: Vec<listener::Listener>) -> Result<(), Error> {
+        let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        rt.block_on(self.serve(listeners, listener::shutdown_signal()))
     }
 }
 
@@ -0,0 +1,280 @@
+//! OpenID Connect single sign-on (authorization code flow with PKCE)
+
+use crate::store::DbConnection;
+use crate::store::Store;
+use crate::types::{Error, ErrorKind};
+use crate::user::UserRole;
+
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{
+    AuthorizationCode,
+    ClaimsVerificationError,
+    ClientId,
+    ClientSecret,
+    CsrfToken,
+    IssuerUrl,
+    Nonce,
+    PkceCodeChallenge,
+    PkceCodeVerifier,
+    RedirectUrl,
+    Scope,
+    SignatureVerificationError,
+    TokenResponse,
+};
+
+use base64::Engine;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+/// How long a started login may take before its state expires
+pub const PENDING_LOGIN_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// How long discovered provider metadata and signing keys are used before discovery runs again
+const DISCOVERY_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum number of logins in progress, to bound memory use
+const MAX_PENDING_LOGINS: usize = 10000;
+
+fn default_scopes() -> Vec<String> {
+    vec!["profile".to_string(), "email".to_string()]
+}
+
+fn default_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+/// OpenID Connect provider settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Issuer URL, used for discovery
+    pub issuer_url: String,
+
+    pub client_id: String,
+
+    #[serde(default)]
+    pub client_secret: Option<String>,
+
+    /// Public URL of `/api/v1/oidc/callback` on this server
+    pub redirect_url: String,
+
+    /// Scopes requested in addition to `openid`
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+
+    /// ID token claim used as the username of users created on their first login
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+
+    /// ID token claim (a string or an array of strings) that decides the role,
+    /// such as `groups`. If unset, roles of existing users are left alone.
+    #[serde(default)]
+    pub role_claim: Option<String>,
+
+    /// Values of `role_claim` that make a user an admin
+    #[serde(default)]
+    pub admin_values: Vec<String>,
+
+    /// Create local users on their first login
+    #[serde(default)]
+    pub create_users: bool,
+}
+
+/// Identity asserted by an ID token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcIdentity {
+    /// `iss` claim
+    pub issuer: String,
+
+    /// `sub` claim, which identifies the user at the issuer
+    pub subject: String,
+
+    /// Value of `username_claim`
+    pub username: String,
+
+    /// `None` if no role claim is configured
+    pub role: Option<UserRole>,
+}
+
+impl OidcConfig {
+    /// Map the claims of a verified ID token to a local identity
+    pub fn map_claims(&self, claims: &serde_json::Map<String, serde_json::Value>) -> Result<OidcIdentity, Error> {
+        let claim = |name: &str| claims.get(name)
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("ID token has no {} claim", name)));
+        let issuer = claim("iss")?;
+        let subject = claim("sub")?;
+        let username = claims.get(&self.username_claim)
+            .and_then(|value| value.as_str())
+            .map(|username| username.trim())
+            .filter(|username| !username.is_empty())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("ID token has no {} claim", self.username_claim)))?;
+
+        let role = self.role_claim.as_ref().map(|role_claim| {
+            let values: Vec<&str> = match claims.get(role_claim) {
+                Some(serde_json::Value::String(value)) => vec![value.as_str()],
+                Some(serde_json::Value::Array(values)) => values.iter().filter_map(|value| value.as_str()).collect(),
+                _ => vec![],
+            };
+            if values.iter().any(|value| self.admin_values.iter().any(|admin| admin == value)) {
+                UserRole::Admin
+            } else {
+                UserRole::User
+            }
+        });
+
+        Ok(OidcIdentity {
+            issuer: issuer.to_string(),
+            subject: subject.to_string(),
+            username: username.to_string(),
+            role,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct PendingLogin {
+    pkce_verifier: PkceCodeVerifier,
+    nonce: Nonce,
+    started_at: Instant,
+}
+
+/// An OpenID Connect provider. Discovery happens on first use, so that the server
+/// starts even while the provider is unreachable, and again once it is stale.
+#[derive(Debug)]
+pub struct OidcProvider {
+    config: OidcConfig,
+    client: tokio::sync::Mutex<Option<(CoreClient, Instant)>>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcProvider {
+            config,
+            client: tokio::sync::Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Client from the last discovery, which runs again once it is older than `DISCOVERY_LIFETIME`
+    /// or if `refresh` is set, such as when the provider may have rotated its signing keys
+    async fn client(&self, refresh: bool) -> Result<CoreClient, Error> {
+        let mut client = self.client.lock().await;
+        if let Some((client, discovered_at)) = client.as_ref() {
+            if !refresh && discovered_at.elapsed() < DISCOVERY_LIFETIME {
+                return Ok(client.clone());
+            }
+        }
+
+        let issuer_url = IssuerUrl::new(self.config.issuer_url.clone())?;
+        let redirect_url = RedirectUrl::new(self.config.redirect_url.clone())?;
+        let metadata = CoreProviderMetadata::discover_async(issuer_url, async_http_client).await
+            .map_err(|e| Error::new(ErrorKind::Other, format!("OIDC discovery failed: {}", e)))?;
+        let discovered = CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(self.config.client_id.clone()),
+            self.config.client_secret.clone().map(ClientSecret::new),
+        ).set_redirect_uri(redirect_url);
+        *client = Some((discovered.clone(), Instant::now()));
+        Ok(discovered)
+    }
+
+    /// Start a login, returning the URL of the provider to redirect the browser to and the state,
+    /// which the browser must present again with the callback
+    pub async fn begin_login(&self) -> Result<(String, String), Error> {
+        let client = self.client(false).await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let mut request = client.authorize_url(CoreAuthenticationFlow::AuthorizationCode, CsrfToken::new_random, Nonce::new_random)
+            .set_pkce_challenge(pkce_challenge);
+        for scope in &self.config.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (url, state, nonce) = request.url();
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.started_at.elapsed() < PENDING_LOGIN_LIFETIME);
+        if pending.len() >= MAX_PENDING_LOGINS {
+            return Err(Error::new(ErrorKind::Other, "Too many logins in progress".to_string()));
+        }
+        pending.insert(state.secret().clone(), PendingLogin {
+            pkce_verifier,
+            nonce,
+            started_at: Instant::now(),
+        });
+        Ok((url.to_string(), state.secret().clone()))
+    }
+
+    /// Finish a login: exchange the code, verify the ID token and map its claims
+    pub async fn finish_login(&self, code: &str, state: &str) -> Result<OidcIdentity, Error> {
+        let pending = self.pending.lock().unwrap().remove(state)
+            .filter(|login| login.started_at.elapsed() < PENDING_LOGIN_LIFETIME)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Unknown or expired login state".to_string()))?;
+
+        let client = self.client(false).await?;
+        let response = client.exchange_code(AuthorizationCode::new(code.to_string()))
+            .set_pkce_verifier(pending.pkce_verifier)
+            .request_async(async_http_client).await
+            .map_err(|e| Error::new(ErrorKind::Other, format!("OIDC token exchange failed: {}", e)))?;
+        let id_token = response.id_token()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Provider returned no ID token".to_string()))?;
+        let mut verified = id_token.claims(&client.id_token_verifier(), &pending.nonce).map(|_| ());
+        if let Err(ClaimsVerificationError::SignatureVerification(SignatureVerificationError::NoMatchingKey)) = verified {
+            // Signed with a key newer than the last discovery
+            let client = self.client(true).await?;
+            verified = id_token.claims(&client.id_token_verifier(), &pending.nonce).map(|_| ());
+        }
+        verified.map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Invalid ID token: {}", e)))?;
+
+        // The token is verified; read its claims as plain JSON so that any claim can be mapped.
+        let id_token = id_token.to_string();
+        let payload = id_token.split('.').nth(1)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Malformed ID token".to_string()))?;
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Malformed ID token".to_string()))?;
+        let claims: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(&payload)?;
+        self.config.map_claims(&claims)
+    }
+
+    /// Find the local user linked to `identity` and give it the mapped role, returning its name.
+    /// Logins are never matched to existing users by name, as the provider may let anyone pick
+    /// theirs: an unlinked identity gets a new user if `create_users` is set, or else none
+    /// until an admin links it to an existing user.
+    pub fn sync_user<T>(&self, store: &Store<T>, identity: &OidcIdentity) -> Result<String, Error>
+    where
+        T: DbConnection + Clone + Send + Sync + 'static,
+    {
+        let users = store.users();
+        if let Some(username) = users.get_oidc_user(&identity.issuer, &identity.subject)? {
+            if let Some(mapped) = identity.role {
+                if mapped != users.get_user_role(&username)? {
+                    users.set_role(&username, mapped)?;
+                }
+            }
+            return Ok(username);
+        }
+
+        if !self.config.create_users {
+            return Err(Error::new(ErrorKind::NotFound, "No local user is linked to this identity".to_string()));
+        }
+        // The local password is random; an admin can set one to allow password login.
+        let mut password = [0u8; 32];
+        rand::Rng::fill(&mut rand::thread_rng(), &mut password);
+        users.create_oidc_user(&identity.username, &hex::encode(password), identity.role.unwrap_or_default(), &identity.issuer, &identity.subject)
+            .map_err(|e| match e.kind() {
+                ErrorKind::Conflict => Error::new(ErrorKind::Conflict, format!("User {} exists but is not linked to this identity", identity.username)),
+                _ => e,
+            })?;
+        Ok(identity.username.clone())
+    }
+}
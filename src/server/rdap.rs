@@ -0,0 +1,396 @@
+//! RDAP (RFC 9082/9083) for public resources
+//! - `GET /rdap/ip/:addr` and `GET /rdap/ip/:addr/:len` - The most specific IPv4 or IPv6 network containing an address or CIDR block
+//! - `GET /rdap/autnum/:asn` - The most specific ASN object containing an AS number
+//! - `GET /rdap/entity/:handle` - The holder of assignments, identified by the assignment name
+//! - `GET /rdap/help`
+//!
+//! Only objects that are public along with all their ancestors are served, with or without login.
+
+use crate::store::DbConnection;
+use crate::server::Server;
+use crate::server::api::run_blocking_task;
+use crate::lookup::{self, Found, FoundIpv4, FoundIpv6, FoundAsn, Object};
+use crate::ipv4;
+use crate::ipv6;
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::get;
+use axum::extract::Extension as ExtensionExtractor;
+use axum::extract::Path as PathExtractor;
+
+use http::Response;
+
+use serde::Serialize;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+
+const RDAP_CONFORMANCE: &[&str] = &["rdap_level_0"];
+
+const CONTENT_TYPE: &str = "application/rdap+json";
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Remark {
+    description: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Notice {
+    title: String,
+    description: Vec<String>,
+}
+
+/// RFC 9083 section 5.4
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct IpNetwork {
+    object_class_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rdap_conformance: Option<&'static [&'static str]>,
+    handle: String,
+    start_address: String,
+    end_address: String,
+    ip_version: &'static str,
+    name: String,
+    #[serde(rename = "type")]
+    network_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_handle: Option<String>,
+    status: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remarks: Vec<Remark>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<Entity>,
+}
+
+/// RFC 9083 section 5.5
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Autnum {
+    object_class_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rdap_conformance: Option<&'static [&'static str]>,
+    handle: String,
+    start_autnum: u32,
+    end_autnum: u32,
+    name: String,
+    #[serde(rename = "type")]
+    autnum_type: &'static str,
+    status: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remarks: Vec<Remark>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<Entity>,
+}
+
+/// RFC 9083 section 5.1
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Entity {
+    object_class_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rdap_conformance: Option<&'static [&'static str]>,
+    handle: String,
+    vcard_array: serde_json::Value,
+    roles: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    networks: Vec<IpNetwork>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    autnums: Vec<Autnum>,
+}
+
+/// Registrant of an assignment: entities are identified by the assignment name
+fn registrant(name: &str) -> Entity {
+    Entity {
+        object_class_name: "entity",
+        rdap_conformance: None,
+        handle: name.to_string(),
+        vcard_array: serde_json::json!(["vcard", [
+            ["version", {}, "text", "4.0"],
+            ["fn", {}, "text", name],
+        ]]),
+        roles: vec!["registrant"],
+        networks: Vec::new(),
+        autnums: Vec::new(),
+    }
+}
+
+fn remarks(description: &str) -> Vec<Remark> {
+    if description.trim().is_empty() {
+        return Vec::new();
+    }
+    vec![Remark { description: description.lines().map(|line| line.to_string()).collect() }]
+}
+
+/// Handle, RDAP type and parent handle of an object
+fn describe<S, P, A>(found: &Found<S, P, A>, family: &str) -> (String, &'static str, Option<String>)
+where
+    S: Object,
+    P: Object,
+    A: Object,
+{
+    let id = found.object().id();
+    let parent_id = found.parent_id().unwrap_or_default();
+    match found {
+        Found::Space(..) => (format!("{}-SPACE-{}", family, id), "ALLOCATION", None),
+        Found::Pool(..) => (format!("{}-POOL-{}", family, id), "SUB-ALLOCATION", Some(format!("{}-SPACE-{}", family, parent_id))),
+        Found::Assignment(..) => (format!("{}-ASSIGNMENT-{}", family, id), "ASSIGNMENT", Some(format!("{}-POOL-{}", family, parent_id))),
+    }
+}
+
+/// The registrant of assignments; spaces and pools have none
+fn entities<S, P, A>(found: &Found<S, P, A>) -> Vec<Entity>
+where
+    S: Object,
+    P: Object,
+    A: Object,
+{
+    match found {
+        Found::Assignment(_, _, assignment) => vec![registrant(assignment.name())],
+        _ => Vec::new(),
+    }
+}
+
+fn ipv4_network(found: &FoundIpv4) -> IpNetwork {
+    let (handle, network_type, parent_handle) = describe(found, "IPV4");
+    let (prefix, prefix_len) = found.prefix();
+    IpNetwork {
+        object_class_name: "ip network",
+        rdap_conformance: None,
+        handle,
+        start_address: Ipv4Addr::from(ipv4::ipv4_network_address(prefix, prefix_len)).to_string(),
+        end_address: Ipv4Addr::from(ipv4::ipv4_broadcast_address(prefix, prefix_len)).to_string(),
+        ip_version: "v4",
+        name: found.object().name().to_string(),
+        network_type,
+        parent_handle,
+        status: vec!["active"],
+        remarks: remarks(found.object().description()),
+        entities: entities(found),
+    }
+}
+
+fn ipv6_network(found: &FoundIpv6) -> IpNetwork {
+    let (handle, network_type, parent_handle) = describe(found, "IPV6");
+    let (prefix, prefix_len) = found.prefix();
+    IpNetwork {
+        object_class_name: "ip network",
+        rdap_conformance: None,
+        handle,
+        start_address: Ipv6Addr::from(ipv6::ipv6_network_address(prefix, prefix_len)).to_string(),
+        end_address: Ipv6Addr::from(ipv6::ipv6_broadcast_address(prefix, prefix_len)).to_string(),
+        ip_version: "v6",
+        name: found.object().name().to_string(),
+        network_type,
+        parent_handle,
+        status: vec!["active"],
+        remarks: remarks(found.object().description()),
+        entities: entities(found),
+    }
+}
+
+fn autnum(found: &FoundAsn) -> Autnum {
+    let (handle, autnum_type, _) = describe(found, "ASN");
+    let (start_autnum, end_autnum) = found.range();
+    Autnum {
+        object_class_name: "autnum",
+        rdap_conformance: None,
+        handle,
+        start_autnum,
+        end_autnum,
+        name: found.object().name().to_string(),
+        autnum_type,
+        status: vec!["active"],
+        remarks: remarks(found.object().description()),
+        entities: entities(found),
+    }
+}
+
+fn rdap_response(body: &impl Serialize, status: u16) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", CONTENT_TYPE)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::from(serde_json::to_string(body).unwrap()))
+        .unwrap()
+}
+
+/// RFC 9083 section 6
+fn rdap_error(status: u16, title: &str, description: impl ToString) -> Response<Body> {
+    let body = serde_json::json!({
+        "rdapConformance": RDAP_CONFORMANCE,
+        "errorCode": status,
+        "title": title,
+        "description": [description.to_string()],
+    });
+    rdap_response(&body, status)
+}
+
+fn rdap_bad_request(description: impl ToString) -> Response<Body> {
+    rdap_error(400, "Bad Request", description)
+}
+
+fn rdap_not_found() -> Response<Body> {
+    rdap_error(404, "Not Found", "No matching public object")
+}
+
+fn rdap_internal_error(context: &str, e: &crate::types::Error) -> Response<Body> {
+    log::error!("{}: {}", context, e);
+    rdap_error(500, "Internal Server Error", context)
+}
+
+async fn rdap_ip<T>(ext: Option<ExtensionExtractor<Server<T>>>, addr: String, prefix_len: Option<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let Some(ext) = ext else {
+        return rdap_error(500, "Internal Server Error", "Internal Server Error");
+    };
+    let addr = match addr.parse::<IpAddr>() {
+        Ok(addr) => addr,
+        Err(_) => return rdap_bad_request(format!("Invalid IP address: {}", addr)),
+    };
+    let max_len = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix_len.map(|len| len.parse::<u8>()) {
+        None => max_len,
+        Some(Ok(len)) if len <= max_len => len,
+        Some(_) => return rdap_bad_request("Invalid prefix length"),
+    };
+
+    let store = ext.0.store().clone();
+    let network = match addr {
+        IpAddr::V4(addr) => run_blocking_task(store, move |store| {
+            lookup::lookup_ipv4(store.ipv4_assignments().as_ref(), addr.octets(), prefix_len, true)
+                .map(|found| found.as_ref().map(ipv4_network))
+        }).await,
+        IpAddr::V6(addr) => run_blocking_task(store, move |store| {
+            lookup::lookup_ipv6(store.ipv6_assignments().as_ref(), addr.octets(), prefix_len, true)
+                .map(|found| found.as_ref().map(ipv6_network))
+        }).await,
+    };
+    match network {
+        Ok(Some(mut network)) => {
+            network.rdap_conformance = Some(RDAP_CONFORMANCE);
+            rdap_response(&network, 200)
+        },
+        Ok(None) => rdap_not_found(),
+        Err(e) => rdap_internal_error("Error looking up IP network", &e),
+    }
+}
+
+async fn rdap_ip_addr<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(addr): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    rdap_ip(ext, addr, None).await
+}
+
+async fn rdap_ip_cidr<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor((addr, prefix_len)): PathExtractor<(String, String)>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    rdap_ip(ext, addr, Some(prefix_len)).await
+}
+
+async fn rdap_autnum<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(asn): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let Some(ext) = ext else {
+        return rdap_error(500, "Internal Server Error", "Internal Server Error");
+    };
+    let asn = match asn.parse::<u32>() {
+        Ok(asn) => asn,
+        Err(_) => return rdap_bad_request(format!("Invalid AS number: {}", asn)),
+    };
+    let found = run_blocking_task(ext.0.store().clone(), move |store| {
+        lookup::lookup_asn(store.asn_assignments().as_ref(), asn, true)
+            .map(|found| found.as_ref().map(autnum))
+    }).await;
+    match found {
+        Ok(Some(mut autnum)) => {
+            autnum.rdap_conformance = Some(RDAP_CONFORMANCE);
+            rdap_response(&autnum, 200)
+        },
+        Ok(None) => rdap_not_found(),
+        Err(e) => rdap_internal_error("Error looking up autnum", &e),
+    }
+}
+
+async fn rdap_entity<T>(ext: Option<ExtensionExtractor<Server<T>>>, PathExtractor(handle): PathExtractor<String>) -> Response<Body>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let Some(ext) = ext else {
+        return rdap_error(500, "Internal Server Error", "Internal Server Error");
+    };
+    let entity_handle = handle.clone();
+    let entity = run_blocking_task(ext.0.store().clone(), move |store| {
+        let mut entity = registrant(&entity_handle);
+        for found in lookup::ipv4_objects(store.ipv4_assignments().as_ref(), true)? {
+            if matches!(&found, Found::Assignment(_, _, assignment) if assignment.name == entity_handle) {
+                entity.networks.push(ipv4_network(&found));
+            }
+        }
+        for found in lookup::ipv6_objects(store.ipv6_assignments().as_ref(), true)? {
+            if matches!(&found, Found::Assignment(_, _, assignment) if assignment.name == entity_handle) {
+                entity.networks.push(ipv6_network(&found));
+            }
+        }
+        for found in lookup::asn_objects(store.asn_assignments().as_ref(), true)? {
+            if matches!(&found, Found::Assignment(_, _, assignment) if assignment.name == entity_handle) {
+                entity.autnums.push(autnum(&found));
+            }
+        }
+        Ok::<_, crate::types::Error>(entity)
+    }).await;
+    match entity {
+        Ok(entity) if entity.networks.is_empty() && entity.autnums.is_empty() => rdap_not_found(),
+        Ok(mut entity) => {
+            // The entity is the registrant already; do not repeat it inside each object
+            for network in entity.networks.iter_mut() {
+                network.entities.clear();
+            }
+            for autnum in entity.autnums.iter_mut() {
+                autnum.entities.clear();
+            }
+            entity.rdap_conformance = Some(RDAP_CONFORMANCE);
+            rdap_response(&entity, 200)
+        },
+        Err(e) => rdap_internal_error("Error looking up entity", &e),
+    }
+}
+
+async fn rdap_help() -> Response<Body> {
+    let body = serde_json::json!({
+        "rdapConformance": RDAP_CONFORMANCE,
+        "notices": [Notice {
+            title: "Help".to_string(),
+            description: vec![
+                "Queries: ip/<address>, ip/<address>/<prefix length>, autnum/<AS number>, entity/<handle>.".to_string(),
+                "Only public resources are available. Entities are the holders of assignments, identified by the assignment name.".to_string(),
+            ],
+        }],
+    });
+    rdap_response(&body, 200)
+}
+
+pub fn build_router<T>() -> Router<Server<T>>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new();
+
+    router = router.route("/ip/:addr", get(rdap_ip_addr::<T>));
+    router = router.route("/ip/:addr/:len", get(rdap_ip_cidr::<T>));
+    router = router.route("/autnum/:asn", get(rdap_autnum::<T>));
+    router = router.route("/entity/:handle", get(rdap_entity::<T>));
+    router = router.route("/help", get(rdap_help));
+
+    router = router.fallback(|| async { rdap_bad_request("Unsupported query") });
+
+    router
+}
@@ -0,0 +1,568 @@
+//! RPKI to Router protocol (RFC 8210, and version 0 from RFC 6810) for our ROAs
+//!
+//! Routers are served the ROAs of the store as validated ROA payloads (VRPs), as if they
+//! had been validated by a relying party. The serial of the cache is the serial of the ROA
+//! set in the store, so it survives restarts; the session ID is chosen at startup. Changes
+//! made through the store, including by other processes, are noticed within
+//! `poll_interval` seconds and announced to connected routers with a Serial Notify.
+
+use crate::store::{DbConnection, Store};
+use crate::types::Error;
+use crate::roa::Roa;
+use crate::config::one_or_many;
+use super::api::run_blocking_task;
+use super::listener::{self, Accepted, ListenAddr, Listener};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::{BTreeSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+
+fn default_refresh_interval() -> u32 {
+    3600
+}
+
+fn default_retry_interval() -> u32 {
+    600
+}
+
+fn default_expire_interval() -> u32 {
+    7200
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RtrConfig {
+    /// Addresses to listen on, such as `[::]:323`
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen: Vec<ListenAddr>,
+
+    /// Seconds routers should wait before polling again, sent in End of Data
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u32,
+
+    /// Seconds routers should wait before retrying a failed poll
+    #[serde(default = "default_retry_interval")]
+    pub retry_interval: u32,
+
+    /// Seconds routers may keep using the data without a successful poll
+    #[serde(default = "default_expire_interval")]
+    pub expire_interval: u32,
+
+    /// Seconds between checks of the store for changes to the ROAs
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+}
+
+impl RtrConfig {
+    /// Listen on `listen` with the default intervals
+    pub fn new(listen: Vec<ListenAddr>) -> Self {
+        RtrConfig {
+            listen,
+            refresh_interval: default_refresh_interval(),
+            retry_interval: default_retry_interval(),
+            expire_interval: default_expire_interval(),
+            poll_interval: default_poll_interval(),
+        }
+    }
+}
+
+/// Highest protocol version we speak
+pub const MAX_VERSION: u8 = 1;
+
+/// Longest PDU accepted from routers. Routers only send short queries and error reports.
+const MAX_PDU_LENGTH: usize = 64 * 1024;
+
+/// Number of earlier ROA sets kept to answer Serial Queries incrementally
+const HISTORY_LENGTH: usize = 32;
+
+pub const ERROR_CORRUPT_DATA: u16 = 0;
+pub const ERROR_INTERNAL_ERROR: u16 = 1;
+pub const ERROR_NO_DATA_AVAILABLE: u16 = 2;
+pub const ERROR_INVALID_REQUEST: u16 = 3;
+pub const ERROR_UNSUPPORTED_PROTOCOL_VERSION: u16 = 4;
+pub const ERROR_UNSUPPORTED_PDU_TYPE: u16 = 5;
+pub const ERROR_UNEXPECTED_PROTOCOL_VERSION: u16 = 8;
+
+/// Validated ROA payload: what a router learns from a ROA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vrp {
+    pub prefix: IpAddr,
+    pub prefix_len: u8,
+    pub max_length: u8,
+    pub asn: u32,
+}
+
+impl From<&Roa> for Vrp {
+    fn from(roa: &Roa) -> Self {
+        Vrp {
+            prefix: roa.prefix,
+            prefix_len: roa.prefix_len,
+            max_length: roa.max_length,
+            asn: roa.asn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pdu {
+    SerialNotify { session_id: u16, serial: u32 },
+    SerialQuery { session_id: u16, serial: u32 },
+    ResetQuery,
+    CacheResponse { session_id: u16 },
+
+    /// IPv4 Prefix or IPv6 Prefix, announcing or withdrawing `vrp`
+    Prefix { announce: bool, vrp: Vrp },
+
+    /// The intervals are only sent in version 1
+    EndOfData { session_id: u16, serial: u32, refresh_interval: u32, retry_interval: u32, expire_interval: u32 },
+    CacheReset,
+
+    /// `pdu` is the erroneous PDU, if any
+    ErrorReport { code: u16, pdu: Vec<u8>, text: String },
+}
+
+fn header(buf: &mut Vec<u8>, version: u8, pdu_type: u8, session: u16, length: usize) {
+    buf.push(version);
+    buf.push(pdu_type);
+    buf.extend_from_slice(&session.to_be_bytes());
+    buf.extend_from_slice(&(length as u32).to_be_bytes());
+}
+
+impl Pdu {
+    /// Append the PDU in protocol `version` to `buf`
+    pub fn encode(&self, version: u8, buf: &mut Vec<u8>) {
+        match self {
+            Pdu::SerialNotify { session_id, serial } => {
+                header(buf, version, 0, *session_id, 12);
+                buf.extend_from_slice(&serial.to_be_bytes());
+            },
+            Pdu::SerialQuery { session_id, serial } => {
+                header(buf, version, 1, *session_id, 12);
+                buf.extend_from_slice(&serial.to_be_bytes());
+            },
+            Pdu::ResetQuery => header(buf, version, 2, 0, 8),
+            Pdu::CacheResponse { session_id } => header(buf, version, 3, *session_id, 8),
+            Pdu::Prefix { announce, vrp } => {
+                let (pdu_type, length) = match vrp.prefix {
+                    IpAddr::V4(_) => (4, 20),
+                    IpAddr::V6(_) => (6, 32),
+                };
+                header(buf, version, pdu_type, 0, length);
+                buf.extend_from_slice(&[*announce as u8, vrp.prefix_len, vrp.max_length, 0]);
+                match vrp.prefix {
+                    IpAddr::V4(addr) => buf.extend_from_slice(&addr.octets()),
+                    IpAddr::V6(addr) => buf.extend_from_slice(&addr.octets()),
+                }
+                buf.extend_from_slice(&vrp.asn.to_be_bytes());
+            },
+            Pdu::EndOfData { session_id, serial, refresh_interval, retry_interval, expire_interval } => {
+                if version == 0 {
+                    header(buf, version, 7, *session_id, 12);
+                    buf.extend_from_slice(&serial.to_be_bytes());
+                } else {
+                    header(buf, version, 7, *session_id, 24);
+                    for value in [serial, refresh_interval, retry_interval, expire_interval] {
+                        buf.extend_from_slice(&value.to_be_bytes());
+                    }
+                }
+            },
+            Pdu::CacheReset => header(buf, version, 8, 0, 8),
+            Pdu::ErrorReport { code, pdu, text } => {
+                header(buf, version, 10, *code, 16 + pdu.len() + text.len());
+                buf.extend_from_slice(&(pdu.len() as u32).to_be_bytes());
+                buf.extend_from_slice(pdu);
+                buf.extend_from_slice(&(text.len() as u32).to_be_bytes());
+                buf.extend_from_slice(text.as_bytes());
+            },
+        }
+    }
+
+    /// Decode one complete PDU as returned by [`read_pdu`], with its protocol version.
+    /// On failure, returns the error code to report.
+    pub fn decode(raw: &[u8]) -> Result<(u8, Pdu), u16> {
+        if raw.len() < 8 || u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]) as usize != raw.len() {
+            return Err(ERROR_CORRUPT_DATA);
+        }
+        let version = raw[0];
+        if version > MAX_VERSION {
+            return Err(ERROR_UNSUPPORTED_PROTOCOL_VERSION);
+        }
+        let session = u16::from_be_bytes([raw[2], raw[3]]);
+        let body = &raw[8..];
+        let u32_at = |offset: usize| u32::from_be_bytes([body[offset], body[offset + 1], body[offset + 2], body[offset + 3]]);
+        let expect_length = |length: usize| if body.len() == length { Ok(()) } else { Err(ERROR_CORRUPT_DATA) };
+        let pdu = match raw[1] {
+            0 => {
+                expect_length(4)?;
+                Pdu::SerialNotify { session_id: session, serial: u32_at(0) }
+            },
+            1 => {
+                expect_length(4)?;
+                Pdu::SerialQuery { session_id: session, serial: u32_at(0) }
+            },
+            2 => {
+                expect_length(0)?;
+                Pdu::ResetQuery
+            },
+            3 => {
+                expect_length(0)?;
+                Pdu::CacheResponse { session_id: session }
+            },
+            pdu_type @ (4 | 6) => {
+                let addr_len = if pdu_type == 4 { 4 } else { 16 };
+                expect_length(8 + addr_len)?;
+                let prefix = if pdu_type == 4 {
+                    IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&body[4..8]).unwrap()))
+                } else {
+                    IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&body[4..20]).unwrap()))
+                };
+                let vrp = Vrp { prefix, prefix_len: body[1], max_length: body[2], asn: u32_at(4 + addr_len) };
+                Pdu::Prefix { announce: body[0] & 1 == 1, vrp }
+            },
+            7 => {
+                if version == 0 {
+                    expect_length(4)?;
+                    Pdu::EndOfData { session_id: session, serial: u32_at(0), refresh_interval: 0, retry_interval: 0, expire_interval: 0 }
+                } else {
+                    expect_length(16)?;
+                    Pdu::EndOfData { session_id: session, serial: u32_at(0), refresh_interval: u32_at(4), retry_interval: u32_at(8), expire_interval: u32_at(12) }
+                }
+            },
+            8 => {
+                expect_length(0)?;
+                Pdu::CacheReset
+            },
+            10 => {
+                if body.len() < 8 {
+                    return Err(ERROR_CORRUPT_DATA);
+                }
+                let pdu_len = u32_at(0) as usize;
+                if body.len() < 8 + pdu_len {
+                    return Err(ERROR_CORRUPT_DATA);
+                }
+                let text_len = u32_at(4 + pdu_len) as usize;
+                expect_length(8 + pdu_len + text_len)?;
+                Pdu::ErrorReport {
+                    code: session,
+                    pdu: body[4..4 + pdu_len].to_vec(),
+                    text: String::from_utf8_lossy(&body[8 + pdu_len..]).into_owned(),
+                }
+            },
+            _ => return Err(ERROR_UNSUPPORTED_PDU_TYPE),
+        };
+        Ok((version, pdu))
+    }
+}
+
+/// Read one PDU as raw bytes, `None` at the end of the stream. A PDU with an impossible
+/// length is returned as its header only, which [`Pdu::decode`] rejects as corrupt.
+pub async fn read_pdu<I>(io: &mut I) -> std::io::Result<Option<Vec<u8>>>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut raw = vec![0u8; 8];
+    match io.read_exact(&mut raw).await {
+        Ok(_) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]) as usize;
+    if !(8..=MAX_PDU_LENGTH).contains(&length) {
+        return Ok(Some(raw));
+    }
+    raw.resize(length, 0);
+    io.read_exact(&mut raw[8..]).await?;
+    Ok(Some(raw))
+}
+
+#[derive(Debug)]
+struct CacheState {
+    serial: u32,
+    vrps: Arc<BTreeSet<Vrp>>,
+
+    /// Earlier serials and their VRPs, oldest first
+    history: VecDeque<(u32, Arc<BTreeSet<Vrp>>)>,
+}
+
+/// The VRPs served to routers, with recent history for incremental updates
+#[derive(Debug)]
+pub struct RtrCache {
+    session_id: u16,
+    state: RwLock<CacheState>,
+    serial_sender: watch::Sender<u32>,
+}
+
+/// Serial and VRPs of the store, read consistently
+fn load<T>(store: &Store<T>) -> Result<(u32, BTreeSet<Vrp>), Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let roas = store.roas();
+    loop {
+        let serial = roas.get_serial()?;
+        let vrps = roas.get_roas()?.iter().map(Vrp::from).collect();
+        if roas.get_serial()? == serial {
+            return Ok((serial, vrps));
+        }
+    }
+}
+
+impl RtrCache {
+    /// Load the current ROAs from the store, with a new random session ID
+    pub async fn load<T>(store: &Store<T>) -> Result<Arc<RtrCache>, Error>
+    where
+        T: DbConnection + Clone + Send + Sync + 'static,
+    {
+        let (serial, vrps) = run_blocking_task(store.clone(), |store| load(&store)).await?;
+        let (serial_sender, _) = watch::channel(serial);
+        Ok(Arc::new(RtrCache {
+            session_id: rand::random(),
+            state: RwLock::new(CacheState {
+                serial,
+                vrps: Arc::new(vrps),
+                history: VecDeque::new(),
+            }),
+            serial_sender,
+        }))
+    }
+
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.state.read().unwrap().serial
+    }
+
+    /// Reload the ROAs if their serial in the store changed, and notify the connections.
+    /// Returns whether anything changed.
+    pub async fn refresh<T>(&self, store: &Store<T>) -> Result<bool, Error>
+    where
+        T: DbConnection + Clone + Send + Sync + 'static,
+    {
+        let serial = run_blocking_task(store.clone(), |store| store.roas().get_serial()).await?;
+        if serial == self.serial() {
+            return Ok(false);
+        }
+        let (serial, vrps) = run_blocking_task(store.clone(), |store| load(&store)).await?;
+        {
+            let mut state = self.state.write().unwrap();
+            let previous = (state.serial, state.vrps.clone());
+            state.history.push_back(previous);
+            if state.history.len() > HISTORY_LENGTH {
+                state.history.pop_front();
+            }
+            state.serial = serial;
+            state.vrps = Arc::new(vrps);
+        }
+        log::info!("ROAs changed; RTR serial is now {}", serial);
+        self.serial_sender.send_replace(serial);
+        Ok(true)
+    }
+
+    /// Current serial and all VRPs
+    fn snapshot(&self) -> (u32, Arc<BTreeSet<Vrp>>) {
+        let state = self.state.read().unwrap();
+        (state.serial, state.vrps.clone())
+    }
+
+    /// Current serial and the withdrawals and announcements since `serial`, or `None` if
+    /// `serial` is too old or unknown
+    fn changes_since(&self, serial: u32) -> Option<(u32, Vec<Pdu>)> {
+        let state = self.state.read().unwrap();
+        let old = if serial == state.serial {
+            return Some((state.serial, Vec::new()));
+        } else {
+            state.history.iter().find(|(old_serial, _)| *old_serial == serial).map(|(_, vrps)| vrps.clone())?
+        };
+        let withdrawn = old.difference(&state.vrps).map(|vrp| Pdu::Prefix { announce: false, vrp: *vrp });
+        let announced = state.vrps.difference(&old).map(|vrp| Pdu::Prefix { announce: true, vrp: *vrp });
+        Some((state.serial, withdrawn.chain(announced).collect()))
+    }
+}
+
+/// Check the store for changes every `poll_interval` seconds until `shutdown` is set
+pub(crate) async fn poll<T>(cache: Arc<RtrCache>, store: Store<T>, config: Arc<RtrConfig>, mut shutdown: watch::Receiver<bool>)
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let mut interval = tokio::time::interval(Duration::from_secs(config.poll_interval));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = listener::shutdown_requested(&mut shutdown) => break,
+        }
+        if let Err(e) = cache.refresh(&store).await {
+            log::error!("Error reloading ROAs for RTR: {}", e);
+        }
+    }
+}
+
+/// Answer one decoded query. Returns the response, and whether to close the connection.
+fn answer(cache: &RtrCache, config: &RtrConfig, pdu: Pdu) -> (Vec<Pdu>, bool) {
+    let end_of_data = |serial: u32| Pdu::EndOfData {
+        session_id: cache.session_id,
+        serial,
+        refresh_interval: config.refresh_interval,
+        retry_interval: config.retry_interval,
+        expire_interval: config.expire_interval,
+    };
+    match pdu {
+        Pdu::ResetQuery => {
+            let (serial, vrps) = cache.snapshot();
+            let mut response = vec![Pdu::CacheResponse { session_id: cache.session_id }];
+            response.extend(vrps.iter().map(|vrp| Pdu::Prefix { announce: true, vrp: *vrp }));
+            response.push(end_of_data(serial));
+            (response, false)
+        },
+        Pdu::SerialQuery { session_id, serial } => {
+            let changes = if session_id == cache.session_id { cache.changes_since(serial) } else { None };
+            match changes {
+                Some((serial, changes)) => {
+                    let mut response = vec![Pdu::CacheResponse { session_id: cache.session_id }];
+                    response.extend(changes);
+                    response.push(end_of_data(serial));
+                    (response, false)
+                },
+                None => (vec![Pdu::CacheReset], false),
+            }
+        },
+        Pdu::ErrorReport { code, text, .. } => {
+            log::warn!("RTR client reported error {}: {}", code, text);
+            (Vec::new(), true)
+        },
+        _ => (vec![Pdu::ErrorReport { code: ERROR_INVALID_REQUEST, pdu: Vec::new(), text: "Unexpected PDU from a router".to_string() }], true),
+    }
+}
+
+async fn serve_connection<I>(io: I, cache: Arc<RtrCache>, config: Arc<RtrConfig>, mut shutdown: watch::Receiver<bool>) -> std::io::Result<()>
+where
+    I: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(io);
+
+    // Reading is not cancel safe, so PDUs are read in a separate task
+    let (pdu_sender, mut pdus) = mpsc::channel(4);
+    let reader = tokio::spawn(async move {
+        loop {
+            match read_pdu(&mut reader).await {
+                Ok(Some(raw)) => {
+                    if pdu_sender.send(raw).await.is_err() {
+                        break;
+                    }
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    log::debug!("RTR connection closed with error: {}", e);
+                    break;
+                },
+            }
+        }
+    });
+
+    let mut serials = cache.serial_sender.subscribe();
+    let mut version = None;
+    let result = async {
+        loop {
+            let (response, close) = tokio::select! {
+                raw = pdus.recv() => {
+                    let Some(raw) = raw else {
+                        break;
+                    };
+                    match Pdu::decode(&raw) {
+                        Ok((pdu_version, _)) if version.is_some_and(|version| version != pdu_version) => {
+                            let text = "Protocol version changed within the session".to_string();
+                            (vec![Pdu::ErrorReport { code: ERROR_UNEXPECTED_PROTOCOL_VERSION, pdu: raw, text }], true)
+                        },
+                        Ok((pdu_version, pdu)) => {
+                            version = Some(pdu_version);
+                            answer(&cache, &config, pdu)
+                        },
+                        Err(code) => {
+                            // Error reports are not answered with error reports
+                            let pdu = if raw.get(1) == Some(&10) { Vec::new() } else { raw };
+                            (vec![Pdu::ErrorReport { code, pdu, text: "Cannot decode PDU".to_string() }], true)
+                        },
+                    }
+                },
+                Ok(()) = serials.changed() => {
+                    let serial = *serials.borrow_and_update();
+                    if version.is_none() {
+                        continue;
+                    }
+                    (vec![Pdu::SerialNotify { session_id: cache.session_id, serial }], false)
+                },
+                _ = listener::shutdown_requested(&mut shutdown) => break,
+            };
+            let mut buf = Vec::new();
+            for pdu in &response {
+                pdu.encode(version.unwrap_or(MAX_VERSION), &mut buf);
+            }
+            writer.write_all(&buf).await?;
+            if close {
+                break;
+            }
+        }
+        writer.shutdown().await
+    }.await;
+    reader.abort();
+    result
+}
+
+/// Serve routers on `listener` until `shutdown` is set, then close the connections
+pub(crate) async fn serve(listener: Listener, cache: Arc<RtrCache>, config: Arc<RtrConfig>, shutdown: watch::Receiver<bool>) {
+    let name = listener.to_string();
+    let listener = match listener.into_tokio() {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Cannot use RTR listener {}: {}", name, e);
+            return;
+        },
+    };
+
+    let connection = |result: std::io::Result<()>| {
+        if let Err(e) = result {
+            log::debug!("RTR connection closed with error: {}", e);
+        }
+    };
+    let mut connections = JoinSet::new();
+    let mut listener_shutdown = shutdown.clone();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = listener::shutdown_requested(&mut listener_shutdown) => break,
+        };
+        match accepted {
+            Ok(Accepted::Tcp(stream, addr)) => {
+                log::debug!("RTR connection from {}", addr);
+                let serving = serve_connection(stream, cache.clone(), config.clone(), shutdown.clone());
+                connections.spawn(async move { connection(serving.await) });
+            },
+            Ok(Accepted::Unix(stream)) => {
+                let serving = serve_connection(stream, cache.clone(), config.clone(), shutdown.clone());
+                connections.spawn(async move { connection(serving.await) });
+            },
+            Err(e) => {
+                log::warn!("Failed to accept RTR connection on {}: {}", name, e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            },
+        }
+    }
+
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
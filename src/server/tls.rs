@@ -0,0 +1,226 @@
+//! HTTPS with rustls, certificate reloading and HTTP-to-HTTPS redirection
+
+use crate::types::{Error, ErrorKind};
+use super::auth::{AttributeValue, ClientCertificate, DistinguishedName};
+
+use axum::Router;
+use axum::body::Body;
+
+use http::{Request, Response, header::{HOST, LOCATION}};
+
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+use x509_parser::der_parser::asn1_rs::{Tag, ToDer};
+use x509_parser::x509::AttributeTypeAndValue;
+
+use serde::{Serialize, Deserialize};
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTPS settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain
+    pub cert_path: PathBuf,
+
+    /// PEM file with the private key
+    pub key_path: PathBuf,
+
+    /// PEM file with CAs for client certificates. Clients without a certificate are still accepted.
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+
+    /// Plain HTTP address that redirects to HTTPS
+    #[serde(default)]
+    pub redirect_http_addr: Option<String>,
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Cannot read {}: {}", path.display(), e)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let pem = read_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice()).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("No certificates in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, Error> {
+    let pem = read_pem(path)?;
+    rustls_pemfile::private_key(&mut pem.as_slice())?
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("No private key in {}", path.display())))
+}
+
+impl TlsConfig {
+    /// Read the certificate, key and client CAs
+    pub fn load(&self) -> Result<ServerConfig, Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if let Some(client_ca_path) = &self.client_ca_path {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in load_certs(client_ca_path)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Invalid client CA: {}", e)))?;
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let mut config = builder.with_single_cert(load_certs(&self.cert_path)?, load_key(&self.key_path)?)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// The current rustls configuration, replaced on reload
+#[derive(Debug)]
+pub struct TlsState {
+    config: TlsConfig,
+    server_config: RwLock<Arc<ServerConfig>>,
+}
+
+impl TlsState {
+    pub fn new(config: TlsConfig) -> Result<Self, Error> {
+        let server_config = config.load()?;
+        Ok(TlsState {
+            config,
+            server_config: RwLock::new(Arc::new(server_config)),
+        })
+    }
+
+    pub fn config(&self) -> &TlsConfig {
+        &self.config
+    }
+
+    /// Re-read the certificate files. On failure, the previous certificate stays in use.
+    pub fn reload(&self) -> Result<(), Error> {
+        let server_config = self.config.load()?;
+        *self.server_config.write().unwrap() = Arc::new(server_config);
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.server_config.read().unwrap().clone())
+    }
+}
+
+/// Value of a subject attribute, as a string if it has a string type
+fn attribute_value(attr: &AttributeTypeAndValue<'_>) -> AttributeValue {
+    let value = attr.attr_value();
+    if value.tag() == Tag::BmpString && value.data.len() % 2 == 0 {
+        let units = value.data.chunks(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect::<Vec<_>>();
+        if let Ok(value) = String::from_utf16(&units) {
+            return AttributeValue::String(value);
+        }
+    }
+    match attr.as_str() {
+        Ok(value) => AttributeValue::String(value.to_string()),
+        Err(_) => AttributeValue::Encoded(value.to_der_vec().unwrap_or_default()),
+    }
+}
+
+/// Subject of the verified client certificate of a connection
+fn client_certificate(certs: Option<&[CertificateDer<'_>]>) -> Option<ClientCertificate> {
+    let cert = certs?.first()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let subject = cert.subject();
+    let common_name = subject.iter_common_name().next().and_then(|cn| cn.as_str().ok()).map(|cn| cn.to_string());
+    let rdns = subject.iter().map(|rdn| {
+        rdn.iter().map(|attr| (attr.attr_type().to_id_string(), attribute_value(attr))).collect()
+    }).collect();
+    let subject = DistinguishedName::from_asn1_rdns(rdns);
+    Some(ClientCertificate {
+        subject,
+        common_name,
+    })
+}
+
+/// Reload certificates whenever the process receives SIGHUP
+pub fn reload_on_sighup(state: Arc<TlsState>) {
+    tokio::spawn(async move {
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                log::error!("Cannot listen for SIGHUP: {}", e);
+                return;
+            },
+        };
+        while hangup.recv().await.is_some() {
+            match state.reload() {
+                Ok(_) => log::info!("Reloaded TLS certificate from {}", state.config().cert_path.display()),
+                Err(e) => log::error!("Failed to reload TLS certificate, keeping the old one: {}", e),
+            }
+        }
+    });
+}
+
+/// Complete the TLS handshake with a client at `addr`, returning the verified client certificate if any
+pub(crate) async fn accept(state: &TlsState, stream: TcpStream, addr: SocketAddr) -> Option<(TlsStream<TcpStream>, Option<ClientCertificate>)> {
+    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, state.acceptor().accept(stream)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            log::debug!("TLS handshake with {} failed: {}", addr, e);
+            return None;
+        },
+        Err(_) => {
+            log::debug!("TLS handshake with {} timed out", addr);
+            return None;
+        },
+    };
+    let cert = client_certificate(stream.get_ref().1.peer_certificates());
+    Some((stream, cert))
+}
+
+/// Router that redirects every request to the same URL on HTTPS at `https_port`
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request<Body>| async move {
+        let host = request.headers().get(HOST).and_then(|host| host.to_str().ok());
+        let host = match host {
+            Some(host) if !host.is_empty() => host,
+            _ => {
+                return Response::builder()
+                    .status(400)
+                    .body(Body::from("Host header required"))
+                    .unwrap();
+            },
+        };
+        // Drop any port (keeping IPv6 literals intact) and use the HTTPS one
+        let hostname = match host.rfind(':') {
+            Some(i) if !host[i..].contains(']') => &host[..i],
+            _ => host,
+        };
+        let authority = if https_port == 443 {
+            hostname.to_string()
+        } else {
+            format!("{}:{}", hostname, https_port)
+        };
+        let path = request.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/");
+        Response::builder()
+            .status(308)
+            .header(LOCATION, format!("https://{}{}", authority, path))
+            .body(Body::empty())
+            .unwrap()
+    })
+}
@@ -0,0 +1,213 @@
+//! WHOIS (RFC 3912) for public resources
+//!
+//! A query is one line: an IPv4 or IPv6 address, a prefix (`192.0.2.0/24`) or an AS number
+//! (`AS64500`). The answer is the most specific public object containing it, as RPSL-like
+//! `inetnum:`, `inet6num:`, `aut-num:` or `as-block:` text.
+
+use crate::store::{DbConnection, Store};
+use crate::types::Error;
+use crate::lookup;
+use crate::rpsl;
+use crate::config::one_or_many;
+use super::api::run_blocking_task;
+use super::listener::{self, Accepted, ListenAddr, Listener};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use serde::{Serialize, Deserialize};
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+
+fn default_max_query_length() -> usize {
+    256
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+fn default_source() -> String {
+    rpsl::DEFAULT_SOURCE.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WhoisConfig {
+    /// Addresses to listen on, such as `[::]:43`
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen: Vec<ListenAddr>,
+
+    /// Longest query accepted, in bytes
+    #[serde(default = "default_max_query_length")]
+    pub max_query_length: usize,
+
+    /// Seconds a client has to send its query and receive the answer
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+
+    /// `source:` of the objects
+    #[serde(default = "default_source")]
+    pub source: String,
+}
+
+impl WhoisConfig {
+    /// Listen on `listen` with the default limits
+    pub fn new(listen: Vec<ListenAddr>) -> Self {
+        WhoisConfig {
+            listen,
+            max_query_length: default_max_query_length(),
+            timeout: default_timeout(),
+            source: default_source(),
+        }
+    }
+}
+
+const HEADER: &str = "% MIRAMS WHOIS server. Only public resources are shown.\n\n";
+
+const HELP: &str = "% Query an IPv4 or IPv6 address, a prefix (192.0.2.0/24) or an AS number (AS64500).\n";
+
+const NOT_FOUND: &str = "% No entries found.\n";
+
+/// Answer to one query
+pub fn answer<T>(store: &Store<T>, query: &str, source: &str) -> Result<String, Error>
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let config = rpsl::RpslConfig {
+        source: source.to_string(),
+        maintainer: None,
+    };
+    let query = query.trim();
+    let object = if query.is_empty() || query.eq_ignore_ascii_case("help") {
+        return Ok(format!("{}{}", HEADER, HELP));
+    } else if let Some(asn) = query.strip_prefix("AS").or_else(|| query.strip_prefix("as")).and_then(|asn| asn.parse::<u32>().ok()) {
+        lookup::lookup_asn(store.asn_assignments().as_ref(), asn, true)?
+            .map(|found| rpsl::aut_num(&found, &config))
+    } else {
+        let (addr, prefix_len) = match query.split_once('/') {
+            Some((addr, len)) => match len.parse::<u8>() {
+                Ok(len) => (addr, Some(len)),
+                Err(_) => return Ok(format!("{}% Invalid query.\n{}", HEADER, HELP)),
+            },
+            None => (query, None),
+        };
+        match addr.parse::<IpAddr>() {
+            Ok(IpAddr::V4(addr)) if prefix_len.unwrap_or(32) <= 32 => {
+                lookup::lookup_ipv4(store.ipv4_assignments().as_ref(), addr.octets(), prefix_len.unwrap_or(32), true)?
+                    .map(|found| rpsl::inetnum(&found, &config))
+            },
+            Ok(IpAddr::V6(addr)) if prefix_len.unwrap_or(128) <= 128 => {
+                lookup::lookup_ipv6(store.ipv6_assignments().as_ref(), addr.octets(), prefix_len.unwrap_or(128), true)?
+                    .map(|found| rpsl::inet6num(&found, &config))
+            },
+            _ => return Ok(format!("{}% Invalid query.\n{}", HEADER, HELP)),
+        }
+    };
+    Ok(match object {
+        Some(object) => format!("{}{}\n", HEADER, object),
+        None => format!("{}{}", HEADER, NOT_FOUND),
+    })
+}
+
+/// Read the query line, at most `max_length` bytes without the line ending
+async fn read_query<I>(io: &mut I, max_length: usize) -> std::io::Result<Option<String>>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut query = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let n = io.read(&mut buf).await?;
+        query.extend_from_slice(&buf[..n]);
+        if let Some(end) = query.iter().position(|b| *b == b'\n') {
+            query.truncate(end);
+            break;
+        }
+        if n == 0 {
+            break;
+        }
+        if query.len() > max_length + 1 {
+            return Ok(None);
+        }
+    }
+    if query.ends_with(b"\r") {
+        query.pop();
+    }
+    if query.len() > max_length {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&query).into_owned()))
+}
+
+async fn serve_connection<I, T>(mut io: I, store: Store<T>, config: Arc<WhoisConfig>)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let result = tokio::time::timeout(Duration::from_secs(config.timeout), async {
+        let response = match read_query(&mut io, config.max_query_length).await? {
+            Some(query) => {
+                let source = config.source.clone();
+                match run_blocking_task(store, move |store| answer(&store, &query, &source)).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::error!("Error answering WHOIS query: {}", e);
+                        format!("{}% Internal error.\n", HEADER)
+                    },
+                }
+            },
+            None => format!("{}% Query too long.\n", HEADER),
+        };
+        io.write_all(response.as_bytes()).await?;
+        io.shutdown().await
+    }).await;
+    match result {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => log::debug!("WHOIS connection closed with error: {}", e),
+        Err(_) => log::debug!("WHOIS connection timed out"),
+    }
+}
+
+/// Answer queries on `listener` until `shutdown` is set, then wait for the connections in progress
+pub(crate) async fn serve<T>(listener: Listener, store: Store<T>, config: Arc<WhoisConfig>, mut shutdown: watch::Receiver<bool>)
+where
+    T: DbConnection + Clone + Send + Sync + 'static,
+{
+    let name = listener.to_string();
+    let listener = match listener.into_tokio() {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Cannot use WHOIS listener {}: {}", name, e);
+            return;
+        },
+    };
+
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = listener::shutdown_requested(&mut shutdown) => break,
+        };
+        match accepted {
+            Ok(Accepted::Tcp(stream, _)) => {
+                connections.spawn(serve_connection(stream, store.clone(), config.clone()));
+            },
+            Ok(Accepted::Unix(stream)) => {
+                connections.spawn(serve_connection(stream, store.clone(), config.clone()));
+            },
+            Err(e) => {
+                log::warn!("Failed to accept WHOIS connection on {}: {}", name, e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            },
+        }
+    }
+
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
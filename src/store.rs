@@ -3,6 +3,9 @@ use crate::user::UserStore;
 use crate::ipv4::Ipv4AssignmentStore;
 use crate::ipv6::Ipv6AssignmentStore;
 use crate::asn::AsnAssignmentStore;
+use crate::roa::RoaStore;
+use crate::dns::ReverseDnsStore;
+use crate::dhcp::DhcpStore;
 
 pub trait DbConnection {
     fn user_store(&self) -> Box<dyn UserStore>;
@@ -12,6 +15,57 @@ pub trait DbConnection {
     fn ipv6_assignment_store(&self) -> Box<dyn Ipv6AssignmentStore>;
 
     fn asn_assignment_store(&self) -> Box<dyn AsnAssignmentStore>;
+
+    fn roa_store(&self) -> Box<dyn RoaStore>;
+
+    fn reverse_dns_store(&self) -> Box<dyn ReverseDnsStore>;
+
+    fn dhcp_store(&self) -> Box<dyn DhcpStore>;
+}
+
+/// A connection that wraps another, such as [`crate::ldap::LdapConnection`]. It has the
+/// stores of the inner connection, except for those it overrides.
+pub trait DbConnectionWrapper {
+    type Inner: DbConnection;
+
+    fn inner(&self) -> &Self::Inner;
+
+    fn user_store(&self) -> Box<dyn UserStore> {
+        self.inner().user_store()
+    }
+}
+
+impl<W> DbConnection for W
+where
+    W: DbConnectionWrapper,
+{
+    fn user_store(&self) -> Box<dyn UserStore> {
+        DbConnectionWrapper::user_store(self)
+    }
+
+    fn ipv4_assignment_store(&self) -> Box<dyn Ipv4AssignmentStore> {
+        self.inner().ipv4_assignment_store()
+    }
+
+    fn ipv6_assignment_store(&self) -> Box<dyn Ipv6AssignmentStore> {
+        self.inner().ipv6_assignment_store()
+    }
+
+    fn asn_assignment_store(&self) -> Box<dyn AsnAssignmentStore> {
+        self.inner().asn_assignment_store()
+    }
+
+    fn roa_store(&self) -> Box<dyn RoaStore> {
+        self.inner().roa_store()
+    }
+
+    fn reverse_dns_store(&self) -> Box<dyn ReverseDnsStore> {
+        self.inner().reverse_dns_store()
+    }
+
+    fn dhcp_store(&self) -> Box<dyn DhcpStore> {
+        self.inner().dhcp_store()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -45,4 +99,16 @@ where
     pub fn asn_assignments(&self) -> Box<dyn AsnAssignmentStore> {
         self.db.asn_assignment_store()
     }
+
+    pub fn roas(&self) -> Box<dyn RoaStore> {
+        self.db.roa_store()
+    }
+
+    pub fn reverse_dns(&self) -> Box<dyn ReverseDnsStore> {
+        self.db.reverse_dns_store()
+    }
+
+    pub fn dhcp(&self) -> Box<dyn DhcpStore> {
+        self.db.dhcp_store()
+    }
 }
@@ -0,0 +1,121 @@
+//! RFC 6238 time-based one-time passwords and recovery codes
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+use qrcode::QrCode;
+use qrcode::render::svg;
+
+
+/// Length of generated secrets in bytes (160 bits, as recommended by RFC 4226)
+pub const SECRET_LEN: usize = 20;
+
+/// Time step in seconds
+pub const STEP: i64 = 30;
+
+/// Number of digits in a code
+pub const DIGITS: u32 = 6;
+
+/// Number of steps before and after the current one that are accepted, for clock skew
+pub const SKEW: i64 = 1;
+
+/// Number of recovery codes generated at once
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Issuer shown in authenticator apps
+pub const ISSUER: &str = "MIRAMS";
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 (RFC 4648, unpadded) encoding of a secret, as used in otpauth URIs
+pub fn encode_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, secret)
+}
+
+/// Time step containing `time` (Unix seconds)
+pub fn time_step(time: i64) -> i64 {
+    time.div_euclid(STEP)
+}
+
+/// HOTP value (RFC 4226) for a counter, using HMAC-SHA1
+pub fn code_at_step(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    value % 10u32.pow(DIGITS)
+}
+
+/// Find the step within the allowed skew around `time` whose code matches `code`.
+/// Steps up to and including `last_used_step` are rejected, so that a code cannot be replayed.
+pub fn verify_code(secret: &[u8], code: &str, time: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = time_step(time);
+    ((current - SKEW)..=(current + SKEW))
+        .filter(|step| last_used_step.map(|last| *step > last).unwrap_or(true))
+        .find(|step| code_at_step(secret, *step) == code)
+}
+
+/// `otpauth://` URI for enrolling `username` in an authenticator app
+pub fn otpauth_uri(username: &str, secret: &[u8]) -> String {
+    let label = format!("{}:{}", ISSUER, username);
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(&label),
+        encode_secret(secret),
+        percent_encode(ISSUER),
+        DIGITS,
+        STEP,
+    )
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// QR code of an otpauth URI, as an SVG document
+pub fn qr_code_svg(uri: &str) -> Option<String> {
+    let code = QrCode::new(uri.as_bytes()).ok()?;
+    let image = code.render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Some(image)
+}
+
+/// Generate recovery codes of the form `xxxxx-xxxxx` (lowercase hex)
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT).map(|_| {
+        let mut bytes = [0u8; 5];
+        OsRng.fill_bytes(&mut bytes);
+        let code = hex::encode(bytes);
+        format!("{}-{}", &code[..5], &code[5..])
+    }).collect()
+}
+
+/// Normalize a recovery code as typed by a user
+pub fn normalize_recovery_code(code: &str) -> String {
+    let code: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+    if code.len() == 10 {
+        format!("{}-{}", &code[..5], &code[5..])
+    } else {
+        code
+    }
+}
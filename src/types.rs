@@ -9,10 +9,6 @@ use std::fmt::{
     Display,
 };
 
-use std::any::Any;
-
-use std::error::Error as StdError;
-
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 #[repr(i32)]
@@ -36,12 +32,51 @@ impl TryFrom<i32> for ObjectVisibility {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl ObjectVisibility {
+    /// Visibility of an object given the object followed by its ancestors
+    /// (an assignment, its pool and its space). An object is public only
+    /// if it and all its ancestors are public.
+    pub fn effective(ancestry: &[&dyn HasVisibility]) -> ObjectVisibility {
+        if ancestry.iter().all(|object| object.visibility() == ObjectVisibility::Public) {
+            ObjectVisibility::Public
+        } else {
+            ObjectVisibility::Private
+        }
+    }
+}
+
+/// An object with a visibility of its own: an assignment space, pool or assignment
+pub trait HasVisibility {
+    fn visibility(&self) -> ObjectVisibility;
+}
+
+/// What went wrong, independent of the message. Serialized as the machine-readable
+/// error code of API responses (e.g. `"conflict"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[non_exhaustive]
 pub enum ErrorKind {
     DatabaseError,
     NotFound,
+
+    /// The request is malformed
     InvalidInput,
+
+    /// The request is well-formed but breaks a rule, such as a prefix outside its pool
+    Validation,
+
+    /// The request conflicts with existing data, such as an overlapping assignment
+    Conflict,
+
+    /// Authentication is missing or failed
+    Unauthorized,
+
+    /// The user may not do this
+    Forbidden,
+
+    /// Too many attempts; try again later
+    RateLimited,
+
     InternalError,
     Other,
 }
@@ -67,6 +102,11 @@ impl Error {
     pub fn kind(&self) -> ErrorKind {
         self.kind
     }
+
+    /// The message without the kind
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Display for Error {
@@ -75,20 +115,36 @@ impl Display for Error {
     }
 }
 
-impl<T> From<T> for Error
-where
-    T: StdError + Send + Sync + Any + 'static,
-{
-    fn from(err: T) -> Self {
-        let message = err.to_string();
-        let kind = if let Some(err) = (&err as &dyn Any).downcast_ref::<&(dyn ErrorWithKind + 'static)>() {
-            err.kind()
-        } else {
-            ErrorKind::Other
-        };
-        Error {
-            kind,
-            message,
-        }
+/// Errors of other crates, whose kinds are not known here.
+/// Database errors are converted in `db_sqlite`, which knows their kinds.
+macro_rules! error_from {
+    ($($err:ty),* $(,)?) => {
+        $(
+            impl From<$err> for Error {
+                fn from(err: $err) -> Self {
+                    Error::new(ErrorKind::Other, err)
+                }
+            }
+        )*
+    };
+}
+
+error_from!(
+    std::io::Error,
+    serde_json::Error,
+    openidconnect::url::ParseError,
+    rustls::Error,
+    ldap3::LdapError,
+);
+
+/// `mac` in lower case with colons, such as `00:00:5e:00:53:01`
+/// Also accepts hyphens (`00-00-5E-00-53-01`), dots (`0000.5e00.5301`) or no separators
+pub fn normalize_mac_address(mac: &str) -> Result<String, Error> {
+    let digits: String = mac.trim().chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if digits.len() != 12 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::new(ErrorKind::Validation, format!("Invalid MAC address: {}", mac.trim())));
     }
+    let digits = digits.to_ascii_lowercase();
+    let octets: Vec<&str> = (0..12).step_by(2).map(|i| &digits[i..i + 2]).collect();
+    Ok(octets.join(":"))
 }
@@ -8,6 +8,13 @@ use argon2::{
     Argon2
 };
 
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use serde::{Serialize, Deserialize};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::Error;
 
 pub(crate) fn hash_password(password: &str) -> Result<String, Argon2Error> {
@@ -24,17 +31,703 @@ pub(crate) fn verify_password(hash: &str, password: &str) -> Result<bool, Argon2
     Ok(result)
 }
 
+/// Length of the plaintext prefix stored alongside hashed API keys for lookups
+pub(crate) const API_KEY_PREFIX_LEN: usize = 8;
+
+/// Keyed hash (HMAC-SHA256) of an API key, hex-encoded
+pub(crate) fn hash_api_key(secret: &[u8], api_key: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(api_key.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Constant-time comparison of an API key against a stored hash
+pub(crate) fn verify_api_key(secret: &[u8], api_key: &str, hash: &str) -> bool {
+    let hash = match hex::decode(hash) {
+        Ok(hash) => hash,
+        Err(_) => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(api_key.as_bytes());
+    mac.verify_slice(&hash).is_ok()
+}
+
+/// Minimum length of a configured API key secret, in bytes
+const MIN_API_KEY_SECRET_LEN: usize = 16;
+
+/// Hex-encoded secret keying API key hashes from `path`, which is created with a random secret
+/// readable only by its owner if it does not exist
+pub fn load_api_key_secret(path: &std::path::Path) -> Result<Vec<u8>, Error> {
+    use argon2::password_hash::rand_core::RngCore;
+    use std::io::Write;
+
+    let invalid = |message: String| Error::new(crate::ErrorKind::InvalidInput, format!("{}: {}", path.display(), message));
+    match std::fs::read_to_string(path) {
+        Ok(content) => {
+            let secret = hex::decode(content.trim()).map_err(|e| invalid(e.to_string()))?;
+            if secret.len() < MIN_API_KEY_SECRET_LEN {
+                return Err(invalid(format!("the secret must be at least {} bytes", MIN_API_KEY_SECRET_LEN)));
+            }
+            Ok(secret)
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut secret = vec![0u8; 32];
+            OsRng.fill_bytes(&mut secret);
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path).map_err(|e| invalid(e.to_string()))?;
+            writeln!(file, "{}", hex::encode(&secret)).map_err(|e| invalid(e.to_string()))?;
+            log::info!("Generated a new API key secret in {}", path.display());
+            Ok(secret)
+        },
+        Err(e) => Err(invalid(e.to_string())),
+    }
+}
+
+/// Lookup prefix of an API key
+pub(crate) fn api_key_prefix(api_key: &str) -> &str {
+    api_key.get(..API_KEY_PREFIX_LEN).unwrap_or(api_key)
+}
+
+/// Current time as seconds since the Unix epoch
+pub fn unix_time_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Role of a user
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum UserRole {
+    /// Can view and manage resources
+    #[default]
+    User = 0,
+
+    /// Can additionally manage users
+    Admin = 1,
+}
+
+impl TryFrom<i32> for UserRole {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(UserRole::User),
+            1 => Ok(UserRole::Admin),
+            _ => Err(format!("Invalid role value: {}", value)),
+        }
+    }
+}
+
+impl std::str::FromStr for UserRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "user" => Ok(UserRole::User),
+            "admin" => Ok(UserRole::Admin),
+            _ => Err(format!("Invalid role: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UserRole::User => write!(f, "user"),
+            UserRole::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// A user with metadata, as shown to administrators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub username: String,
+
+    pub role: UserRole,
+
+    /// Creation time (Unix seconds). 0 for users created before this was recorded.
+    pub created_at: i64,
+
+    /// Number of API tokens (including login sessions) of the user
+    pub api_token_count: i64,
+
+    /// Last time any API token of the user was used (Unix seconds)
+    pub last_active_at: Option<i64>,
+}
+
+/// Resource families an API token can be restricted to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceFamily {
+    Asn,
+    Ipv4,
+    Ipv6,
+}
+
+impl ResourceFamily {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourceFamily::Asn => "asn",
+            ResourceFamily::Ipv4 => "ipv4",
+            ResourceFamily::Ipv6 => "ipv6",
+        }
+    }
+}
+
+impl std::str::FromStr for ResourceFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "asn" => Ok(ResourceFamily::Asn),
+            "ipv4" => Ok(ResourceFamily::Ipv4),
+            "ipv6" => Ok(ResourceFamily::Ipv6),
+            _ => Err(format!("Invalid resource family: {}", s)),
+        }
+    }
+}
+
+/// What an API token is allowed to do
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenScope {
+    /// Only allow requests that do not modify anything
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Restrict the token to these resource families. `None` means all families.
+    #[serde(default)]
+    pub families: Option<Vec<ResourceFamily>>,
+}
+
+impl TokenScope {
+    /// Whether the scope allows a request exposing data of `families` (empty for
+    /// requests outside the resource APIs, such as token management).
+    ///
+    /// Tokens restricted to some families can only read outside of them,
+    /// so that they cannot be used to mint broader tokens.
+    pub fn allows(&self, families: &[ResourceFamily], write: bool) -> bool {
+        if self.read_only && write {
+            return false;
+        }
+        match &self.families {
+            None => true,
+            Some(_) if families.is_empty() => !write,
+            Some(allowed) => families.iter().all(|family| allowed.contains(family)),
+        }
+    }
+
+    /// Whether this scope is at least as narrow as `other`
+    pub fn is_subset_of(&self, other: &TokenScope) -> bool {
+        if other.read_only && !self.read_only {
+            return false;
+        }
+        match (&self.families, &other.families) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(mine), Some(theirs)) => mine.iter().all(|f| theirs.contains(f)),
+        }
+    }
+}
+
+/// Metadata of an API token. The secret itself is only returned once, on creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i32,
+
+    /// Human-readable token name
+    pub name: String,
+
+    /// Creation time (Unix seconds)
+    pub created_at: i64,
+
+    /// Expiry time (Unix seconds). `None` means the token never expires.
+    pub expires_at: Option<i64>,
+
+    /// Last time the token was used (Unix seconds)
+    pub last_used_at: Option<i64>,
+
+    pub scope: TokenScope,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.map(|expires_at| expires_at <= now).unwrap_or(false)
+    }
+}
+
+/// A newly created API token, including its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewApiToken {
+    pub token: ApiToken,
+    pub secret: String,
+}
+
+/// Two-factor authentication state of a user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TotpStatus {
+    /// Whether logins require a TOTP or recovery code
+    pub enabled: bool,
+
+    /// Whether an enrolment has been started but not confirmed yet
+    pub pending: bool,
+
+    /// Number of unused recovery codes
+    pub recovery_codes_remaining: i64,
+}
+
+/// A started TOTP enrolment, to be shown to the user once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpEnrolment {
+    /// Base32-encoded secret, for manual entry
+    pub secret: String,
+
+    pub otpauth_uri: String,
+
+    /// QR code of `otpauth_uri`, as an SVG document
+    pub qr_code_svg: Option<String>,
+}
+
+/// Outcome of a login attempt
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum LoginOutcome {
+    Success = 0,
+
+    /// Wrong username or password
+    Failure = 1,
+
+    /// Rejected without checking the password because of throttling or lockout
+    Throttled = 2,
+}
+
+impl TryFrom<i32> for LoginOutcome {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(LoginOutcome::Success),
+            1 => Ok(LoginOutcome::Failure),
+            2 => Ok(LoginOutcome::Throttled),
+            _ => Err(format!("Invalid login outcome value: {}", value)),
+        }
+    }
+}
+
+/// A recorded login attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginAttempt {
+    pub id: i64,
+
+    /// Username as given by the client (the user need not exist)
+    pub username: String,
+
+    /// Source address of the client, if known
+    pub source: Option<String>,
+
+    pub outcome: LoginOutcome,
+
+    /// Time of the attempt (Unix seconds)
+    pub time: i64,
+}
+
+/// What login failures are counted against
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum LoginThrottleKey {
+    Username(String),
+    Source(String),
+}
+
+/// Failed login attempts counted against a key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginFailures {
+    pub key: LoginThrottleKey,
+
+    /// Number of failures that have not been reset by a success or cleared by an admin
+    pub count: i64,
+
+    /// Time of the last counted failure (Unix seconds)
+    pub last_failure_at: i64,
+}
+
+/// A key that is currently throttled or locked out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginLockout {
+    pub key: LoginThrottleKey,
+    pub failures: i64,
+
+    /// Time until which logins are refused (Unix seconds)
+    pub blocked_until: i64,
+
+    /// Whether this is a lockout rather than just a backoff delay
+    pub locked_out: bool,
+}
+
+/// Backoff and lockout policy for failed logins
+///
+/// The first `free_attempts` failures are not delayed. Each further failure doubles
+/// the delay before the next attempt, starting at `base_delay` seconds. After
+/// `lockout_threshold` failures, logins are refused for `lockout_duration` seconds.
+/// Only failures within the last `window` seconds are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginThrottlePolicy {
+    pub free_attempts: i64,
+    pub base_delay: i64,
+    pub lockout_threshold: i64,
+    pub lockout_duration: i64,
+    pub window: i64,
+}
+
+impl LoginThrottlePolicy {
+    /// Policy for failures against a single username
+    pub const USERNAME: LoginThrottlePolicy = LoginThrottlePolicy {
+        free_attempts: 3,
+        base_delay: 1,
+        lockout_threshold: 10,
+        lockout_duration: 15 * 60,
+        window: 60 * 60,
+    };
+
+    /// Policy for failures from a single source address, across usernames
+    pub const SOURCE: LoginThrottlePolicy = LoginThrottlePolicy {
+        free_attempts: 10,
+        base_delay: 1,
+        lockout_threshold: 50,
+        lockout_duration: 15 * 60,
+        window: 60 * 60,
+    };
+
+    /// Policy applying to failures against `key`
+    pub fn for_key(key: &LoginThrottleKey) -> &'static LoginThrottlePolicy {
+        match key {
+            LoginThrottleKey::Username(_) => &Self::USERNAME,
+            LoginThrottleKey::Source(_) => &Self::SOURCE,
+        }
+    }
+
+    /// Earliest time failures are counted from, for a check at `now`
+    pub fn window_start(&self, now: i64) -> i64 {
+        now - self.window
+    }
+
+    /// Whether `failures` reach the lockout threshold
+    pub fn is_locked_out(&self, failures: i64) -> bool {
+        failures >= self.lockout_threshold
+    }
+
+    /// Time until which logins are refused after `failures`. Can be in the past.
+    pub fn blocked_until(&self, failures: &LoginFailures) -> i64 {
+        if failures.count <= 0 {
+            return 0;
+        }
+        if self.is_locked_out(failures.count) {
+            return failures.last_failure_at.saturating_add(self.lockout_duration);
+        }
+        let excess = failures.count - self.free_attempts;
+        if excess <= 0 {
+            return 0;
+        }
+        let delay = self.base_delay.saturating_mul(1i64 << (excess - 1).min(30)).min(self.lockout_duration);
+        failures.last_failure_at.saturating_add(delay)
+    }
+
+    /// The lockout or backoff in effect at `now`, if any
+    pub fn lockout(&self, failures: &LoginFailures, now: i64) -> Option<LoginLockout> {
+        let blocked_until = self.blocked_until(failures);
+        if blocked_until <= now {
+            return None;
+        }
+        Some(LoginLockout {
+            key: failures.key.clone(),
+            failures: failures.count,
+            blocked_until,
+            locked_out: self.is_locked_out(failures.count),
+        })
+    }
+}
+
+/// The longest backoff or lockout in effect at `now`, given the failures against each key
+pub fn longest_login_lockout(failures: &[LoginFailures], now: i64) -> Option<LoginLockout> {
+    failures.iter()
+        .filter_map(|failures| LoginThrottlePolicy::for_key(&failures.key).lockout(failures, now))
+        .max_by_key(|lockout| lockout.blocked_until)
+}
+
+/// Whether a login attempt may go ahead
+#[derive(Debug, Clone)]
+pub enum LoginAttemptStart {
+    /// ID of the attempt, counted as a failure until it is finished
+    Allowed(i64),
+
+    /// The attempt was refused (and recorded as throttled)
+    Throttled(LoginLockout),
+}
+
 pub trait UserStore {
     fn check_password(&self, username: &str, password: &str) -> Result<bool, Error>;
 
-    /// Update the password for a user. If the user does not exist, it will be created.
+    /// Update the password for a user. If the user does not exist, it will be created
+    /// (as an admin if there is no admin yet).
+    /// All existing API tokens of the user are revoked.
     fn set_password(&self, username: &str, password: &str) -> Result<(), Error>;
 
+    /// Update the password for a user, revoking all API tokens except `keep_token_id`
+    /// (typically the token of the session changing the password).
+    fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error>;
+
+    /// Delete a user. Deleting the last admin is refused.
     fn delete_user(&self, username: &str) -> Result<(), Error>;
 
+    /// Create a new user. Fails if the user already exists.
+    fn create_user(&self, username: &str, password: &str, role: UserRole) -> Result<(), Error>;
+
+    /// Create a new user for a single sign-on identity (`subject` at `issuer`) and link the
+    /// two. Fails if the user already exists.
+    fn create_oidc_user(&self, username: &str, password: &str, role: UserRole, issuer: &str, subject: &str) -> Result<(), Error>;
+
+    /// The user linked to a single sign-on identity, if any
+    fn get_oidc_user(&self, issuer: &str, subject: &str) -> Result<Option<String>, Error>;
+
+    /// Link a single sign-on identity to an existing user. Fails if the identity is linked
+    /// to another user.
+    fn link_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error>;
+
+    /// Remove the link of a single sign-on identity to a user
+    fn unlink_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error>;
+
+    /// Get a user with metadata
+    fn get_user(&self, username: &str) -> Result<UserInfo, Error>;
+
+    fn get_user_role(&self, username: &str) -> Result<UserRole, Error>;
+
+    /// List all users with metadata
+    fn list_users_with_metadata(&self) -> Result<Vec<UserInfo>, Error>;
+
+    /// Change the role of a user. Demoting the last admin is refused.
+    fn set_role(&self, username: &str, role: UserRole) -> Result<(), Error>;
+
+    /// Create a fresh, unrestricted, non-expiring API key for a user
     fn generate_api_key(&self, username: &str) -> Result<String, Error>;
 
     fn get_user_from_api_key(&self, api_key: &str) -> Result<Option<String>, Error>;
 
     fn list_users(&self) -> Result<Vec<String>, Error>;
+
+    /// Create a named API token
+    /// `expires_at` is in Unix seconds; `None` means no expiry
+    fn create_api_token(&self, username: &str, name: &str, expires_at: Option<i64>, scope: &TokenScope) -> Result<NewApiToken, Error>;
+
+    /// List the API tokens of a user (without secrets)
+    fn list_api_tokens(&self, username: &str) -> Result<Vec<ApiToken>, Error>;
+
+    /// Revoke an API token owned by a user
+    fn revoke_api_token(&self, username: &str, token_id: i32) -> Result<(), Error>;
+
+    /// Look up a valid (non-expired) API key, returning the owner and token metadata.
+    /// Updates the last-used timestamp of the token.
+    fn get_api_token(&self, api_key: &str) -> Result<Option<(String, ApiToken)>, Error>;
+
+    fn get_totp_status(&self, username: &str) -> Result<TotpStatus, Error>;
+
+    /// Start (or restart) TOTP enrolment with a new secret. Fails if TOTP is already enabled.
+    fn begin_totp_enrolment(&self, username: &str) -> Result<TotpEnrolment, Error>;
+
+    /// Enable TOTP after checking a code from the pending enrolment.
+    /// Returns a fresh set of recovery codes.
+    fn confirm_totp_enrolment(&self, username: &str, code: &str) -> Result<Vec<String>, Error>;
+
+    /// Check a TOTP code or an unused recovery code. Used codes cannot be reused.
+    fn verify_second_factor(&self, username: &str, code: &str) -> Result<bool, Error>;
+
+    /// Replace all recovery codes of an enrolled user
+    fn regenerate_recovery_codes(&self, username: &str) -> Result<Vec<String>, Error>;
+
+    /// Remove TOTP enrolment (pending or enabled) and recovery codes
+    fn disable_totp(&self, username: &str) -> Result<(), Error>;
+
+    /// Record a login attempt. Old records are pruned.
+    fn record_login_attempt(&self, username: &str, source: Option<&str>, outcome: LoginOutcome) -> Result<(), Error>;
+
+    /// Start a password login as `username` from `source`. Unless a backoff or lockout is in
+    /// effect, the attempt is recorded as a failure until `finish_login_attempt` says otherwise.
+    /// The check and the record are one transaction, so parallel attempts cannot all pass.
+    fn begin_login_attempt(&self, username: &str, source: Option<&str>) -> Result<LoginAttemptStart, Error>;
+
+    /// Set the outcome of an attempt from `begin_login_attempt`; `None` withdraws the attempt,
+    /// as when the client is asked for a second factor first
+    fn finish_login_attempt(&self, attempt_id: i64, outcome: Option<LoginOutcome>) -> Result<(), Error>;
+
+    /// Failures against `key` since `since` (Unix seconds). Failures against a username
+    /// are reset by a successful login; those against a source are not.
+    fn get_login_failures(&self, key: &LoginThrottleKey, since: i64) -> Result<LoginFailures, Error>;
+
+    /// Failures since `since` for every username and source that has any
+    fn list_login_failures(&self, since: i64) -> Result<Vec<LoginFailures>, Error>;
+
+    /// Recent login attempts, newest first, optionally filtered by username or source
+    fn list_login_attempts(&self, username: Option<&str>, source: Option<&str>, limit: u32) -> Result<Vec<LoginAttempt>, Error>;
+
+    /// Stop counting past failures against `key`, lifting any lockout
+    fn clear_login_failures(&self, key: &LoginThrottleKey) -> Result<(), Error>;
+}
+
+/// A user store that wraps another, such as [`crate::ldap::LdapUserStore`]. It has the
+/// users, tokens and login records of the inner store, and may override how passwords
+/// are checked and set.
+pub trait UserStoreWrapper {
+    fn inner(&self) -> &dyn UserStore;
+
+    fn check_password(&self, username: &str, password: &str) -> Result<bool, Error> {
+        self.inner().check_password(username, password)
+    }
+
+    fn set_password(&self, username: &str, password: &str) -> Result<(), Error> {
+        self.inner().set_password(username, password)
+    }
+
+    fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error> {
+        self.inner().change_password(username, password, keep_token_id)
+    }
+}
+
+impl<W> UserStore for W
+where
+    W: UserStoreWrapper,
+{
+    fn check_password(&self, username: &str, password: &str) -> Result<bool, Error> {
+        UserStoreWrapper::check_password(self, username, password)
+    }
+
+    fn set_password(&self, username: &str, password: &str) -> Result<(), Error> {
+        UserStoreWrapper::set_password(self, username, password)
+    }
+
+    fn change_password(&self, username: &str, password: &str, keep_token_id: Option<i32>) -> Result<(), Error> {
+        UserStoreWrapper::change_password(self, username, password, keep_token_id)
+    }
+
+    fn delete_user(&self, username: &str) -> Result<(), Error> {
+        self.inner().delete_user(username)
+    }
+
+    fn create_user(&self, username: &str, password: &str, role: UserRole) -> Result<(), Error> {
+        self.inner().create_user(username, password, role)
+    }
+
+    fn create_oidc_user(&self, username: &str, password: &str, role: UserRole, issuer: &str, subject: &str) -> Result<(), Error> {
+        self.inner().create_oidc_user(username, password, role, issuer, subject)
+    }
+
+    fn get_oidc_user(&self, issuer: &str, subject: &str) -> Result<Option<String>, Error> {
+        self.inner().get_oidc_user(issuer, subject)
+    }
+
+    fn link_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        self.inner().link_oidc_user(username, issuer, subject)
+    }
+
+    fn unlink_oidc_user(&self, username: &str, issuer: &str, subject: &str) -> Result<(), Error> {
+        self.inner().unlink_oidc_user(username, issuer, subject)
+    }
+
+    fn get_user(&self, username: &str) -> Result<UserInfo, Error> {
+        self.inner().get_user(username)
+    }
+
+    fn get_user_role(&self, username: &str) -> Result<UserRole, Error> {
+        self.inner().get_user_role(username)
+    }
+
+    fn list_users_with_metadata(&self) -> Result<Vec<UserInfo>, Error> {
+        self.inner().list_users_with_metadata()
+    }
+
+    fn set_role(&self, username: &str, role: UserRole) -> Result<(), Error> {
+        self.inner().set_role(username, role)
+    }
+
+    fn generate_api_key(&self, username: &str) -> Result<String, Error> {
+        self.inner().generate_api_key(username)
+    }
+
+    fn get_user_from_api_key(&self, api_key: &str) -> Result<Option<String>, Error> {
+        self.inner().get_user_from_api_key(api_key)
+    }
+
+    fn list_users(&self) -> Result<Vec<String>, Error> {
+        self.inner().list_users()
+    }
+
+    fn create_api_token(&self, username: &str, name: &str, expires_at: Option<i64>, scope: &TokenScope) -> Result<NewApiToken, Error> {
+        self.inner().create_api_token(username, name, expires_at, scope)
+    }
+
+    fn list_api_tokens(&self, username: &str) -> Result<Vec<ApiToken>, Error> {
+        self.inner().list_api_tokens(username)
+    }
+
+    fn revoke_api_token(&self, username: &str, token_id: i32) -> Result<(), Error> {
+        self.inner().revoke_api_token(username, token_id)
+    }
+
+    fn get_api_token(&self, api_key: &str) -> Result<Option<(String, ApiToken)>, Error> {
+        self.inner().get_api_token(api_key)
+    }
+
+    fn get_totp_status(&self, username: &str) -> Result<TotpStatus, Error> {
+        self.inner().get_totp_status(username)
+    }
+
+    fn begin_totp_enrolment(&self, username: &str) -> Result<TotpEnrolment, Error> {
+        self.inner().begin_totp_enrolment(username)
+    }
+
+    fn confirm_totp_enrolment(&self, username: &str, code: &str) -> Result<Vec<String>, Error> {
+        self.inner().confirm_totp_enrolment(username, code)
+    }
+
+    fn verify_second_factor(&self, username: &str, code: &str) -> Result<bool, Error> {
+        self.inner().verify_second_factor(username, code)
+    }
+
+    fn regenerate_recovery_codes(&self, username: &str) -> Result<Vec<String>, Error> {
+        self.inner().regenerate_recovery_codes(username)
+    }
+
+    fn disable_totp(&self, username: &str) -> Result<(), Error> {
+        self.inner().disable_totp(username)
+    }
+
+    fn record_login_attempt(&self, username: &str, source: Option<&str>, outcome: LoginOutcome) -> Result<(), Error> {
+        self.inner().record_login_attempt(username, source, outcome)
+    }
+
+    fn begin_login_attempt(&self, username: &str, source: Option<&str>) -> Result<LoginAttemptStart, Error> {
+        self.inner().begin_login_attempt(username, source)
+    }
+
+    fn finish_login_attempt(&self, attempt_id: i64, outcome: Option<LoginOutcome>) -> Result<(), Error> {
+        self.inner().finish_login_attempt(attempt_id, outcome)
+    }
+
+    fn get_login_failures(&self, key: &LoginThrottleKey, since: i64) -> Result<LoginFailures, Error> {
+        self.inner().get_login_failures(key, since)
+    }
+
+    fn list_login_failures(&self, since: i64) -> Result<Vec<LoginFailures>, Error> {
+        self.inner().list_login_failures(since)
+    }
+
+    fn list_login_attempts(&self, username: Option<&str>, source: Option<&str>, limit: u32) -> Result<Vec<LoginAttempt>, Error> {
+        self.inner().list_login_attempts(username, source, limit)
+    }
+
+    fn clear_login_failures(&self, key: &LoginThrottleKey) -> Result<(), Error> {
+        self.inner().clear_login_failures(key)
+    }
 }